@@ -0,0 +1,194 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// saved shapes are SVGs too, this tells them apart from any other SVG
+pub const SHAPE_EXTENSION: &str = ".shape.svg";
+const IMAGE_EXTENSIONS: [&str; 6] = ["png", "jpg", "jpeg", "gif", "bmp", "webp"];
+// search stops here so a library pointed at a huge folder stays responsive
+const MAX_SEARCH_DEPTH: usize = 8;
+const MAX_SEARCH_RESULTS: usize = 200;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum AssetKind {
+    Image,
+    Svg,
+    // a polygon saved from the canvas
+    Shape,
+}
+
+impl AssetKind {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let file_name = path.file_name()?.to_str()?.to_lowercase();
+
+        if file_name.ends_with(SHAPE_EXTENSION) {
+            return Some(AssetKind::Shape);
+        }
+
+        let extension = path.extension()?.to_str()?.to_lowercase();
+
+        if extension == "svg" {
+            Some(AssetKind::Svg)
+        } else if IMAGE_EXTENSIONS.contains(&extension.as_str()) {
+            Some(AssetKind::Image)
+        } else {
+            None
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            AssetKind::Image => "Image",
+            AssetKind::Svg => "SVG",
+            AssetKind::Shape => "Shape",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Asset {
+    pub name: String,
+    pub path: PathBuf,
+    pub kind: AssetKind,
+}
+
+impl Asset {
+    pub fn from_path(path: PathBuf) -> Option<Self> {
+        let kind = AssetKind::from_path(&path)?;
+        let file_name = path.file_name()?.to_str()?;
+
+        let name = match kind {
+            AssetKind::Shape => &file_name[..file_name.len() - SHAPE_EXTENSION.len()],
+            _ => path.file_stem()?.to_str()?,
+        }
+        .to_string();
+
+        Some(Asset { name, path, kind })
+    }
+}
+
+/// One folder of the library, folders first, both sorted by name
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AssetFolder {
+    pub path: PathBuf,
+    pub folders: Vec<PathBuf>,
+    pub assets: Vec<Asset>,
+}
+
+fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with('.'))
+}
+
+/// A local folder of images, SVGs and saved shapes
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssetLibrary {
+    pub root: PathBuf,
+}
+
+impl AssetLibrary {
+    pub fn new(root: PathBuf) -> Self {
+        AssetLibrary { root }
+    }
+
+    /// An "assets" folder next to wherever the editor was started from
+    pub fn default_root() -> PathBuf {
+        std::env::current_dir().unwrap_or_default().join("assets")
+    }
+
+    pub fn read_folder(&self, folder: &Path) -> io::Result<AssetFolder> {
+        let mut listing = AssetFolder {
+            path: folder.to_path_buf(),
+            ..AssetFolder::default()
+        };
+
+        for entry in fs::read_dir(folder)? {
+            let path = entry?.path();
+
+            if is_hidden(&path) {
+                continue;
+            }
+
+            if path.is_dir() {
+                listing.folders.push(path);
+            } else if let Some(asset) = Asset::from_path(path) {
+                listing.assets.push(asset);
+            }
+        }
+
+        listing.folders.sort();
+        listing
+            .assets
+            .sort_by_key(|asset| asset.name.to_lowercase());
+
+        Ok(listing)
+    }
+
+    /// Every asset under the root whose name contains the query, ignoring case
+    pub fn search(&self, query: &str) -> Vec<Asset> {
+        let query = query.trim().to_lowercase();
+        let mut results = Vec::new();
+        let mut pending = vec![(self.root.clone(), 0)];
+
+        while let Some((folder, depth)) = pending.pop() {
+            let Ok(listing) = self.read_folder(&folder) else {
+                continue;
+            };
+
+            results.extend(
+                listing
+                    .assets
+                    .into_iter()
+                    .filter(|asset| asset.name.to_lowercase().contains(&query)),
+            );
+
+            if results.len() >= MAX_SEARCH_RESULTS {
+                results.truncate(MAX_SEARCH_RESULTS);
+                break;
+            }
+
+            if depth < MAX_SEARCH_DEPTH {
+                pending.extend(listing.folders.into_iter().map(|path| (path, depth + 1)));
+            }
+        }
+
+        results.sort_by_key(|asset| asset.name.to_lowercase());
+        results
+    }
+
+    /// A path in the root that nothing is using yet, numbered if the name is taken
+    pub fn unused_path(&self, name: &str, extension: &str) -> PathBuf {
+        unused_path(&self.root, name, extension)
+    }
+}
+
+/// A path in the directory that nothing is using yet, numbered if the name is taken
+pub fn unused_path(dir: &Path, name: &str, extension: &str) -> PathBuf {
+    // keep names usable as file names on every platform
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == ' ' || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let name = if name.trim().is_empty() {
+        "Untitled".to_string()
+    } else {
+        name
+    };
+
+    let mut path = dir.join(format!("{}{}", name, extension));
+    let mut number = 2;
+
+    while path.exists() {
+        path = dir.join(format!("{} {}{}", name, number, extension));
+        number += 1;
+    }
+
+    path
+}
@@ -0,0 +1,956 @@
+use std::fmt::Write;
+
+use common_vector::polygon::PolygonConfig;
+
+use crate::helpers::effects::{svg_filter, ShapeEffect};
+
+// line segments each curve, arc and circle is flattened into
+const CURVE_SEGMENTS: usize = 16;
+const ELLIPSE_SEGMENTS: usize = 48;
+// fills we can't draw, like gradients, fall back to this
+const FALLBACK_FILL: [f32; 4] = [0.5, 0.5, 0.5, 1.0];
+
+/// A filled outline read out of an SVG, in pixels
+#[derive(Debug, Clone, PartialEq)]
+pub struct SvgShape {
+    // the element's id, if it had one
+    pub name: Option<String>,
+    pub outline: Vec<[f32; 2]>,
+    pub fill: [f32; 4],
+    pub border_radius: f32,
+}
+
+struct Element<'a> {
+    name: &'a str,
+    attributes: Vec<(&'a str, &'a str)>,
+    closing: bool,
+    self_closing: bool,
+}
+
+impl<'a> Element<'a> {
+    fn attribute(&self, name: &str) -> Option<&'a str> {
+        // a style declaration wins over the attribute, like it would in a browser
+        let styled = self.attribute_only("style").and_then(|style| {
+            style.split(';').find_map(|declaration| {
+                let (key, value) = declaration.split_once(':')?;
+                (key.trim() == name).then(|| value.trim())
+            })
+        });
+
+        styled.or_else(|| self.attribute_only(name))
+    }
+
+    fn attribute_only(&self, name: &str) -> Option<&'a str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| *value)
+    }
+
+    fn number(&self, name: &str) -> f32 {
+        self.attribute(name)
+            .and_then(|value| parse_length(value))
+            .unwrap_or(0.0)
+    }
+}
+
+// Only tags and attributes matter here, text and comments are skipped over
+fn elements(text: &str) -> Vec<Element<'_>> {
+    let mut elements = Vec::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+
+        if let Some(comment) = rest.strip_prefix("!--") {
+            rest = comment.split_once("-->").map_or("", |(_, after)| after);
+            continue;
+        }
+
+        // find the end of the tag, skipping any '>' inside quoted values
+        let mut quote = None;
+        let Some(end) = rest.char_indices().find_map(|(index, c)| {
+            match (quote, c) {
+                (None, '"' | '\'') => quote = Some(c),
+                (Some(open), _) if open == c => quote = None,
+                (None, '>') => return Some(index),
+                _ => {}
+            }
+            None
+        }) else {
+            break;
+        };
+
+        let tag = &rest[..end];
+        rest = &rest[end + 1..];
+
+        if tag.starts_with('?') || tag.starts_with('!') {
+            continue;
+        }
+
+        let closing = tag.starts_with('/');
+        let self_closing = tag.ends_with('/');
+        let tag = tag.trim_start_matches('/').trim_end_matches('/');
+        let name_end = tag.find(|c: char| c.is_whitespace()).unwrap_or(tag.len());
+
+        elements.push(Element {
+            // namespaced tags like svg:path count as path
+            name: tag[..name_end].rsplit(':').next().unwrap_or_default(),
+            attributes: attributes(&tag[name_end..]),
+            closing,
+            self_closing,
+        });
+    }
+
+    elements
+}
+
+fn attributes(text: &str) -> Vec<(&str, &str)> {
+    let mut attributes = Vec::new();
+    let mut rest = text;
+
+    while let Some(equals) = rest.find('=') {
+        let name = rest[..equals].trim();
+        let after = rest[equals + 1..].trim_start();
+
+        let Some(quote) = after.chars().next().filter(|c| *c == '"' || *c == '\'') else {
+            break;
+        };
+        let Some(length) = after[1..].find(quote) else {
+            break;
+        };
+
+        attributes.push((name, &after[1..1 + length]));
+        rest = &after[length + 2..];
+    }
+
+    attributes
+}
+
+// units are ignored, px is by far the most common
+fn parse_length(value: &str) -> Option<f32> {
+    let end = value
+        .trim()
+        .find(|c: char| !(c.is_ascii_digit() || matches!(c, '.' | '-' | '+' | 'e' | 'E')))
+        .unwrap_or(value.trim().len());
+
+    value.trim()[..end].parse().ok()
+}
+
+/// The numbers in a list like "10,20 -5.5.5", which SVG allows to run together
+fn numbers(text: &str) -> Vec<f32> {
+    let mut numbers = Vec::new();
+    let mut current = String::new();
+
+    let mut flush = |current: &mut String| {
+        if let Ok(number) = current.parse() {
+            numbers.push(number);
+        }
+        current.clear();
+    };
+
+    for c in text.chars() {
+        match c {
+            '0'..='9' => current.push(c),
+            '-' | '+' if !current.ends_with(['e', 'E']) => {
+                flush(&mut current);
+                current.push(c);
+            }
+            '-' | '+' | 'e' | 'E' => current.push(c),
+            '.' if current.contains('.') && !current.contains(['e', 'E']) => {
+                flush(&mut current);
+                current.push(c);
+            }
+            '.' => current.push(c),
+            _ => flush(&mut current),
+        }
+    }
+
+    flush(&mut current);
+    numbers
+}
+
+fn parse_color(value: &str) -> Option<[f32; 4]> {
+    let value = value.trim().to_lowercase();
+
+    if value == "none" || value == "transparent" {
+        return None;
+    }
+
+    if let Some(hex) = value.strip_prefix('#') {
+        let channel = |text: &str| u8::from_str_radix(text, 16).ok().map(|c| c as f32 / 255.0);
+
+        let rgb = match hex.len() {
+            3 => hex
+                .chars()
+                .map(|c| channel(&format!("{}{}", c, c)))
+                .collect::<Option<Vec<_>>>(),
+            6 => (0..3)
+                .map(|i| channel(&hex[i * 2..i * 2 + 2]))
+                .collect::<Option<Vec<_>>>(),
+            _ => None,
+        };
+
+        return Some(rgb.map_or(FALLBACK_FILL, |rgb| [rgb[0], rgb[1], rgb[2], 1.0]));
+    }
+
+    if let Some(arguments) = value
+        .strip_prefix("rgb(")
+        .or_else(|| value.strip_prefix("rgba("))
+    {
+        let parts: Vec<&str> = arguments
+            .trim_end_matches(')')
+            .split([',', ' ', '/'])
+            .filter(|part| !part.is_empty())
+            .collect();
+        // colour channels are out of 255, alpha out of 1, and either can be a percentage
+        let channel = |part: &str, full: f32| match part.strip_suffix('%') {
+            Some(percent) => percent.parse::<f32>().unwrap_or(0.0) / 100.0,
+            None => part.parse::<f32>().unwrap_or(0.0) / full,
+        };
+
+        return Some(match parts[..] {
+            [r, g, b] => [channel(r, 255.0), channel(g, 255.0), channel(b, 255.0), 1.0],
+            [r, g, b, a, ..] => [
+                channel(r, 255.0),
+                channel(g, 255.0),
+                channel(b, 255.0),
+                channel(a, 1.0).clamp(0.0, 1.0),
+            ],
+            _ => FALLBACK_FILL,
+        });
+    }
+
+    Some(match value.as_str() {
+        "black" | "currentcolor" => [0.0, 0.0, 0.0, 1.0],
+        "white" => [1.0, 1.0, 1.0, 1.0],
+        "red" => [1.0, 0.0, 0.0, 1.0],
+        "green" => [0.0, 0.5, 0.0, 1.0],
+        "lime" => [0.0, 1.0, 0.0, 1.0],
+        "blue" => [0.0, 0.0, 1.0, 1.0],
+        "yellow" => [1.0, 1.0, 0.0, 1.0],
+        "orange" => [1.0, 0.65, 0.0, 1.0],
+        "purple" => [0.5, 0.0, 0.5, 1.0],
+        "gray" | "grey" => [0.5, 0.5, 0.5, 1.0],
+        _ => FALLBACK_FILL,
+    })
+}
+
+fn quadratic(from: [f32; 2], control: [f32; 2], to: [f32; 2], outline: &mut Vec<[f32; 2]>) {
+    for i in 1..=CURVE_SEGMENTS {
+        let t = i as f32 / CURVE_SEGMENTS as f32;
+        let u = 1.0 - t;
+        outline.push([
+            u * u * from[0] + 2.0 * u * t * control[0] + t * t * to[0],
+            u * u * from[1] + 2.0 * u * t * control[1] + t * t * to[1],
+        ]);
+    }
+}
+
+fn cubic(
+    from: [f32; 2],
+    control_a: [f32; 2],
+    control_b: [f32; 2],
+    to: [f32; 2],
+    outline: &mut Vec<[f32; 2]>,
+) {
+    for i in 1..=CURVE_SEGMENTS {
+        let t = i as f32 / CURVE_SEGMENTS as f32;
+        let u = 1.0 - t;
+        let point = |axis: usize| {
+            u * u * u * from[axis]
+                + 3.0 * u * u * t * control_a[axis]
+                + 3.0 * u * t * t * control_b[axis]
+                + t * t * t * to[axis]
+        };
+        outline.push([point(0), point(1)]);
+    }
+}
+
+fn ellipse(cx: f32, cy: f32, rx: f32, ry: f32) -> Vec<[f32; 2]> {
+    (0..ELLIPSE_SEGMENTS)
+        .map(|i| {
+            let angle = i as f32 / ELLIPSE_SEGMENTS as f32 * std::f32::consts::TAU;
+            [cx + angle.cos() * rx, cy + angle.sin() * ry]
+        })
+        .collect()
+}
+
+// An endpoint arc turned into its centre and angles the way the SVG spec describes,
+// then flattened like an ellipse. Radii too small to reach the end are scaled up until
+// they just do.
+fn arc(
+    from: [f32; 2],
+    radii: [f32; 2],
+    rotation: f32,
+    large_arc: bool,
+    sweep: bool,
+    to: [f32; 2],
+    outline: &mut Vec<[f32; 2]>,
+) {
+    if from == to {
+        return;
+    }
+
+    let (mut rx, mut ry) = (radii[0].abs(), radii[1].abs());
+    if rx <= f32::EPSILON || ry <= f32::EPSILON {
+        outline.push(to);
+        return;
+    }
+
+    let (sin, cos) = rotation.to_radians().sin_cos();
+    let (dx, dy) = ((from[0] - to[0]) / 2.0, (from[1] - to[1]) / 2.0);
+    let (x1, y1) = (cos * dx + sin * dy, -sin * dx + cos * dy);
+
+    let reach = x1 * x1 / (rx * rx) + y1 * y1 / (ry * ry);
+    if reach > 1.0 {
+        rx *= reach.sqrt();
+        ry *= reach.sqrt();
+    }
+
+    let (rx2, ry2) = (rx * rx, ry * ry);
+    let mut factor = ((rx2 * ry2 - rx2 * y1 * y1 - ry2 * x1 * x1)
+        / (rx2 * y1 * y1 + ry2 * x1 * x1))
+        .max(0.0)
+        .sqrt();
+    if large_arc == sweep {
+        factor = -factor;
+    }
+    let (cx1, cy1) = (factor * rx * y1 / ry, -factor * ry * x1 / rx);
+    let (cx, cy) = (
+        cos * cx1 - sin * cy1 + (from[0] + to[0]) / 2.0,
+        sin * cx1 + cos * cy1 + (from[1] + to[1]) / 2.0,
+    );
+
+    let start = ((y1 - cy1) / ry).atan2((x1 - cx1) / rx);
+    let end = ((-y1 - cy1) / ry).atan2((-x1 - cx1) / rx);
+    let mut delta = end - start;
+    if sweep && delta < 0.0 {
+        delta += std::f32::consts::TAU;
+    } else if !sweep && delta > 0.0 {
+        delta -= std::f32::consts::TAU;
+    }
+
+    let segments =
+        ((delta.abs() / std::f32::consts::TAU * ELLIPSE_SEGMENTS as f32).ceil() as usize).max(1);
+    for i in 1..segments {
+        let angle = start + delta * i as f32 / segments as f32;
+        let (x, y) = (rx * angle.cos(), ry * angle.sin());
+        outline.push([cos * x - sin * y + cx, sin * x + cos * y + cy]);
+    }
+    // the end lands exactly where it was asked to
+    outline.push(to);
+}
+
+// An arc's flags are single digits that can run straight into the next number,
+// like "a5 5 0 015 5"
+fn arc_numbers(text: &str) -> Vec<f32> {
+    let mut numbers = Vec::new();
+    let mut rest = text;
+
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == ',');
+        if rest.is_empty() {
+            break;
+        }
+
+        if matches!(numbers.len() % 7, 3 | 4) {
+            let flag = match rest.as_bytes()[0] {
+                b'0' => 0.0,
+                b'1' => 1.0,
+                _ => break,
+            };
+            numbers.push(flag);
+            rest = &rest[1..];
+            continue;
+        }
+
+        let end = number_end(rest);
+        let Ok(number) = rest[..end].parse() else {
+            break;
+        };
+        numbers.push(number);
+        rest = &rest[end..];
+    }
+
+    numbers
+}
+
+// how far the number at the start of the text runs
+fn number_end(text: &str) -> usize {
+    let bytes = text.as_bytes();
+    let mut end = 0;
+    if matches!(bytes.first(), Some(b'-' | b'+')) {
+        end += 1;
+    }
+
+    let mut dot = false;
+    while let Some(c) = bytes.get(end) {
+        match c {
+            b'0'..=b'9' => {}
+            b'.' if !dot => dot = true,
+            _ => break,
+        }
+        end += 1;
+    }
+
+    if matches!(bytes.get(end), Some(b'e' | b'E')) {
+        let mut exponent = end + 1;
+        if matches!(bytes.get(exponent), Some(b'-' | b'+')) {
+            exponent += 1;
+        }
+        let digits = bytes[exponent..]
+            .iter()
+            .take_while(|c| c.is_ascii_digit())
+            .count();
+        if digits > 0 {
+            end = exponent + digits;
+        }
+    }
+
+    end
+}
+
+/// A 2D affine transform as the six numbers of an SVG matrix(a b c d e f)
+type Transform = [f32; 6];
+
+const IDENTITY: Transform = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+
+// m then n, like "m n" in a transform list
+fn multiply(m: Transform, n: Transform) -> Transform {
+    [
+        m[0] * n[0] + m[2] * n[1],
+        m[1] * n[0] + m[3] * n[1],
+        m[0] * n[2] + m[2] * n[3],
+        m[1] * n[2] + m[3] * n[3],
+        m[0] * n[4] + m[2] * n[5] + m[4],
+        m[1] * n[4] + m[3] * n[5] + m[5],
+    ]
+}
+
+fn transform_point(m: &Transform, [x, y]: [f32; 2]) -> [f32; 2] {
+    [m[0] * x + m[2] * y + m[4], m[1] * x + m[3] * y + m[5]]
+}
+
+/// A transform attribute like "translate(10 20) rotate(45)". Functions it doesn't know
+/// are skipped.
+fn parse_transform(text: &str) -> Transform {
+    text.split(')')
+        .filter_map(|function| {
+            let (name, arguments) = function.split_once('(')?;
+            let arguments = numbers(arguments);
+            let argument = |i: usize, default: f32| arguments.get(i).copied().unwrap_or(default);
+
+            let transform = match name.trim_matches(|c: char| c.is_whitespace() || c == ',') {
+                "matrix" => match arguments[..] {
+                    [a, b, c, d, e, f] => [a, b, c, d, e, f],
+                    _ => return None,
+                },
+                "translate" => [1.0, 0.0, 0.0, 1.0, argument(0, 0.0), argument(1, 0.0)],
+                "scale" => {
+                    let x = argument(0, 1.0);
+                    [x, 0.0, 0.0, argument(1, x), 0.0, 0.0]
+                }
+                "rotate" => {
+                    let (sin, cos) = argument(0, 0.0).to_radians().sin_cos();
+                    let (cx, cy) = (argument(1, 0.0), argument(2, 0.0));
+                    // about the centre, moved there and back
+                    multiply(
+                        multiply(
+                            [1.0, 0.0, 0.0, 1.0, cx, cy],
+                            [cos, sin, -sin, cos, 0.0, 0.0],
+                        ),
+                        [1.0, 0.0, 0.0, 1.0, -cx, -cy],
+                    )
+                }
+                "skewX" => [1.0, 0.0, argument(0, 0.0).to_radians().tan(), 1.0, 0.0, 0.0],
+                "skewY" => [1.0, argument(0, 0.0).to_radians().tan(), 0.0, 1.0, 0.0, 0.0],
+                _ => return None,
+            };
+
+            Some(transform)
+        })
+        .fold(IDENTITY, multiply)
+}
+
+/// Each subpath of a path's data as its own outline
+fn path_outlines(data: &str) -> Vec<Vec<[f32; 2]>> {
+    let mut outlines = Vec::new();
+    let mut outline: Vec<[f32; 2]> = Vec::new();
+    let mut current = [0.0, 0.0];
+    let mut start = [0.0, 0.0];
+    // the last control point, for the smooth curve commands to reflect
+    let mut last_control: Option<(char, [f32; 2])> = None;
+
+    // split the data into each command and the numbers after it
+    let mut commands = Vec::new();
+    for (index, c) in data.char_indices() {
+        if c.is_ascii_alphabetic() && c != 'e' && c != 'E' {
+            commands.push((c, index));
+        }
+    }
+
+    for (i, (command, index)) in commands.iter().enumerate() {
+        let end = commands.get(i + 1).map_or(data.len(), |(_, next)| *next);
+        let arguments = if matches!(command, 'A' | 'a') {
+            arc_numbers(&data[index + 1..end])
+        } else {
+            numbers(&data[index + 1..end])
+        };
+        let relative = command.is_ascii_lowercase();
+        let offset = |point: [f32; 2], current: [f32; 2]| {
+            if relative {
+                [current[0] + point[0], current[1] + point[1]]
+            } else {
+                point
+            }
+        };
+
+        let stride = match command.to_ascii_uppercase() {
+            'M' | 'L' | 'T' => 2,
+            'H' | 'V' => 1,
+            'C' => 6,
+            'S' | 'Q' => 4,
+            'A' => 7,
+            _ => 0,
+        };
+
+        if stride == 0 {
+            // Z
+            if outline.len() >= 3 {
+                outlines.push(std::mem::take(&mut outline));
+            }
+            outline.clear();
+            current = start;
+            last_control = None;
+            continue;
+        }
+
+        for (n, chunk) in arguments.chunks_exact(stride).enumerate() {
+            let smooth_from = |kinds: &[char]| match last_control {
+                Some((kind, control)) if kinds.contains(&kind) => {
+                    [2.0 * current[0] - control[0], 2.0 * current[1] - control[1]]
+                }
+                _ => current,
+            };
+
+            let mut control = None;
+
+            match command.to_ascii_uppercase() {
+                // numbers after the first pair of a move are lines
+                'M' if n == 0 => {
+                    if outline.len() >= 3 {
+                        outlines.push(std::mem::take(&mut outline));
+                    }
+                    outline.clear();
+                    current = offset([chunk[0], chunk[1]], current);
+                    start = current;
+                    outline.push(current);
+                }
+                'M' | 'L' => {
+                    current = offset([chunk[0], chunk[1]], current);
+                    outline.push(current);
+                }
+                'H' => {
+                    current[0] = if relative {
+                        current[0] + chunk[0]
+                    } else {
+                        chunk[0]
+                    };
+                    outline.push(current);
+                }
+                'V' => {
+                    current[1] = if relative {
+                        current[1] + chunk[0]
+                    } else {
+                        chunk[0]
+                    };
+                    outline.push(current);
+                }
+                'C' => {
+                    let a = offset([chunk[0], chunk[1]], current);
+                    let b = offset([chunk[2], chunk[3]], current);
+                    let to = offset([chunk[4], chunk[5]], current);
+                    cubic(current, a, b, to, &mut outline);
+                    control = Some(('C', b));
+                    current = to;
+                }
+                'S' => {
+                    let a = smooth_from(&['C']);
+                    let b = offset([chunk[0], chunk[1]], current);
+                    let to = offset([chunk[2], chunk[3]], current);
+                    cubic(current, a, b, to, &mut outline);
+                    control = Some(('C', b));
+                    current = to;
+                }
+                'Q' => {
+                    let a = offset([chunk[0], chunk[1]], current);
+                    let to = offset([chunk[2], chunk[3]], current);
+                    quadratic(current, a, to, &mut outline);
+                    control = Some(('Q', a));
+                    current = to;
+                }
+                'T' => {
+                    let a = smooth_from(&['Q']);
+                    let to = offset([chunk[0], chunk[1]], current);
+                    quadratic(current, a, to, &mut outline);
+                    control = Some(('Q', a));
+                    current = to;
+                }
+                'A' => {
+                    let to = offset([chunk[5], chunk[6]], current);
+                    arc(
+                        current,
+                        [chunk[0], chunk[1]],
+                        chunk[2],
+                        chunk[3] != 0.0,
+                        chunk[4] != 0.0,
+                        to,
+                        &mut outline,
+                    );
+                    current = to;
+                }
+                _ => {}
+            }
+
+            last_control = control;
+        }
+    }
+
+    if outline.len() >= 3 {
+        outlines.push(outline);
+    }
+
+    outlines
+}
+
+fn element_outlines(element: &Element) -> Vec<Vec<[f32; 2]>> {
+    match element.name {
+        "polygon" | "polyline" => {
+            let points: Vec<[f32; 2]> = numbers(element.attribute("points").unwrap_or_default())
+                .chunks_exact(2)
+                .map(|pair| [pair[0], pair[1]])
+                .collect();
+            vec![points]
+        }
+        "rect" => {
+            let (x, y) = (element.number("x"), element.number("y"));
+            let (width, height) = (element.number("width"), element.number("height"));
+            vec![vec![
+                [x, y],
+                [x + width, y],
+                [x + width, y + height],
+                [x, y + height],
+            ]]
+        }
+        "circle" => {
+            let r = element.number("r");
+            vec![ellipse(element.number("cx"), element.number("cy"), r, r)]
+        }
+        "ellipse" => vec![ellipse(
+            element.number("cx"),
+            element.number("cy"),
+            element.number("rx"),
+            element.number("ry"),
+        )],
+        "path" => path_outlines(element.attribute("d").unwrap_or_default()),
+        _ => Vec::new(),
+    }
+}
+
+/// The top left and bottom right corners of the box around the points
+pub fn bounds<'a>(points: impl Iterator<Item = &'a [f32; 2]>) -> ([f32; 2], [f32; 2]) {
+    points.fold(
+        ([f32::MAX, f32::MAX], [f32::MIN, f32::MIN]),
+        |(min, max), [x, y]| {
+            (
+                [min[0].min(*x), min[1].min(*y)],
+                [max[0].max(*x), max[1].max(*y)],
+            )
+        },
+    )
+}
+
+/// The filled shapes in an SVG, moved by their transforms and scaled from its viewBox to
+/// its width and height. Strokes and gradients aren't supported, gradients fill gray.
+pub fn parse_shapes(text: &str) -> Vec<SvgShape> {
+    let mut shapes = Vec::new();
+    // fill, opacity and transform inherited from the svg and g elements around the shape
+    let mut inherited: Vec<(Option<[f32; 4]>, f32, Transform)> =
+        vec![(Some([0.0, 0.0, 0.0, 1.0]), 1.0, IDENTITY)];
+    let mut scale = (1.0, 1.0);
+    let mut origin = (0.0, 0.0);
+
+    for element in elements(text) {
+        if element.closing {
+            if matches!(element.name, "svg" | "g") && inherited.len() > 1 {
+                inherited.pop();
+            }
+            continue;
+        }
+
+        let (parent_fill, parent_opacity, parent_transform) = *inherited.last().unwrap();
+
+        let fill = match element.attribute("fill") {
+            Some(value) => parse_color(value),
+            None => parent_fill,
+        };
+        let opacity = parent_opacity
+            * element
+                .attribute("opacity")
+                .and_then(parse_length)
+                .unwrap_or(1.0);
+        let transform = match element.attribute_only("transform") {
+            Some(value) => multiply(parent_transform, parse_transform(value)),
+            None => parent_transform,
+        };
+
+        if element.name == "svg" && inherited.len() == 1 {
+            let view_box = numbers(element.attribute_only("viewBox").unwrap_or_default());
+
+            if let [min_x, min_y, width, height] = view_box[..] {
+                origin = (min_x, min_y);
+
+                let size = (
+                    element.attribute_only("width").and_then(parse_length),
+                    element.attribute_only("height").and_then(parse_length),
+                );
+                if let (Some(w), Some(h)) = size {
+                    if width > 0.0 && height > 0.0 {
+                        scale = (w / width, h / height);
+                    }
+                }
+            }
+        }
+
+        if matches!(element.name, "svg" | "g") {
+            if !element.self_closing {
+                inherited.push((fill, opacity, transform));
+            }
+            continue;
+        }
+
+        // shapes that are only outlined have nothing to fill
+        let Some(mut fill) = fill else {
+            continue;
+        };
+        fill[3] *= opacity
+            * element
+                .attribute("fill-opacity")
+                .and_then(parse_length)
+                .unwrap_or(1.0);
+
+        let border_radius = element
+            .attribute_only("data-border-radius")
+            .and_then(parse_length)
+            .unwrap_or(0.0);
+
+        for outline in element_outlines(&element) {
+            if outline.len() < 3 {
+                continue;
+            }
+
+            shapes.push(SvgShape {
+                name: element.attribute_only("id").map(unescape),
+                outline: outline
+                    .iter()
+                    .map(|point| {
+                        let [x, y] = transform_point(&transform, *point);
+                        [(x - origin.0) * scale.0, (y - origin.1) * scale.1]
+                    })
+                    .collect(),
+                fill,
+                border_radius,
+            });
+        }
+    }
+
+    shapes
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&quot;", "\"")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Standard base64 with padding, for files embedded as data URLs
+pub fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let triple = chunk.iter().enumerate().fold(0u32, |triple, (i, byte)| {
+            triple | ((*byte as u32) << (16 - i * 8))
+        });
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[((triple >> (18 - i * 6)) & 63) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+
+    encoded
+}
+
+/// A polygon as a standalone SVG, with its effects as a filter. The view box has room
+/// around the shape for whatever the effects draw outside of it.
+pub fn shape_svg(config: &PolygonConfig, effects: &[ShapeEffect]) -> String {
+    let (width, height) = config.dimensions;
+    let margin = effects
+        .iter()
+        .filter(|effect| effect.enabled)
+        .map(|effect| {
+            effect.blur + effect.spread.max(0.0) + effect.offset.0.abs().max(effect.offset.1.abs())
+        })
+        .fold(0.0f32, f32::max)
+        .ceil();
+
+    let points = config
+        .points
+        .iter()
+        .map(|point| format!("{},{}", point.x * width, point.y * height))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    let [r, g, b, a] = config.fill.map(|channel| channel.clamp(0.0, 1.0));
+
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"{} {} {} {}\">",
+        width + margin * 2.0,
+        height + margin * 2.0,
+        -margin,
+        -margin,
+        width + margin * 2.0,
+        height + margin * 2.0,
+    );
+
+    let filter = svg_filter("effects", effects);
+    if let Some(filter) = &filter {
+        let _ = writeln!(svg, "<defs>\n{}\n</defs>", filter);
+    }
+
+    let _ = writeln!(
+        svg,
+        "<polygon id=\"{}\" points=\"{}\" fill=\"rgb({},{},{})\" fill-opacity=\"{}\" data-border-radius=\"{}\"{}/>",
+        escape(&config.name),
+        points,
+        (r * 255.0).round(),
+        (g * 255.0).round(),
+        (b * 255.0).round(),
+        a,
+        config.border_radius,
+        if filter.is_some() {
+            " filter=\"url(#effects)\""
+        } else {
+            ""
+        },
+    );
+    svg.push_str("</svg>\n");
+
+    svg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(actual: [f32; 2], expected: [f32; 2]) {
+        assert!(
+            (actual[0] - expected[0]).abs() < 1e-3 && (actual[1] - expected[1]).abs() < 1e-3,
+            "{:?} != {:?}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn transforms_apply_in_order_and_inherit() {
+        let shapes = parse_shapes(
+            "<svg><g transform=\"translate(100 0)\"><rect x=\"0\" y=\"0\" width=\"10\" height=\"10\" transform=\"scale(2)\"/></g></svg>",
+        );
+
+        assert_eq!(
+            shapes[0].outline,
+            vec![[100.0, 0.0], [120.0, 0.0], [120.0, 20.0], [100.0, 20.0]]
+        );
+    }
+
+    #[test]
+    fn rotations_turn_about_their_centre() {
+        let transform = parse_transform("rotate(90 10 10)");
+
+        assert_near(transform_point(&transform, [20.0, 10.0]), [10.0, 20.0]);
+        assert_near(transform_point(&transform, [10.0, 10.0]), [10.0, 10.0]);
+    }
+
+    #[test]
+    fn matrices_and_skews_are_read() {
+        assert_eq!(
+            parse_transform("matrix(1,2,3,4,5,6)"),
+            [1.0, 2.0, 3.0, 4.0, 5.0, 6.0]
+        );
+        assert_near(
+            transform_point(&parse_transform("skewX(45)"), [0.0, 10.0]),
+            [10.0, 10.0],
+        );
+    }
+
+    #[test]
+    fn arcs_follow_the_ellipse() {
+        let outlines = path_outlines("M0,0 A10,10 0 0,1 20,0 Z");
+        let outline = &outlines[0];
+
+        // half a circle is flattened into half an ellipse's segments
+        assert_eq!(outline.len(), 1 + ELLIPSE_SEGMENTS / 2);
+        assert_near(outline[outline.len() - 1], [20.0, 0.0]);
+        for point in outline {
+            let distance = ((point[0] - 10.0).powi(2) + point[1].powi(2)).sqrt();
+            assert!((distance - 10.0).abs() < 1e-3);
+            // sweeping clockwise from the left goes over the top, where y is smaller
+            assert!(point[1] <= 1e-3);
+        }
+    }
+
+    #[test]
+    fn arcs_too_small_to_reach_are_scaled_up() {
+        let mut outline = vec![[0.0, 0.0]];
+        arc(
+            [0.0, 0.0],
+            [1.0, 1.0],
+            0.0,
+            false,
+            true,
+            [20.0, 0.0],
+            &mut outline,
+        );
+
+        let middle = outline[outline.len() / 2];
+        assert_near(middle, [10.0, -10.0]);
+    }
+
+    #[test]
+    fn arc_flags_can_run_into_the_next_number() {
+        assert_eq!(
+            arc_numbers("5 5 0 015 5"),
+            vec![5.0, 5.0, 0.0, 0.0, 1.0, 5.0, 5.0]
+        );
+        assert_eq!(
+            arc_numbers("1,1,0,1,0,-2e1.5"),
+            vec![1.0, 1.0, 0.0, 1.0, 0.0, -20.0, 0.5]
+        );
+    }
+}
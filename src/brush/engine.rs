@@ -0,0 +1,563 @@
+use std::f32::consts::PI;
+
+use super::{BrushKind, BrushSample, BrushSettings};
+
+// particles per square pixel the airbrush sweeps, dabs overlap so each only covers the
+// strip between it and the next
+const AIRBRUSH_DENSITY: f32 = 0.2;
+const AIRBRUSH_MAX_PARTICLES: usize = 48;
+const AIRBRUSH_PARTICLE_SIZE: f32 = 1.5;
+
+// calligraphy strokes never get thinner than this fraction of the nib
+const CALLIGRAPHY_MIN_THICKNESS: f32 = 0.08;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MeshVertex {
+    // window pixels
+    pub position: [f32; 2],
+    pub color: [f32; 4],
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct StrokeMesh {
+    pub vertices: Vec<MeshVertex>,
+    pub indices: Vec<u32>,
+}
+
+impl StrokeMesh {
+    fn push_vertex(&mut self, position: [f32; 2], color: [f32; 4]) -> u32 {
+        self.vertices.push(MeshVertex { position, color });
+        self.vertices.len() as u32 - 1
+    }
+
+    fn push_quad(&mut self, a: u32, b: u32, c: u32, d: u32) {
+        self.indices.extend_from_slice(&[a, b, c, a, c, d]);
+    }
+}
+
+/// Turns raw stroke samples into triangles for the chosen brush
+pub fn tessellate(settings: &BrushSettings, samples: &[BrushSample]) -> StrokeMesh {
+    let mut builder = StrokeBuilder::new(*settings);
+    builder.update(samples);
+
+    builder.mesh
+}
+
+/// Spaces samples evenly as they arrive, see resample
+#[derive(Debug, Clone)]
+struct Resampler {
+    step: f32,
+    previous: Option<BrushSample>,
+    // distance covered since the last placed sample
+    carried: f32,
+}
+
+impl Resampler {
+    fn new(step: f32) -> Self {
+        Resampler {
+            step,
+            previous: None,
+            carried: 0.0,
+        }
+    }
+
+    fn push(&mut self, next: BrushSample, path: &mut Vec<BrushSample>) {
+        let Some(previous) = self.previous else {
+            path.push(next);
+            self.previous = Some(next);
+            return;
+        };
+
+        let (dx, dy) = (next.x - previous.x, next.y - previous.y);
+        let length = (dx * dx + dy * dy).sqrt();
+
+        if length == 0.0 {
+            return;
+        }
+
+        let mut travelled = self.step - self.carried;
+
+        while travelled <= length {
+            let t = travelled / length;
+            path.push(BrushSample {
+                x: previous.x + dx * t,
+                y: previous.y + dy * t,
+                pressure: previous.pressure + (next.pressure - previous.pressure) * t,
+            });
+            travelled += self.step;
+        }
+
+        self.carried = length - (travelled - self.step);
+        self.previous = Some(next);
+    }
+}
+
+/// Places samples evenly along the path, keeping the first and last
+pub fn resample(samples: &[BrushSample], step: f32) -> Vec<BrushSample> {
+    let mut path = Vec::new();
+    let mut resampler = Resampler::new(step);
+
+    for sample in samples {
+        resampler.push(*sample, &mut path);
+    }
+
+    if let Some(last) = samples.last() {
+        if path.last() != Some(last) && samples.len() > 1 {
+            path.push(*last);
+        }
+    }
+
+    path
+}
+
+/// Tessellates a stroke while it's drawn. New samples only change the end of the stroke,
+/// so the mesh before that is kept and only the end is built again.
+#[derive(Debug, Clone)]
+pub struct StrokeBuilder {
+    settings: BrushSettings,
+    resampler: Resampler,
+    // samples already handed to the resampler
+    consumed: usize,
+    // evenly spaced, the stroke's latest sample may still follow them
+    path: Vec<BrushSample>,
+    mesh: StrokeMesh,
+    // the mesh up to here stays the same however the stroke goes on
+    stable_vertices: usize,
+    stable_indices: usize,
+    // path points whose geometry is stable, and the rail the next one joins onto
+    built: usize,
+    last_rail: Option<[u32; 4]>,
+}
+
+impl StrokeBuilder {
+    pub fn new(settings: BrushSettings) -> Self {
+        StrokeBuilder {
+            settings,
+            resampler: Resampler::new(settings.spacing_px()),
+            consumed: 0,
+            path: Vec::new(),
+            mesh: StrokeMesh::default(),
+            stable_vertices: 0,
+            stable_indices: 0,
+            built: 0,
+            last_rail: None,
+        }
+    }
+
+    pub fn settings(&self) -> &BrushSettings {
+        &self.settings
+    }
+
+    pub fn mesh(&self) -> &StrokeMesh {
+        &self.mesh
+    }
+
+    /// Takes every sample of the stroke so far, of which only the new ones are looked at.
+    /// Returns how many vertices and indices at the start of the mesh are unchanged.
+    pub fn update(&mut self, samples: &[BrushSample]) -> (usize, usize) {
+        for sample in &samples[self.consumed.min(samples.len())..] {
+            self.resampler.push(*sample, &mut self.path);
+        }
+        self.consumed = samples.len();
+
+        self.mesh.vertices.truncate(self.stable_vertices);
+        self.mesh.indices.truncate(self.stable_indices);
+        let unchanged = (self.stable_vertices, self.stable_indices);
+
+        let settings = self.settings;
+        let softness = 1.0 - settings.hardness.clamp(0.0, 1.0);
+
+        // the start cap goes first so it's part of what never changes
+        if self.built == 0 && self.stable_vertices == 0 && settings.kind == BrushKind::Solid {
+            if let Some(first) = self.path.first() {
+                cap(&mut self.mesh, &settings, first, softness);
+            }
+        }
+
+        // a point's direction depends on the one after it, airbrush dabs stand alone
+        let stable = match settings.kind {
+            BrushKind::Airbrush => self.path.len(),
+            _ => self.path.len().saturating_sub(1),
+        };
+        for i in self.built..stable {
+            self.last_rail =
+                point_geometry(&settings, &mut self.mesh, &self.path, i, self.last_rail);
+        }
+        self.built = self.built.max(stable);
+        self.stable_vertices = self.mesh.vertices.len();
+        self.stable_indices = self.mesh.indices.len();
+
+        // the rest follows the latest sample, and is built again with the next one
+        let tail = samples
+            .last()
+            .filter(|last| samples.len() > 1 && self.path.last() != Some(*last));
+        self.path.extend(tail);
+
+        let mut rail = self.last_rail;
+        for i in self.built..self.path.len() {
+            rail = point_geometry(&settings, &mut self.mesh, &self.path, i, rail);
+        }
+        if settings.kind == BrushKind::Solid && self.path.len() > 1 {
+            cap(
+                &mut self.mesh,
+                &settings,
+                &self.path[self.path.len() - 1],
+                softness,
+            );
+        }
+
+        if tail.is_some() {
+            self.path.pop();
+        }
+
+        unchanged
+    }
+}
+
+fn with_alpha(color: [f32; 4], alpha: f32) -> [f32; 4] {
+    [color[0], color[1], color[2], alpha]
+}
+
+// the brush color at a sample, after pressure
+fn sample_color(settings: &BrushSettings, sample: &BrushSample) -> [f32; 4] {
+    with_alpha(
+        settings.color,
+        settings.color[3] * settings.opacity * settings.pressure_curve.opacity_scale(sample),
+    )
+}
+
+// unit direction of travel at a point, averaged at corners
+fn direction(path: &[BrushSample], i: usize) -> [f32; 2] {
+    let before = path[i.saturating_sub(1)];
+    let after = path[(i + 1).min(path.len() - 1)];
+    let (dx, dy) = (after.x - before.x, after.y - before.y);
+    let length = (dx * dx + dy * dy).sqrt();
+
+    if length == 0.0 {
+        [1.0, 0.0]
+    } else {
+        [dx / length, dy / length]
+    }
+}
+
+/// Cross sections along a path: outer left, left, right, outer right
+type Rail = [[f32; 2]; 4];
+
+// each rail carries its own core color so opacity can follow pressure
+fn join_rail(
+    mesh: &mut StrokeMesh,
+    (rail, core): (Rail, [f32; 4]),
+    previous: Option<[u32; 4]>,
+) -> [u32; 4] {
+    let edge = with_alpha(core, 0.0);
+    let current = [
+        mesh.push_vertex(rail[0], edge),
+        mesh.push_vertex(rail[1], core),
+        mesh.push_vertex(rail[2], core),
+        mesh.push_vertex(rail[3], edge),
+    ];
+
+    if let Some(prev) = previous {
+        mesh.push_quad(prev[0], prev[1], current[1], current[0]);
+        mesh.push_quad(prev[1], prev[2], current[2], current[1]);
+        mesh.push_quad(prev[2], prev[3], current[3], current[2]);
+    }
+
+    current
+}
+
+// the geometry for one point of the path, joined onto the rail before it
+fn point_geometry(
+    settings: &BrushSettings,
+    mesh: &mut StrokeMesh,
+    path: &[BrushSample],
+    i: usize,
+    previous: Option<[u32; 4]>,
+) -> Option<[u32; 4]> {
+    match settings.kind {
+        BrushKind::Solid => Some(join_rail(
+            mesh,
+            solid_rail(settings, &path[i], direction(path, i)),
+            previous,
+        )),
+        BrushKind::Calligraphy => Some(join_rail(
+            mesh,
+            calligraphy_rail(settings, &path[i], direction(path, i)),
+            previous,
+        )),
+        BrushKind::Airbrush => {
+            airbrush_dab(settings, mesh, i, &path[i]);
+            None
+        }
+    }
+}
+
+fn dab(mesh: &mut StrokeMesh, center: BrushSample, radius: f32, feather: f32, core: [f32; 4]) {
+    let edge = with_alpha(core, 0.0);
+    let segments = (radius * 1.5).clamp(12.0, 48.0) as u32;
+    let inner = radius - feather;
+
+    let middle = mesh.push_vertex([center.x, center.y], core);
+    let first = mesh.vertices.len() as u32;
+
+    for s in 0..segments {
+        let angle = s as f32 / segments as f32 * 2.0 * PI;
+        let (sin, cos) = angle.sin_cos();
+        mesh.push_vertex([center.x + cos * inner, center.y + sin * inner], core);
+        mesh.push_vertex([center.x + cos * radius, center.y + sin * radius], edge);
+    }
+
+    for s in 0..segments {
+        let inner_a = first + s * 2;
+        let outer_a = inner_a + 1;
+        let inner_b = first + ((s + 1) % segments) * 2;
+        let outer_b = inner_b + 1;
+
+        mesh.indices.extend_from_slice(&[middle, inner_a, inner_b]);
+        if feather > 0.0 {
+            mesh.push_quad(inner_a, outer_a, outer_b, inner_b);
+        }
+    }
+}
+
+fn radius_at(settings: &BrushSettings, p: &BrushSample) -> f32 {
+    settings.size / 2.0 * settings.pressure_curve.size_scale(p)
+}
+
+// round ends for the solid brush
+fn cap(mesh: &mut StrokeMesh, settings: &BrushSettings, p: &BrushSample, softness: f32) {
+    let radius = radius_at(settings, p);
+    dab(
+        mesh,
+        *p,
+        radius,
+        radius * softness,
+        sample_color(settings, p),
+    );
+}
+
+fn solid_rail(settings: &BrushSettings, p: &BrushSample, [dx, dy]: [f32; 2]) -> (Rail, [f32; 4]) {
+    let softness = 1.0 - settings.hardness.clamp(0.0, 1.0);
+    let (nx, ny) = (-dy, dx);
+    let radius = radius_at(settings, p);
+    let inner = radius - radius * softness;
+
+    (
+        [
+            [p.x + nx * radius, p.y + ny * radius],
+            [p.x + nx * inner, p.y + ny * inner],
+            [p.x - nx * inner, p.y - ny * inner],
+            [p.x - nx * radius, p.y - ny * radius],
+        ],
+        sample_color(settings, p),
+    )
+}
+
+// the flat nib is swept along the path, so the mark is widest moving across it
+fn calligraphy_rail(
+    settings: &BrushSettings,
+    p: &BrushSample,
+    [dx, dy]: [f32; 2],
+) -> (Rail, [f32; 4]) {
+    let (sin, cos) = settings.nib_angle.to_radians().sin_cos();
+    let softness = 1.0 - settings.hardness.clamp(0.0, 1.0);
+
+    let half = radius_at(settings, p);
+    let nib = [cos * half, sin * half];
+    let thickness = (half * CALLIGRAPHY_MIN_THICKNESS).max(0.5);
+    let feather = half * softness * 0.5;
+    let (nx, ny) = (-dy, dx);
+    let side = if nx * nib[0] + ny * nib[1] < 0.0 {
+        -1.0
+    } else {
+        1.0
+    };
+    let (ox, oy) = (
+        nib[0] + nx * thickness * side,
+        nib[1] + ny * thickness * side,
+    );
+    let (fx, fy) = (nx * feather * side, ny * feather * side);
+
+    (
+        [
+            [p.x + ox + fx, p.y + oy + fy],
+            [p.x + ox, p.y + oy],
+            [p.x - ox, p.y - oy],
+            [p.x - ox - fx, p.y - oy - fy],
+        ],
+        sample_color(settings, p),
+    )
+}
+
+// small deterministic generator so a stroke looks the same every time it's rebuilt
+struct XorShift(u32);
+
+impl XorShift {
+    fn new(seed: u32) -> Self {
+        XorShift(seed.wrapping_mul(2654435761).max(1))
+    }
+
+    fn next(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        self.0 as f32 / u32::MAX as f32
+    }
+}
+
+// seeded by the point's place along the path, so it comes out the same when rebuilt
+fn airbrush_dab(settings: &BrushSettings, mesh: &mut StrokeMesh, i: usize, center: &BrushSample) {
+    let softness = 1.0 - settings.hardness.clamp(0.0, 1.0);
+    let half = AIRBRUSH_PARTICLE_SIZE / 2.0;
+
+    let mut rng = XorShift::new(i as u32 + 1);
+    let radius = radius_at(settings, center);
+    let swept = radius * 2.0 * settings.spacing_px();
+    let particles = ((swept * AIRBRUSH_DENSITY) as usize).clamp(4, AIRBRUSH_MAX_PARTICLES);
+    let core = sample_color(settings, center);
+
+    for _ in 0..particles {
+        let angle = rng.next() * 2.0 * PI;
+        // a square root spreads evenly over the disc, softer brushes bunch toward the middle
+        let distance = radius * rng.next().powf(0.5 + softness);
+        let falloff = (1.0 - distance / radius).max(0.0).powf(softness);
+        let color = with_alpha(core, core[3] * falloff);
+
+        let (x, y) = (
+            center.x + angle.cos() * distance,
+            center.y + angle.sin() * distance,
+        );
+
+        let a = mesh.push_vertex([x - half, y - half], color);
+        let b = mesh.push_vertex([x + half, y - half], color);
+        let c = mesh.push_vertex([x + half, y + half], color);
+        let d = mesh.push_vertex([x - half, y + half], color);
+        mesh.push_quad(a, b, c, d);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(x: f32, y: f32, pressure: f32) -> BrushSample {
+        BrushSample { x, y, pressure }
+    }
+
+    fn wavy_stroke() -> Vec<BrushSample> {
+        (0..40)
+            .map(|i| {
+                let t = i as f32;
+                sample(
+                    t * 3.7,
+                    (t * 0.4).sin() * 20.0,
+                    0.3 + (t * 0.1).cos().abs() * 0.7,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn resampling_spaces_samples_evenly() {
+        let path = resample(&[sample(0.0, 0.0, 1.0), sample(10.0, 0.0, 0.0)], 2.5);
+        let xs: Vec<f32> = path.iter().map(|s| s.x).collect();
+
+        assert_eq!(xs, vec![0.0, 2.5, 5.0, 7.5, 10.0]);
+        assert_eq!(path[2].pressure, 0.5);
+    }
+
+    #[test]
+    fn resampling_carries_the_distance_across_samples() {
+        let samples = [
+            sample(0.0, 0.0, 1.0),
+            sample(3.0, 0.0, 1.0),
+            sample(3.0, 0.0, 1.0),
+            sample(7.0, 0.0, 1.0),
+        ];
+        let xs: Vec<f32> = resample(&samples, 2.0).iter().map(|s| s.x).collect();
+
+        assert_eq!(xs, vec![0.0, 2.0, 4.0, 6.0, 7.0]);
+    }
+
+    #[test]
+    fn building_as_samples_arrive_matches_building_at_once() {
+        let stroke = wavy_stroke();
+
+        for kind in [
+            BrushKind::Solid,
+            BrushKind::Calligraphy,
+            BrushKind::Airbrush,
+        ] {
+            let settings = BrushSettings::for_kind(kind);
+            let mut builder = StrokeBuilder::new(settings);
+            let mut previous = StrokeMesh::default();
+
+            for end in 1..=stroke.len() {
+                let (vertices, indices) = builder.update(&stroke[..end]);
+
+                // what was reported unchanged really is
+                assert_eq!(
+                    builder.mesh().vertices[..vertices],
+                    previous.vertices[..vertices]
+                );
+                assert_eq!(
+                    builder.mesh().indices[..indices],
+                    previous.indices[..indices]
+                );
+                previous = builder.mesh().clone();
+            }
+
+            let whole = tessellate(&settings, &stroke);
+            assert_eq!(builder.mesh().vertices, whole.vertices, "{:?}", kind);
+            assert_eq!(builder.mesh().indices, whole.indices, "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn most_of_the_mesh_is_kept_between_samples() {
+        let stroke = wavy_stroke();
+        let mut builder = StrokeBuilder::new(BrushSettings::for_kind(BrushKind::Solid));
+
+        builder.update(&stroke[..stroke.len() - 1]);
+        let before = builder.mesh().vertices.len();
+        let (kept, _) = builder.update(&stroke);
+
+        assert!(kept > before / 2);
+    }
+
+    #[test]
+    fn indices_stay_within_the_mesh() {
+        for kind in [
+            BrushKind::Solid,
+            BrushKind::Calligraphy,
+            BrushKind::Airbrush,
+        ] {
+            let mesh = tessellate(&BrushSettings::for_kind(kind), &wavy_stroke());
+            let count = mesh.vertices.len() as u32;
+
+            assert!(!mesh.indices.is_empty());
+            assert_eq!(mesh.indices.len() % 3, 0);
+            assert!(mesh.indices.iter().all(|index| *index < count));
+        }
+    }
+
+    #[test]
+    fn airbrush_particles_are_capped() {
+        let settings = BrushSettings {
+            size: 400.0,
+            ..BrushSettings::for_kind(BrushKind::Airbrush)
+        };
+        let mesh = tessellate(&settings, &[sample(0.0, 0.0, 1.0)]);
+
+        assert_eq!(mesh.vertices.len(), AIRBRUSH_MAX_PARTICLES * 4);
+    }
+
+    #[test]
+    fn a_single_sample_is_a_dot() {
+        let settings = BrushSettings::for_kind(BrushKind::Solid);
+        let mesh = tessellate(&settings, &[sample(5.0, 5.0, 1.0)]);
+
+        assert!(!mesh.indices.is_empty());
+        assert!(tessellate(&settings, &[]).vertices.is_empty());
+    }
+}
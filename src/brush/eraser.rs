@@ -0,0 +1,372 @@
+use common_vector::basic::Point;
+use common_vector::polygon::PolygonConfig;
+use geo::{
+    Area, BooleanOps, BoundingRect, ConvexHull, Coord, LineString, MultiPoint, MultiPolygon,
+};
+use strum_macros::EnumIter;
+
+use super::engine::resample;
+use super::pressure::PressureCurve;
+use super::smoothing::{catmull_rom, simplify, SmoothingSettings, StabilizerKind};
+use super::strokes::StrokeLayer;
+use super::{BrushKind, BrushSample, BrushSettings};
+
+// sides on the circles that make up the eraser's swept region
+const ERASER_SEGMENTS: usize = 16;
+// pieces of a shape smaller than this many square pixels are dropped
+const MIN_PIECE_AREA: f64 = 1.0;
+// what's left of a cut stroke is simplified again, but only lightly
+const PIECE_TOLERANCE: f32 = 0.5;
+const PREVIEW_COLOR: [f32; 4] = [0.5, 0.5, 0.5, 1.0];
+const PREVIEW_OPACITY: f32 = 0.35;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, EnumIter)]
+pub enum EraserMode {
+    // cuts brush strokes where the eraser passes over them
+    Vector,
+    // subtracts the eraser's path from polygons
+    Shape,
+}
+
+impl EraserMode {
+    pub fn label(&self) -> &'static str {
+        match self {
+            EraserMode::Vector => "Vector",
+            EraserMode::Shape => "Shape",
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct EraserSettings {
+    pub mode: EraserMode,
+    // diameter in pixels
+    pub size: f32,
+}
+
+impl Default for EraserSettings {
+    fn default() -> Self {
+        EraserSettings {
+            mode: EraserMode::Vector,
+            size: 16.0,
+        }
+    }
+}
+
+impl EraserSettings {
+    /// How the eraser's path is drawn while it's dragged, as wide as what it will take out
+    pub fn preview(&self) -> BrushSettings {
+        BrushSettings {
+            size: self.size,
+            opacity: PREVIEW_OPACITY,
+            color: PREVIEW_COLOR,
+            pressure_curve: PressureCurve {
+                gamma: 1.0,
+                min_size: 1.0,
+                min_opacity: 1.0,
+            },
+            // the eraser goes exactly where it's dragged
+            smoothing: SmoothingSettings {
+                stabilizer: StabilizerKind::Off,
+                strength: 0.0,
+            },
+            ..BrushSettings::for_kind(BrushKind::Solid)
+        }
+    }
+}
+
+fn distance_to_path(x: f32, y: f32, path: &[[f32; 2]]) -> f32 {
+    if let [only] = path {
+        return (x - only[0]).hypot(y - only[1]);
+    }
+
+    path.windows(2)
+        .map(|pair| {
+            let ([ax, ay], [bx, by]) = (pair[0], pair[1]);
+            let (dx, dy) = (bx - ax, by - ay);
+            let length_squared = dx * dx + dy * dy;
+
+            let t = if length_squared == 0.0 {
+                0.0
+            } else {
+                (((x - ax) * dx + (y - ay) * dy) / length_squared).clamp(0.0, 1.0)
+            };
+
+            (x - (ax + dx * t)).hypot(y - (ay + dy * t))
+        })
+        .fold(f32::MAX, f32::min)
+}
+
+/// The runs of a stroke the eraser missed, or None if it didn't touch the stroke.
+/// An empty list means the whole stroke was erased.
+pub fn erase_stroke(
+    layer: &StrokeLayer,
+    path: &[[f32; 2]],
+    radius: f32,
+) -> Option<Vec<Vec<BrushSample>>> {
+    // cut along the curve that's drawn, not the simplified samples behind it
+    let step = (layer.settings.size / 4.0).clamp(0.5, 2.0);
+    let centerline = resample(&catmull_rom(&layer.samples), step);
+
+    let mut pieces = Vec::new();
+    let mut current = Vec::new();
+    let mut touched = false;
+
+    for sample in centerline {
+        if distance_to_path(sample.x, sample.y, path) <= radius {
+            touched = true;
+            if current.len() > 1 {
+                pieces.push(simplify(&current, PIECE_TOLERANCE));
+            }
+            current.clear();
+        } else {
+            current.push(sample);
+        }
+    }
+
+    if !touched {
+        return None;
+    }
+
+    if current.len() > 1 {
+        pieces.push(simplify(&current, PIECE_TOLERANCE));
+    }
+
+    Some(pieces)
+}
+
+/// The polygon's outline in window pixels. Points are normalized to the polygon's box,
+/// which has its top left corner at the polygon's position.
+pub fn polygon_outline(config: &PolygonConfig) -> Vec<[f32; 2]> {
+    config
+        .points
+        .iter()
+        .map(|point| {
+            [
+                config.position.x + point.x * config.dimensions.0,
+                config.position.y + point.y * config.dimensions.1,
+            ]
+        })
+        .collect()
+}
+
+/// A polygon piece in the editor's terms: normalized points, dimensions and position
+pub struct ShapePiece {
+    pub points: Vec<Point>,
+    pub dimensions: (f32, f32),
+    pub position: Point,
+    pub area: f64,
+}
+
+pub(crate) fn to_geo_polygon(outline: &[[f32; 2]]) -> geo::Polygon<f64> {
+    geo::Polygon::new(
+        LineString::from(
+            outline
+                .iter()
+                .map(|[x, y]| (*x as f64, *y as f64))
+                .collect::<Vec<_>>(),
+        ),
+        vec![],
+    )
+}
+
+// the area covered by a round eraser moving from a to b
+fn capsule(a: [f32; 2], b: [f32; 2], radius: f32) -> geo::Polygon<f64> {
+    tapered_capsule(a, radius, b, radius)
+}
+
+/// The area covered by a round tip moving from a to b while its radius changes
+pub(crate) fn tapered_capsule(
+    a: [f32; 2],
+    radius_a: f32,
+    b: [f32; 2],
+    radius_b: f32,
+) -> geo::Polygon<f64> {
+    let points: Vec<geo::Point<f64>> = [(a, radius_a), (b, radius_b)]
+        .iter()
+        .flat_map(|([x, y], radius)| {
+            (0..ERASER_SEGMENTS).map(move |i| {
+                let angle = i as f32 / ERASER_SEGMENTS as f32 * std::f32::consts::TAU;
+                geo::Point::new(
+                    (x + angle.cos() * radius) as f64,
+                    (y + angle.sin() * radius) as f64,
+                )
+            })
+        })
+        .collect();
+
+    MultiPoint::new(points).convex_hull()
+}
+
+// The editor's polygons have no holes, so each hole is joined to the outside by a seam
+fn bridge_holes(polygon: &geo::Polygon<f64>) -> Vec<Coord<f64>> {
+    let mut ring: Vec<Coord<f64>> = polygon.exterior().0.clone();
+    ring.pop();
+
+    for hole in polygon.interiors() {
+        let mut hole_ring: Vec<Coord<f64>> = hole.0.clone();
+        hole_ring.pop();
+
+        let Some((outer, inner)) = ring
+            .iter()
+            .enumerate()
+            .flat_map(|(i, a)| {
+                hole_ring
+                    .iter()
+                    .enumerate()
+                    .map(move |(j, b)| (i, j, (a.x - b.x).hypot(a.y - b.y)))
+            })
+            .min_by(|x, y| x.2.total_cmp(&y.2))
+            .map(|(i, j, _)| (i, j))
+        else {
+            continue;
+        };
+
+        let mut seam = Vec::with_capacity(hole_ring.len() + 2);
+        seam.extend_from_slice(&hole_ring[inner..]);
+        seam.extend_from_slice(&hole_ring[..=inner]);
+        seam.push(ring[outer]);
+
+        ring.splice(outer + 1..outer + 1, seam);
+    }
+
+    ring
+}
+
+/// What's left of a polygon after the eraser's path is taken out of it, or None if the
+/// eraser missed. Pieces come back largest first.
+pub fn subtract_path(
+    outline: &[[f32; 2]],
+    path: &[[f32; 2]],
+    radius: f32,
+) -> Option<Vec<ShapePiece>> {
+    let original = to_geo_polygon(outline);
+    let original_area = original.unsigned_area();
+    let bounds = original.bounding_rect()?;
+
+    let mut shape = MultiPolygon::new(vec![original]);
+
+    let segments: Vec<([f32; 2], [f32; 2])> = match path {
+        [only] => vec![(*only, *only)],
+        _ => path.windows(2).map(|pair| (pair[0], pair[1])).collect(),
+    };
+
+    for (a, b) in segments {
+        let (min_x, max_x) = (a[0].min(b[0]) - radius, a[0].max(b[0]) + radius);
+        let (min_y, max_y) = (a[1].min(b[1]) - radius, a[1].max(b[1]) + radius);
+
+        // most of the path is usually nowhere near the shape
+        if (max_x as f64) < bounds.min().x
+            || (min_x as f64) > bounds.max().x
+            || (max_y as f64) < bounds.min().y
+            || (min_y as f64) > bounds.max().y
+        {
+            continue;
+        }
+
+        shape = shape.difference(&MultiPolygon::new(vec![capsule(a, b, radius)]));
+    }
+
+    if (original_area - shape.unsigned_area()).abs() < MIN_PIECE_AREA {
+        return None;
+    }
+
+    let mut pieces: Vec<ShapePiece> = shape
+        .iter()
+        .filter(|piece| piece.unsigned_area() >= MIN_PIECE_AREA)
+        .filter_map(|piece| {
+            let rect = piece.bounding_rect()?;
+            let (width, height) = (rect.width().max(1.0), rect.height().max(1.0));
+
+            Some(ShapePiece {
+                points: bridge_holes(piece)
+                    .iter()
+                    .map(|coord| Point {
+                        x: ((coord.x - rect.min().x) / width) as f32,
+                        y: ((coord.y - rect.min().y) / height) as f32,
+                    })
+                    .collect(),
+                dimensions: (width as f32, height as f32),
+                position: Point {
+                    x: rect.min().x as f32,
+                    y: rect.min().y as f32,
+                },
+                area: piece.unsigned_area(),
+            })
+        })
+        .collect();
+
+    pieces.sort_by(|a, b| b.area.total_cmp(&a.area));
+
+    Some(pieces)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SQUARE: [[f32; 2]; 4] = [[0.0, 0.0], [100.0, 0.0], [100.0, 100.0], [0.0, 100.0]];
+
+    #[test]
+    fn a_path_that_misses_leaves_the_shape_alone() {
+        assert!(subtract_path(&SQUARE, &[[200.0, 0.0], [200.0, 100.0]], 5.0).is_none());
+        // close enough for the bounds check, but not to the shape itself
+        assert!(subtract_path(&SQUARE, &[[103.0, 50.0]], 2.0).is_none());
+    }
+
+    #[test]
+    fn a_cut_through_the_middle_splits_the_shape() {
+        let pieces = subtract_path(&SQUARE, &[[50.0, -10.0], [50.0, 110.0]], 5.0).unwrap();
+
+        assert_eq!(pieces.len(), 2);
+        for piece in &pieces {
+            assert!((piece.dimensions.0 - 45.0).abs() < 0.1);
+            assert!((piece.dimensions.1 - 100.0).abs() < 0.1);
+            assert!((piece.area - 4500.0).abs() < 10.0);
+            assert!(piece.position.y.abs() < 0.1);
+        }
+
+        let mut lefts: Vec<f32> = pieces.iter().map(|piece| piece.position.x).collect();
+        lefts.sort_by(f32::total_cmp);
+        assert!(lefts[0].abs() < 0.1 && (lefts[1] - 55.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn pieces_are_normalized_to_their_box() {
+        let pieces = subtract_path(&SQUARE, &[[0.0, 0.0]], 20.0).unwrap();
+
+        assert_eq!(pieces.len(), 1);
+        for point in &pieces[0].points {
+            assert!((0.0..=1.0).contains(&point.x) && (0.0..=1.0).contains(&point.y));
+        }
+    }
+
+    #[test]
+    fn a_hole_is_bridged_to_the_outline() {
+        let pieces = subtract_path(&SQUARE, &[[50.0, 50.0]], 10.0).unwrap();
+
+        assert_eq!(pieces.len(), 1);
+        // the square's corners, the hole's ring and the seam back out
+        assert_eq!(pieces[0].points.len(), 4 + ERASER_SEGMENTS + 2);
+        assert!(pieces[0].area < 10_000.0 - 300.0);
+        assert!((pieces[0].dimensions.0 - 100.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn pieces_come_back_largest_first() {
+        let pieces = subtract_path(&SQUARE, &[[30.0, -10.0], [30.0, 110.0]], 5.0).unwrap();
+
+        assert_eq!(pieces.len(), 2);
+        assert!(pieces[0].area > pieces[1].area);
+    }
+
+    #[test]
+    fn distance_to_path_measures_to_the_nearest_segment() {
+        let path = [[0.0, 0.0], [10.0, 0.0], [10.0, 10.0]];
+
+        assert_eq!(distance_to_path(5.0, 3.0, &path), 3.0);
+        assert_eq!(distance_to_path(14.0, 5.0, &path), 4.0);
+        assert_eq!(distance_to_path(-3.0, -4.0, &path), 5.0);
+        assert_eq!(distance_to_path(3.0, 4.0, &[[0.0, 0.0]]), 5.0);
+    }
+}
@@ -0,0 +1,286 @@
+pub mod engine;
+pub mod eraser;
+pub mod pressure;
+pub mod smoothing;
+pub mod strokes;
+
+use std::time::Instant;
+
+use common_vector::basic::{Point, WindowSize};
+use common_vector::editor::Editor;
+use common_vector::guideline::point_to_ndc;
+use common_vector::vertex::Vertex;
+use serde::{Deserialize, Serialize};
+use strum_macros::EnumIter;
+
+use engine::{MeshVertex, StrokeBuilder};
+use pressure::{PressureCurve, PressureTracker};
+use smoothing::{SmoothingSettings, Stabilizer};
+
+/// Whether dragging in brush mode paints or erases
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BrushTool {
+    Paint,
+    Erase,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, EnumIter, Serialize, Deserialize)]
+pub enum BrushKind {
+    Solid,
+    Calligraphy,
+    Airbrush,
+}
+
+impl BrushKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            BrushKind::Solid => "Solid",
+            BrushKind::Calligraphy => "Calligraphy",
+            BrushKind::Airbrush => "Airbrush",
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BrushSettings {
+    pub kind: BrushKind,
+    // diameter in pixels
+    pub size: f32,
+    // 0.0 - 1.0
+    pub opacity: f32,
+    // distance between dabs, as a fraction of size
+    pub spacing: f32,
+    // 0.0 is fully feathered, 1.0 is a hard edge
+    pub hardness: f32,
+    // calligraphy only, in degrees
+    pub nib_angle: f32,
+    pub color: [f32; 4],
+    pub pressure_curve: PressureCurve,
+    pub smoothing: SmoothingSettings,
+}
+
+impl BrushSettings {
+    pub fn for_kind(kind: BrushKind) -> Self {
+        match kind {
+            BrushKind::Solid => BrushSettings {
+                kind,
+                size: 8.0,
+                opacity: 1.0,
+                spacing: 0.25,
+                hardness: 0.8,
+                nib_angle: 45.0,
+                color: [0.0, 0.0, 0.0, 1.0],
+                pressure_curve: PressureCurve::default(),
+                smoothing: SmoothingSettings::default(),
+            },
+            BrushKind::Calligraphy => BrushSettings {
+                kind,
+                size: 14.0,
+                opacity: 1.0,
+                spacing: 0.1,
+                hardness: 1.0,
+                nib_angle: 45.0,
+                color: [0.0, 0.0, 0.0, 1.0],
+                pressure_curve: PressureCurve::default(),
+                smoothing: SmoothingSettings::default(),
+            },
+            BrushKind::Airbrush => BrushSettings {
+                kind,
+                size: 32.0,
+                opacity: 0.3,
+                spacing: 0.15,
+                hardness: 0.2,
+                nib_angle: 45.0,
+                color: [0.0, 0.0, 0.0, 1.0],
+                pressure_curve: PressureCurve::default(),
+                smoothing: SmoothingSettings::default(),
+            },
+        }
+    }
+
+    pub fn spacing_px(&self) -> f32 {
+        (self.size * self.spacing).max(0.5)
+    }
+}
+
+impl Default for BrushSettings {
+    fn default() -> Self {
+        BrushSettings::for_kind(BrushKind::Solid)
+    }
+}
+
+/// One cursor position along a stroke, in window pixels
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BrushSample {
+    pub x: f32,
+    pub y: f32,
+    // 0.0 - 1.0, simulated from the speed of the cursor
+    pub pressure: f32,
+}
+
+/// The stroke being drawn and the samples collected for it so far
+#[derive(Debug, Clone)]
+pub struct ActiveStroke {
+    // index into editor.brush_strokes, which may not exist until the first move
+    pub stroke_index: usize,
+    pub samples: Vec<BrushSample>,
+    // kept between moves so only the end of the stroke is tessellated again
+    builder: Option<StrokeBuilder>,
+    // what the editor's vertices were converted for
+    window_size: (u32, u32),
+    tracker: PressureTracker,
+    stabilizer: Stabilizer,
+    started: Instant,
+}
+
+impl ActiveStroke {
+    pub fn new(stroke_index: usize, smoothing: SmoothingSettings) -> Self {
+        ActiveStroke {
+            stroke_index,
+            samples: Vec::new(),
+            builder: None,
+            window_size: (0, 0),
+            tracker: PressureTracker::new(),
+            stabilizer: Stabilizer::new(smoothing),
+            started: Instant::now(),
+        }
+    }
+
+    pub fn record(&mut self, x: f32, y: f32) {
+        let time = self.started.elapsed().as_secs_f64();
+        // pressure is simulated from the raw cursor, before the stabilizer slows it down
+        let raw = self.tracker.sample(x, y, time);
+
+        if let Some(sample) = self.stabilizer.apply(raw) {
+            self.samples.push(sample);
+        }
+    }
+
+    /// The samples to commit, with the brush caught up to where the stroke ended
+    pub fn finish(mut self) -> Vec<BrushSample> {
+        if let Some(sample) = self.stabilizer.finish() {
+            self.samples.push(sample);
+        }
+
+        self.samples
+    }
+}
+
+pub fn mesh_to_vertices(vertices: &[MeshVertex], window_size: &WindowSize) -> Vec<Vertex> {
+    vertices
+        .iter()
+        .map(|vertex| {
+            let ndc = point_to_ndc(
+                Point {
+                    x: vertex.position[0],
+                    y: vertex.position[1],
+                },
+                window_size,
+            );
+
+            Vertex {
+                position: [ndc.x, ndc.y, 0.0],
+                tex_coords: [0.0, 0.0],
+                color: vertex.color,
+            }
+        })
+        .collect()
+}
+
+/// Brings the editor's geometry for the stroke up to date with the active brush engine.
+/// Only the end of the stroke changes between moves, so the rest is kept and the buffers
+/// are written from where it changed, growing when they run out of room.
+pub fn rebuild_stroke(
+    editor: &mut Editor,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    window_size: &WindowSize,
+    settings: &BrushSettings,
+    active: &mut ActiveStroke,
+) {
+    let Some(stroke) = editor.brush_strokes.get_mut(active.stroke_index) else {
+        return;
+    };
+
+    // anything built for another brush or window size is started over
+    let size = (window_size.width, window_size.height);
+    if active.window_size != size
+        || active
+            .builder
+            .as_ref()
+            .is_some_and(|builder| builder.settings() != settings)
+    {
+        active.builder = None;
+        active.window_size = size;
+    }
+    // the editor's own buffers can't be written to, so a new stroke gets ours
+    if active.builder.is_none() {
+        stroke.vertex_buffer = None;
+        stroke.index_buffer = None;
+    }
+    let builder = active
+        .builder
+        .get_or_insert_with(|| StrokeBuilder::new(*settings));
+
+    let (from_vertex, from_index) = builder.update(&active.samples);
+    let mesh = builder.mesh();
+
+    stroke.vertices.truncate(from_vertex);
+    stroke
+        .vertices
+        .extend(mesh_to_vertices(&mesh.vertices[from_vertex..], window_size));
+    stroke.indices.truncate(from_index);
+    stroke
+        .indices
+        .extend_from_slice(&mesh.indices[from_index..]);
+
+    write_growing(
+        device,
+        queue,
+        &mut stroke.vertex_buffer,
+        bytemuck::cast_slice(&stroke.vertices),
+        from_vertex * std::mem::size_of::<Vertex>(),
+        wgpu::BufferUsages::VERTEX,
+    );
+    write_growing(
+        device,
+        queue,
+        &mut stroke.index_buffer,
+        bytemuck::cast_slice(&stroke.indices),
+        from_index * std::mem::size_of::<u32>(),
+        wgpu::BufferUsages::INDEX,
+    );
+}
+
+// room for a short stroke before the first time a buffer has to grow
+const MIN_STROKE_BUFFER: u64 = 16 * 1024;
+
+// Writes the contents from the first changed byte on, replacing the buffer with one twice
+// the size when they no longer fit
+fn write_growing(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    buffer: &mut Option<wgpu::Buffer>,
+    contents: &[u8],
+    changed_from: usize,
+    usage: wgpu::BufferUsages,
+) {
+    let size = contents.len() as u64;
+    let mut changed_from = changed_from.min(contents.len());
+
+    if !buffer.as_ref().is_some_and(|buffer| buffer.size() >= size) {
+        *buffer = Some(device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Brush Stroke Buffer"),
+            size: size.next_power_of_two().max(MIN_STROKE_BUFFER),
+            usage: usage | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        }));
+        changed_from = 0;
+    }
+
+    if let Some(buffer) = buffer {
+        if changed_from < contents.len() {
+            queue.write_buffer(buffer, changed_from as u64, &contents[changed_from..]);
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use serde::{Deserialize, Serialize};
+
+use super::BrushSample;
+
+// speed in pixels per second at which simulated pressure bottoms out
+const SIMULATED_FAST_SPEED: f32 = 3000.0;
+const SIMULATED_MIN_PRESSURE: f32 = 0.3;
+// how quickly simulated pressure follows speed changes, 1.0 is instantly
+const SIMULATED_RESPONSE: f32 = 0.3;
+
+/// Maps raw pressure onto brush size and opacity
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PressureCurve {
+    // below 1.0 light touches count for more, above 1.0 for less
+    pub gamma: f32,
+    // size and opacity at zero pressure, as a fraction of the full value
+    pub min_size: f32,
+    pub min_opacity: f32,
+}
+
+impl Default for PressureCurve {
+    fn default() -> Self {
+        PressureCurve {
+            gamma: 1.0,
+            min_size: 0.2,
+            min_opacity: 0.6,
+        }
+    }
+}
+
+impl PressureCurve {
+    pub fn apply(&self, pressure: f32) -> f32 {
+        pressure.clamp(0.0, 1.0).powf(self.gamma.max(0.05))
+    }
+
+    pub fn size_scale(&self, sample: &BrushSample) -> f32 {
+        let min = self.min_size.clamp(0.0, 1.0);
+        min + (1.0 - min) * self.apply(sample.pressure)
+    }
+
+    pub fn opacity_scale(&self, sample: &BrushSample) -> f32 {
+        let min = self.min_opacity.clamp(0.0, 1.0);
+        min + (1.0 - min) * self.apply(sample.pressure)
+    }
+}
+
+/// Turns positions into samples, simulating pressure from how fast the cursor moves.
+/// Times are seconds since the stroke started, so recorded or synthetic streams replay exactly.
+#[derive(Debug, Clone)]
+pub struct PressureTracker {
+    last: Option<(f32, f32, f64)>,
+    simulated: f32,
+}
+
+impl PressureTracker {
+    pub fn new() -> Self {
+        PressureTracker {
+            last: None,
+            simulated: 1.0,
+        }
+    }
+
+    pub fn sample(&mut self, x: f32, y: f32, time: f64) -> BrushSample {
+        let pressure = self.simulate(x, y, time);
+
+        self.last = Some((x, y, time));
+
+        BrushSample { x, y, pressure }
+    }
+
+    // fast strokes thin out, slow ones fill in, like ink from a real pen
+    fn simulate(&mut self, x: f32, y: f32, time: f64) -> f32 {
+        if let Some((last_x, last_y, last_time)) = self.last {
+            let elapsed = (time - last_time) as f32;
+
+            if elapsed > 0.0 {
+                let distance = ((x - last_x).powi(2) + (y - last_y).powi(2)).sqrt();
+                let speed = (distance / elapsed / SIMULATED_FAST_SPEED).min(1.0);
+                let target = 1.0 - speed * (1.0 - SIMULATED_MIN_PRESSURE);
+
+                self.simulated += (target - self.simulated) * SIMULATED_RESPONSE;
+            }
+        }
+
+        self.simulated
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(pressure: f32) -> BrushSample {
+        BrushSample {
+            x: 0.0,
+            y: 0.0,
+            pressure,
+        }
+    }
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-5
+    }
+
+    #[test]
+    fn the_curve_spans_its_minimums_to_full() {
+        let curve = PressureCurve::default();
+
+        assert!(close(curve.size_scale(&sample(0.0)), 0.2));
+        assert!(close(curve.size_scale(&sample(1.0)), 1.0));
+        assert!(close(curve.opacity_scale(&sample(0.0)), 0.6));
+        assert!(close(curve.opacity_scale(&sample(0.5)), 0.8));
+    }
+
+    #[test]
+    fn gamma_bends_light_touches() {
+        let soft = PressureCurve {
+            gamma: 0.5,
+            ..PressureCurve::default()
+        };
+        let firm = PressureCurve {
+            gamma: 2.0,
+            ..PressureCurve::default()
+        };
+
+        assert!(close(soft.apply(0.25), 0.5));
+        assert!(close(firm.apply(0.5), 0.25));
+        // out of range readings are clamped
+        assert_eq!(firm.apply(1.5), 1.0);
+        assert_eq!(firm.apply(-0.5), 0.0);
+    }
+
+    #[test]
+    fn slow_strokes_keep_full_pressure() {
+        let mut tracker = PressureTracker::new();
+
+        // a pixel a second
+        for i in 0..20 {
+            let sample = tracker.sample(i as f32, 0.0, i as f64);
+            assert!(sample.pressure > 0.999);
+        }
+    }
+
+    #[test]
+    fn fast_strokes_thin_out_gradually() {
+        let mut tracker = PressureTracker::new();
+        let mut pressures = Vec::new();
+
+        // far faster than SIMULATED_FAST_SPEED
+        for i in 0..30 {
+            pressures.push(
+                tracker
+                    .sample(i as f32 * 1000.0, 0.0, i as f64 * 0.01)
+                    .pressure,
+            );
+        }
+
+        assert_eq!(pressures[0], 1.0);
+        assert!(pressures.windows(2).all(|pair| pair[1] < pair[0]));
+        assert!(close(
+            pressures[1],
+            1.0 - (1.0 - SIMULATED_MIN_PRESSURE) * SIMULATED_RESPONSE
+        ));
+        assert!((pressures[29] - SIMULATED_MIN_PRESSURE).abs() < 1e-3);
+    }
+
+    #[test]
+    fn repeated_times_leave_pressure_alone() {
+        let mut tracker = PressureTracker::new();
+
+        tracker.sample(0.0, 0.0, 0.0);
+        let sample = tracker.sample(500.0, 0.0, 0.0);
+
+        assert_eq!(sample.pressure, 1.0);
+    }
+}
@@ -0,0 +1,373 @@
+use serde::{Deserialize, Serialize};
+use strum_macros::EnumIter;
+
+use super::BrushSample;
+
+// what 100% strength means for each pass
+const MAX_LAZY_RADIUS: f32 = 40.0;
+const MAX_AVERAGE_WINDOW: usize = 16;
+const MAX_SIMPLIFY_TOLERANCE: f32 = 3.0;
+
+// committed strokes are rebuilt with roughly this many pixels per curve point
+const CURVE_STEP: f32 = 3.0;
+const MAX_CURVE_STEPS: usize = 24;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, EnumIter, Serialize, Deserialize)]
+pub enum StabilizerKind {
+    Off,
+    // the brush trails the cursor on a string and only moves once it's pulled tight
+    LazyMouse,
+    // the brush sits at the average of the last few cursor positions
+    MovingAverage,
+}
+
+impl StabilizerKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            StabilizerKind::Off => "Off",
+            StabilizerKind::LazyMouse => "Lazy Mouse",
+            StabilizerKind::MovingAverage => "Average",
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SmoothingSettings {
+    pub stabilizer: StabilizerKind,
+    // 0.0 - 1.0, drives both the stabilizer and the pass after the stroke is committed
+    pub strength: f32,
+}
+
+impl Default for SmoothingSettings {
+    fn default() -> Self {
+        SmoothingSettings {
+            stabilizer: StabilizerKind::LazyMouse,
+            strength: 0.3,
+        }
+    }
+}
+
+impl SmoothingSettings {
+    pub fn lazy_radius(&self) -> f32 {
+        self.strength.clamp(0.0, 1.0) * MAX_LAZY_RADIUS
+    }
+
+    pub fn average_window(&self) -> usize {
+        1 + (self.strength.clamp(0.0, 1.0) * (MAX_AVERAGE_WINDOW - 1) as f32).round() as usize
+    }
+
+    pub fn simplify_tolerance(&self) -> f32 {
+        self.strength.clamp(0.0, 1.0) * MAX_SIMPLIFY_TOLERANCE
+    }
+}
+
+/// Steadies samples while a stroke is being drawn
+#[derive(Debug, Clone)]
+pub struct Stabilizer {
+    settings: SmoothingSettings,
+    brush: Option<BrushSample>,
+    recent: Vec<BrushSample>,
+    last_raw: Option<BrushSample>,
+}
+
+impl Stabilizer {
+    pub fn new(settings: SmoothingSettings) -> Self {
+        Stabilizer {
+            settings,
+            brush: None,
+            recent: Vec::new(),
+            last_raw: None,
+        }
+    }
+
+    /// Takes the raw sample and returns where the brush should be, if it moved
+    pub fn apply(&mut self, raw: BrushSample) -> Option<BrushSample> {
+        self.last_raw = Some(raw);
+
+        match self.settings.stabilizer {
+            StabilizerKind::Off => {
+                self.brush = Some(raw);
+                Some(raw)
+            }
+            StabilizerKind::LazyMouse => self.lazy_mouse(raw),
+            StabilizerKind::MovingAverage => Some(self.moving_average(raw)),
+        }
+    }
+
+    /// The brush catches up with wherever the stroke actually ended
+    pub fn finish(&mut self) -> Option<BrushSample> {
+        let raw = self.last_raw?;
+
+        match self.brush {
+            Some(brush) if brush.x == raw.x && brush.y == raw.y => None,
+            _ => {
+                self.brush = Some(raw);
+                Some(raw)
+            }
+        }
+    }
+
+    fn lazy_mouse(&mut self, raw: BrushSample) -> Option<BrushSample> {
+        let Some(brush) = self.brush else {
+            self.brush = Some(raw);
+            return Some(raw);
+        };
+
+        let (dx, dy) = (raw.x - brush.x, raw.y - brush.y);
+        let distance = dx.hypot(dy);
+        let radius = self.settings.lazy_radius();
+
+        if distance <= radius {
+            return None;
+        }
+
+        let pull = (distance - radius) / distance;
+        let moved = BrushSample {
+            x: brush.x + dx * pull,
+            y: brush.y + dy * pull,
+            pressure: raw.pressure,
+        };
+
+        self.brush = Some(moved);
+        Some(moved)
+    }
+
+    fn moving_average(&mut self, raw: BrushSample) -> BrushSample {
+        self.recent.push(raw);
+
+        let window = self.settings.average_window();
+        if self.recent.len() > window {
+            self.recent.drain(..self.recent.len() - window);
+        }
+
+        let count = self.recent.len() as f32;
+        let (x, y) = self
+            .recent
+            .iter()
+            .fold((0.0, 0.0), |(x, y), s| (x + s.x, y + s.y));
+
+        let averaged = BrushSample {
+            x: x / count,
+            y: y / count,
+            pressure: raw.pressure,
+        };
+
+        self.brush = Some(averaged);
+        averaged
+    }
+}
+
+/// Ramer–Douglas–Peucker, keeping every sample further than the tolerance from the simplified line.
+/// Committed strokes only store what survives, and are curved through with catmull_rom when drawn.
+pub fn simplify(samples: &[BrushSample], tolerance: f32) -> Vec<BrushSample> {
+    if tolerance <= 0.0 || samples.len() < 3 {
+        return samples.to_vec();
+    }
+
+    let mut keep = vec![false; samples.len()];
+    keep[0] = true;
+    keep[samples.len() - 1] = true;
+
+    // an explicit stack, long strokes would recurse too deep
+    let mut ranges = vec![(0, samples.len() - 1)];
+
+    while let Some((start, end)) = ranges.pop() {
+        let (a, b) = (samples[start], samples[end]);
+
+        let furthest = (start + 1..end)
+            .map(|i| (i, distance_to_segment(&samples[i], &a, &b)))
+            .max_by(|(_, d1), (_, d2)| d1.total_cmp(d2));
+
+        if let Some((index, distance)) = furthest {
+            if distance > tolerance {
+                keep[index] = true;
+                ranges.push((start, index));
+                ranges.push((index, end));
+            }
+        }
+    }
+
+    samples
+        .iter()
+        .zip(keep)
+        .filter_map(|(sample, kept)| kept.then_some(*sample))
+        .collect()
+}
+
+fn distance_to_segment(p: &BrushSample, a: &BrushSample, b: &BrushSample) -> f32 {
+    let (dx, dy) = (b.x - a.x, b.y - a.y);
+    let length_squared = dx * dx + dy * dy;
+
+    if length_squared == 0.0 {
+        return (p.x - a.x).hypot(p.y - a.y);
+    }
+
+    let t = (((p.x - a.x) * dx + (p.y - a.y) * dy) / length_squared).clamp(0.0, 1.0);
+
+    (p.x - (a.x + dx * t)).hypot(p.y - (a.y + dy * t))
+}
+
+/// Curves through every sample, with the ends repeated so the stroke keeps its endpoints.
+/// Dense samples come back unchanged, since each short segment is a single step.
+pub fn catmull_rom(samples: &[BrushSample]) -> Vec<BrushSample> {
+    if samples.len() < 3 {
+        return samples.to_vec();
+    }
+
+    let mut curve = vec![samples[0]];
+    let last = samples.len() - 1;
+
+    for i in 0..last {
+        let p0 = samples[i.saturating_sub(1)];
+        let (p1, p2) = (samples[i], samples[i + 1]);
+        let p3 = samples[(i + 2).min(last)];
+
+        let length = (p2.x - p1.x).hypot(p2.y - p1.y);
+        let steps = ((length / CURVE_STEP).ceil() as usize).clamp(1, MAX_CURVE_STEPS);
+
+        for step in 1..=steps {
+            let t = step as f32 / steps as f32;
+            let (t2, t3) = (t * t, t * t * t);
+
+            let point = |v0: f32, v1: f32, v2: f32, v3: f32| {
+                0.5 * (2.0 * v1
+                    + (v2 - v0) * t
+                    + (2.0 * v0 - 5.0 * v1 + 4.0 * v2 - v3) * t2
+                    + (3.0 * v1 - v0 - 3.0 * v2 + v3) * t3)
+            };
+
+            curve.push(BrushSample {
+                x: point(p0.x, p1.x, p2.x, p3.x),
+                y: point(p0.y, p1.y, p2.y, p3.y),
+                pressure: p1.pressure + (p2.pressure - p1.pressure) * t,
+            });
+        }
+    }
+
+    curve
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(x: f32, y: f32) -> BrushSample {
+        BrushSample {
+            x,
+            y,
+            pressure: 1.0,
+        }
+    }
+
+    fn points(samples: &[BrushSample]) -> Vec<(f32, f32)> {
+        samples.iter().map(|s| (s.x, s.y)).collect()
+    }
+
+    #[test]
+    fn simplify_drops_collinear_samples() {
+        let line: Vec<_> = (0..10).map(|i| sample(i as f32, i as f32 * 2.0)).collect();
+
+        assert_eq!(points(&simplify(&line, 0.5)), vec![(0.0, 0.0), (9.0, 18.0)]);
+    }
+
+    #[test]
+    fn simplify_keeps_corners() {
+        let corner = [
+            sample(0.0, 0.0),
+            sample(5.0, 0.0),
+            sample(10.0, 0.0),
+            sample(10.0, 5.0),
+            sample(10.0, 10.0),
+        ];
+
+        assert_eq!(
+            points(&simplify(&corner, 1.0)),
+            vec![(0.0, 0.0), (10.0, 0.0), (10.0, 10.0)]
+        );
+    }
+
+    #[test]
+    fn simplify_keeps_samples_further_than_the_tolerance() {
+        let bump = [sample(0.0, 0.0), sample(5.0, 2.0), sample(10.0, 0.0)];
+
+        assert_eq!(simplify(&bump, 1.0).len(), 3);
+        assert_eq!(simplify(&bump, 3.0).len(), 2);
+    }
+
+    #[test]
+    fn simplify_leaves_short_strokes_and_zero_tolerance_alone() {
+        let short = [sample(0.0, 0.0), sample(1.0, 1.0)];
+        let line: Vec<_> = (0..5).map(|i| sample(i as f32, 0.0)).collect();
+
+        assert_eq!(simplify(&short, 2.0), short.to_vec());
+        assert_eq!(simplify(&line, 0.0), line);
+    }
+
+    #[test]
+    fn catmull_rom_passes_through_every_sample() {
+        let samples = [
+            sample(0.0, 0.0),
+            sample(30.0, 10.0),
+            sample(60.0, -10.0),
+            sample(90.0, 0.0),
+        ];
+        let curve = catmull_rom(&samples);
+
+        assert!(curve.len() > samples.len());
+        assert_eq!(curve[0], samples[0]);
+        for s in &samples[1..] {
+            assert!(curve
+                .iter()
+                .any(|c| (c.x - s.x).abs() < 1e-3 && (c.y - s.y).abs() < 1e-3));
+        }
+
+        let end = curve[curve.len() - 1];
+        assert!((end.x - 90.0).abs() < 1e-3 && end.y.abs() < 1e-3);
+    }
+
+    #[test]
+    fn catmull_rom_caps_the_steps_per_segment() {
+        let samples = [sample(0.0, 0.0), sample(1000.0, 0.0), sample(2000.0, 0.0)];
+
+        assert_eq!(catmull_rom(&samples).len(), 1 + 2 * MAX_CURVE_STEPS);
+    }
+
+    #[test]
+    fn catmull_rom_leaves_dense_samples_unchanged() {
+        let short = [sample(0.0, 0.0), sample(1.0, 1.0)];
+        let dense: Vec<_> = (0..5).map(|i| sample(i as f32, 0.0)).collect();
+
+        assert_eq!(catmull_rom(&short), short.to_vec());
+        assert_eq!(points(&catmull_rom(&dense)), points(&dense));
+    }
+
+    #[test]
+    fn lazy_mouse_waits_until_the_string_is_pulled_tight() {
+        let mut stabilizer = Stabilizer::new(SmoothingSettings {
+            stabilizer: StabilizerKind::LazyMouse,
+            strength: 0.25,
+        });
+        let radius = MAX_LAZY_RADIUS * 0.25;
+
+        assert_eq!(stabilizer.apply(sample(0.0, 0.0)), Some(sample(0.0, 0.0)));
+        assert_eq!(stabilizer.apply(sample(radius - 1.0, 0.0)), None);
+
+        let moved = stabilizer.apply(sample(radius + 5.0, 0.0)).unwrap();
+        assert!((moved.x - 5.0).abs() < 1e-4 && moved.y == 0.0);
+
+        assert_eq!(stabilizer.finish(), Some(sample(radius + 5.0, 0.0)));
+    }
+
+    #[test]
+    fn moving_average_trails_the_cursor() {
+        let mut stabilizer = Stabilizer::new(SmoothingSettings {
+            stabilizer: StabilizerKind::MovingAverage,
+            strength: 1.0,
+        });
+
+        stabilizer.apply(sample(0.0, 0.0));
+        let averaged = stabilizer.apply(sample(10.0, 20.0)).unwrap();
+
+        assert_eq!((averaged.x, averaged.y), (5.0, 10.0));
+    }
+}
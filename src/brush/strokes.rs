@@ -0,0 +1,200 @@
+use std::sync::{Arc, Mutex};
+
+use common_vector::basic::{wgpu_to_human, WindowSize};
+use common_vector::vertex::Vertex;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::engine::tessellate;
+use super::smoothing::catmull_rom;
+use super::{mesh_to_vertices, BrushSample, BrushSettings};
+
+/// A finished brush stroke, kept as a layer so it can be selected, edited and restacked.
+/// The samples are kept so the stroke can be rebuilt whenever its settings change.
+/// Only the samples and settings are serialized, so a loaded layer needs a rebuild.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StrokeLayer {
+    pub id: Uuid,
+    pub name: String,
+    pub settings: BrushSettings,
+    pub samples: Vec<BrushSample>,
+    #[serde(skip)]
+    pub vertices: Vec<Vertex>,
+    #[serde(skip)]
+    pub indices: Vec<u32>,
+    // bumped on every rebuild so the scene batch knows to rewrite the slot
+    #[serde(skip)]
+    pub generation: u64,
+}
+
+impl StrokeLayer {
+    pub fn new(
+        name: String,
+        settings: BrushSettings,
+        samples: Vec<BrushSample>,
+        window_size: &WindowSize,
+    ) -> Self {
+        let mut layer = StrokeLayer {
+            id: Uuid::new_v4(),
+            name,
+            settings,
+            samples,
+            vertices: Vec::new(),
+            indices: Vec::new(),
+            generation: 0,
+        };
+
+        layer.rebuild(window_size);
+
+        layer
+    }
+
+    pub fn rebuild(&mut self, window_size: &WindowSize) {
+        // samples were simplified when the stroke was committed, the curve restores the shape
+        let mesh = tessellate(&self.settings, &catmull_rom(&self.samples));
+
+        self.vertices = mesh_to_vertices(&mesh.vertices, window_size);
+        self.indices = mesh.indices;
+        self.generation += 1;
+    }
+
+    /// The box around the samples, in scene pixels
+    pub fn bounds(&self) -> Option<([f32; 2], [f32; 2])> {
+        let first = self.samples.first()?;
+
+        Some(self.samples.iter().fold(
+            ([first.x, first.y], [first.x, first.y]),
+            |(min, max), sample| {
+                (
+                    [min[0].min(sample.x), min[1].min(sample.y)],
+                    [max[0].max(sample.x), max[1].max(sample.y)],
+                )
+            },
+        ))
+    }
+
+    /// Tolerance is the extra pixels around the stroke that still count as clicking it
+    pub fn hit_test(&self, x: f32, y: f32, tolerance: f32) -> bool {
+        let reach = |sample: &BrushSample| {
+            self.settings.size / 2.0 * self.settings.pressure_curve.size_scale(sample) + tolerance
+        };
+
+        if let [only] = self.samples.as_slice() {
+            return (x - only.x).hypot(y - only.y) <= reach(only);
+        }
+
+        self.samples.windows(2).any(|pair| {
+            let (a, b) = (pair[0], pair[1]);
+            let (dx, dy) = (b.x - a.x, b.y - a.y);
+            let length_squared = dx * dx + dy * dy;
+
+            let t = if length_squared == 0.0 {
+                0.0
+            } else {
+                (((x - a.x) * dx + (y - a.y) * dy) / length_squared).clamp(0.0, 1.0)
+            };
+
+            let distance = (x - (a.x + dx * t)).hypot(y - (a.y + dy * t));
+
+            distance <= reach(&a).max(reach(&b))
+        })
+    }
+}
+
+/// Stroke settings that can be changed from the properties panel, in brush units
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum StrokeProperty {
+    Width(f32),
+    // 0.0 - 1.0
+    Opacity(f32),
+    // 0.0 - 1.0, like the polygon fill
+    Red(f32),
+    Green(f32),
+    Blue(f32),
+}
+
+impl StrokeProperty {
+    pub fn apply(&self, settings: &mut BrushSettings) {
+        match *self {
+            StrokeProperty::Width(w) => settings.size = w.max(1.0),
+            StrokeProperty::Opacity(o) => settings.opacity = o.clamp(0.0, 1.0),
+            StrokeProperty::Red(r) => settings.color[0] = r,
+            StrokeProperty::Green(g) => settings.color[1] = g,
+            StrokeProperty::Blue(b) => settings.color[2] = b,
+        }
+    }
+
+    // the value as it is entered in the properties panel
+    pub fn display(&self) -> String {
+        match *self {
+            StrokeProperty::Width(w) => w.to_string(),
+            StrokeProperty::Opacity(o) => (o * 100.0).round().to_string(),
+            StrokeProperty::Red(c) | StrokeProperty::Green(c) | StrokeProperty::Blue(c) => {
+                wgpu_to_human(c).to_string()
+            }
+        }
+    }
+}
+
+/// Every finished stroke in the document. Stacking order lives in the editor's layer list.
+#[derive(Debug, Default)]
+pub struct StrokeLayers {
+    layers: Vec<StrokeLayer>,
+    created: usize,
+}
+
+// Lock after the editor, never before
+pub type SharedStrokeLayers = Arc<Mutex<StrokeLayers>>;
+
+impl StrokeLayers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn next_name(&mut self) -> String {
+        self.created += 1;
+        format!("Stroke {}", self.created)
+    }
+
+    pub fn get(&self, id: Uuid) -> Option<&StrokeLayer> {
+        self.layers.iter().find(|layer| layer.id == id)
+    }
+
+    pub fn get_mut(&mut self, id: Uuid) -> Option<&mut StrokeLayer> {
+        self.layers.iter_mut().find(|layer| layer.id == id)
+    }
+
+    pub fn clear(&mut self) {
+        self.layers.clear();
+    }
+
+    pub fn insert(&mut self, layer: StrokeLayer) {
+        self.layers.push(layer);
+    }
+
+    pub fn remove(&mut self, id: Uuid) -> Option<StrokeLayer> {
+        let index = self.layers.iter().position(|layer| layer.id == id)?;
+        Some(self.layers.remove(index))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &StrokeLayer> {
+        self.layers.iter()
+    }
+
+    // stroke vertices are in window space, so they follow the window size
+    pub fn rebuild_all(&mut self, window_size: &WindowSize) {
+        for layer in &mut self.layers {
+            layer.rebuild(window_size);
+        }
+    }
+
+    /// The topmost stroke under the cursor, checked in reverse stacking order
+    pub fn hit_test(&self, layer_list: &[Uuid], x: f32, y: f32, tolerance: f32) -> Option<Uuid> {
+        layer_list
+            .iter()
+            .rev()
+            .filter_map(|id| self.get(*id))
+            .find(|layer| layer.hit_test(x, y, tolerance))
+            .map(|layer| layer.id)
+    }
+}
@@ -0,0 +1,193 @@
+use std::fmt::Write;
+use std::fs;
+use std::path::PathBuf;
+
+use uuid::Uuid;
+
+use crate::assets::library::unused_path;
+use crate::photo::metadata::ExportMetadata;
+
+use super::page::Artboard;
+use super::DocumentSnapshot;
+
+pub fn export_dir() -> Option<PathBuf> {
+    dirs::document_dir()
+        .or_else(dirs::home_dir)
+        .map(|dir| dir.join("Sensor"))
+}
+
+/// The artboard as an SVG, sized in the page's unit so it prints at the size it was set
+/// up with. Anything off the artboard is cut off.
+pub fn artboard_svg(
+    document: &DocumentSnapshot,
+    artboard: &Artboard,
+    metadata: ExportMetadata,
+) -> String {
+    let page = &document.page;
+    let ([left, top], _) = page.bounds(artboard);
+    let (width, height) = page.size_px(artboard);
+    let unit = page.unit.label();
+
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}{}\" height=\"{}{}\" viewBox=\"{} {} {} {}\">",
+        artboard.width, unit, artboard.height, unit, left, top, width, height,
+    );
+    document.write_defs(&mut svg);
+    document.write_layers(&mut svg, true, metadata);
+    svg.push_str("</svg>\n");
+
+    svg
+}
+
+/// Writes each artboard to the export directory, named after it. Without an id every
+/// artboard is exported.
+pub fn export_artboards(
+    document: &DocumentSnapshot,
+    artboard_id: Option<Uuid>,
+    metadata: ExportMetadata,
+) -> Result<Vec<PathBuf>, String> {
+    let dir = export_dir().ok_or("Couldn't find documents directory")?;
+    fs::create_dir_all(&dir).map_err(|_| "Couldn't create export directory")?;
+
+    let artboards = document
+        .page
+        .artboards
+        .iter()
+        .filter(|artboard| artboard_id.map_or(true, |id| artboard.id == id))
+        .collect::<Vec<_>>();
+    if artboards.is_empty() {
+        return Err("Couldn't find artboard".to_string());
+    }
+
+    let mut paths = Vec::new();
+    for artboard in artboards {
+        let path = unused_path(&dir, &artboard.name, ".svg");
+        fs::write(&path, artboard_svg(document, artboard, metadata))
+            .map_err(|_| "Couldn't write export")?;
+        paths.push(path);
+    }
+
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use geo::{LineString, MultiPolygon, Polygon};
+
+    use super::*;
+    use crate::document::page::PageSetup;
+    use crate::document::PolygonSnapshot;
+    use crate::helpers::masks::{LayerMask, MaskState};
+    use crate::helpers::preferences::Unit;
+
+    #[test]
+    fn artboards_are_exported_at_their_size_in_the_page_unit() {
+        let square = Uuid::new_v4();
+        let mut document = DocumentSnapshot {
+            polygons: vec![PolygonSnapshot {
+                id: square,
+                name: "Square".to_string(),
+                points: vec![[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]],
+                dimensions: (100.0, 100.0),
+                position: [500.0, 100.0],
+                border_radius: 0.0,
+                fill: [0.0, 0.0, 1.0, 1.0],
+                stroke_fill: [0.0, 0.0, 0.0, 1.0],
+                stroke_thickness: 0.0,
+            }],
+            layer_list: vec![square],
+            ..Default::default()
+        };
+        let second = document.page.add_artboard();
+
+        // the second artboard only shows its own part of the scene
+        let artboard = document
+            .page
+            .artboards
+            .iter()
+            .find(|artboard| artboard.id == second)
+            .unwrap();
+        let svg = artboard_svg(&document, artboard, ExportMetadata::Preserve);
+        assert!(svg.starts_with(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"800px\" height=\"600px\" viewBox=\"1350 50 800 600\">"
+        ));
+
+        let svg = artboard_svg(
+            &document,
+            &document.page.artboards[0],
+            ExportMetadata::Preserve,
+        );
+        assert!(svg.contains("viewBox=\"450 50 800 600\""));
+        assert!(svg.contains("<polygon id=\"Square\" points=\"500,100 600,100 600,200 500,200\""));
+
+        document.page = PageSetup::new(Unit::Mm);
+        let svg = artboard_svg(
+            &document,
+            &document.page.artboards[0],
+            ExportMetadata::Preserve,
+        );
+        assert!(svg.contains("width=\"210mm\" height=\"297mm\""));
+    }
+
+    #[test]
+    fn masks_and_clipping_are_exported_like_the_canvas_draws_them() {
+        let (base, clipped) = (Uuid::new_v4(), Uuid::new_v4());
+        let square = |id, position| PolygonSnapshot {
+            id,
+            name: "Square".to_string(),
+            points: vec![[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]],
+            dimensions: (100.0, 100.0),
+            position,
+            border_radius: 0.0,
+            fill: [0.0, 0.0, 1.0, 1.0],
+            stroke_fill: [0.0, 0.0, 0.0, 1.0],
+            stroke_thickness: 0.0,
+        };
+        // the base only shows its left half
+        let half = MultiPolygon::new(vec![Polygon::new(
+            LineString::from(vec![(0.0, 0.0), (50.0, 0.0), (50.0, 100.0), (0.0, 100.0)]),
+            vec![],
+        )]);
+
+        let document = DocumentSnapshot {
+            polygons: vec![square(base, [0.0, 0.0]), square(clipped, [25.0, 25.0])],
+            layer_list: vec![base, clipped],
+            masks: vec![
+                (
+                    base,
+                    MaskState {
+                        mask: Some(LayerMask {
+                            shape: Some(half),
+                            ..Default::default()
+                        }),
+                        clipped: false,
+                    },
+                ),
+                (
+                    clipped,
+                    MaskState {
+                        mask: None,
+                        clipped: true,
+                    },
+                ),
+            ],
+            ..Default::default()
+        };
+        let artboard = document.page.artboards[0].clone();
+
+        let svg = artboard_svg(&document, &artboard, ExportMetadata::Preserve);
+
+        for layer_id in [base, clipped] {
+            assert!(svg.contains(&format!("<clipPath id=\"clip-{}\"", layer_id)));
+            assert!(svg.contains(&format!("<g clip-path=\"url(#clip-{})\">", layer_id)));
+        }
+        // the clipped square is cut to the part of the base its mask lets through
+        let clip = svg
+            .lines()
+            .find(|line| line.contains(&format!("clip-{}\"", clipped)))
+            .unwrap();
+        assert!(clip.contains("50,100") && !clip.contains("100,100"));
+    }
+}
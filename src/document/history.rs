@@ -0,0 +1,457 @@
+use common_vector::basic::{Point, WindowSize};
+use common_vector::editor::{Editor, PolygonProperty};
+use serde::{Deserialize, Serialize};
+use undo::Record;
+use uuid::Uuid;
+
+use crate::brush::strokes::{StrokeLayer, StrokeProperty};
+use crate::editor_state::{
+    AdjustmentEdit, BlendEdit, CropEdit, EffectEdit, EraseEdit, FilterEdit, ImageEdit, LayerSwap,
+    MaskEdit, PolygonEdit, SceneEdit, SceneLayer, StrokeEdit,
+};
+use crate::helpers::blending::LayerBlend;
+use crate::helpers::effects::ShapeEffect;
+use crate::helpers::masks::MaskState;
+use crate::photo::adjustments::AdjustmentStack;
+use crate::photo::crop::CropParams;
+use crate::photo::filters::LayerFilters;
+use crate::photo::images::ImageLayer;
+
+use super::{LayerSnapshot, PolygonSnapshot};
+
+/// A polygon field as it was before or after an edit
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum PropertySnapshot {
+    Width(f32),
+    Height(f32),
+    Red(f32),
+    Green(f32),
+    Blue(f32),
+    BorderRadius(f32),
+    StrokeThickness(f32),
+    StrokeRed(f32),
+    StrokeGreen(f32),
+    StrokeBlue(f32),
+    Points(Vec<[f32; 2]>),
+}
+
+impl PropertySnapshot {
+    fn from_property(property: &PolygonProperty) -> Self {
+        match property {
+            PolygonProperty::Width(value) => PropertySnapshot::Width(*value),
+            PolygonProperty::Height(value) => PropertySnapshot::Height(*value),
+            PolygonProperty::Red(value) => PropertySnapshot::Red(*value),
+            PolygonProperty::Green(value) => PropertySnapshot::Green(*value),
+            PolygonProperty::Blue(value) => PropertySnapshot::Blue(*value),
+            PolygonProperty::BorderRadius(value) => PropertySnapshot::BorderRadius(*value),
+            PolygonProperty::StrokeThickness(value) => PropertySnapshot::StrokeThickness(*value),
+            PolygonProperty::StrokeRed(value) => PropertySnapshot::StrokeRed(*value),
+            PolygonProperty::StrokeGreen(value) => PropertySnapshot::StrokeGreen(*value),
+            PolygonProperty::StrokeBlue(value) => PropertySnapshot::StrokeBlue(*value),
+            PolygonProperty::Points(points) => {
+                PropertySnapshot::Points(points.iter().map(|point| [point.x, point.y]).collect())
+            }
+        }
+    }
+
+    fn to_property(&self) -> PolygonProperty {
+        match self {
+            PropertySnapshot::Width(value) => PolygonProperty::Width(*value),
+            PropertySnapshot::Height(value) => PolygonProperty::Height(*value),
+            PropertySnapshot::Red(value) => PolygonProperty::Red(*value),
+            PropertySnapshot::Green(value) => PolygonProperty::Green(*value),
+            PropertySnapshot::Blue(value) => PolygonProperty::Blue(*value),
+            PropertySnapshot::BorderRadius(value) => PolygonProperty::BorderRadius(*value),
+            PropertySnapshot::StrokeThickness(value) => PolygonProperty::StrokeThickness(*value),
+            PropertySnapshot::StrokeRed(value) => PolygonProperty::StrokeRed(*value),
+            PropertySnapshot::StrokeGreen(value) => PolygonProperty::StrokeGreen(*value),
+            PropertySnapshot::StrokeBlue(value) => PolygonProperty::StrokeBlue(*value),
+            PropertySnapshot::Points(points) => PolygonProperty::Points(
+                points.iter().map(|[x, y]| Point { x: *x, y: *y }).collect(),
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SwapSnapshot {
+    pub original_id: Uuid,
+    pub replacement_ids: Vec<Uuid>,
+    pub original: Option<LayerSnapshot>,
+    pub replacements: Vec<LayerSnapshot>,
+    pub position: Option<usize>,
+}
+
+/// One entry of the undo history as plain data. Layers an edit is holding are kept
+/// with it, so undo and redo work the same after a restore. The inputs an edit
+/// updates aren't kept, they're registered again as the panels mount.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum EditSnapshot {
+    Polygon {
+        polygon_id: Uuid,
+        field_name: String,
+        old_value: PropertySnapshot,
+        new_value: PropertySnapshot,
+    },
+    StrokeCreate {
+        stroke_id: Uuid,
+        layer: Option<StrokeLayer>,
+    },
+    StrokeDelete {
+        stroke_id: Uuid,
+        layer: Option<StrokeLayer>,
+        position: Option<usize>,
+    },
+    StrokeUpdate {
+        stroke_id: Uuid,
+        field_name: String,
+        old_value: StrokeProperty,
+        new_value: StrokeProperty,
+    },
+    ImageCreate {
+        image_id: Uuid,
+        layer: Option<ImageLayer>,
+    },
+    ImageDelete {
+        image_id: Uuid,
+        layer: Option<ImageLayer>,
+        position: Option<usize>,
+    },
+    Erase {
+        swaps: Vec<SwapSnapshot>,
+    },
+    Adjustment {
+        layer_id: Uuid,
+        old_stack: AdjustmentStack,
+        new_stack: AdjustmentStack,
+    },
+    Crop {
+        image_id: Uuid,
+        old_value: CropParams,
+        new_value: CropParams,
+    },
+    Filter {
+        image_id: Uuid,
+        old_value: LayerFilters,
+        new_value: LayerFilters,
+    },
+    Mask {
+        layer_id: Uuid,
+        old_state: MaskState,
+        new_state: MaskState,
+        consumed_id: Option<Uuid>,
+        consumed: Option<LayerSnapshot>,
+        position: Option<usize>,
+    },
+    Blend {
+        layer_id: Uuid,
+        old_value: LayerBlend,
+        new_value: LayerBlend,
+    },
+    Effect {
+        layer_id: Uuid,
+        old_stack: Vec<ShapeEffect>,
+        new_stack: Vec<ShapeEffect>,
+    },
+}
+
+fn layer_snapshot(layer: &SceneLayer) -> LayerSnapshot {
+    match layer {
+        SceneLayer::Polygon(polygon) => {
+            LayerSnapshot::Polygon(PolygonSnapshot::from_config(&polygon.to_config()))
+        }
+        SceneLayer::Stroke(layer) => LayerSnapshot::Stroke(layer.clone()),
+        SceneLayer::Image(layer) => LayerSnapshot::Image(layer.clone()),
+    }
+}
+
+/// Rebuilds what a snapshot dropped: polygon buffers, stroke meshes and photo pixels
+pub struct LayerBuilder<'a> {
+    pub editor: &'a mut Editor,
+    pub device: &'a wgpu::Device,
+    pub window_size: &'a WindowSize,
+}
+
+impl LayerBuilder<'_> {
+    pub fn stroke(&self, mut layer: StrokeLayer) -> StrokeLayer {
+        layer.rebuild(self.window_size);
+        layer
+    }
+
+    pub fn image(&self, layer: ImageLayer) -> ImageLayer {
+        layer.restored(self.window_size)
+    }
+
+    pub fn layer(&mut self, snapshot: LayerSnapshot) -> Result<SceneLayer, String> {
+        Ok(match snapshot {
+            LayerSnapshot::Polygon(polygon) => {
+                SceneLayer::Polygon(polygon.build(self.editor, self.device, self.window_size)?)
+            }
+            LayerSnapshot::Stroke(layer) => SceneLayer::Stroke(self.stroke(layer)),
+            LayerSnapshot::Image(layer) => SceneLayer::Image(self.image(layer)),
+        })
+    }
+}
+
+impl EditSnapshot {
+    pub fn from_edit(edit: &SceneEdit) -> Self {
+        match edit {
+            SceneEdit::Polygon(edit) => EditSnapshot::Polygon {
+                polygon_id: edit.polygon_id,
+                field_name: edit.field_name.clone(),
+                old_value: PropertySnapshot::from_property(&edit.old_value),
+                new_value: PropertySnapshot::from_property(&edit.new_value),
+            },
+            SceneEdit::Stroke(StrokeEdit::Create { stroke_id, layer }) => {
+                EditSnapshot::StrokeCreate {
+                    stroke_id: *stroke_id,
+                    layer: layer.clone(),
+                }
+            }
+            SceneEdit::Stroke(StrokeEdit::Delete {
+                stroke_id,
+                layer,
+                position,
+            }) => EditSnapshot::StrokeDelete {
+                stroke_id: *stroke_id,
+                layer: layer.clone(),
+                position: *position,
+            },
+            SceneEdit::Stroke(StrokeEdit::Update {
+                stroke_id,
+                field_name,
+                old_value,
+                new_value,
+                ..
+            }) => EditSnapshot::StrokeUpdate {
+                stroke_id: *stroke_id,
+                field_name: field_name.clone(),
+                old_value: *old_value,
+                new_value: *new_value,
+            },
+            SceneEdit::Image(ImageEdit::Create { image_id, layer }) => EditSnapshot::ImageCreate {
+                image_id: *image_id,
+                layer: layer.clone(),
+            },
+            SceneEdit::Image(ImageEdit::Delete {
+                image_id,
+                layer,
+                position,
+            }) => EditSnapshot::ImageDelete {
+                image_id: *image_id,
+                layer: layer.clone(),
+                position: *position,
+            },
+            SceneEdit::Erase(edit) => EditSnapshot::Erase {
+                swaps: edit
+                    .swaps
+                    .iter()
+                    .map(|swap| SwapSnapshot {
+                        original_id: swap.original_id,
+                        replacement_ids: swap.replacement_ids.clone(),
+                        original: swap.original.as_ref().map(layer_snapshot),
+                        replacements: swap.replacements.iter().map(layer_snapshot).collect(),
+                        position: swap.position,
+                    })
+                    .collect(),
+            },
+            SceneEdit::Adjustment(edit) => EditSnapshot::Adjustment {
+                layer_id: edit.layer_id,
+                old_stack: edit.old_stack.clone(),
+                new_stack: edit.new_stack.clone(),
+            },
+            SceneEdit::Crop(edit) => EditSnapshot::Crop {
+                image_id: edit.image_id,
+                old_value: edit.old_value,
+                new_value: edit.new_value,
+            },
+            SceneEdit::Filter(edit) => EditSnapshot::Filter {
+                image_id: edit.image_id,
+                old_value: edit.old_value.clone(),
+                new_value: edit.new_value.clone(),
+            },
+            SceneEdit::Mask(edit) => EditSnapshot::Mask {
+                layer_id: edit.layer_id,
+                old_state: edit.old_state.clone(),
+                new_state: edit.new_state.clone(),
+                consumed_id: edit.consumed_id,
+                consumed: edit.consumed.as_ref().map(layer_snapshot),
+                position: edit.position,
+            },
+            SceneEdit::Blend(edit) => EditSnapshot::Blend {
+                layer_id: edit.layer_id,
+                old_value: edit.old_value,
+                new_value: edit.new_value,
+            },
+            SceneEdit::Effect(edit) => EditSnapshot::Effect {
+                layer_id: edit.layer_id,
+                old_stack: edit.old_stack.clone(),
+                new_stack: edit.new_stack.clone(),
+            },
+        }
+    }
+
+    pub fn into_edit(self, builder: &mut LayerBuilder) -> Result<SceneEdit, String> {
+        Ok(match self {
+            EditSnapshot::Polygon {
+                polygon_id,
+                field_name,
+                old_value,
+                new_value,
+            } => SceneEdit::Polygon(PolygonEdit {
+                polygon_id,
+                field_name,
+                old_value: old_value.to_property(),
+                new_value: new_value.to_property(),
+                signal: None,
+            }),
+            EditSnapshot::StrokeCreate { stroke_id, layer } => {
+                SceneEdit::Stroke(StrokeEdit::Create {
+                    stroke_id,
+                    layer: layer.map(|layer| builder.stroke(layer)),
+                })
+            }
+            EditSnapshot::StrokeDelete {
+                stroke_id,
+                layer,
+                position,
+            } => SceneEdit::Stroke(StrokeEdit::Delete {
+                stroke_id,
+                layer: layer.map(|layer| builder.stroke(layer)),
+                position,
+            }),
+            EditSnapshot::StrokeUpdate {
+                stroke_id,
+                field_name,
+                old_value,
+                new_value,
+            } => SceneEdit::Stroke(StrokeEdit::Update {
+                stroke_id,
+                field_name,
+                old_value,
+                new_value,
+                signal: None,
+            }),
+            EditSnapshot::ImageCreate { image_id, layer } => SceneEdit::Image(ImageEdit::Create {
+                image_id,
+                layer: layer.map(|layer| builder.image(layer)),
+            }),
+            EditSnapshot::ImageDelete {
+                image_id,
+                layer,
+                position,
+            } => SceneEdit::Image(ImageEdit::Delete {
+                image_id,
+                layer: layer.map(|layer| builder.image(layer)),
+                position,
+            }),
+            EditSnapshot::Erase { swaps } => SceneEdit::Erase(EraseEdit {
+                swaps: swaps
+                    .into_iter()
+                    .map(|swap| {
+                        Ok(LayerSwap {
+                            original_id: swap.original_id,
+                            replacement_ids: swap.replacement_ids,
+                            original: swap
+                                .original
+                                .map(|layer| builder.layer(layer))
+                                .transpose()?,
+                            replacements: swap
+                                .replacements
+                                .into_iter()
+                                .map(|layer| builder.layer(layer))
+                                .collect::<Result<_, String>>()?,
+                            position: swap.position,
+                        })
+                    })
+                    .collect::<Result<_, String>>()?,
+            }),
+            EditSnapshot::Adjustment {
+                layer_id,
+                old_stack,
+                new_stack,
+            } => SceneEdit::Adjustment(AdjustmentEdit {
+                layer_id,
+                old_stack,
+                new_stack,
+                signals: Vec::new(),
+            }),
+            EditSnapshot::Crop {
+                image_id,
+                old_value,
+                new_value,
+            } => SceneEdit::Crop(CropEdit {
+                image_id,
+                old_value,
+                new_value,
+                signal: None,
+            }),
+            // the pixels a bake replaced aren't kept, they're made again from the file
+            EditSnapshot::Filter {
+                image_id,
+                old_value,
+                new_value,
+            } => SceneEdit::Filter(FilterEdit {
+                image_id,
+                old_value,
+                new_value,
+                sources: None,
+            }),
+            EditSnapshot::Mask {
+                layer_id,
+                old_state,
+                new_state,
+                consumed_id,
+                consumed,
+                position,
+            } => SceneEdit::Mask(MaskEdit {
+                layer_id,
+                old_state,
+                new_state,
+                consumed_id,
+                consumed: consumed.map(|layer| builder.layer(layer)).transpose()?,
+                position,
+            }),
+            EditSnapshot::Blend {
+                layer_id,
+                old_value,
+                new_value,
+            } => SceneEdit::Blend(BlendEdit {
+                layer_id,
+                old_value,
+                new_value,
+                signal: None,
+            }),
+            EditSnapshot::Effect {
+                layer_id,
+                old_stack,
+                new_stack,
+            } => SceneEdit::Effect(EffectEdit {
+                layer_id,
+                old_stack,
+                new_stack,
+                signals: Vec::new(),
+            }),
+        })
+    }
+}
+
+/// The undo history, with how far into it the document is
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HistorySnapshot {
+    pub edits: Vec<EditSnapshot>,
+    // edits before this are applied, the rest are undone and can be redone
+    pub head: usize,
+}
+
+impl HistorySnapshot {
+    pub fn capture(record: &Record<SceneEdit>) -> Self {
+        HistorySnapshot {
+            edits: record
+                .entries()
+                .map(|entry| EditSnapshot::from_edit(entry.get()))
+                .collect(),
+            head: record.head(),
+        }
+    }
+}
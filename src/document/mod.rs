@@ -0,0 +1,796 @@
+pub mod export;
+pub mod history;
+pub mod page;
+pub mod recovery;
+
+use std::fmt::Write;
+use std::fs;
+use std::io::Cursor;
+
+use common_vector::basic::{wgpu_to_human, Point, WindowSize};
+use common_vector::editor::{Editor, InputValue};
+use common_vector::polygon::{Polygon, PolygonConfig, Stroke};
+use geo::{BoundingRect, MultiPolygon};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::assets::svg::{base64, bounds, escape};
+use crate::brush::eraser::to_geo_polygon;
+use crate::brush::smoothing::catmull_rom;
+use crate::brush::strokes::{StrokeLayer, StrokeLayers};
+use crate::helpers::blending::{LayerBlend, LayerBlends};
+use crate::helpers::effects::{svg_filter, ShapeEffect, ShapeEffects};
+use crate::helpers::masks::{clip_region, LayerMasks, LayerShape, MaskState, RasterMask};
+use crate::photo::adjustments::{AdjustmentStack, LayerAdjustments};
+use crate::photo::images::{ImageLayer, ImageLayers};
+use crate::photo::metadata::ExportMetadata;
+
+use page::PageSetup;
+
+// previews are drawn this wide, whatever the size of the document
+const PREVIEW_WIDTH: f32 = 240.0;
+
+/// A polygon as plain data, everything `Polygon::new` and the stroke fields need to
+/// build it again
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PolygonSnapshot {
+    pub id: Uuid,
+    pub name: String,
+    // normalized to the dimensions, like the editor keeps them
+    pub points: Vec<[f32; 2]>,
+    pub dimensions: (f32, f32),
+    pub position: [f32; 2],
+    pub border_radius: f32,
+    pub fill: [f32; 4],
+    pub stroke_fill: [f32; 4],
+    pub stroke_thickness: f32,
+}
+
+impl PolygonSnapshot {
+    pub fn from_config(config: &PolygonConfig) -> Self {
+        PolygonSnapshot {
+            id: config.id,
+            name: config.name.clone(),
+            points: config
+                .points
+                .iter()
+                .map(|point| [point.x, point.y])
+                .collect(),
+            dimensions: config.dimensions,
+            position: [config.position.x, config.position.y],
+            border_radius: config.border_radius,
+            fill: config.fill,
+            stroke_fill: config.stroke.fill,
+            stroke_thickness: config.stroke.thickness,
+        }
+    }
+
+    pub fn to_config(&self) -> PolygonConfig {
+        PolygonConfig {
+            id: self.id,
+            name: self.name.clone(),
+            points: self
+                .points
+                .iter()
+                .map(|[x, y]| Point { x: *x, y: *y })
+                .collect(),
+            dimensions: self.dimensions,
+            position: Point {
+                x: self.position[0],
+                y: self.position[1],
+            },
+            border_radius: self.border_radius,
+            fill: self.fill,
+            stroke: Stroke {
+                fill: self.stroke_fill,
+                thickness: self.stroke_thickness,
+            },
+        }
+    }
+
+    /// The points in scene pixels
+    pub fn outline(&self) -> Vec<[f32; 2]> {
+        let (width, height) = self.dimensions;
+
+        self.points
+            .iter()
+            .map(|[x, y]| [self.position[0] + x * width, self.position[1] + y * height])
+            .collect()
+    }
+
+    /// Builds the polygon with its original id. It passes through the editor so its
+    /// stroke can be set, and is handed back out of the scene.
+    pub fn build(
+        &self,
+        editor: &mut Editor,
+        device: &wgpu::Device,
+        window_size: &WindowSize,
+    ) -> Result<Polygon, String> {
+        let camera = editor.camera.ok_or("Couldn't get camera")?;
+        let config = self.to_config();
+
+        let mut polygon = Polygon::new(
+            window_size,
+            device,
+            &camera,
+            config.points.clone(),
+            config.dimensions,
+            config.position,
+            config.border_radius,
+            config.fill,
+            config.name.clone(),
+        );
+        polygon.id = config.id;
+
+        editor.add_polygon(polygon);
+        set_polygon_stroke(editor, config.id, &config.stroke);
+
+        editor.layer_list.retain(|id| *id != config.id);
+        let index = editor
+            .polygons
+            .iter()
+            .rposition(|polygon| polygon.id == config.id)
+            .ok_or("Couldn't find rebuilt polygon")?;
+
+        Ok(editor.polygons.remove(index))
+    }
+}
+
+/// Polygon::new has no stroke, so it's set once the polygon is in the editor
+pub fn set_polygon_stroke(editor: &mut Editor, polygon_id: Uuid, stroke: &Stroke) {
+    editor.update_polygon(
+        polygon_id,
+        "stroke_thickness",
+        InputValue::Number(stroke.thickness),
+    );
+
+    for (field_name, channel) in ["stroke_red", "stroke_green", "stroke_blue"]
+        .iter()
+        .zip(stroke.fill)
+    {
+        editor.update_polygon(
+            polygon_id,
+            field_name,
+            InputValue::Number(wgpu_to_human(channel)),
+        );
+    }
+}
+
+/// A layer held outside the scene, by an edit that can put it back
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum LayerSnapshot {
+    Polygon(PolygonSnapshot),
+    Stroke(StrokeLayer),
+    Image(ImageLayer),
+}
+
+/// Everything in the document, as plain data
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DocumentSnapshot {
+    pub polygons: Vec<PolygonSnapshot>,
+    pub strokes: Vec<StrokeLayer>,
+    pub images: Vec<ImageLayer>,
+    // stacking order, bottom first
+    pub layer_list: Vec<Uuid>,
+    pub masks: Vec<(Uuid, MaskState)>,
+    pub blends: Vec<(Uuid, LayerBlend)>,
+    pub effects: Vec<(Uuid, Vec<ShapeEffect>)>,
+    pub adjustments: Vec<(Uuid, AdjustmentStack)>,
+    // autosaves from before pages existed open on the default page
+    #[serde(default)]
+    pub page: PageSetup,
+}
+
+impl DocumentSnapshot {
+    pub fn capture(
+        editor: &Editor,
+        strokes: &StrokeLayers,
+        images: &ImageLayers,
+        masks: &LayerMasks,
+        blends: &LayerBlends,
+        effects: &ShapeEffects,
+        adjustments: &LayerAdjustments,
+        page: &PageSetup,
+    ) -> Self {
+        DocumentSnapshot {
+            polygons: editor
+                .polygons
+                .iter()
+                .map(|polygon| PolygonSnapshot::from_config(&polygon.to_config()))
+                .collect(),
+            strokes: strokes.iter().cloned().collect(),
+            images: images.iter().cloned().collect(),
+            layer_list: editor.layer_list.clone(),
+            masks: masks
+                .iter()
+                .map(|(layer_id, state)| (layer_id, state.clone()))
+                .collect(),
+            blends: blends.iter().collect(),
+            effects: effects
+                .iter()
+                .map(|(layer_id, stack)| (layer_id, stack.to_vec()))
+                .collect(),
+            adjustments: adjustments
+                .iter()
+                .map(|(layer_id, stack)| (layer_id, stack.clone()))
+                .collect(),
+            page: page.clone(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layer_list.is_empty()
+    }
+
+    /// A small SVG of the layers in stacking order, masks and effects aside. Photos are
+    /// drawn as their outline. Empty when there's nothing to draw.
+    pub fn preview_svg(&self) -> String {
+        let points: Vec<[f32; 2]> = self
+            .polygons
+            .iter()
+            .flat_map(|polygon| polygon.outline())
+            .chain(
+                self.strokes
+                    .iter()
+                    .flat_map(|layer| layer.samples.iter().map(|sample| [sample.x, sample.y])),
+            )
+            .chain(self.images.iter().flat_map(|layer| layer.outline()))
+            .collect();
+
+        if points.is_empty() {
+            return String::new();
+        }
+
+        // strokes reach half their width past their samples
+        let margin = self
+            .strokes
+            .iter()
+            .map(|layer| layer.settings.size / 2.0)
+            .chain(
+                self.polygons
+                    .iter()
+                    .map(|polygon| polygon.stroke_thickness / 2.0),
+            )
+            .fold(0.0f32, f32::max);
+        let (min, max) = bounds(points.iter());
+        let min = [min[0] - margin, min[1] - margin];
+        let (width, height) = (
+            (max[0] + margin - min[0]).max(1.0),
+            (max[1] + margin - min[1]).max(1.0),
+        );
+
+        let mut svg = String::new();
+        let _ = writeln!(
+            svg,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"{} {} {} {}\">",
+            PREVIEW_WIDTH,
+            (PREVIEW_WIDTH * height / width).round(),
+            min[0],
+            min[1],
+            width,
+            height,
+        );
+        self.write_layers(&mut svg, false, ExportMetadata::Strip);
+        svg.push_str("</svg>\n");
+
+        svg
+    }
+
+    /// The artboards and the outline of every layer for the navigator, without the svg
+    /// element around them, which is sized to whatever part of the scene is shown. It's a
+    /// wireframe to find your way around by, so fills, masks, blending and effects are left
+    /// out. Lines are as wide as whatever holds it sets.
+    pub fn overview_svg_body(&self) -> String {
+        let mut svg = String::new();
+
+        for artboard in &self.page.artboards {
+            let ([left, top], [right, bottom]) = self.page.bounds(artboard);
+            let _ = writeln!(
+                svg,
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"white\"/>",
+                left,
+                top,
+                right - left,
+                bottom - top,
+            );
+        }
+
+        let points = |outline: &[[f32; 2]]| {
+            outline
+                .iter()
+                .map(|[x, y]| format!("{},{}", x, y))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+
+        svg.push_str("<g fill=\"none\" stroke=\"rgb(60,60,60)\">\n");
+        for layer_id in &self.layer_list {
+            if let Some(polygon) = self.polygons.iter().find(|polygon| polygon.id == *layer_id) {
+                let _ = writeln!(svg, "<polygon points=\"{}\"/>", points(&polygon.outline()));
+            } else if let Some(layer) = self.strokes.iter().find(|layer| layer.id == *layer_id) {
+                let path: Vec<[f32; 2]> = catmull_rom(&layer.samples)
+                    .iter()
+                    .map(|sample| [sample.x, sample.y])
+                    .collect();
+                let _ = writeln!(svg, "<polyline points=\"{}\"/>", points(&path));
+            } else if let Some(layer) = self.images.iter().find(|layer| layer.id == *layer_id) {
+                let _ = writeln!(svg, "<polygon points=\"{}\"/>", points(&layer.outline()));
+            }
+        }
+        svg.push_str("</g>\n");
+
+        svg
+    }
+
+    // every layer in stacking order. Styled layers carry their effects, masks, clipping
+    // and blending, which need the defs from write_defs in the same svg, and photos keep or
+    // lose their metadata as asked.
+    fn write_layers(&self, svg: &mut String, styled: bool, metadata: ExportMetadata) {
+        for (index, layer_id) in self.layer_list.iter().enumerate() {
+            let mut blending = match self.blends.iter().find(|(id, _)| id == layer_id) {
+                Some((_, blend)) if styled && !blend.is_plain() => format!(
+                    " opacity=\"{}\" style=\"mix-blend-mode:{}\"",
+                    blend.opacity,
+                    blend.mode.label().to_lowercase(),
+                ),
+                _ => String::new(),
+            };
+
+            // masked layers go in a group so the mask sits in the scene's space whatever the
+            // layer is drawn with, and the group blends in their place
+            let mut masking = String::new();
+            if styled && self.is_cut(index) {
+                let _ = write!(masking, " clip-path=\"url(#{})\"", clip_id(*layer_id));
+            }
+            if styled && self.painted(*layer_id).is_some() {
+                let _ = write!(masking, " mask=\"url(#{})\"", mask_id(*layer_id));
+            }
+            if !masking.is_empty() {
+                let _ = writeln!(svg, "<g{}{}>", masking, blending);
+                blending = String::new();
+            }
+
+            if let Some(polygon) = self.polygons.iter().find(|polygon| polygon.id == *layer_id) {
+                let points = polygon
+                    .outline()
+                    .iter()
+                    .map(|[x, y]| format!("{},{}", x, y))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                let stroke = if polygon.stroke_thickness > 0.0 {
+                    format!(
+                        " stroke=\"{}\" stroke-opacity=\"{}\" stroke-width=\"{}\"",
+                        svg_rgb(polygon.stroke_fill),
+                        polygon.stroke_fill[3].clamp(0.0, 1.0),
+                        polygon.stroke_thickness,
+                    )
+                } else {
+                    String::new()
+                };
+                let filter = if styled && self.layer_effects(*layer_id).is_some() {
+                    format!(" filter=\"url(#{})\"", filter_id(*layer_id))
+                } else {
+                    String::new()
+                };
+
+                let _ = writeln!(
+                    svg,
+                    "<polygon id=\"{}\" points=\"{}\" fill=\"{}\" fill-opacity=\"{}\"{}{}{}/>",
+                    escape(&polygon.name),
+                    points,
+                    svg_rgb(polygon.fill),
+                    polygon.fill[3].clamp(0.0, 1.0),
+                    stroke,
+                    filter,
+                    blending,
+                );
+            } else if let Some(layer) = self.strokes.iter().find(|layer| layer.id == *layer_id) {
+                let points = catmull_rom(&layer.samples)
+                    .iter()
+                    .map(|sample| format!("{},{}", sample.x, sample.y))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+
+                let _ = writeln!(
+                    svg,
+                    "<polyline points=\"{}\" fill=\"none\" stroke=\"{}\" stroke-opacity=\"{}\" stroke-width=\"{}\" stroke-linecap=\"round\" stroke-linejoin=\"round\"{}/>",
+                    points,
+                    svg_rgb(layer.settings.color),
+                    layer.settings.opacity,
+                    layer.settings.size,
+                    blending,
+                );
+            } else if let Some(layer) = self.images.iter().find(|layer| layer.id == *layer_id) {
+                write_image(svg, layer, &blending, styled, metadata);
+            }
+
+            if !masking.is_empty() {
+                svg.push_str("</g>\n");
+            }
+        }
+    }
+
+    // the defs write_layers refers to when it's styled, or nothing if no layer has effects,
+    // masks or clipping
+    fn write_defs(&self, svg: &mut String) {
+        let mut defs: Vec<String> = self
+            .layer_list
+            .iter()
+            .filter_map(|layer_id| {
+                self.polygons
+                    .iter()
+                    .find(|polygon| polygon.id == *layer_id)?;
+                svg_filter(&filter_id(*layer_id), self.layer_effects(*layer_id)?)
+            })
+            .collect();
+
+        for (index, layer_id) in self.layer_list.iter().enumerate() {
+            if self.is_cut(index) {
+                let base = self.clip_base(index).map(|base_id| {
+                    (
+                        self.layer_outline(base_id)
+                            .unwrap_or(MultiPolygon::new(vec![])),
+                        self.mask(base_id).and_then(|state| state.mask.as_ref()),
+                    )
+                });
+                let mask = self.mask(*layer_id).and_then(|state| state.mask.as_ref());
+                let region = clip_region(mask, base).unwrap_or(MultiPolygon::new(vec![]));
+
+                defs.push(format!(
+                    "<clipPath id=\"{}\" clipPathUnits=\"userSpaceOnUse\"><path d=\"{}\" clip-rule=\"evenodd\"/></clipPath>",
+                    clip_id(*layer_id),
+                    svg_path(&region),
+                ));
+            }
+
+            if let Some(painted) = self.painted(*layer_id) {
+                if let Some(mask) = self.mask_def(*layer_id, painted) {
+                    defs.push(mask);
+                }
+            }
+        }
+
+        if !defs.is_empty() {
+            let _ = writeln!(svg, "<defs>\n{}\n</defs>", defs.join("\n"));
+        }
+    }
+
+    // A painted mask as an svg mask. Everything outside the painted rectangle shows, so
+    // it's white across the layer with the levels as a picture over it. The levels are
+    // in the picture's alpha over white, which reads the same as a luminance mask.
+    fn mask_def(&self, layer_id: Uuid, painted: &RasterMask) -> Option<String> {
+        let (min, max) = self.layer_bounds(layer_id)?;
+        let pixels: Vec<u8> = painted
+            .levels
+            .iter()
+            .flat_map(|level| [255, *level])
+            .collect();
+        let picture = image::GrayAlphaImage::from_raw(painted.width, painted.height, pixels)?;
+        let mut png = Vec::new();
+        picture
+            .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+            .ok()?;
+
+        Some(format!(
+            "<mask id=\"{}\" maskUnits=\"userSpaceOnUse\" x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\">\n<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"white\"/>\n<image href=\"data:image/png;base64,{}\" x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" preserveAspectRatio=\"none\"/>\n</mask>",
+            mask_id(layer_id),
+            min[0],
+            min[1],
+            max[0] - min[0],
+            max[1] - min[1],
+            min[0],
+            min[1],
+            max[0] - min[0],
+            max[1] - min[1],
+            base64(&png),
+            painted.origin[0],
+            painted.origin[1],
+            painted.width,
+            painted.height,
+        ))
+    }
+
+    fn mask(&self, layer_id: Uuid) -> Option<&MaskState> {
+        self.masks
+            .iter()
+            .find(|(id, _)| *id == layer_id)
+            .map(|(_, state)| state)
+    }
+
+    // the painted levels a layer is drawn through, like LayerMasks::painted
+    fn painted(&self, layer_id: Uuid) -> Option<&RasterMask> {
+        self.mask(layer_id)?
+            .mask
+            .as_ref()
+            .filter(|mask| mask.enabled)
+            .map(|mask| &mask.painted)
+            .filter(|painted| !painted.is_empty())
+    }
+
+    // clipping groups stack on the first unclipped layer below them
+    fn clip_base(&self, index: usize) -> Option<Uuid> {
+        let clipped = |layer_id: &Uuid| self.mask(*layer_id).is_some_and(|state| state.clipped);
+        if !clipped(&self.layer_list[index]) {
+            return None;
+        }
+
+        self.layer_list[..index]
+            .iter()
+            .rev()
+            .find(|layer_id| !clipped(layer_id))
+            .copied()
+    }
+
+    // whether a shape cuts the layer, its own vector mask or the layer it's clipped to
+    fn is_cut(&self, index: usize) -> bool {
+        let shaped = self
+            .mask(self.layer_list[index])
+            .and_then(|state| state.mask.as_ref())
+            .is_some_and(|mask| mask.enabled && mask.shape.is_some());
+
+        shaped || self.clip_base(index).is_some()
+    }
+
+    // the area a layer covers, like LayerShape::outline
+    fn layer_outline(&self, layer_id: Uuid) -> Option<MultiPolygon<f64>> {
+        if let Some(polygon) = self.polygons.iter().find(|polygon| polygon.id == layer_id) {
+            Some(MultiPolygon::new(vec![to_geo_polygon(&polygon.outline())]))
+        } else if let Some(layer) = self.strokes.iter().find(|layer| layer.id == layer_id) {
+            Some(LayerShape::Stroke(layer).outline())
+        } else {
+            let layer = self.images.iter().find(|layer| layer.id == layer_id)?;
+            Some(LayerShape::Image(layer).outline())
+        }
+    }
+
+    // everything a layer draws over, with its outline's stroke
+    fn layer_bounds(&self, layer_id: Uuid) -> Option<([f32; 2], [f32; 2])> {
+        let rect = self.layer_outline(layer_id)?.bounding_rect()?;
+        let margin = self
+            .polygons
+            .iter()
+            .find(|polygon| polygon.id == layer_id)
+            .map_or(0.0, |polygon| polygon.stroke_thickness / 2.0);
+
+        Some((
+            [rect.min().x as f32 - margin, rect.min().y as f32 - margin],
+            [rect.max().x as f32 + margin, rect.max().y as f32 + margin],
+        ))
+    }
+
+    fn layer_effects(&self, layer_id: Uuid) -> Option<&[ShapeEffect]> {
+        self.effects
+            .iter()
+            .find(|(id, stack)| *id == layer_id && stack.iter().any(|effect| effect.enabled))
+            .map(|(_, stack)| stack.as_slice())
+    }
+}
+
+// Exported photos are embedded as they're drawn, so the crop, the turn upright and the
+// filters come along. Previews, and photos that couldn't be loaded, show their outline.
+fn write_image(
+    svg: &mut String,
+    layer: &ImageLayer,
+    blending: &str,
+    styled: bool,
+    metadata: ExportMetadata,
+) {
+    let ([left, top], [right, bottom]) = layer.bounds();
+
+    // the original file is only read for its metadata
+    let encoded = styled.then(|| {
+        let original = fs::read(&layer.path).unwrap_or_default();
+        layer.encode_export(&original, metadata)
+    });
+    let encoded = match encoded {
+        Some(Ok(encoded)) => Some(encoded),
+        Some(Err(e)) => {
+            println!("{} {}", e, layer.path.display());
+            None
+        }
+        None => None,
+    };
+
+    match encoded {
+        Some((media_type, bytes)) => {
+            let _ = writeln!(
+                svg,
+                "<image id=\"{}\" href=\"data:{};base64,{}\" x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" preserveAspectRatio=\"none\"{}/>",
+                escape(&layer.name),
+                media_type,
+                base64(&bytes),
+                left,
+                top,
+                right - left,
+                bottom - top,
+                blending,
+            );
+        }
+        None => {
+            let points = layer
+                .outline()
+                .iter()
+                .map(|[x, y]| format!("{},{}", x, y))
+                .collect::<Vec<_>>()
+                .join(" ");
+
+            let _ = writeln!(
+                svg,
+                "<polygon points=\"{}\" fill=\"rgb(160,160,160)\" stroke=\"rgb(96,96,96)\"/>",
+                points,
+            );
+        }
+    }
+}
+
+fn filter_id(layer_id: Uuid) -> String {
+    format!("effects-{}", layer_id)
+}
+
+fn clip_id(layer_id: Uuid) -> String {
+    format!("clip-{}", layer_id)
+}
+
+fn mask_id(layer_id: Uuid) -> String {
+    format!("mask-{}", layer_id)
+}
+
+// every ring as its own closed subpath, holes cut out by the even-odd rule
+fn svg_path(region: &MultiPolygon<f64>) -> String {
+    region
+        .iter()
+        .flat_map(|polygon| std::iter::once(polygon.exterior()).chain(polygon.interiors()))
+        .filter(|ring| ring.0.len() > 2)
+        .map(|ring| {
+            let points = ring
+                .coords()
+                .map(|coord| format!("{},{}", coord.x, coord.y))
+                .collect::<Vec<_>>()
+                .join(" L");
+            format!("M{} Z", points)
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut text = String::with_capacity(bytes.len().div_ceil(3) * 4);
+
+    for chunk in bytes.chunks(3) {
+        let word = chunk.iter().enumerate().fold(0u32, |word, (i, byte)| {
+            word | (*byte as u32) << (16 - i * 8)
+        });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                text.push(ALPHABET[(word >> (18 - i * 6)) as usize & 63] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+
+    text
+}
+
+fn svg_rgb(color: [f32; 4]) -> String {
+    let [r, g, b, _] = color.map(|channel| (channel.clamp(0.0, 1.0) * 255.0).round());
+    format!("rgb({},{},{})", r, g, b)
+}
+
+#[cfg(test)]
+mod tests {
+    use geo::{LineString, Polygon as GeoPolygon};
+
+    use super::*;
+    use crate::helpers::masks::LayerMask;
+
+    fn square(id: Uuid, position: [f32; 2]) -> PolygonSnapshot {
+        PolygonSnapshot {
+            id,
+            name: "Square".to_string(),
+            points: vec![[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]],
+            dimensions: (10.0, 10.0),
+            position,
+            border_radius: 0.0,
+            fill: [1.0, 0.0, 0.0, 1.0],
+            stroke_fill: [0.0, 0.0, 0.0, 1.0],
+            stroke_thickness: 0.0,
+        }
+    }
+
+    #[test]
+    fn bytes_are_written_as_base64() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+    }
+
+    #[test]
+    fn holes_are_their_own_subpaths() {
+        let ring = |points: &[(f64, f64)]| LineString::from(points.to_vec());
+        let region = MultiPolygon::new(vec![GeoPolygon::new(
+            ring(&[(0.0, 0.0), (4.0, 0.0), (4.0, 4.0), (0.0, 0.0)]),
+            vec![ring(&[(1.0, 1.0), (2.0, 1.0), (2.0, 2.0), (1.0, 1.0)])],
+        )]);
+
+        assert_eq!(
+            svg_path(&region),
+            "M0,0 L4,0 L4,4 L0,0 Z M1,1 L2,1 L2,2 L1,1 Z"
+        );
+    }
+
+    #[test]
+    fn clipped_layers_are_cut_to_the_layer_below() {
+        let (base, clipped) = (Uuid::new_v4(), Uuid::new_v4());
+        let document = DocumentSnapshot {
+            polygons: vec![square(base, [0.0, 0.0]), square(clipped, [5.0, 5.0])],
+            layer_list: vec![base, clipped],
+            masks: vec![(
+                clipped,
+                MaskState {
+                    mask: None,
+                    clipped: true,
+                },
+            )],
+            ..Default::default()
+        };
+
+        let mut svg = String::new();
+        document.write_defs(&mut svg);
+        document.write_layers(&mut svg, true, ExportMetadata::Strip);
+
+        assert!(svg.contains(&format!("<clipPath id=\"{}\"", clip_id(clipped))));
+        assert!(svg.contains(&format!("<g clip-path=\"url(#{})\">", clip_id(clipped))));
+        assert!(!svg.contains(&clip_id(base)));
+    }
+
+    #[test]
+    fn the_overview_is_a_wireframe() {
+        let layer_id = Uuid::new_v4();
+        let document = DocumentSnapshot {
+            polygons: vec![square(layer_id, [0.0, 0.0])],
+            layer_list: vec![layer_id],
+            ..Default::default()
+        };
+
+        let body = document.overview_svg_body();
+
+        assert!(body.contains("<polygon points=\"0,0 10,0 10,10 0,10\"/>"));
+        assert!(!body.contains("rgb(255,0,0)"));
+    }
+
+    #[test]
+    fn painted_masks_are_written_as_pictures() {
+        let layer_id = Uuid::new_v4();
+        let mut painted = RasterMask::default();
+        painted.paint(&[([5.0, 5.0], 2.0)], 1.0, false);
+        let document = DocumentSnapshot {
+            polygons: vec![square(layer_id, [0.0, 0.0])],
+            layer_list: vec![layer_id],
+            masks: vec![(
+                layer_id,
+                MaskState {
+                    mask: Some(LayerMask {
+                        painted,
+                        ..Default::default()
+                    }),
+                    clipped: false,
+                },
+            )],
+            ..Default::default()
+        };
+
+        let mut svg = String::new();
+        document.write_defs(&mut svg);
+        document.write_layers(&mut svg, true, ExportMetadata::Strip);
+
+        assert!(svg.contains(&format!("<mask id=\"{}\"", mask_id(layer_id))));
+        assert!(svg.contains("data:image/png;base64,"));
+        assert!(svg.contains(&format!("<g mask=\"url(#{})\">", mask_id(layer_id))));
+        // nothing cuts its shape
+        assert!(!svg.contains("clip-path"));
+    }
+}
@@ -0,0 +1,354 @@
+use std::sync::{Arc, Mutex};
+
+use common_vector::basic::string_to_f32;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::helpers::preferences::Unit;
+
+pub const DEFAULT_DPI: f32 = 96.0;
+// clear of the panels on the left of the window
+const FIRST_ARTBOARD_POSITION: [f32; 2] = [450.0, 50.0];
+// between artboards laid out side by side
+const ARTBOARD_GAP: f32 = 100.0;
+
+/// One page of the document, with its own size and name
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Artboard {
+    pub id: Uuid,
+    pub name: String,
+    // in the page's unit
+    pub width: f32,
+    pub height: f32,
+    // the top left corner in the scene, in pixels
+    pub position: [f32; 2],
+}
+
+/// How the document is measured, and the artboards it's drawn on. The scene is measured
+/// in pixels at the dpi.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PageSetup {
+    pub unit: Unit,
+    pub dpi: f32,
+    // left to right, as they were added
+    pub artboards: Vec<Artboard>,
+}
+
+impl Default for PageSetup {
+    fn default() -> Self {
+        PageSetup::new(Unit::Px)
+    }
+}
+
+impl PageSetup {
+    /// One artboard of the size people usually start with in that unit
+    pub fn new(unit: Unit) -> Self {
+        let (width, height) = match unit {
+            Unit::Px => (800.0, 600.0),
+            // A4
+            Unit::Mm => (210.0, 297.0),
+            // US Letter
+            Unit::In => (8.5, 11.0),
+            Unit::Pt => (612.0, 792.0),
+        };
+
+        PageSetup {
+            unit,
+            dpi: DEFAULT_DPI,
+            artboards: vec![Artboard {
+                id: Uuid::new_v4(),
+                name: "Artboard 1".to_string(),
+                width,
+                height,
+                position: FIRST_ARTBOARD_POSITION,
+            }],
+        }
+    }
+
+    pub fn size_px(&self, artboard: &Artboard) -> (f32, f32) {
+        (
+            self.unit.to_px(artboard.width, self.dpi),
+            self.unit.to_px(artboard.height, self.dpi),
+        )
+    }
+
+    /// The top left and bottom right corners in the scene
+    pub fn bounds(&self, artboard: &Artboard) -> ([f32; 2], [f32; 2]) {
+        let (width, height) = self.size_px(artboard);
+        let [x, y] = artboard.position;
+
+        ([x, y], [x + width, y + height])
+    }
+
+    /// The artboard a point sits on, the last added if they overlap
+    pub fn artboard_at(&self, [x, y]: [f32; 2]) -> Option<Uuid> {
+        self.artboards
+            .iter()
+            .rev()
+            .find(|artboard| {
+                let (min, max) = self.bounds(artboard);
+                x >= min[0] && x <= max[0] && y >= min[1] && y <= max[1]
+            })
+            .map(|artboard| artboard.id)
+    }
+
+    /// Adds an artboard the size of the last one, to the right of the others
+    pub fn add_artboard(&mut self) -> Uuid {
+        let right = self
+            .artboards
+            .iter()
+            .map(|artboard| self.bounds(artboard).1[0])
+            .fold(None, |right: Option<f32>, edge| {
+                Some(right.map_or(edge, |right| right.max(edge)))
+            });
+        let position = match right {
+            Some(right) => [right + ARTBOARD_GAP, FIRST_ARTBOARD_POSITION[1]],
+            None => FIRST_ARTBOARD_POSITION,
+        };
+        let (width, height) = match self.artboards.last() {
+            Some(last) => (last.width, last.height),
+            None => {
+                let first = &PageSetup::new(self.unit).artboards[0];
+                (first.width, first.height)
+            }
+        };
+
+        // numbered past any artboard already using the name
+        let number = (self.artboards.len() + 1..)
+            .find(|number| {
+                let name = format!("Artboard {}", number);
+                !self.artboards.iter().any(|artboard| artboard.name == name)
+            })
+            .unwrap_or(1);
+
+        let artboard = Artboard {
+            id: Uuid::new_v4(),
+            name: format!("Artboard {}", number),
+            width,
+            height,
+            position,
+        };
+        let artboard_id = artboard.id;
+        self.artboards.push(artboard);
+
+        artboard_id
+    }
+
+    /// The shapes on it stay where they are, outside any artboard
+    pub fn remove_artboard(&mut self, artboard_id: Uuid) {
+        self.artboards.retain(|artboard| artboard.id != artboard_id);
+    }
+
+    /// A length as it's typed, e.g. "10mm" or "0.5in", in pixels. Numbers without a unit
+    /// are in the page's unit.
+    pub fn parse_length(&self, text: &str) -> Result<f32, String> {
+        let text = text.trim();
+        let number_end = text
+            .rfind(|c: char| c.is_ascii_digit() || c == '.')
+            .map_or(0, |index| index + 1);
+        let (number, suffix) = text.split_at(number_end);
+
+        let unit = match suffix.trim() {
+            "" => self.unit,
+            suffix => Unit::from_suffix(suffix).ok_or("Couldn't recognize unit")?,
+        };
+        let value = string_to_f32(number.trim()).map_err(|_| "Couldn't convert string to f32")?;
+
+        Ok(unit.to_px(value, self.dpi))
+    }
+
+    /// A length in the page's unit, as the inputs show it
+    pub fn format_length(&self, px: f32) -> String {
+        let value = self.unit.from_px(px, self.dpi);
+        ((value * 100.0).round() / 100.0).to_string()
+    }
+
+    /// Measures the artboards in another unit, keeping their size
+    pub fn set_unit(&mut self, unit: Unit) {
+        for artboard in &mut self.artboards {
+            artboard.width = unit.from_px(self.unit.to_px(artboard.width, self.dpi), self.dpi);
+            artboard.height = unit.from_px(self.unit.to_px(artboard.height, self.dpi), self.dpi);
+        }
+
+        self.unit = unit;
+    }
+
+    // artboards measured in pixels keep their pixels when the dpi changes, other units
+    // keep their printed size and gain or lose pixels
+    pub fn set_dpi(&mut self, text: &str) -> Result<(), String> {
+        let dpi = string_to_f32(text).map_err(|_| "Couldn't convert string to f32")?;
+        if dpi <= 0.0 {
+            return Err("DPI must be above 0".to_string());
+        }
+
+        self.dpi = dpi;
+
+        Ok(())
+    }
+}
+
+/// What the document panel edits about an artboard as text
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ArtboardField {
+    Name,
+    Width,
+    Height,
+}
+
+impl ArtboardField {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ArtboardField::Name => "Name:",
+            ArtboardField::Width => "Width:",
+            ArtboardField::Height => "Height:",
+        }
+    }
+
+    /// The name the field's input signal is registered under
+    pub fn signal_name(&self, artboard_id: Uuid) -> String {
+        format!("artboard_{:?}_{}", self, artboard_id).to_lowercase()
+    }
+
+    pub fn read(&self, page: &PageSetup, artboard: &Artboard) -> String {
+        let (width, height) = page.size_px(artboard);
+
+        match self {
+            ArtboardField::Name => artboard.name.clone(),
+            ArtboardField::Width => page.format_length(width),
+            ArtboardField::Height => page.format_length(height),
+        }
+    }
+
+    pub fn apply(&self, page: &mut PageSetup, artboard_id: Uuid, text: &str) -> Result<(), String> {
+        let length = match self {
+            ArtboardField::Name => None,
+            _ => Some(
+                page.unit
+                    .from_px(page.parse_length(text)?.max(1.0), page.dpi),
+            ),
+        };
+        let artboard = page
+            .artboards
+            .iter_mut()
+            .find(|artboard| artboard.id == artboard_id)
+            .ok_or("Couldn't find artboard")?;
+
+        match (self, length) {
+            (ArtboardField::Width, Some(width)) => artboard.width = width,
+            (ArtboardField::Height, Some(height)) => artboard.height = height,
+            _ => artboard.name = text.to_string(),
+        }
+
+        Ok(())
+    }
+}
+
+pub type SharedPageSetup = Arc<Mutex<PageSetup>>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-3
+    }
+
+    #[test]
+    fn lengths_without_a_unit_are_in_the_page_unit() {
+        assert!(close(
+            PageSetup::new(Unit::Px).parse_length("120").unwrap(),
+            120.0
+        ));
+        assert!(close(
+            PageSetup::new(Unit::In).parse_length(" 2 ").unwrap(),
+            192.0
+        ));
+        assert!(close(
+            PageSetup::new(Unit::Mm).parse_length("25.4").unwrap(),
+            96.0
+        ));
+    }
+
+    #[test]
+    fn lengths_with_a_unit_override_the_page_unit() {
+        let page = PageSetup::new(Unit::Px);
+
+        assert!(close(page.parse_length("0.5in").unwrap(), 48.0));
+        assert!(close(page.parse_length("72 pt").unwrap(), 96.0));
+        assert!(close(
+            page.parse_length("10MM").unwrap(),
+            10.0 * 96.0 / 25.4
+        ));
+        assert!(close(page.parse_length("5px").unwrap(), 5.0));
+    }
+
+    #[test]
+    fn lengths_follow_the_dpi() {
+        let mut page = PageSetup::new(Unit::Px);
+        page.set_dpi("300").unwrap();
+
+        assert!(close(page.parse_length("1in").unwrap(), 300.0));
+        assert!(close(page.parse_length("300").unwrap(), 300.0));
+    }
+
+    #[test]
+    fn bad_lengths_are_errors() {
+        let page = PageSetup::new(Unit::Px);
+
+        assert!(page.parse_length("").is_err());
+        assert!(page.parse_length("abc").is_err());
+        assert!(page.parse_length("10ft").is_err());
+        assert!(page.parse_length("mm").is_err());
+    }
+
+    #[test]
+    fn bad_dpis_are_errors() {
+        let mut page = PageSetup::new(Unit::Px);
+
+        assert!(page.set_dpi("0").is_err());
+        assert!(page.set_dpi("-72").is_err());
+        assert!(page.set_dpi("high").is_err());
+        assert_eq!(page.dpi, DEFAULT_DPI);
+    }
+
+    #[test]
+    fn changing_unit_keeps_the_size() {
+        let mut page = PageSetup::new(Unit::In);
+        let before = page.size_px(&page.artboards[0]);
+
+        page.set_unit(Unit::Mm);
+
+        assert!(close(page.artboards[0].width, 8.5 * 25.4));
+        let after = page.size_px(&page.artboards[0]);
+        assert!(close(before.0, after.0) && close(before.1, after.1));
+        assert_eq!(page.format_length(after.0), "215.9");
+    }
+
+    #[test]
+    fn artboards_are_added_to_the_right() {
+        let mut page = PageSetup::new(Unit::Px);
+        let id = page.add_artboard();
+        let added = page.artboards.iter().find(|a| a.id == id).unwrap();
+
+        assert_eq!(added.name, "Artboard 2");
+        assert_eq!(added.position, [450.0 + 800.0 + ARTBOARD_GAP, 50.0]);
+        assert_eq!(page.artboard_at([1400.0, 100.0]), Some(id));
+        assert_eq!(page.artboard_at([1300.0, 100.0]), None);
+    }
+
+    #[test]
+    fn artboard_fields_apply_typed_lengths() {
+        let mut page = PageSetup::new(Unit::Px);
+        let id = page.artboards[0].id;
+
+        ArtboardField::Width.apply(&mut page, id, "1in").unwrap();
+        ArtboardField::Name.apply(&mut page, id, "Cover").unwrap();
+
+        assert_eq!(ArtboardField::Width.read(&page, &page.artboards[0]), "96");
+        assert_eq!(page.artboards[0].name, "Cover");
+        assert!(ArtboardField::Height.apply(&mut page, id, "tall").is_err());
+        assert!(ArtboardField::Height
+            .apply(&mut page, Uuid::new_v4(), "10")
+            .is_err());
+    }
+}
@@ -0,0 +1,250 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use chrono::{DateTime, Local};
+use floem::ext_event::{register_ext_trigger, ExtSendTrigger};
+use floem::reactive::create_effect;
+use serde::{Deserialize, Serialize};
+
+use crate::editor_state::EditorState;
+use crate::helpers::locking::LockRecover;
+
+use super::history::HistorySnapshot;
+use super::DocumentSnapshot;
+
+const RECOVERY_EXTENSION: &str = "json";
+// how often the autosave thread looks at the clock and the interval
+const AUTOSAVE_POLL: Duration = Duration::from_secs(1);
+
+/// What an autosave writes: the document and the undo history that led to it
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RecoveryFile {
+    pub document: DocumentSnapshot,
+    pub history: HistorySnapshot,
+}
+
+/// An autosave left behind by an earlier session
+#[derive(Debug, Clone)]
+pub struct Recovery {
+    pub path: PathBuf,
+    pub saved_at: SystemTime,
+    pub file: RecoveryFile,
+}
+
+impl Recovery {
+    pub fn saved_label(&self) -> String {
+        let saved_at: DateTime<Local> = self.saved_at.into();
+        format!("Saved {}", saved_at.format("%b %-d, %H:%M"))
+    }
+
+    pub fn discard(&self) -> Result<(), String> {
+        fs::remove_file(&self.path).map_err(|_| "Couldn't remove recovery file".to_string())
+    }
+}
+
+pub fn recovery_dir() -> Option<PathBuf> {
+    dirs::data_local_dir().map(|dir| dir.join("sensor").join("recovery"))
+}
+
+// Where a session autosaves, named for when it started
+fn session_path() -> Option<PathBuf> {
+    let started = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_millis());
+
+    recovery_dir().map(|dir| dir.join(format!("session-{}.{}", started, RECOVERY_EXTENSION)))
+}
+
+#[derive(Debug, Default)]
+struct SessionState {
+    // the last thing written, so an untouched document isn't written again
+    written: Option<String>,
+    // once the app closes normally nothing more is written
+    ended: bool,
+}
+
+/// This session's autosave file. Only a session that didn't end leaves it behind, so
+/// whatever is found at startup came from a crash.
+#[derive(Debug, Clone)]
+pub struct AutosaveSession {
+    path: PathBuf,
+    state: Arc<Mutex<SessionState>>,
+}
+
+impl AutosaveSession {
+    /// None when there's nowhere to autosave
+    pub fn new() -> Option<Self> {
+        Some(AutosaveSession {
+            path: session_path()?,
+            state: Arc::new(Mutex::new(SessionState::default())),
+        })
+    }
+
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    /// Removes the autosave on a clean exit. A write still in flight finishes first and
+    /// none start after.
+    pub fn end(&self) {
+        let mut state = self.state.lock_or_recover();
+        state.ended = true;
+
+        if state.written.is_some() {
+            if let Err(error) = fs::remove_file(&self.path) {
+                println!("Couldn't remove autosave: {}", error);
+            }
+        }
+    }
+
+    fn write(&self, file: &RecoveryFile) {
+        let text = match serde_json::to_string(file) {
+            Ok(text) => text,
+            Err(error) => {
+                println!("Couldn't serialize autosave: {}", error);
+                return;
+            }
+        };
+
+        let mut state = self.state.lock_or_recover();
+        let unchanged = state.written.as_ref() == Some(&text);
+        // nothing worth recovering until something has been drawn
+        let untouched = state.written.is_none() && file.document.is_empty();
+
+        if state.ended || unchanged || untouched {
+            return;
+        }
+
+        match write_recovery(&self.path, &text) {
+            Ok(()) => state.written = Some(text),
+            Err(error) => println!("{}", error),
+        }
+    }
+}
+
+/// Autosaves from earlier sessions, newest first. Files that can't be read are skipped.
+pub fn previous_sessions(current: Option<&PathBuf>) -> Vec<Recovery> {
+    let Some(entries) = recovery_dir().and_then(|dir| fs::read_dir(dir).ok()) else {
+        return Vec::new();
+    };
+
+    let mut recoveries: Vec<Recovery> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|ext| ext == RECOVERY_EXTENSION)
+                && Some(path) != current
+        })
+        .filter_map(|path| {
+            let saved_at = fs::metadata(&path).ok()?.modified().ok()?;
+            let text = fs::read_to_string(&path).ok()?;
+            let file = serde_json::from_str(&text)
+                .map_err(|error| println!("Couldn't read {}: {}", path.display(), error))
+                .ok()?;
+
+            Some(Recovery {
+                path,
+                saved_at,
+                file,
+            })
+        })
+        .collect();
+
+    recoveries.sort_by(|a, b| b.saved_at.cmp(&a.saved_at));
+
+    recoveries
+}
+
+// written beside the old file then renamed over it, so a crash mid-write can't lose both
+fn write_recovery(path: &PathBuf, text: &str) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|_| "Couldn't create recovery directory")?;
+    }
+
+    let partial = path.with_extension("partial");
+    fs::write(&partial, text).map_err(|_| "Couldn't write recovery file")?;
+    fs::rename(&partial, path).map_err(|_| "Couldn't replace recovery file")?;
+
+    Ok(())
+}
+
+/// Saves the document and its history every few minutes, as set in the preferences.
+/// The snapshot is taken on the UI thread, the file is written on another.
+pub fn start_autosave(editor_state: Arc<Mutex<EditorState>>, session: Option<AutosaveSession>) {
+    let Some(session) = session else {
+        println!("Couldn't find recovery directory, autosave is off");
+        return;
+    };
+
+    let preferences = Arc::clone(&editor_state.lock_or_recover().preferences);
+    let trigger = ExtSendTrigger::new();
+
+    create_effect(move |started: Option<()>| {
+        trigger.track();
+
+        // the first run only tracks the trigger
+        if started.is_none() {
+            return;
+        }
+
+        let file = editor_state.lock_or_recover().recovery_file();
+        let session = session.clone();
+
+        thread::spawn(move || session.write(&file));
+    });
+
+    thread::spawn(move || {
+        let mut last_save = Instant::now();
+
+        loop {
+            thread::sleep(AUTOSAVE_POLL);
+
+            let minutes = preferences.lock_or_recover().autosave_minutes;
+            if minutes <= 0.0 {
+                last_save = Instant::now();
+                continue;
+            }
+
+            if last_save.elapsed().as_secs_f32() >= minutes * 60.0 {
+                last_save = Instant::now();
+                register_ext_trigger(trigger);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+
+    #[test]
+    fn a_clean_exit_removes_the_autosave_for_good() {
+        let session = AutosaveSession {
+            path: std::env::temp_dir().join(format!("sensor-session-{}.json", Uuid::new_v4())),
+            state: Arc::new(Mutex::new(SessionState::default())),
+        };
+        let mut file = RecoveryFile::default();
+
+        // an empty document isn't worth writing
+        session.write(&file);
+        assert!(!session.path().exists());
+
+        file.document.layer_list.push(Uuid::new_v4());
+        session.write(&file);
+        assert!(session.path().exists());
+
+        session.end();
+        assert!(!session.path().exists());
+
+        // a save that was already on its way doesn't bring it back
+        file.document.layer_list.push(Uuid::new_v4());
+        session.write(&file);
+        assert!(!session.path().exists());
+    }
+}
@@ -1,15 +1,53 @@
 use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 
-use common_vector::basic::wgpu_to_human;
+use common_vector::basic::{wgpu_to_human, Point, WindowSize};
 use common_vector::editor::{InputValue, PolygonProperty};
+use common_vector::polygon::Polygon;
 use common_vector::{basic::string_to_f32, editor::Editor};
 use floem::keyboard::ModifiersState;
 use floem::reactive::{RwSignal, SignalUpdate};
+use strum::IntoEnumIterator;
 use undo::Edit;
 use undo::Record;
 use uuid::Uuid;
 
+use crate::assets::library::{Asset, AssetKind, AssetLibrary, SHAPE_EXTENSION};
+use crate::assets::svg::{bounds, parse_shapes, shape_svg};
+use crate::brush::eraser::{
+    erase_stroke, polygon_outline, subtract_path, EraserMode, EraserSettings,
+};
+use crate::brush::smoothing::{simplify, StabilizerKind};
+use crate::brush::strokes::{SharedStrokeLayers, StrokeLayer, StrokeLayers, StrokeProperty};
+use crate::brush::{ActiveStroke, BrushKind, BrushSettings, BrushTool};
+use crate::document::export::export_artboards;
+use crate::document::history::{HistorySnapshot, LayerBuilder};
+use crate::document::page::{ArtboardField, PageSetup, SharedPageSetup};
+use crate::document::recovery::RecoveryFile;
+use crate::document::{set_polygon_stroke, DocumentSnapshot};
+use crate::helpers::blending::{BlendMode, LayerBlend, SharedLayerBlends};
+use crate::helpers::effects::{EffectField, EffectKind, ShapeEffect, SharedShapeEffects};
+use crate::helpers::events::{EditorEvent, EditorEventSender};
+use crate::helpers::layers::{editor_layer, editor_layers, Layer, LayerChange, LayerKind};
+use crate::helpers::locking::LockRecover;
+use crate::helpers::masks::{LayerLinks, LayerMask, LayerShape, MaskState, SharedLayerMasks};
+use crate::helpers::navigation::{union_bounds, View};
+use crate::helpers::preferences::{PreferenceField, Preferences, SharedPreferences};
+use crate::helpers::redraw::{Invalidation, Invalidator};
+use crate::photo::adjustments::{
+    Adjustment, AdjustmentField, AdjustmentStack, SharedLayerAdjustments,
+};
+use crate::photo::crop::{AspectPreset, CropParams, CropTool, MIN_STRAIGHTEN_LENGTH};
+use crate::photo::filters::{
+    Filter, FilterField, FilterKind, FilterMode, FilterWorker, LayerFilters,
+};
+use crate::photo::images::{ImageLayer, ImageLayers, SharedImageLayers};
+use crate::photo::metadata::PhotoMetadata;
+use crate::renderer::batch::BatchItemId;
+
 #[derive(Debug)]
 pub struct PolygonEdit {
     pub polygon_id: Uuid,
@@ -19,95 +57,122 @@ pub struct PolygonEdit {
     pub signal: Option<RwSignal<String>>,
 }
 
+impl PolygonEdit {
+    // edits restored from an autosave have no inputs to update
+    fn set_signal(&self, value: String) {
+        if let Some(signal) = self.signal {
+            signal.set(value);
+        }
+    }
+}
+
+// only the size and outline decide which artboard a shape sits on
+fn moves_polygon(value: &PolygonProperty) -> bool {
+    matches!(
+        value,
+        PolygonProperty::Width(_) | PolygonProperty::Height(_) | PolygonProperty::Points(_)
+    )
+}
+
 impl Edit for PolygonEdit {
     type Target = RecordState;
     type Output = ();
 
     fn edit(&mut self, record_state: &mut RecordState) {
-        let mut editor = record_state.editor.lock().unwrap();
+        let mut editor = record_state.editor.lock_or_recover();
 
         match &self.new_value {
             PolygonProperty::Width(w) => {
                 editor.update_polygon(self.polygon_id, "width", InputValue::Number(*w));
 
-                let mut width = w.to_string();
-                self.signal.expect("signal error").set(width);
+                let width = record_state.page.lock_or_recover().format_length(*w);
+                self.set_signal(width);
             }
             PolygonProperty::Height(h) => {
                 editor.update_polygon(self.polygon_id, "height", InputValue::Number(*h));
 
-                let mut height = h.to_string();
-                self.signal.expect("signal error").set(height);
+                let height = record_state.page.lock_or_recover().format_length(*h);
+                self.set_signal(height);
             }
             PolygonProperty::Red(h) => {
                 editor.update_polygon(self.polygon_id, "red", InputValue::Number(*h));
 
                 let mut red = h.to_string();
-                self.signal.expect("signal error").set(red);
+                self.set_signal(red);
             }
             PolygonProperty::Green(h) => {
                 editor.update_polygon(self.polygon_id, "green", InputValue::Number(*h));
 
                 let mut green = h.to_string();
-                self.signal.expect("signal error").set(green);
+                self.set_signal(green);
             }
             PolygonProperty::Blue(h) => {
                 editor.update_polygon(self.polygon_id, "blue", InputValue::Number(*h));
 
                 let mut blue = h.to_string();
-                self.signal.expect("signal error").set(blue);
+                self.set_signal(blue);
             }
             PolygonProperty::BorderRadius(h) => {
                 editor.update_polygon(self.polygon_id, "border_radius", InputValue::Number(*h));
 
-                let mut border_radius = h.to_string();
-                self.signal.expect("signal error").set(border_radius);
+                let border_radius = record_state.page.lock_or_recover().format_length(*h);
+                self.set_signal(border_radius);
             }
             PolygonProperty::StrokeThickness(h) => {
                 editor.update_polygon(self.polygon_id, "stroke_thickness", InputValue::Number(*h));
 
-                let mut stroke_thickness = h.to_string();
-                self.signal.expect("signal error").set(stroke_thickness);
+                let stroke_thickness = record_state.page.lock_or_recover().format_length(*h);
+                self.set_signal(stroke_thickness);
             }
             PolygonProperty::StrokeRed(h) => {
                 editor.update_polygon(self.polygon_id, "stroke_red", InputValue::Number(*h));
 
                 let mut stroke_red = h.to_string();
-                self.signal.expect("signal error").set(stroke_red);
+                self.set_signal(stroke_red);
             }
             PolygonProperty::StrokeGreen(h) => {
                 editor.update_polygon(self.polygon_id, "stroke_green", InputValue::Number(*h));
 
                 let mut stroke_green = h.to_string();
-                self.signal.expect("signal error").set(stroke_green);
+                self.set_signal(stroke_green);
             }
             PolygonProperty::StrokeBlue(h) => {
                 editor.update_polygon(self.polygon_id, "stroke_blue", InputValue::Number(*h));
 
                 let mut stroke_blue = h.to_string();
-                self.signal.expect("signal error").set(stroke_blue);
+                self.set_signal(stroke_blue);
             }
             PolygonProperty::Points(w) => {
                 editor.update_polygon(self.polygon_id, "points", InputValue::Points(w.clone()));
             }
         }
+
+        // a resized or dragged shape may have landed on another artboard
+        if moves_polygon(&self.new_value) {
+            record_state.emit_layer_moved(&editor, self.polygon_id);
+        }
+
+        record_state.emit_polygon_updated(self.polygon_id, &self.field_name);
+        record_state
+            .invalidator
+            .shape_changed(BatchItemId::Polygon(self.polygon_id));
     }
 
     fn undo(&mut self, record_state: &mut RecordState) {
-        let mut editor = record_state.editor.lock().unwrap();
+        let mut editor = record_state.editor.lock_or_recover();
 
         match &self.old_value {
             PolygonProperty::Width(w) => {
                 editor.update_polygon(self.polygon_id, "width", InputValue::Number(*w));
 
-                let mut width = w.to_string();
-                self.signal.expect("signal error").set(width);
+                let width = record_state.page.lock_or_recover().format_length(*w);
+                self.set_signal(width);
             }
             PolygonProperty::Height(h) => {
                 editor.update_polygon(self.polygon_id, "height", InputValue::Number(*h));
 
-                let mut height = h.to_string();
-                self.signal.expect("signal error").set(height);
+                let height = record_state.page.lock_or_recover().format_length(*h);
+                self.set_signal(height);
             }
             PolygonProperty::Red(h) => {
                 // let mut stroke_green = h.to_string();
@@ -115,9 +180,7 @@ impl Edit for PolygonEdit {
 
                 editor.update_polygon(self.polygon_id, "red", InputValue::Number(red_human));
 
-                self.signal
-                    .expect("signal error")
-                    .set(red_human.to_string());
+                self.set_signal(red_human.to_string());
             }
             PolygonProperty::Green(h) => {
                 // let mut stroke_green = h.to_string();
@@ -125,9 +188,7 @@ impl Edit for PolygonEdit {
 
                 editor.update_polygon(self.polygon_id, "green", InputValue::Number(green_human));
 
-                self.signal
-                    .expect("signal error")
-                    .set(green_human.to_string());
+                self.set_signal(green_human.to_string());
             }
             PolygonProperty::Blue(h) => {
                 // let mut stroke_green = h.to_string();
@@ -135,110 +196,2282 @@ impl Edit for PolygonEdit {
 
                 editor.update_polygon(self.polygon_id, "blue", InputValue::Number(blue_human));
 
-                self.signal
-                    .expect("signal error")
-                    .set(blue_human.to_string());
+                self.set_signal(blue_human.to_string());
             }
             PolygonProperty::BorderRadius(h) => {
                 editor.update_polygon(self.polygon_id, "border_radius", InputValue::Number(*h));
 
-                let mut border_radius = h.to_string();
-                self.signal.expect("signal error").set(border_radius);
-            }
-            PolygonProperty::StrokeThickness(h) => {
-                editor.update_polygon(self.polygon_id, "stroke_thickness", InputValue::Number(*h));
+                let border_radius = record_state.page.lock_or_recover().format_length(*h);
+                self.set_signal(border_radius);
+            }
+            PolygonProperty::StrokeThickness(h) => {
+                editor.update_polygon(self.polygon_id, "stroke_thickness", InputValue::Number(*h));
+
+                let stroke_thickness = record_state.page.lock_or_recover().format_length(*h);
+                self.set_signal(stroke_thickness);
+            }
+            PolygonProperty::StrokeRed(h) => {
+                // let mut stroke_red = h.to_string();
+                let red_human = wgpu_to_human(*h);
+
+                editor.update_polygon(self.polygon_id, "stroke_red", InputValue::Number(red_human));
+
+                self.set_signal(red_human.to_string());
+            }
+            PolygonProperty::StrokeGreen(h) => {
+                // let mut stroke_green = h.to_string();
+                let green_human = wgpu_to_human(*h);
+
+                editor.update_polygon(
+                    self.polygon_id,
+                    "stroke_green",
+                    InputValue::Number(green_human),
+                );
+
+                self.set_signal(green_human.to_string());
+            }
+            PolygonProperty::StrokeBlue(h) => {
+                // let mut stroke_blue = h.to_string();
+                let blue_human = wgpu_to_human(*h);
+
+                editor.update_polygon(
+                    self.polygon_id,
+                    "stroke_blue",
+                    InputValue::Number(blue_human),
+                );
+
+                self.set_signal(blue_human.to_string());
+            }
+            PolygonProperty::Points(w) => {
+                editor.update_polygon(self.polygon_id, "points", InputValue::Points(w.clone()));
+            }
+        }
+
+        // a resized or dragged shape may have landed on another artboard
+        if moves_polygon(&self.old_value) {
+            record_state.emit_layer_moved(&editor, self.polygon_id);
+        }
+
+        record_state.emit_polygon_updated(self.polygon_id, &self.field_name);
+        record_state
+            .invalidator
+            .shape_changed(BatchItemId::Polygon(self.polygon_id));
+    }
+}
+
+#[derive(Debug)]
+pub enum StrokeEdit {
+    // the layer is held by the edit whenever it isn't in the scene
+    Create {
+        stroke_id: Uuid,
+        layer: Option<StrokeLayer>,
+    },
+    Delete {
+        stroke_id: Uuid,
+        layer: Option<StrokeLayer>,
+        // where it sat in the layer list, so undo puts it back in place
+        position: Option<usize>,
+    },
+    Update {
+        stroke_id: Uuid,
+        field_name: String,
+        old_value: StrokeProperty,
+        new_value: StrokeProperty,
+        signal: Option<RwSignal<String>>,
+    },
+}
+
+impl Edit for StrokeEdit {
+    type Target = RecordState;
+    type Output = ();
+
+    fn edit(&mut self, record_state: &mut RecordState) {
+        match self {
+            StrokeEdit::Create { layer, .. } => {
+                if let Some(layer) = layer.take() {
+                    record_state.insert_stroke(layer, None);
+                }
+            }
+            StrokeEdit::Delete {
+                stroke_id,
+                layer,
+                position,
+            } => {
+                if let Some((removed, index)) = record_state.remove_stroke(*stroke_id) {
+                    *layer = Some(removed);
+                    *position = Some(index);
+                }
+            }
+            StrokeEdit::Update {
+                stroke_id,
+                field_name,
+                new_value,
+                signal,
+                ..
+            } => {
+                record_state.update_stroke(*stroke_id, new_value);
+
+                if let Some(signal) = signal {
+                    signal.set(new_value.display());
+                }
+                record_state.emit_stroke_updated(*stroke_id, field_name);
+            }
+        }
+
+        record_state.invalidator.invalidate(Invalidation::Scene);
+    }
+
+    fn undo(&mut self, record_state: &mut RecordState) {
+        match self {
+            StrokeEdit::Create { stroke_id, layer } => {
+                if let Some((removed, _)) = record_state.remove_stroke(*stroke_id) {
+                    *layer = Some(removed);
+                }
+            }
+            StrokeEdit::Delete {
+                layer, position, ..
+            } => {
+                if let Some(layer) = layer.take() {
+                    record_state.insert_stroke(layer, *position);
+                }
+            }
+            StrokeEdit::Update {
+                stroke_id,
+                field_name,
+                old_value,
+                signal,
+                ..
+            } => {
+                record_state.update_stroke(*stroke_id, old_value);
+
+                if let Some(signal) = signal {
+                    signal.set(old_value.display());
+                }
+                record_state.emit_stroke_updated(*stroke_id, field_name);
+            }
+        }
+
+        record_state.invalidator.invalidate(Invalidation::Scene);
+    }
+}
+
+#[derive(Debug)]
+pub enum ImageEdit {
+    // the layer is held by the edit whenever it isn't in the scene
+    Create {
+        image_id: Uuid,
+        layer: Option<ImageLayer>,
+    },
+    Delete {
+        image_id: Uuid,
+        layer: Option<ImageLayer>,
+        // where it sat in the layer list, so undo puts it back in place
+        position: Option<usize>,
+    },
+}
+
+impl Edit for ImageEdit {
+    type Target = RecordState;
+    type Output = ();
+
+    fn edit(&mut self, record_state: &mut RecordState) {
+        match self {
+            ImageEdit::Create { layer, .. } => {
+                if let Some(layer) = layer.take() {
+                    record_state.put_layer(SceneLayer::Image(layer), None);
+                }
+            }
+            ImageEdit::Delete {
+                image_id,
+                layer,
+                position,
+            } => {
+                if let Some((removed, index)) = record_state.remove_image(*image_id) {
+                    *layer = Some(removed);
+                    *position = Some(index);
+                }
+            }
+        }
+
+        record_state.invalidator.invalidate(Invalidation::Scene);
+    }
+
+    fn undo(&mut self, record_state: &mut RecordState) {
+        match self {
+            ImageEdit::Create { image_id, layer } => {
+                if let Some((removed, _)) = record_state.remove_image(*image_id) {
+                    *layer = Some(removed);
+                }
+            }
+            ImageEdit::Delete {
+                layer, position, ..
+            } => {
+                if let Some(layer) = layer.take() {
+                    record_state.put_layer(SceneLayer::Image(layer), *position);
+                }
+            }
+        }
+
+        record_state.invalidator.invalidate(Invalidation::Scene);
+    }
+}
+
+/// A layer lifted out of the scene, so an edit can hold it and put it back later
+pub enum SceneLayer {
+    Polygon(Polygon),
+    Stroke(StrokeLayer),
+    Image(ImageLayer),
+}
+
+impl SceneLayer {
+    pub fn id(&self) -> Uuid {
+        match self {
+            SceneLayer::Polygon(polygon) => polygon.id,
+            SceneLayer::Stroke(layer) => layer.id,
+            SceneLayer::Image(layer) => layer.id,
+        }
+    }
+
+    pub fn kind(&self) -> LayerKind {
+        match self {
+            SceneLayer::Polygon(_) => LayerKind::Polygon,
+            SceneLayer::Stroke(_) => LayerKind::Stroke,
+            SceneLayer::Image(_) => LayerKind::Image,
+        }
+    }
+}
+
+// polygons hold gpu buffers and photos their pixels, so only the id is worth printing
+impl fmt::Debug for SceneLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneLayer::Polygon(polygon) => f.debug_tuple("Polygon").field(&polygon.id).finish(),
+            SceneLayer::Stroke(layer) => f.debug_tuple("Stroke").field(&layer.id).finish(),
+            SceneLayer::Image(layer) => f.debug_tuple("Image").field(&layer.id).finish(),
+        }
+    }
+}
+
+/// A layer the eraser cut into, swapped for whatever was left of it
+#[derive(Debug)]
+pub struct LayerSwap {
+    pub original_id: Uuid,
+    pub replacement_ids: Vec<Uuid>,
+    // whichever side is out of the scene is held here
+    pub original: Option<SceneLayer>,
+    pub replacements: Vec<SceneLayer>,
+    // where the original sat in the layer list, the pieces take its place
+    pub position: Option<usize>,
+}
+
+impl LayerSwap {
+    pub fn new(original_id: Uuid, replacements: Vec<SceneLayer>) -> Self {
+        LayerSwap {
+            original_id,
+            replacement_ids: replacements.iter().map(|layer| layer.id()).collect(),
+            original: None,
+            replacements,
+            position: None,
+        }
+    }
+}
+
+/// One erase gesture, however many layers it went through
+#[derive(Debug)]
+pub struct EraseEdit {
+    pub swaps: Vec<LayerSwap>,
+}
+
+impl Edit for EraseEdit {
+    type Target = RecordState;
+    type Output = ();
+
+    fn edit(&mut self, record_state: &mut RecordState) {
+        for swap in &mut self.swaps {
+            let Some((original, position)) = record_state.take_layer(swap.original_id) else {
+                continue;
+            };
+
+            for (offset, layer) in swap.replacements.drain(..).enumerate() {
+                record_state.put_layer(layer, Some(position + offset));
+            }
+
+            record_state.emit_swap_updated(swap, &original);
+
+            swap.original = Some(original);
+            swap.position = Some(position);
+        }
+
+        record_state.invalidator.invalidate(Invalidation::Scene);
+    }
+
+    fn undo(&mut self, record_state: &mut RecordState) {
+        for swap in self.swaps.iter_mut().rev() {
+            let Some(original) = swap.original.take() else {
+                continue;
+            };
+
+            swap.replacements = swap
+                .replacement_ids
+                .iter()
+                .filter_map(|id| record_state.take_layer(*id))
+                .map(|(layer, _)| layer)
+                .collect();
+
+            record_state.emit_swap_updated(swap, &original);
+            record_state.put_layer(original, swap.position);
+        }
+
+        record_state.invalidator.invalidate(Invalidation::Scene);
+    }
+}
+
+/// A change to a layer's adjustment stack, swapped as a whole
+#[derive(Debug)]
+pub struct AdjustmentEdit {
+    pub layer_id: Uuid,
+    pub old_stack: AdjustmentStack,
+    pub new_stack: AdjustmentStack,
+    // the inputs of the fields that changed, so they follow undo
+    pub signals: Vec<(Uuid, AdjustmentField, RwSignal<String>)>,
+}
+
+impl AdjustmentEdit {
+    fn apply(&self, record_state: &mut RecordState, stack: &AdjustmentStack) {
+        record_state
+            .adjustments
+            .lock_or_recover()
+            .set(self.layer_id, stack.clone());
+
+        for (entry_id, field, signal) in &self.signals {
+            if let Some(value) = stack
+                .get(*entry_id)
+                .and_then(|entry| field.read(&entry.adjustment))
+            {
+                signal.set(value.to_string());
+            }
+        }
+
+        let _ = record_state
+            .events
+            .send(EditorEvent::AdjustmentsChanged(self.layer_id));
+        record_state.invalidator.invalidate(Invalidation::Scene);
+    }
+}
+
+impl Edit for AdjustmentEdit {
+    type Target = RecordState;
+    type Output = ();
+
+    fn edit(&mut self, record_state: &mut RecordState) {
+        self.apply(record_state, &self.new_stack);
+    }
+
+    fn undo(&mut self, record_state: &mut RecordState) {
+        self.apply(record_state, &self.old_stack);
+    }
+}
+
+/// A change to a photo's crop, turn or flips
+#[derive(Debug)]
+pub struct CropEdit {
+    pub image_id: Uuid,
+    pub old_value: CropParams,
+    pub new_value: CropParams,
+    // the rotation field, so it follows undo
+    pub signal: Option<RwSignal<String>>,
+}
+
+impl CropEdit {
+    fn apply(&self, record_state: &mut RecordState, crop: CropParams) {
+        let window_size = {
+            let editor = record_state.editor.lock_or_recover();
+            let viewport = editor.viewport.lock_or_recover();
+            WindowSize {
+                width: viewport.width as u32,
+                height: viewport.height as u32,
+            }
+        };
+
+        if let Some(layer) = record_state.images.lock_or_recover().get_mut(self.image_id) {
+            layer.crop = crop;
+            layer.rebuild(&window_size);
+        }
+
+        if let Some(signal) = self.signal {
+            signal.set(crop.rotation.to_string());
+        }
+
+        // the Scene list files a photo under the artboard its cropped middle is on
+        record_state.emit_layer_moved(&record_state.editor.lock_or_recover(), self.image_id);
+
+        let _ = record_state
+            .events
+            .send(EditorEvent::CropChanged(self.image_id));
+        record_state.invalidator.invalidate(Invalidation::Scene);
+    }
+}
+
+impl Edit for CropEdit {
+    type Target = RecordState;
+    type Output = ();
+
+    fn edit(&mut self, record_state: &mut RecordState) {
+        self.apply(record_state, self.new_value);
+    }
+
+    fn undo(&mut self, record_state: &mut RecordState) {
+        self.apply(record_state, self.old_value);
+    }
+}
+
+/// A filter applied to a photo or taken off it, the filters swapped as a whole
+#[derive(Debug)]
+pub struct FilterEdit {
+    pub image_id: Uuid,
+    pub old_value: LayerFilters,
+    pub new_value: LayerFilters,
+    // the source before and after a filter was baked into it, None when only the
+    // effects changed or the edit was restored from an autosave
+    pub sources: Option<(Arc<Vec<u8>>, Arc<Vec<u8>>)>,
+}
+
+impl FilterEdit {
+    fn apply(
+        &self,
+        record_state: &mut RecordState,
+        filters: &LayerFilters,
+        source: Option<&Arc<Vec<u8>>>,
+    ) {
+        let window_size = {
+            let editor = record_state.editor.lock_or_recover();
+            let viewport = editor.viewport.lock_or_recover();
+            WindowSize {
+                width: viewport.width as u32,
+                height: viewport.height as u32,
+            }
+        };
+
+        if let Some(layer) = record_state.images.lock_or_recover().get_mut(self.image_id) {
+            let rebake = layer.filters.baked != filters.baked;
+            layer.filters = filters.clone();
+
+            match source {
+                Some(source) => layer.source = Arc::clone(source),
+                // autosaves don't keep pixels, so the bakes are made again from the file
+                None if rebake => {
+                    if let Err(e) = layer.reload(&window_size) {
+                        println!("{}", e);
+                    }
+                }
+                None => {}
+            }
+        }
+
+        record_state.render_filters(self.image_id, None);
+
+        let _ = record_state
+            .events
+            .send(EditorEvent::FiltersChanged(self.image_id));
+    }
+}
+
+impl Edit for FilterEdit {
+    type Target = RecordState;
+    type Output = ();
+
+    fn edit(&mut self, record_state: &mut RecordState) {
+        let source = self.sources.as_ref().map(|(_, new_source)| new_source);
+        self.apply(record_state, &self.new_value, source);
+    }
+
+    fn undo(&mut self, record_state: &mut RecordState) {
+        let source = self.sources.as_ref().map(|(old_source, _)| old_source);
+        self.apply(record_state, &self.old_value, source);
+    }
+}
+
+/// A change to one layer's mask or clipping
+#[derive(Debug)]
+pub struct MaskEdit {
+    pub layer_id: Uuid,
+    pub old_state: MaskState,
+    pub new_state: MaskState,
+    // a layer turned into a vector mask leaves the scene, and is held here until undo
+    pub consumed_id: Option<Uuid>,
+    pub consumed: Option<SceneLayer>,
+    pub position: Option<usize>,
+}
+
+impl MaskEdit {
+    pub fn new(layer_id: Uuid, old_state: MaskState, new_state: MaskState) -> Self {
+        MaskEdit {
+            layer_id,
+            old_state,
+            new_state,
+            consumed_id: None,
+            consumed: None,
+            position: None,
+        }
+    }
+}
+
+impl Edit for MaskEdit {
+    type Target = RecordState;
+    type Output = ();
+
+    fn edit(&mut self, record_state: &mut RecordState) {
+        if let Some(consumed_id) = self.consumed_id {
+            if let Some((layer, position)) = record_state.take_layer(consumed_id) {
+                self.consumed = Some(layer);
+                self.position = Some(position);
+            }
+        }
+
+        record_state
+            .masks
+            .lock_or_recover()
+            .set_state(self.layer_id, self.new_state.clone());
+        record_state.emit_layer_linked(self.layer_id);
+
+        let _ = record_state
+            .events
+            .send(EditorEvent::MaskChanged(self.layer_id));
+        record_state.invalidator.invalidate(Invalidation::Scene);
+    }
+
+    fn undo(&mut self, record_state: &mut RecordState) {
+        record_state
+            .masks
+            .lock_or_recover()
+            .set_state(self.layer_id, self.old_state.clone());
+        record_state.emit_layer_linked(self.layer_id);
+
+        if let Some(layer) = self.consumed.take() {
+            record_state.put_layer(layer, self.position);
+        }
+
+        let _ = record_state
+            .events
+            .send(EditorEvent::MaskChanged(self.layer_id));
+        record_state.invalidator.invalidate(Invalidation::Scene);
+    }
+}
+
+/// A change to one layer's blend mode or opacity
+#[derive(Debug)]
+pub struct BlendEdit {
+    pub layer_id: Uuid,
+    pub old_value: LayerBlend,
+    pub new_value: LayerBlend,
+    // the opacity field, so it follows undo
+    pub signal: Option<RwSignal<String>>,
+}
+
+impl BlendEdit {
+    fn apply(&self, record_state: &mut RecordState, blend: LayerBlend) {
+        record_state
+            .blends
+            .lock_or_recover()
+            .set(self.layer_id, blend);
+
+        if let Some(signal) = self.signal {
+            signal.set((blend.opacity * 100.0).to_string());
+        }
+
+        let _ = record_state
+            .events
+            .send(EditorEvent::BlendChanged(self.layer_id));
+        record_state.invalidator.invalidate(Invalidation::Scene);
+    }
+}
+
+impl Edit for BlendEdit {
+    type Target = RecordState;
+    type Output = ();
+
+    fn edit(&mut self, record_state: &mut RecordState) {
+        self.apply(record_state, self.new_value);
+    }
+
+    fn undo(&mut self, record_state: &mut RecordState) {
+        self.apply(record_state, self.old_value);
+    }
+}
+
+/// A change to a polygon's effects stack, swapped as a whole
+#[derive(Debug)]
+pub struct EffectEdit {
+    pub layer_id: Uuid,
+    pub old_stack: Vec<ShapeEffect>,
+    pub new_stack: Vec<ShapeEffect>,
+    // the inputs of the fields that changed, so they follow undo
+    pub signals: Vec<(usize, EffectField, RwSignal<String>)>,
+}
+
+impl EffectEdit {
+    fn apply(&self, record_state: &mut RecordState, stack: &[ShapeEffect]) {
+        record_state
+            .effects
+            .lock_or_recover()
+            .set(self.layer_id, stack.to_vec());
+
+        for (index, field, signal) in &self.signals {
+            if let Some(effect) = stack.get(*index) {
+                signal.set(field.read(effect).to_string());
+            }
+        }
+
+        let _ = record_state
+            .events
+            .send(EditorEvent::EffectsChanged(self.layer_id));
+        record_state.invalidator.invalidate(Invalidation::Scene);
+    }
+}
+
+impl Edit for EffectEdit {
+    type Target = RecordState;
+    type Output = ();
+
+    fn edit(&mut self, record_state: &mut RecordState) {
+        self.apply(record_state, &self.new_stack);
+    }
+
+    fn undo(&mut self, record_state: &mut RecordState) {
+        self.apply(record_state, &self.old_stack);
+    }
+}
+
+/// Everything that goes through the undo history
+#[derive(Debug)]
+pub enum SceneEdit {
+    Polygon(PolygonEdit),
+    Stroke(StrokeEdit),
+    Image(ImageEdit),
+    Erase(EraseEdit),
+    Adjustment(AdjustmentEdit),
+    Crop(CropEdit),
+    Filter(FilterEdit),
+    Mask(MaskEdit),
+    Blend(BlendEdit),
+    Effect(EffectEdit),
+}
+
+impl From<PolygonEdit> for SceneEdit {
+    fn from(edit: PolygonEdit) -> Self {
+        SceneEdit::Polygon(edit)
+    }
+}
+
+impl From<StrokeEdit> for SceneEdit {
+    fn from(edit: StrokeEdit) -> Self {
+        SceneEdit::Stroke(edit)
+    }
+}
+
+impl From<ImageEdit> for SceneEdit {
+    fn from(edit: ImageEdit) -> Self {
+        SceneEdit::Image(edit)
+    }
+}
+
+impl From<EraseEdit> for SceneEdit {
+    fn from(edit: EraseEdit) -> Self {
+        SceneEdit::Erase(edit)
+    }
+}
+
+impl From<AdjustmentEdit> for SceneEdit {
+    fn from(edit: AdjustmentEdit) -> Self {
+        SceneEdit::Adjustment(edit)
+    }
+}
+
+impl From<CropEdit> for SceneEdit {
+    fn from(edit: CropEdit) -> Self {
+        SceneEdit::Crop(edit)
+    }
+}
+
+impl From<FilterEdit> for SceneEdit {
+    fn from(edit: FilterEdit) -> Self {
+        SceneEdit::Filter(edit)
+    }
+}
+
+impl From<MaskEdit> for SceneEdit {
+    fn from(edit: MaskEdit) -> Self {
+        SceneEdit::Mask(edit)
+    }
+}
+
+impl From<BlendEdit> for SceneEdit {
+    fn from(edit: BlendEdit) -> Self {
+        SceneEdit::Blend(edit)
+    }
+}
+
+impl From<EffectEdit> for SceneEdit {
+    fn from(edit: EffectEdit) -> Self {
+        SceneEdit::Effect(edit)
+    }
+}
+
+impl Edit for SceneEdit {
+    type Target = RecordState;
+    type Output = ();
+
+    fn edit(&mut self, record_state: &mut RecordState) {
+        match self {
+            SceneEdit::Polygon(edit) => edit.edit(record_state),
+            SceneEdit::Stroke(edit) => edit.edit(record_state),
+            SceneEdit::Image(edit) => edit.edit(record_state),
+            SceneEdit::Erase(edit) => edit.edit(record_state),
+            SceneEdit::Adjustment(edit) => edit.edit(record_state),
+            SceneEdit::Crop(edit) => edit.edit(record_state),
+            SceneEdit::Filter(edit) => edit.edit(record_state),
+            SceneEdit::Mask(edit) => edit.edit(record_state),
+            SceneEdit::Blend(edit) => edit.edit(record_state),
+            SceneEdit::Effect(edit) => edit.edit(record_state),
+        }
+    }
+
+    fn undo(&mut self, record_state: &mut RecordState) {
+        match self {
+            SceneEdit::Polygon(edit) => edit.undo(record_state),
+            SceneEdit::Stroke(edit) => edit.undo(record_state),
+            SceneEdit::Image(edit) => edit.undo(record_state),
+            SceneEdit::Erase(edit) => edit.undo(record_state),
+            SceneEdit::Adjustment(edit) => edit.undo(record_state),
+            SceneEdit::Crop(edit) => edit.undo(record_state),
+            SceneEdit::Filter(edit) => edit.undo(record_state),
+            SceneEdit::Mask(edit) => edit.undo(record_state),
+            SceneEdit::Blend(edit) => edit.undo(record_state),
+            SceneEdit::Effect(edit) => edit.undo(record_state),
+        }
+    }
+}
+
+pub struct EditorState {
+    pub editor: Arc<Mutex<Editor>>,
+    pub record: Arc<Mutex<Record<SceneEdit>>>,
+    pub record_state: RecordState,
+    pub polygon_selected: bool,
+    pub selected_polygon_id: Uuid,
+    pub value_signals: Arc<Mutex<HashMap<String, RwSignal<String>>>>,
+    pub current_modifiers: ModifiersState,
+    pub events: EditorEventSender,
+    pub invalidator: Invalidator,
+    pub brush_settings: BrushSettings,
+    pub brush_tool: BrushTool,
+    pub eraser_settings: EraserSettings,
+    // the eraser is drawn like a stroke while it's dragged
+    pub active_stroke: Option<ActiveStroke>,
+    pub last_cursor: Point,
+    // held space turns dragging on the canvas into panning
+    pub space_held: bool,
+    // keys go to the focused text input rather than the canvas
+    pub text_input_focused: bool,
+    // where the cursor was when the view was last panned, while it's dragged
+    pub pan_from: Option<Point>,
+    pub strokes: SharedStrokeLayers,
+    pub selected_stroke_id: Option<Uuid>,
+    pub images: SharedImageLayers,
+    pub selected_image_id: Option<Uuid>,
+    // the filter being tried on a photo, before it's applied or added as an effect
+    pub filter_preview: Option<(Uuid, Filter)>,
+    pub adjustments: SharedLayerAdjustments,
+    pub masks: SharedLayerMasks,
+    // the layer whose mask the brushes paint on instead of the canvas
+    pub mask_editing: Option<Uuid>,
+    pub blends: SharedLayerBlends,
+    pub effects: SharedShapeEffects,
+    pub asset_library: AssetLibrary,
+    // the asset tile being dragged toward the canvas
+    pub asset_drag: Option<Asset>,
+    pub preferences: SharedPreferences,
+    pub page: SharedPageSetup,
+}
+
+pub struct RecordState {
+    pub editor: Arc<Mutex<Editor>>,
+    // pub record: Arc<Mutex<Record<PolygonEdit>>>,
+    pub events: EditorEventSender,
+    pub invalidator: Invalidator,
+    pub strokes: SharedStrokeLayers,
+    pub images: SharedImageLayers,
+    pub adjustments: SharedLayerAdjustments,
+    pub filter_worker: FilterWorker,
+    pub masks: SharedLayerMasks,
+    pub blends: SharedLayerBlends,
+    pub effects: SharedShapeEffects,
+    // lengths in the inputs are shown in the page's unit
+    pub page: SharedPageSetup,
+}
+
+impl RecordState {
+    pub fn emit_polygon_updated(&self, polygon_id: Uuid, field_name: &str) {
+        let _ = self.events.send(EditorEvent::PolygonUpdated {
+            polygon_id,
+            field_name: field_name.to_string(),
+        });
+    }
+
+    pub fn emit_stroke_updated(&self, stroke_id: Uuid, field_name: &str) {
+        let _ = self.events.send(EditorEvent::StrokeUpdated {
+            stroke_id,
+            field_name: field_name.to_string(),
+        });
+    }
+
+    // the first piece keeps the original's id, so anything showing it needs a refresh
+    fn emit_swap_updated(&self, swap: &LayerSwap, original: &SceneLayer) {
+        if !swap.replacement_ids.contains(&swap.original_id) {
+            return;
+        }
+
+        match original {
+            SceneLayer::Polygon(_) => self.emit_polygon_updated(swap.original_id, "points"),
+            SceneLayer::Stroke(_) => self.emit_stroke_updated(swap.original_id, "samples"),
+            // the eraser leaves photos whole
+            SceneLayer::Image(_) => {}
+        }
+    }
+
+    pub fn emit_layer_changes(&self, changes: Vec<LayerChange>) {
+        if !changes.is_empty() {
+            let _ = self.events.send(EditorEvent::LayersChanged(changes));
+        }
+    }
+
+    // the Scene list's view of a layer, as it is now
+    fn layer(&self, editor: &Editor, layer_id: Uuid) -> Option<Layer> {
+        editor_layer(
+            editor,
+            &self.strokes.lock_or_recover(),
+            &self.images.lock_or_recover(),
+            &self.masks.lock_or_recover(),
+            &self.page.lock_or_recover(),
+            layer_id,
+        )
+    }
+
+    fn emit_layer_moved(&self, editor: &Editor, layer_id: Uuid) {
+        if let Some(layer) = self.layer(editor, layer_id) {
+            self.emit_layer_changes(vec![LayerChange::Moved {
+                id: layer_id,
+                artboard: layer.artboard,
+            }]);
+        }
+    }
+
+    fn emit_layer_linked(&self, layer_id: Uuid) {
+        let links = self.masks.lock_or_recover().links(layer_id);
+        self.emit_layer_changes(vec![LayerChange::Linked {
+            id: layer_id,
+            links,
+        }]);
+    }
+
+    // puts the layer back into the scene, on top unless a position is given
+    fn put_layer(&self, layer: SceneLayer, position: Option<usize>) {
+        let mut editor = self.editor.lock_or_recover();
+        let position = position
+            .unwrap_or(editor.layer_list.len())
+            .min(editor.layer_list.len());
+        let layer_id = layer.id();
+
+        editor.layer_list.insert(position, layer_id);
+
+        match layer {
+            SceneLayer::Polygon(polygon) => editor.polygons.push(polygon),
+            SceneLayer::Stroke(layer) => self.strokes.lock_or_recover().insert(layer),
+            SceneLayer::Image(layer) => self.images.lock_or_recover().insert(layer),
+        }
+
+        if let Some(layer) = self.layer(&editor, layer_id) {
+            self.emit_layer_changes(vec![LayerChange::Added {
+                index: position,
+                layer,
+            }]);
+        }
+    }
+
+    fn take_layer(&self, layer_id: Uuid) -> Option<(SceneLayer, usize)> {
+        let mut editor = self.editor.lock_or_recover();
+        let position = editor.layer_list.iter().position(|id| *id == layer_id)?;
+
+        let layer = match editor.polygons.iter().position(|p| p.id == layer_id) {
+            Some(index) => SceneLayer::Polygon(editor.polygons.remove(index)),
+            None => match self.strokes.lock_or_recover().remove(layer_id) {
+                Some(layer) => SceneLayer::Stroke(layer),
+                None => SceneLayer::Image(self.images.lock_or_recover().remove(layer_id)?),
+            },
+        };
+
+        editor.layer_list.remove(position);
+
+        self.emit_layer_changes(vec![LayerChange::Removed {
+            id: layer_id,
+            kind: layer.kind(),
+        }]);
+
+        Some((layer, position))
+    }
+
+    fn insert_stroke(&self, layer: StrokeLayer, position: Option<usize>) {
+        self.put_layer(SceneLayer::Stroke(layer), position);
+    }
+
+    fn remove_stroke(&self, stroke_id: Uuid) -> Option<(StrokeLayer, usize)> {
+        // checked first so nothing else is taken out and put back
+        if self.strokes.lock_or_recover().get(stroke_id).is_none() {
+            return None;
+        }
+
+        match self.take_layer(stroke_id)? {
+            (SceneLayer::Stroke(layer), position) => Some((layer, position)),
+            (layer, position) => {
+                // not a stroke after all, leave it where it was
+                self.put_layer(layer, Some(position));
+                None
+            }
+        }
+    }
+
+    fn remove_image(&self, image_id: Uuid) -> Option<(ImageLayer, usize)> {
+        if self.images.lock_or_recover().get(image_id).is_none() {
+            return None;
+        }
+
+        match self.take_layer(image_id)? {
+            (SceneLayer::Image(layer), position) => Some((layer, position)),
+            (layer, position) => {
+                // not a photo after all, leave it where it was
+                self.put_layer(layer, Some(position));
+                None
+            }
+        }
+    }
+
+    /// Runs the effects over the photo again on the worker, with the filter being previewed
+    /// run last. The pixels drawn until it's done are the last ones filtered.
+    pub fn render_filters(&self, image_id: Uuid, preview: Option<Filter>) {
+        let Some((source, width, height, filters)) =
+            self.images.lock_or_recover().get(image_id).map(|layer| {
+                (
+                    Arc::clone(&layer.source),
+                    layer.width,
+                    layer.height,
+                    layer.filters.clone(),
+                )
+            })
+        else {
+            return;
+        };
+
+        let chain: Vec<Filter> = filters.effects.iter().copied().chain(preview).collect();
+
+        if chain.is_empty() {
+            self.filter_worker.cancel(image_id);
+            if let Some(layer) = self.images.lock_or_recover().get_mut(image_id) {
+                layer.pixels = source;
+            }
+            self.invalidator.invalidate(Invalidation::Scene);
+            return;
+        }
+
+        let images = Arc::clone(&self.images);
+        let invalidator = self.invalidator.clone();
+        self.filter_worker.run(
+            image_id,
+            chain,
+            Arc::clone(&source),
+            width,
+            height,
+            move |filtered| {
+                let mut images = images.lock_or_recover();
+                let Some(layer) = images.get_mut(image_id) else {
+                    return;
+                };
+
+                // reloaded, or filtered differently, while this was running
+                if layer.filters != filters || !Arc::ptr_eq(&layer.source, &source) {
+                    return;
+                }
+
+                layer.pixels = Arc::new(filtered);
+                invalidator.invalidate(Invalidation::Scene);
+            },
+        );
+    }
+
+    fn update_stroke(&self, stroke_id: Uuid, value: &StrokeProperty) {
+        let editor = self.editor.lock_or_recover();
+        let viewport = editor.viewport.lock_or_recover();
+        let window_size = WindowSize {
+            width: viewport.width as u32,
+            height: viewport.height as u32,
+        };
+
+        if let Some(layer) = self.strokes.lock_or_recover().get_mut(stroke_id) {
+            value.apply(&mut layer.settings);
+            layer.rebuild(&window_size);
+        }
+    }
+}
+
+impl EditorState {
+    pub fn new(
+        editor: Arc<Mutex<Editor>>,
+        record: Arc<Mutex<Record<SceneEdit>>>,
+        events: EditorEventSender,
+        invalidator: Invalidator,
+        strokes: SharedStrokeLayers,
+        images: SharedImageLayers,
+        adjustments: SharedLayerAdjustments,
+        masks: SharedLayerMasks,
+        blends: SharedLayerBlends,
+        effects: SharedShapeEffects,
+        preferences: SharedPreferences,
+        page: SharedPageSetup,
+    ) -> Self {
+        Self {
+            editor: Arc::clone(&editor),
+            record: Arc::clone(&record),
+            record_state: RecordState {
+                editor: Arc::clone(&editor),
+                // record: Arc::clone(&record),
+                events: events.clone(),
+                invalidator: invalidator.clone(),
+                strokes: Arc::clone(&strokes),
+                images: Arc::clone(&images),
+                adjustments: Arc::clone(&adjustments),
+                filter_worker: FilterWorker::new(),
+                masks: Arc::clone(&masks),
+                blends: Arc::clone(&blends),
+                effects: Arc::clone(&effects),
+                page: Arc::clone(&page),
+            },
+            polygon_selected: false,
+            selected_polygon_id: Uuid::nil(),
+            value_signals: Arc::new(Mutex::new(HashMap::new())),
+            current_modifiers: ModifiersState::empty(),
+            events,
+            invalidator,
+            brush_settings: BrushSettings::default(),
+            brush_tool: BrushTool::Paint,
+            eraser_settings: EraserSettings::default(),
+            active_stroke: None,
+            last_cursor: Point { x: 0.0, y: 0.0 },
+            space_held: false,
+            text_input_focused: false,
+            pan_from: None,
+            strokes,
+            selected_stroke_id: None,
+            images,
+            selected_image_id: None,
+            filter_preview: None,
+            adjustments,
+            masks,
+            mask_editing: None,
+            blends,
+            effects,
+            asset_library: AssetLibrary::new(AssetLibrary::default_root()),
+            asset_drag: None,
+            preferences,
+            page,
+        }
+    }
+
+    // Scene list mounts with the editor's current layers, then only receives changes
+    // Must not be called while the editor is locked
+    pub fn scene_layers(&self) -> Vec<Layer> {
+        let editor = self.editor.lock_or_recover();
+        let strokes = self.strokes.lock_or_recover();
+        let images = self.images.lock_or_recover();
+        let masks = self.masks.lock_or_recover();
+        let page = self.page.lock_or_recover();
+
+        editor_layers(&editor, &strokes, &images, &masks, &page)
+    }
+
+    /// Lists a polygon the editor added by itself, e.g. from the shape buttons
+    // Must not be called while the editor is locked
+    pub fn polygon_added(&self, polygon_id: Uuid) {
+        let editor = self.editor.lock_or_recover();
+
+        // built only to be held by an edit, which lists it once it's applied
+        let Some(index) = editor.layer_list.iter().position(|id| *id == polygon_id) else {
+            return;
+        };
+
+        if let Some(layer) = self.record_state.layer(&editor, polygon_id) {
+            self.record_state
+                .emit_layer_changes(vec![LayerChange::Added { index, layer }]);
+        }
+    }
+
+    /// Lets go of whatever was selected or being worked on in a layer that left the scene
+    pub fn layer_removed(&mut self, layer_id: Uuid, kind: LayerKind) {
+        // a mask can't be painted on once its layer is gone
+        if self.mask_editing == Some(layer_id) {
+            self.mask_editing = None;
+            let _ = self.events.send(EditorEvent::MaskChanged(layer_id));
+        }
+
+        match kind {
+            LayerKind::Polygon => {
+                let _ = self.events.send(EditorEvent::PolygonRemoved(layer_id));
+            }
+            LayerKind::Stroke => {
+                if self.selected_stroke_id == Some(layer_id) {
+                    let _ = self.events.send(EditorEvent::StrokeSelectionChanged(None));
+                }
+            }
+            LayerKind::Image => {
+                if self.selected_image_id == Some(layer_id) {
+                    let _ = self.events.send(EditorEvent::ImageSelectionChanged(None));
+                }
+                if self.cropping() == Some(layer_id) {
+                    self.set_cropping(None);
+                }
+                if self
+                    .filter_preview
+                    .is_some_and(|(image_id, _)| image_id == layer_id)
+                {
+                    self.filter_preview = None;
+                }
+            }
+        }
+    }
+
+    /// Moves a layer to where another one is in the stack, as the Scene list is dragged
+    // Must not be called while the editor is locked
+    pub fn reorder_layer(&self, layer_id: Uuid, onto_id: Uuid) {
+        let order = {
+            let mut editor = self.editor.lock_or_recover();
+            let from = editor.layer_list.iter().position(|id| *id == layer_id);
+            let to = editor.layer_list.iter().position(|id| *id == onto_id);
+
+            let (Some(from), Some(to)) = (from, to) else {
+                return;
+            };
+
+            let layer_id = editor.layer_list.remove(from);
+            editor.layer_list.insert(to, layer_id);
+
+            editor.layer_list.clone()
+        };
+
+        self.record_state
+            .emit_layer_changes(vec![LayerChange::Reordered { order }]);
+        self.invalidator.invalidate(Invalidation::Scene);
+    }
+
+    pub fn emit_history_changed(&self, record: &Record<SceneEdit>) {
+        let _ = self.events.send(EditorEvent::HistoryChanged {
+            can_undo: record.can_undo(),
+            can_redo: record.can_redo(),
+        });
+    }
+
+    pub fn apply_edit(&mut self, edit: impl Into<SceneEdit>) {
+        let mut record = self.record.lock_or_recover();
+        record.edit(&mut self.record_state, edit.into());
+
+        self.emit_history_changed(&record);
+    }
+
+    // Helper method to register a new signal
+    pub fn register_signal(&mut self, name: String, signal: RwSignal<String>) {
+        let selected_id = self
+            .selected_stroke_id
+            .or(self.selected_image_id)
+            .unwrap_or(self.selected_polygon_id);
+
+        let mut signals = self.value_signals.lock_or_recover();
+        signals.insert(name + &selected_id.to_string(), signal);
+    }
+
+    /// Selects whatever the layer is, as if it had been clicked on the canvas
+    pub fn select_layer(&self, layer_id: Uuid) {
+        let polygon = {
+            let editor = self.editor.lock_or_recover();
+            editor
+                .polygons
+                .iter()
+                .find(|polygon| polygon.id == layer_id)
+                .map(|polygon| polygon.to_config())
+        };
+
+        let is_image = self.images.lock_or_recover().get(layer_id).is_some();
+
+        let event = match polygon {
+            Some(config) => EditorEvent::SelectionChanged(Some((layer_id, config))),
+            None if is_image => EditorEvent::ImageSelectionChanged(Some(layer_id)),
+            None => EditorEvent::StrokeSelectionChanged(Some(layer_id)),
+        };
+
+        let _ = self.events.send(event);
+    }
+
+    /// What the stroke being dragged is drawn with, the eraser shows its own path
+    pub fn stroke_settings(&self) -> BrushSettings {
+        match self.brush_tool {
+            BrushTool::Paint => self.brush_settings,
+            BrushTool::Erase => self.eraser_settings.preview(),
+        }
+    }
+
+    /// Turns the stroke that was just drawn into a layer, through the history
+    // Must not be called while the editor is locked
+    pub fn finish_stroke(&mut self, active: ActiveStroke, window_size: &WindowSize) {
+        if let Some(layer_id) = self.mask_editing {
+            self.finish_mask_stroke(layer_id, active);
+            return;
+        }
+
+        let settings = self.brush_settings;
+        let samples = simplify(&active.finish(), settings.smoothing.simplify_tolerance());
+
+        if samples.is_empty() {
+            return;
+        }
+
+        let name = self.strokes.lock_or_recover().next_name();
+        let layer = StrokeLayer::new(name, settings, samples, window_size);
+
+        self.apply_edit(StrokeEdit::Create {
+            stroke_id: layer.id,
+            layer: Some(layer),
+        });
+    }
+
+    /// Cuts whatever the eraser went over, as a single step in the history
+    // Must not be called while the editor is locked
+    pub fn finish_erase(
+        &mut self,
+        active: ActiveStroke,
+        device: &wgpu::Device,
+        window_size: &WindowSize,
+    ) {
+        if let Some(layer_id) = self.mask_editing {
+            self.finish_mask_stroke(layer_id, active);
+            return;
+        }
+
+        let path: Vec<[f32; 2]> = active
+            .finish()
+            .iter()
+            .map(|sample| [sample.x, sample.y])
+            .collect();
+
+        if path.is_empty() {
+            return;
+        }
+
+        let radius = self.eraser_settings.size / 2.0;
+
+        let swaps = match self.eraser_settings.mode {
+            EraserMode::Vector => {
+                let mut strokes = self.strokes.lock_or_recover();
+
+                let cut: Vec<(StrokeLayer, Vec<_>)> = strokes
+                    .iter()
+                    .filter_map(|layer| {
+                        erase_stroke(layer, &path, radius).map(|pieces| (layer.clone(), pieces))
+                    })
+                    .collect();
+
+                cut.into_iter()
+                    .map(|(original, pieces)| {
+                        let replacements = pieces
+                            .into_iter()
+                            .enumerate()
+                            .map(|(index, samples)| {
+                                let mut piece = StrokeLayer::new(
+                                    original.name.clone(),
+                                    original.settings,
+                                    samples,
+                                    window_size,
+                                );
+
+                                // the first piece carries on as the original stroke
+                                if index == 0 {
+                                    piece.id = original.id;
+                                } else {
+                                    piece.name = strokes.next_name();
+                                }
+
+                                SceneLayer::Stroke(piece)
+                            })
+                            .collect();
+
+                        LayerSwap::new(original.id, replacements)
+                    })
+                    .collect::<Vec<_>>()
+            }
+            EraserMode::Shape => {
+                let mut editor = self.editor.lock_or_recover();
+                let camera = editor.camera.expect("Couldn't get camera");
+
+                let cuts = editor
+                    .polygons
+                    .iter()
+                    .filter_map(|polygon| {
+                        let config = polygon.to_config();
+                        let pieces = subtract_path(&polygon_outline(&config), &path, radius)?;
+                        Some((config, pieces))
+                    })
+                    .collect::<Vec<_>>();
+
+                cuts.into_iter()
+                    .map(|(config, pieces)| {
+                        let replacements = pieces
+                            .into_iter()
+                            .enumerate()
+                            .map(|(index, piece)| {
+                                let name = if index == 0 {
+                                    config.name.clone()
+                                } else {
+                                    format!("{} {}", config.name, index + 1)
+                                };
+
+                                let polygon = Polygon::new(
+                                    window_size,
+                                    device,
+                                    &camera,
+                                    piece.points,
+                                    piece.dimensions,
+                                    piece.position,
+                                    config.border_radius,
+                                    config.fill,
+                                    name,
+                                );
+
+                                // Polygon::new has no stroke, so the piece passes through the
+                                // editor under its own id to pick up the original's
+                                let piece_id = polygon.id;
+                                editor.add_polygon(polygon);
+                                set_polygon_stroke(&mut editor, piece_id, &config.stroke);
+                                editor.layer_list.retain(|id| *id != piece_id);
+                                let index_in_editor = editor
+                                    .polygons
+                                    .iter()
+                                    .rposition(|polygon| polygon.id == piece_id)
+                                    .expect("Couldn't find erased piece");
+                                let mut polygon = editor.polygons.remove(index_in_editor);
+
+                                // the largest piece carries on as the original polygon
+                                if index == 0 {
+                                    polygon.id = config.id;
+                                }
+
+                                SceneLayer::Polygon(polygon)
+                            })
+                            .collect();
+
+                        LayerSwap::new(config.id, replacements)
+                    })
+                    .collect::<Vec<_>>()
+            }
+        };
+
+        if swaps.is_empty() {
+            return;
+        }
+
+        self.apply_edit(EraseEdit { swaps });
+    }
+
+    /// Adds a photo on top of the scene and selects it. Its metadata is read on the way
+    /// in and the pixels turned upright to match.
+    // Must not be called while the editor is locked
+    pub fn import_image(
+        &mut self,
+        path: PathBuf,
+        center: [f32; 2],
+        window_size: &WindowSize,
+    ) -> Result<(), String> {
+        let layer = ImageLayer::open(path, center, window_size)?;
+        let image_id = layer.id;
+
+        self.apply_edit(ImageEdit::Create {
+            image_id,
+            layer: Some(layer),
+        });
+        let _ = self
+            .events
+            .send(EditorEvent::ImageSelectionChanged(Some(image_id)));
+
+        Ok(())
+    }
+
+    pub fn delete_selected_image(&mut self) {
+        let Some(image_id) = self.selected_image_id else {
+            return;
+        };
+
+        self.apply_edit(ImageEdit::Delete {
+            image_id,
+            layer: None,
+            position: None,
+        });
+    }
+
+    pub fn image_metadata(&self, image_id: Uuid) -> PhotoMetadata {
+        self.images
+            .lock_or_recover()
+            .get(image_id)
+            .map(|layer| layer.metadata.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn image_crop(&self, image_id: Uuid) -> CropParams {
+        self.images
+            .lock_or_recover()
+            .get(image_id)
+            .map(|layer| layer.crop)
+            .unwrap_or_default()
+    }
+
+    /// The photo being cropped on the canvas
+    pub fn cropping(&self) -> Option<Uuid> {
+        self.images
+            .lock_or_recover()
+            .crop_tool
+            .map(|tool| tool.image_id)
+    }
+
+    /// Starts or stops cropping on the canvas, which shows the crop's guides and turns
+    /// dragging into drawing a line to straighten along. One photo is cropped at a time.
+    pub fn set_cropping(&self, image_id: Option<Uuid>) {
+        let previous = {
+            let mut images = self.images.lock_or_recover();
+            let previous = images.crop_tool.map(|tool| tool.image_id);
+            images.crop_tool = image_id.map(|image_id| CropTool {
+                image_id,
+                straighten: None,
+            });
+            previous
+        };
+
+        for id in [previous, image_id].into_iter().flatten() {
+            let _ = self.events.send(EditorEvent::CropChanged(id));
+        }
+        self.invalidator.invalidate(Invalidation::Overlay);
+    }
+
+    /// Stops cropping once the photo is no longer the one selected
+    pub fn stop_cropping_unselected(&self) {
+        if self
+            .cropping()
+            .is_some_and(|image_id| self.selected_image_id != Some(image_id))
+        {
+            self.set_cropping(None);
+        }
+    }
+
+    /// Starts the line to straighten along, in scene pixels. False when nothing is
+    /// being cropped, so the click is left to the canvas.
+    pub fn begin_straighten(&self, point: [f32; 2]) -> bool {
+        let mut images = self.images.lock_or_recover();
+        let Some(tool) = images.crop_tool.as_mut() else {
+            return false;
+        };
+
+        tool.straighten = Some([point, point]);
+        self.invalidator.invalidate(Invalidation::Overlay);
+
+        true
+    }
+
+    /// Follows the cursor with the end of the straighten line, if one is being drawn
+    pub fn drag_straighten(&self, point: [f32; 2]) -> bool {
+        let mut images = self.images.lock_or_recover();
+        let Some(line) = images
+            .crop_tool
+            .as_mut()
+            .and_then(|tool| tool.straighten.as_mut())
+        else {
+            return false;
+        };
+
+        line[1] = point;
+        self.invalidator.invalidate(Invalidation::Overlay);
+
+        true
+    }
+
+    /// Levels the photo along the line that was drawn. Clicks too short to have a
+    /// direction leave it as it is.
+    pub fn finish_straighten(&mut self) -> bool {
+        let (image_id, line) = {
+            let mut images = self.images.lock_or_recover();
+            let Some(tool) = images.crop_tool.as_mut() else {
+                return false;
+            };
+
+            (tool.image_id, tool.straighten.take())
+        };
+        self.invalidator.invalidate(Invalidation::Overlay);
+
+        if let Some([start, end]) = line {
+            if (end[0] - start[0]).hypot(end[1] - start[1]) >= MIN_STRAIGHTEN_LENGTH {
+                self.update_crop(image_id, |crop, width, height| {
+                    crop.straighten([start, end], width, height)
+                });
+            }
+        }
+
+        true
+    }
+
+    fn update_crop(&mut self, image_id: Uuid, update: impl FnOnce(&mut CropParams, u32, u32)) {
+        let Some((old_value, width, height)) = self
+            .images
+            .lock_or_recover()
+            .get(image_id)
+            .map(|layer| (layer.crop, layer.width, layer.height))
+        else {
+            return;
+        };
+
+        // a photo whose file has gone has no size to crop to
+        if width == 0 || height == 0 {
+            return;
+        }
+
+        let mut new_value = old_value;
+        update(&mut new_value, width, height);
+
+        if new_value == old_value {
+            return;
+        }
+
+        let edit = CropEdit {
+            image_id,
+            old_value,
+            new_value,
+            signal: self
+                .value_signals
+                .lock_or_recover()
+                .get(&format!("crop_rotation{}", image_id))
+                .cloned(),
+        };
+
+        self.apply_edit(edit);
+    }
+
+    pub fn apply_crop_preset(&mut self, image_id: Uuid, preset: AspectPreset) {
+        self.update_crop(image_id, |crop, width, height| {
+            crop.apply_preset(preset, width, height)
+        });
+    }
+
+    pub fn rotate_crop(&mut self, image_id: Uuid, degrees: f32) {
+        self.update_crop(image_id, |crop, width, height| {
+            crop.rotate_by(degrees, width, height)
+        });
+    }
+
+    pub fn flip_crop(&mut self, image_id: Uuid, horizontal: bool) {
+        self.update_crop(image_id, |crop, _, _| crop.flip(horizontal));
+    }
+
+    pub fn reset_crop(&mut self, image_id: Uuid) {
+        self.update_crop(image_id, |crop, _, _| *crop = CropParams::default());
+    }
+
+    /// Turns the selected photo to the rotation entered, in degrees clockwise
+    pub fn update_crop_rotation(&mut self, new_rotation_str: &str) -> Result<(), String> {
+        let new_rotation =
+            string_to_f32(new_rotation_str).map_err(|_| "Couldn't convert string to f32")?;
+        let Some(image_id) = self.selected_image_id else {
+            return Ok(());
+        };
+
+        self.update_crop(image_id, |crop, width, height| {
+            crop.rotation = new_rotation.rem_euclid(360.0);
+            crop.fit_rotation(width, height);
+        });
+
+        Ok(())
+    }
+
+    pub fn image_filters(&self, image_id: Uuid) -> LayerFilters {
+        self.images
+            .lock_or_recover()
+            .get(image_id)
+            .map(|layer| layer.filters.clone())
+            .unwrap_or_default()
+    }
+
+    /// The filter being tried on the photo, if it's the one being previewed
+    pub fn filter_preview(&self, image_id: Uuid) -> Option<Filter> {
+        self.filter_preview
+            .filter(|(id, _)| *id == image_id)
+            .map(|(_, filter)| filter)
+    }
+
+    /// Tries a filter on the photo with its default settings. The photo shows it while
+    /// the settings are changed, nothing is kept until it's applied.
+    pub fn start_filter_preview(&mut self, image_id: Uuid, kind: FilterKind) {
+        self.stop_filter_preview();
+
+        let filter = kind.default_filter();
+        self.filter_preview = Some((image_id, filter));
+        self.record_state.render_filters(image_id, Some(filter));
+
+        let _ = self.events.send(EditorEvent::FiltersChanged(image_id));
+    }
+
+    pub fn update_filter_field(
+        &mut self,
+        image_id: Uuid,
+        field: FilterField,
+        new_value_str: &str,
+    ) -> Result<(), String> {
+        let new_value =
+            string_to_f32(new_value_str).map_err(|_| "Couldn't convert string to f32")?;
+        let Some((_, filter)) = self
+            .filter_preview
+            .as_mut()
+            .filter(|(id, _)| *id == image_id)
+        else {
+            return Ok(());
+        };
+
+        field.apply(filter, new_value);
+        let filter = *filter;
+        self.record_state.render_filters(image_id, Some(filter));
+
+        Ok(())
+    }
+
+    /// Puts the photo back as it was before the preview
+    pub fn stop_filter_preview(&mut self) {
+        let Some((image_id, _)) = self.filter_preview.take() else {
+            return;
+        };
+
+        self.record_state.render_filters(image_id, None);
+        let _ = self.events.send(EditorEvent::FiltersChanged(image_id));
+    }
+
+    /// Stops the preview once its photo is no longer the one selected
+    pub fn stop_filter_preview_unselected(&mut self) {
+        if self
+            .filter_preview
+            .is_some_and(|(image_id, _)| self.selected_image_id != Some(image_id))
+        {
+            self.stop_filter_preview();
+        }
+    }
+
+    /// Keeps the previewed filter, baked into the photo's pixels or as an effect that can
+    /// be taken off later. Baking runs on the worker and goes through the history once
+    /// it's done, see commit_baked_filter.
+    pub fn apply_filter_preview(&mut self, image_id: Uuid, mode: FilterMode) {
+        let Some(filter) = self.filter_preview(image_id) else {
+            return;
+        };
+        self.filter_preview = None;
+
+        match mode {
+            FilterMode::Effect => {
+                let old_value = self.image_filters(image_id);
+                let mut new_value = old_value.clone();
+                new_value.effects.push(filter);
+
+                self.apply_edit(FilterEdit {
+                    image_id,
+                    old_value,
+                    new_value,
+                    sources: None,
+                });
+            }
+            FilterMode::Destructive => {
+                self.bake_filter(image_id, filter);
+                let _ = self.events.send(EditorEvent::FiltersChanged(image_id));
+            }
+        }
+    }
+
+    // the preview stays on the canvas until the baked pixels replace it
+    fn bake_filter(&self, image_id: Uuid, filter: Filter) {
+        let Some((source, width, height)) = self
+            .images
+            .lock_or_recover()
+            .get(image_id)
+            .map(|layer| (Arc::clone(&layer.source), layer.width, layer.height))
+        else {
+            return;
+        };
+
+        let events = self.events.clone();
+        // a run of its own, so trying the next filter doesn't cancel it
+        self.record_state.filter_worker.run(
+            Uuid::new_v4(),
+            vec![filter],
+            Arc::clone(&source),
+            width,
+            height,
+            move |baked| {
+                let _ = events.send(EditorEvent::FilterBaked {
+                    image_id,
+                    filter,
+                    source,
+                    baked: Arc::new(baked),
+                });
+            },
+        );
+    }
+
+    /// Replaces the photo's source pixels with the ones the filter was baked into, as an
+    /// edit whose undo puts the old pixels back
+    pub fn commit_baked_filter(
+        &mut self,
+        image_id: Uuid,
+        filter: Filter,
+        source: Arc<Vec<u8>>,
+        baked: Arc<Vec<u8>>,
+    ) {
+        let Some(old_value) = self
+            .images
+            .lock_or_recover()
+            .get(image_id)
+            // deleted, reloaded or baked again while this was running
+            .filter(|layer| Arc::ptr_eq(&layer.source, &source))
+            .map(|layer| layer.filters.clone())
+        else {
+            return;
+        };
+
+        let mut new_value = old_value.clone();
+        new_value.baked.push(filter);
+
+        self.apply_edit(FilterEdit {
+            image_id,
+            old_value,
+            new_value,
+            sources: Some((source, baked)),
+        });
+
+        // another filter may have been tried while this one was baking
+        if let Some(preview) = self.filter_preview(image_id) {
+            self.record_state.render_filters(image_id, Some(preview));
+        }
+    }
+
+    pub fn remove_filter_effect(&mut self, image_id: Uuid, index: usize) {
+        let old_value = self.image_filters(image_id);
+        if index >= old_value.effects.len() {
+            return;
+        }
+
+        let mut new_value = old_value.clone();
+        new_value.effects.remove(index);
+
+        self.apply_edit(FilterEdit {
+            image_id,
+            old_value,
+            new_value,
+            sources: None,
+        });
+    }
+
+    /// Paints the stroke onto the mask being edited: brushes show the layer again,
+    /// the eraser hides it
+    // Must not be called while the editor is locked
+    fn finish_mask_stroke(&mut self, layer_id: Uuid, active: ActiveStroke) {
+        let settings = self.stroke_settings();
+        let path: Vec<([f32; 2], f32)> = active
+            .finish()
+            .iter()
+            .map(|sample| {
+                let radius = settings.size / 2.0 * settings.pressure_curve.size_scale(sample);
+                ([sample.x, sample.y], radius)
+            })
+            .collect();
+
+        if path.is_empty() {
+            return;
+        }
+
+        let reveal = self.brush_tool == BrushTool::Paint;
+
+        // painted per pixel, a soft or faint brush leaves the layer partly showing
+        self.update_mask_state(layer_id, |state| {
+            let mask = state.mask.get_or_insert_with(LayerMask::default);
+            mask.painted.paint(&path, settings.opacity, reveal);
+        });
+    }
+
+    fn update_mask_state(&mut self, layer_id: Uuid, update: impl FnOnce(&mut MaskState)) {
+        let old_state = self.masks.lock_or_recover().state(layer_id);
+        let mut new_state = old_state.clone();
+        update(&mut new_state);
+
+        if new_state == old_state {
+            return;
+        }
+
+        self.apply_edit(MaskEdit::new(layer_id, old_state, new_state));
+    }
+
+    /// What the mask section of the properties panel shows: links, whether the mask
+    /// is enabled and whether it's being painted on
+    pub fn mask_status(&self, layer_id: Uuid) -> (LayerLinks, bool, bool) {
+        let masks = self.masks.lock_or_recover();
+        let enabled = masks.get(layer_id).is_some_and(|mask| mask.enabled);
+
+        (
+            masks.links(layer_id),
+            enabled,
+            self.mask_editing == Some(layer_id),
+        )
+    }
+
+    pub fn add_mask(&mut self, layer_id: Uuid) {
+        self.update_mask_state(layer_id, |state| {
+            state.mask.get_or_insert_with(LayerMask::default);
+        });
+    }
+
+    pub fn delete_mask(&mut self, layer_id: Uuid) {
+        if self.mask_editing == Some(layer_id) {
+            self.set_mask_editing(None);
+        }
+
+        self.update_mask_state(layer_id, |state| state.mask = None);
+    }
+
+    pub fn toggle_mask_enabled(&mut self, layer_id: Uuid) {
+        self.update_mask_state(layer_id, |state| {
+            if let Some(mask) = &mut state.mask {
+                mask.enabled = !mask.enabled;
+            }
+        });
+    }
+
+    pub fn toggle_clipping(&mut self, layer_id: Uuid) {
+        self.update_mask_state(layer_id, |state| state.clipped = !state.clipped);
+    }
+
+    /// Points the brushes at the layer's mask, or back at the canvas with None
+    pub fn set_mask_editing(&mut self, layer_id: Option<Uuid>) {
+        let previous = std::mem::replace(&mut self.mask_editing, layer_id);
+
+        for id in [previous, layer_id].into_iter().flatten() {
+            let _ = self.events.send(EditorEvent::MaskChanged(id));
+        }
+    }
+
+    /// Turns the layer just above into this layer's vector mask. The shape leaves the
+    /// scene and comes back on undo.
+    // Must not be called while the editor is locked
+    pub fn mask_with_layer_above(&mut self, layer_id: Uuid) {
+        let above = {
+            let editor = self.editor.lock_or_recover();
+            let strokes = self.strokes.lock_or_recover();
+            let images = self.images.lock_or_recover();
+
+            editor
+                .layer_list
+                .iter()
+                .position(|id| *id == layer_id)
+                .and_then(|position| editor.layer_list.get(position + 1))
+                .and_then(|above_id| {
+                    let shape = match editor.polygons.iter().find(|p| p.id == *above_id) {
+                        Some(polygon) => LayerShape::Polygon(polygon),
+                        None => match strokes.get(*above_id) {
+                            Some(stroke) => LayerShape::Stroke(stroke),
+                            None => LayerShape::Image(images.get(*above_id)?),
+                        },
+                    };
+
+                    Some((*above_id, shape.outline()))
+                })
+        };
+
+        let Some((above_id, outline)) = above else {
+            return;
+        };
+
+        let old_state = self.masks.lock_or_recover().state(layer_id);
+        let mut new_state = old_state.clone();
+        new_state.mask.get_or_insert_with(LayerMask::default).shape = Some(outline);
+
+        self.apply_edit(MaskEdit {
+            consumed_id: Some(above_id),
+            ..MaskEdit::new(layer_id, old_state, new_state)
+        });
+    }
+
+    pub fn layer_blend(&self, layer_id: Uuid) -> LayerBlend {
+        self.blends.lock_or_recover().get(layer_id)
+    }
+
+    fn update_layer_blend(&mut self, layer_id: Uuid, update: impl FnOnce(&mut LayerBlend)) {
+        let old_value = self.layer_blend(layer_id);
+        let mut new_value = old_value;
+        update(&mut new_value);
+
+        if new_value == old_value {
+            return;
+        }
+
+        let edit = BlendEdit {
+            layer_id,
+            old_value,
+            new_value,
+            signal: self
+                .value_signals
+                .lock_or_recover()
+                .get(&format!("layer_opacity{}", layer_id))
+                .cloned(),
+        };
+
+        self.apply_edit(edit);
+    }
+
+    pub fn set_blend_mode(&mut self, layer_id: Uuid, mode: BlendMode) {
+        self.update_layer_blend(layer_id, |blend| blend.mode = mode);
+    }
+
+    /// Opacity of the selected stroke, photo or polygon as a whole
+    pub fn update_layer_opacity(&mut self, new_opacity_str: &str) -> Result<(), String> {
+        let new_opacity =
+            string_to_f32(new_opacity_str).map_err(|_| "Couldn't convert string to f32")?;
+        let layer_id = self
+            .selected_stroke_id
+            .or(self.selected_image_id)
+            .unwrap_or(self.selected_polygon_id);
+
+        // entered as a percentage
+        self.update_layer_blend(layer_id, |blend| {
+            blend.opacity = (new_opacity / 100.0).clamp(0.0, 1.0)
+        });
+
+        Ok(())
+    }
+
+    pub fn shape_effects(&self, layer_id: Uuid) -> Vec<ShapeEffect> {
+        self.effects.lock_or_recover().get(layer_id).to_vec()
+    }
+
+    fn update_effects(&mut self, layer_id: Uuid, update: impl FnOnce(&mut Vec<ShapeEffect>)) {
+        let old_stack = self.shape_effects(layer_id);
+        let mut new_stack = old_stack.clone();
+        update(&mut new_stack);
+
+        if new_stack == old_stack {
+            return;
+        }
+
+        // only fields that changed, so the input being typed in isn't rewritten
+        let signals = {
+            let value_signals = self.value_signals.lock_or_recover();
+
+            new_stack
+                .iter()
+                .zip(&old_stack)
+                .enumerate()
+                .flat_map(|(index, (new, old))| {
+                    EffectField::iter()
+                        .filter(move |field| field.read(new) != field.read(old))
+                        .map(move |field| (index, field))
+                })
+                .filter_map(|(index, field)| {
+                    let name = format!("{}{}", field.signal_name(index), layer_id);
+                    value_signals
+                        .get(&name)
+                        .map(|signal| (index, field, *signal))
+                })
+                .collect()
+        };
+
+        self.apply_edit(EffectEdit {
+            layer_id,
+            old_stack,
+            new_stack,
+            signals,
+        });
+    }
+
+    pub fn add_effect(&mut self, layer_id: Uuid, kind: EffectKind) {
+        self.update_effects(layer_id, |stack| stack.push(ShapeEffect::new(kind)));
+    }
+
+    pub fn remove_effect(&mut self, layer_id: Uuid, index: usize) {
+        self.update_effects(layer_id, |stack| {
+            if index < stack.len() {
+                stack.remove(index);
+            }
+        });
+    }
+
+    pub fn toggle_effect(&mut self, layer_id: Uuid, index: usize) {
+        self.update_effects(layer_id, |stack| {
+            if let Some(effect) = stack.get_mut(index) {
+                effect.enabled = !effect.enabled;
+            }
+        });
+    }
+
+    pub fn update_effect_field(
+        &mut self,
+        layer_id: Uuid,
+        index: usize,
+        field: EffectField,
+        new_value_str: &str,
+    ) -> Result<(), String> {
+        let new_value =
+            string_to_f32(new_value_str).map_err(|_| "Couldn't convert string to f32")?;
+
+        self.update_effects(layer_id, |stack| {
+            if let Some(effect) = stack.get_mut(index) {
+                field.apply(effect, new_value);
+            }
+        });
+
+        Ok(())
+    }
+
+    pub fn set_asset_directory(&mut self, directory: &str) -> Result<(), String> {
+        let root = PathBuf::from(directory.trim());
+
+        if !root.is_dir() {
+            return Err("Couldn't find asset directory".to_string());
+        }
+
+        self.asset_library = AssetLibrary::new(root);
+        let _ = self.events.send(EditorEvent::AssetsChanged);
+
+        Ok(())
+    }
+
+    /// Places an SVG or saved shape as polygons, or a photo as an image layer, centred
+    /// on the cursor
+    // Must not be called while the editor is locked
+    pub fn drop_asset(
+        &mut self,
+        asset: &Asset,
+        device: &wgpu::Device,
+        window_size: &WindowSize,
+    ) -> Result<(), String> {
+        // dropped where the cursor is over the scene, however the view is zoomed
+        let cursor = self
+            .view()
+            .screen_to_scene([self.last_cursor.x, self.last_cursor.y]);
+
+        if asset.kind == AssetKind::Image {
+            return self.import_image(asset.path.clone(), cursor, window_size);
+        }
+
+        let text = fs::read_to_string(&asset.path).map_err(|_| "Couldn't read asset")?;
+        let shapes = parse_shapes(&text);
+
+        if shapes.is_empty() {
+            return Err("Couldn't find any filled shapes in asset".to_string());
+        }
+
+        let (min, max) = bounds(shapes.iter().flat_map(|shape| shape.outline.iter()));
+
+        // the asset keeps its layout, moved so its middle is under the cursor
+        let offset = [
+            cursor[0] - (min[0] + max[0]) / 2.0,
+            cursor[1] - (min[1] + max[1]) / 2.0,
+        ];
+
+        let mut editor = self.editor.lock_or_recover();
+        let camera = editor.camera.expect("Couldn't get camera");
+
+        for (index, shape) in shapes.iter().enumerate() {
+            let (shape_min, shape_max) = bounds(shape.outline.iter());
+            let width = (shape_max[0] - shape_min[0]).max(1.0);
+            let height = (shape_max[1] - shape_min[1]).max(1.0);
+
+            let name = match &shape.name {
+                Some(name) => name.clone(),
+                None if shapes.len() == 1 => asset.name.clone(),
+                None => format!("{} {}", asset.name, index + 1),
+            };
+
+            editor.add_polygon(Polygon::new(
+                window_size,
+                device,
+                &camera,
+                shape
+                    .outline
+                    .iter()
+                    .map(|[x, y]| Point {
+                        x: (x - shape_min[0]) / width,
+                        y: (y - shape_min[1]) / height,
+                    })
+                    .collect(),
+                (width, height),
+                Point {
+                    x: shape_min[0] + offset[0],
+                    y: shape_min[1] + offset[1],
+                },
+                shape.border_radius,
+                shape.fill,
+                name,
+            ));
+        }
+
+        drop(editor);
+        self.invalidator.invalidate(Invalidation::Scene);
+
+        Ok(())
+    }
+
+    /// Writes the polygon and its effects to the top of the asset library
+    pub fn save_shape_asset(&self, polygon_id: Uuid) -> Result<(), String> {
+        let config = {
+            let editor = self.editor.lock_or_recover();
+            editor
+                .polygons
+                .iter()
+                .find(|polygon| polygon.id == polygon_id)
+                .map(|polygon| polygon.to_config())
+                .ok_or("Couldn't find polygon")?
+        };
+        let effects = self.shape_effects(polygon_id);
+
+        fs::create_dir_all(&self.asset_library.root)
+            .map_err(|_| "Couldn't create asset directory")?;
+
+        let path = self
+            .asset_library
+            .unused_path(&config.name, SHAPE_EXTENSION);
+        fs::write(&path, shape_svg(&config, &effects)).map_err(|_| "Couldn't write asset")?;
+
+        let _ = self.events.send(EditorEvent::AssetsChanged);
+
+        Ok(())
+    }
+
+    pub fn delete_selected_stroke(&mut self) {
+        let Some(stroke_id) = self.selected_stroke_id else {
+            return;
+        };
+
+        self.apply_edit(StrokeEdit::Delete {
+            stroke_id,
+            layer: None,
+            position: None,
+        });
+    }
+
+    fn update_selected_stroke(
+        &mut self,
+        field_name: &str,
+        read: impl Fn(&BrushSettings) -> StrokeProperty,
+        new_value: StrokeProperty,
+    ) {
+        let Some(stroke_id) = self.selected_stroke_id else {
+            return;
+        };
+
+        let Some(old_value) = self
+            .strokes
+            .lock_or_recover()
+            .get(stroke_id)
+            .map(|layer| read(&layer.settings))
+        else {
+            return;
+        };
+
+        let edit = StrokeEdit::Update {
+            stroke_id,
+            field_name: field_name.to_string(),
+            old_value,
+            new_value,
+            signal: self
+                .value_signals
+                .lock_or_recover()
+                .get(&format!("{}{}", field_name, stroke_id))
+                .cloned(),
+        };
+
+        self.apply_edit(edit);
+    }
+
+    pub fn update_stroke_width(&mut self, new_width_str: &str) -> Result<(), String> {
+        let new_width =
+            string_to_f32(new_width_str).map_err(|_| "Couldn't convert string to f32")?;
+
+        self.update_selected_stroke(
+            "stroke_layer_width",
+            |settings| StrokeProperty::Width(settings.size),
+            StrokeProperty::Width(new_width),
+        );
+
+        Ok(())
+    }
 
-                let mut stroke_thickness = h.to_string();
-                self.signal.expect("signal error").set(stroke_thickness);
-            }
-            PolygonProperty::StrokeRed(h) => {
-                // let mut stroke_red = h.to_string();
-                let red_human = wgpu_to_human(*h);
+    pub fn update_stroke_opacity(&mut self, new_opacity_str: &str) -> Result<(), String> {
+        let new_opacity =
+            string_to_f32(new_opacity_str).map_err(|_| "Couldn't convert string to f32")?;
 
-                editor.update_polygon(self.polygon_id, "stroke_red", InputValue::Number(red_human));
+        // entered as a percentage
+        self.update_selected_stroke(
+            "stroke_layer_opacity",
+            |settings| StrokeProperty::Opacity(settings.opacity),
+            StrokeProperty::Opacity(new_opacity / 100.0),
+        );
 
-                self.signal
-                    .expect("signal error")
-                    .set(red_human.to_string());
-            }
-            PolygonProperty::StrokeGreen(h) => {
-                // let mut stroke_green = h.to_string();
-                let green_human = wgpu_to_human(*h);
+        Ok(())
+    }
 
-                editor.update_polygon(
-                    self.polygon_id,
-                    "stroke_green",
-                    InputValue::Number(green_human),
-                );
+    pub fn update_stroke_layer_red(&mut self, new_red_str: &str) -> Result<(), String> {
+        let new_red = string_to_f32(new_red_str).map_err(|_| "Couldn't convert string to f32")?;
 
-                self.signal
-                    .expect("signal error")
-                    .set(green_human.to_string());
-            }
-            PolygonProperty::StrokeBlue(h) => {
-                // let mut stroke_blue = h.to_string();
-                let blue_human = wgpu_to_human(*h);
+        // entered as 0-255, like the polygon colors
 
-                editor.update_polygon(
-                    self.polygon_id,
-                    "stroke_blue",
-                    InputValue::Number(blue_human),
-                );
+        self.update_selected_stroke(
+            "stroke_layer_red",
+            |settings| StrokeProperty::Red(settings.color[0]),
+            StrokeProperty::Red((new_red / 255.0).clamp(0.0, 1.0)),
+        );
 
-                self.signal
-                    .expect("signal error")
-                    .set(blue_human.to_string());
-            }
-            PolygonProperty::Points(w) => {
-                editor.update_polygon(self.polygon_id, "points", InputValue::Points(w.clone()));
-            }
-        }
+        Ok(())
     }
-}
 
-pub struct EditorState {
-    pub editor: Arc<Mutex<Editor>>,
-    pub record: Arc<Mutex<Record<PolygonEdit>>>,
-    pub record_state: RecordState,
-    pub polygon_selected: bool,
-    pub selected_polygon_id: Uuid,
-    pub value_signals: Arc<Mutex<HashMap<String, RwSignal<String>>>>,
-    pub current_modifiers: ModifiersState,
-}
+    pub fn update_stroke_layer_green(&mut self, new_green_str: &str) -> Result<(), String> {
+        let new_green =
+            string_to_f32(new_green_str).map_err(|_| "Couldn't convert string to f32")?;
 
-pub struct RecordState {
-    pub editor: Arc<Mutex<Editor>>,
-    // pub record: Arc<Mutex<Record<PolygonEdit>>>,
-}
+        self.update_selected_stroke(
+            "stroke_layer_green",
+            |settings| StrokeProperty::Green(settings.color[1]),
+            StrokeProperty::Green((new_green / 255.0).clamp(0.0, 1.0)),
+        );
 
-impl EditorState {
-    pub fn new(editor: Arc<Mutex<Editor>>, record: Arc<Mutex<Record<PolygonEdit>>>) -> Self {
-        Self {
-            editor: Arc::clone(&editor),
-            record: Arc::clone(&record),
-            record_state: RecordState {
-                editor: Arc::clone(&editor),
-                // record: Arc::clone(&record),
-            },
-            polygon_selected: false,
-            selected_polygon_id: Uuid::nil(),
-            value_signals: Arc::new(Mutex::new(HashMap::new())),
-            current_modifiers: ModifiersState::empty(),
-        }
+        Ok(())
     }
 
-    // Helper method to register a new signal
-    pub fn register_signal(&mut self, name: String, signal: RwSignal<String>) {
-        let mut signals = self.value_signals.lock().unwrap();
-        signals.insert(name + &self.selected_polygon_id.to_string(), signal);
+    pub fn update_stroke_layer_blue(&mut self, new_blue_str: &str) -> Result<(), String> {
+        let new_blue = string_to_f32(new_blue_str).map_err(|_| "Couldn't convert string to f32")?;
+
+        self.update_selected_stroke(
+            "stroke_layer_blue",
+            |settings| StrokeProperty::Blue(settings.color[2]),
+            StrokeProperty::Blue((new_blue / 255.0).clamp(0.0, 1.0)),
+        );
+
+        Ok(())
     }
 
     pub fn update_width(&mut self, new_width_str: &str) -> Result<(), String> {
-        let new_width =
-            string_to_f32(new_width_str).map_err(|_| "Couldn't convert string to f32")?;
+        let new_width = self.parse_length(new_width_str)?;
 
         let old_width = {
-            let editor = self.record_state.editor.lock().unwrap();
+            let editor = self.record_state.editor.lock_or_recover();
             editor.get_polygon_width(self.selected_polygon_id)
         };
 
@@ -249,26 +2482,23 @@ impl EditorState {
             field_name: "width".to_string(),
             signal: Some(
                 self.value_signals
-                    .lock()
-                    .unwrap()
+                    .lock_or_recover()
                     .get(&format!("width{}", self.selected_polygon_id))
                     .cloned()
                     .expect("Couldn't get width value signal"),
             ),
         };
 
-        let mut record = self.record.lock().unwrap();
-        record.edit(&mut self.record_state, edit);
+        self.apply_edit(edit);
 
         Ok(())
     }
 
     pub fn update_height(&mut self, new_height_str: &str) -> Result<(), String> {
-        let new_height =
-            string_to_f32(new_height_str).map_err(|_| "Couldn't convert string to f32")?;
+        let new_height = self.parse_length(new_height_str)?;
 
         let old_height = {
-            let editor = self.editor.lock().unwrap();
+            let editor = self.editor.lock_or_recover();
             editor.get_polygon_height(self.selected_polygon_id)
         };
 
@@ -279,16 +2509,14 @@ impl EditorState {
             field_name: "height".to_string(),
             signal: Some(
                 self.value_signals
-                    .lock()
-                    .unwrap()
+                    .lock_or_recover()
                     .get(&format!("height{}", self.selected_polygon_id))
                     .cloned()
                     .expect("Couldn't get width value signal"),
             ),
         };
 
-        let mut record = self.record.lock().unwrap();
-        record.edit(&mut self.record_state, edit);
+        self.apply_edit(edit);
 
         Ok(())
     }
@@ -297,7 +2525,7 @@ impl EditorState {
         let new_red = string_to_f32(new_red_str).map_err(|_| "Couldn't convert string to f32")?;
 
         let old_red = {
-            let editor = self.editor.lock().unwrap();
+            let editor = self.editor.lock_or_recover();
             editor.get_polygon_red(self.selected_polygon_id)
         };
 
@@ -308,16 +2536,14 @@ impl EditorState {
             field_name: "red".to_string(),
             signal: Some(
                 self.value_signals
-                    .lock()
-                    .unwrap()
+                    .lock_or_recover()
                     .get(&format!("red{}", self.selected_polygon_id))
                     .cloned()
                     .expect("Couldn't get width value signal"),
             ),
         };
 
-        let mut record = self.record.lock().unwrap();
-        record.edit(&mut self.record_state, edit);
+        self.apply_edit(edit);
 
         Ok(())
     }
@@ -327,7 +2553,7 @@ impl EditorState {
             string_to_f32(new_green_str).map_err(|_| "Couldn't convert string to f32")?;
 
         let old_green = {
-            let editor = self.editor.lock().unwrap();
+            let editor = self.editor.lock_or_recover();
             editor.get_polygon_green(self.selected_polygon_id)
         };
 
@@ -338,16 +2564,14 @@ impl EditorState {
             field_name: "green".to_string(),
             signal: Some(
                 self.value_signals
-                    .lock()
-                    .unwrap()
+                    .lock_or_recover()
                     .get(&format!("green{}", self.selected_polygon_id))
                     .cloned()
                     .expect("Couldn't get green value signal"),
             ),
         };
 
-        let mut record = self.record.lock().unwrap();
-        record.edit(&mut self.record_state, edit);
+        self.apply_edit(edit);
 
         Ok(())
     }
@@ -356,7 +2580,7 @@ impl EditorState {
         let new_blue = string_to_f32(new_blue_str).map_err(|_| "Couldn't convert string to f32")?;
 
         let old_blue = {
-            let editor = self.editor.lock().unwrap();
+            let editor = self.editor.lock_or_recover();
             editor.get_polygon_blue(self.selected_polygon_id)
         };
 
@@ -367,26 +2591,23 @@ impl EditorState {
             field_name: "blue".to_string(),
             signal: Some(
                 self.value_signals
-                    .lock()
-                    .unwrap()
+                    .lock_or_recover()
                     .get(&format!("blue{}", self.selected_polygon_id))
                     .cloned()
                     .expect("Couldn't get blue value signal"),
             ),
         };
 
-        let mut record = self.record.lock().unwrap();
-        record.edit(&mut self.record_state, edit);
+        self.apply_edit(edit);
 
         Ok(())
     }
 
     pub fn update_border_radius(&mut self, new_border_radius_str: &str) -> Result<(), String> {
-        let new_border_radius = string_to_f32(new_border_radius_str)
-            .map_err(|_| "Couldn't convert string to height")?;
+        let new_border_radius = self.parse_length(new_border_radius_str)?;
 
         let old_border_radius = {
-            let editor = self.editor.lock().unwrap();
+            let editor = self.editor.lock_or_recover();
             editor.get_polygon_border_radius(self.selected_polygon_id)
         };
 
@@ -397,16 +2618,14 @@ impl EditorState {
             field_name: "border_radius".to_string(),
             signal: Some(
                 self.value_signals
-                    .lock()
-                    .unwrap()
+                    .lock_or_recover()
                     .get(&format!("border_radius{}", self.selected_polygon_id))
                     .cloned()
                     .expect("Couldn't get border_radius value signal"),
             ),
         };
 
-        let mut record = self.record.lock().unwrap();
-        record.edit(&mut self.record_state, edit);
+        self.apply_edit(edit);
 
         Ok(())
     }
@@ -415,11 +2634,10 @@ impl EditorState {
         &mut self,
         new_stroke_thickness_str: &str,
     ) -> Result<(), String> {
-        let new_stroke_thickness = string_to_f32(new_stroke_thickness_str)
-            .map_err(|_| "Couldn't convert string to height")?;
+        let new_stroke_thickness = self.parse_length(new_stroke_thickness_str)?;
 
         let old_stroke_thickness = {
-            let editor = self.editor.lock().unwrap();
+            let editor = self.editor.lock_or_recover();
             editor.get_polygon_stroke_thickness(self.selected_polygon_id)
         };
 
@@ -430,16 +2648,14 @@ impl EditorState {
             field_name: "stroke_thickness".to_string(),
             signal: Some(
                 self.value_signals
-                    .lock()
-                    .unwrap()
+                    .lock_or_recover()
                     .get(&format!("stroke_thickness{}", self.selected_polygon_id))
                     .cloned()
                     .expect("Couldn't get stroke_thickness value signal"),
             ),
         };
 
-        let mut record = self.record.lock().unwrap();
-        record.edit(&mut self.record_state, edit);
+        self.apply_edit(edit);
 
         Ok(())
     }
@@ -449,7 +2665,7 @@ impl EditorState {
             string_to_f32(new_stroke_red_str).map_err(|_| "Couldn't convert string to height")?;
 
         let old_stroke_red = {
-            let editor = self.editor.lock().unwrap();
+            let editor = self.editor.lock_or_recover();
             editor.get_polygon_stroke_red(self.selected_polygon_id)
         };
 
@@ -460,16 +2676,14 @@ impl EditorState {
             field_name: "stroke_red".to_string(),
             signal: Some(
                 self.value_signals
-                    .lock()
-                    .unwrap()
+                    .lock_or_recover()
                     .get(&format!("stroke_red{}", self.selected_polygon_id))
                     .cloned()
                     .expect("Couldn't get stroke_red value signal"),
             ),
         };
 
-        let mut record = self.record.lock().unwrap();
-        record.edit(&mut self.record_state, edit);
+        self.apply_edit(edit);
 
         Ok(())
     }
@@ -479,7 +2693,7 @@ impl EditorState {
             string_to_f32(new_stroke_green_str).map_err(|_| "Couldn't convert string to height")?;
 
         let old_stroke_green = {
-            let editor = self.editor.lock().unwrap();
+            let editor = self.editor.lock_or_recover();
             editor.get_polygon_stroke_green(self.selected_polygon_id)
         };
 
@@ -490,16 +2704,14 @@ impl EditorState {
             field_name: "stroke_green".to_string(),
             signal: Some(
                 self.value_signals
-                    .lock()
-                    .unwrap()
+                    .lock_or_recover()
                     .get(&format!("stroke_green{}", self.selected_polygon_id))
                     .cloned()
                     .expect("Couldn't get stroke_green value signal"),
             ),
         };
 
-        let mut record = self.record.lock().unwrap();
-        record.edit(&mut self.record_state, edit);
+        self.apply_edit(edit);
 
         Ok(())
     }
@@ -509,7 +2721,7 @@ impl EditorState {
             string_to_f32(new_stroke_blue_str).map_err(|_| "Couldn't convert string to height")?;
 
         let old_stroke_blue = {
-            let editor = self.editor.lock().unwrap();
+            let editor = self.editor.lock_or_recover();
             editor.get_polygon_stroke_blue(self.selected_polygon_id)
         };
 
@@ -520,34 +2732,621 @@ impl EditorState {
             field_name: "stroke_blue".to_string(),
             signal: Some(
                 self.value_signals
-                    .lock()
-                    .unwrap()
+                    .lock_or_recover()
                     .get(&format!("stroke_blue{}", self.selected_polygon_id))
                     .cloned()
                     .expect("Couldn't get stroke_blue value signal"),
             ),
         };
 
-        let mut record = self.record.lock().unwrap();
-        record.edit(&mut self.record_state, edit);
+        self.apply_edit(edit);
+
+        Ok(())
+    }
+
+    pub fn layer_adjustments(&self, layer_id: Uuid) -> AdjustmentStack {
+        self.adjustments.lock_or_recover().get(layer_id)
+    }
+
+    fn update_adjustments(&mut self, layer_id: Uuid, update: impl FnOnce(&mut AdjustmentStack)) {
+        let old_stack = self.layer_adjustments(layer_id);
+        let mut new_stack = old_stack.clone();
+        update(&mut new_stack);
+
+        if new_stack == old_stack {
+            return;
+        }
+
+        // only fields that changed, so the input being typed in isn't rewritten
+        let signals = {
+            let value_signals = self.value_signals.lock_or_recover();
+
+            new_stack
+                .entries()
+                .iter()
+                .filter_map(|new| Some((new, old_stack.get(new.id)?)))
+                .flat_map(|(new, old)| {
+                    new.adjustment
+                        .fields()
+                        .into_iter()
+                        .filter(move |field| {
+                            field.read(&new.adjustment) != field.read(&old.adjustment)
+                        })
+                        .map(move |field| (new.id, field))
+                })
+                .filter_map(|(entry_id, field)| {
+                    let name = format!("{}{}", field.signal_name(entry_id), layer_id);
+                    value_signals
+                        .get(&name)
+                        .map(|signal| (entry_id, field, *signal))
+                })
+                .collect()
+        };
+
+        self.apply_edit(AdjustmentEdit {
+            layer_id,
+            old_stack,
+            new_stack,
+            signals,
+        });
+    }
+
+    pub fn add_adjustment(&mut self, layer_id: Uuid, adjustment: Adjustment) {
+        self.update_adjustments(layer_id, |stack| {
+            stack.add(adjustment);
+        });
+    }
+
+    pub fn remove_adjustment(&mut self, layer_id: Uuid, entry_id: Uuid) {
+        self.update_adjustments(layer_id, |stack| {
+            stack.remove(entry_id);
+        });
+    }
+
+    pub fn toggle_adjustment(&mut self, layer_id: Uuid, entry_id: Uuid) {
+        self.update_adjustments(layer_id, |stack| {
+            if let Some(entry) = stack.get(entry_id) {
+                let enabled = !entry.enabled;
+                stack.set_enabled(entry_id, enabled);
+            }
+        });
+    }
+
+    /// Moves the adjustment up the stack when the offset is negative, down when it's positive
+    pub fn move_adjustment(&mut self, layer_id: Uuid, entry_id: Uuid, offset: isize) {
+        self.update_adjustments(layer_id, |stack| {
+            if let Some(position) = stack.position(entry_id) {
+                let last = stack.entries().len().saturating_sub(1) as isize;
+                let target = (position as isize + offset).clamp(0, last);
+                stack.move_to(entry_id, target as usize);
+            }
+        });
+    }
+
+    pub fn update_adjustment_field(
+        &mut self,
+        layer_id: Uuid,
+        entry_id: Uuid,
+        field: AdjustmentField,
+        new_value_str: &str,
+    ) -> Result<(), String> {
+        let new_value =
+            string_to_f32(new_value_str).map_err(|_| "Couldn't convert string to f32")?;
+
+        self.update_adjustments(layer_id, |stack| {
+            if let Some(entry) = stack.get(entry_id) {
+                let mut adjustment = entry.adjustment.clone();
+                field.apply(&mut adjustment, new_value);
+                stack.update(entry_id, adjustment);
+            }
+        });
+
+        Ok(())
+    }
+
+    pub fn set_brush_kind(&mut self, kind: BrushKind) {
+        let color = self.brush_settings.color;
+        let pressure_curve = self.brush_settings.pressure_curve;
+        let smoothing = self.brush_settings.smoothing;
+
+        self.brush_tool = BrushTool::Paint;
+        self.brush_settings = BrushSettings {
+            color,
+            pressure_curve,
+            smoothing,
+            ..BrushSettings::for_kind(kind)
+        };
+    }
+
+    pub fn set_brush_tool(&mut self, tool: BrushTool) {
+        self.brush_tool = tool;
+    }
+
+    pub fn set_eraser_mode(&mut self, mode: EraserMode) {
+        self.eraser_settings.mode = mode;
+    }
+
+    pub fn update_eraser_size(&mut self, new_size_str: &str) -> Result<(), String> {
+        let new_size = string_to_f32(new_size_str).map_err(|_| "Couldn't convert string to f32")?;
+
+        self.eraser_settings.size = new_size.max(1.0);
+
+        Ok(())
+    }
+
+    pub fn update_brush_size(&mut self, new_size_str: &str) -> Result<(), String> {
+        let new_size = string_to_f32(new_size_str).map_err(|_| "Couldn't convert string to f32")?;
+
+        self.brush_settings.size = new_size.max(1.0);
+
+        Ok(())
+    }
+
+    pub fn update_brush_opacity(&mut self, new_opacity_str: &str) -> Result<(), String> {
+        let new_opacity =
+            string_to_f32(new_opacity_str).map_err(|_| "Couldn't convert string to f32")?;
+
+        // entered as a percentage
+        self.brush_settings.opacity = (new_opacity / 100.0).clamp(0.0, 1.0);
+
+        Ok(())
+    }
+
+    pub fn update_brush_spacing(&mut self, new_spacing_str: &str) -> Result<(), String> {
+        let new_spacing =
+            string_to_f32(new_spacing_str).map_err(|_| "Couldn't convert string to f32")?;
+
+        // entered as a percentage of the brush size
+        self.brush_settings.spacing = (new_spacing / 100.0).max(0.01);
+
+        Ok(())
+    }
+
+    pub fn update_brush_hardness(&mut self, new_hardness_str: &str) -> Result<(), String> {
+        let new_hardness =
+            string_to_f32(new_hardness_str).map_err(|_| "Couldn't convert string to f32")?;
+
+        self.brush_settings.hardness = (new_hardness / 100.0).clamp(0.0, 1.0);
+
+        Ok(())
+    }
+
+    pub fn set_stabilizer(&mut self, stabilizer: StabilizerKind) {
+        self.brush_settings.smoothing.stabilizer = stabilizer;
+    }
+
+    pub fn update_smoothing_strength(&mut self, new_strength_str: &str) -> Result<(), String> {
+        let new_strength =
+            string_to_f32(new_strength_str).map_err(|_| "Couldn't convert string to f32")?;
+
+        // entered as a percentage
+        self.brush_settings.smoothing.strength = (new_strength / 100.0).clamp(0.0, 1.0);
+
+        Ok(())
+    }
+
+    pub fn update_pressure_gamma(&mut self, new_gamma_str: &str) -> Result<(), String> {
+        let new_gamma =
+            string_to_f32(new_gamma_str).map_err(|_| "Couldn't convert string to f32")?;
+
+        self.brush_settings.pressure_curve.gamma = new_gamma.clamp(0.1, 10.0);
+
+        Ok(())
+    }
+
+    pub fn update_pressure_min_size(&mut self, new_min_size_str: &str) -> Result<(), String> {
+        let new_min_size =
+            string_to_f32(new_min_size_str).map_err(|_| "Couldn't convert string to f32")?;
+
+        // entered as a percentage of the full size
+        self.brush_settings.pressure_curve.min_size = (new_min_size / 100.0).clamp(0.0, 1.0);
+
+        Ok(())
+    }
+
+    pub fn update_pressure_min_opacity(&mut self, new_min_opacity_str: &str) -> Result<(), String> {
+        let new_min_opacity =
+            string_to_f32(new_min_opacity_str).map_err(|_| "Couldn't convert string to f32")?;
+
+        // entered as a percentage of the full opacity
+        self.brush_settings.pressure_curve.min_opacity = (new_min_opacity / 100.0).clamp(0.0, 1.0);
+
+        Ok(())
+    }
+
+    /// Saves the change and lets everything showing a preference catch up
+    pub fn update_preferences(&mut self, update: impl FnOnce(&mut Preferences)) {
+        let preferences = {
+            let mut preferences = self.preferences.lock_or_recover();
+            let before = preferences.clone();
+            update(&mut preferences);
+
+            if *preferences == before {
+                return;
+            }
+
+            preferences.clone()
+        };
+
+        if let Err(e) = preferences.save() {
+            println!("{}", e);
+        }
+
+        let _ = self.events.send(EditorEvent::PreferencesChanged);
+        // the background and sample count are read while drawing
+        self.invalidator.invalidate(Invalidation::Scene);
+    }
+
+    pub fn update_preference_field(
+        &mut self,
+        field: PreferenceField,
+        new_value_str: &str,
+    ) -> Result<(), String> {
+        let new_value =
+            string_to_f32(new_value_str).map_err(|_| "Couldn't convert string to f32")?;
+
+        self.update_preferences(|preferences| field.apply(preferences, new_value));
+
+        Ok(())
+    }
+
+    pub fn page_setup(&self) -> PageSetup {
+        self.page.lock_or_recover().clone()
+    }
+
+    /// A length typed into an input, in pixels. See PageSetup::parse_length.
+    pub fn parse_length(&self, text: &str) -> Result<f32, String> {
+        self.page.lock_or_recover().parse_length(text)
+    }
+
+    /// Resizes or remeasures the artboards. Like the preferences, this isn't undoable.
+    // Must not be called while the editor is locked
+    pub fn update_page(&mut self, update: impl FnOnce(&mut PageSetup)) {
+        {
+            let mut page = self.page.lock_or_recover();
+            let before = page.clone();
+            update(&mut page);
+
+            if *page == before {
+                return;
+            }
+        }
+
+        let _ = self.events.send(EditorEvent::PageChanged);
+        self.invalidator.invalidate(Invalidation::Overlay);
+
+        // shapes may now sit on another artboard
+        let moved = self
+            .scene_layers()
+            .into_iter()
+            .map(|layer| LayerChange::Moved {
+                id: layer.instance_id,
+                artboard: layer.artboard,
+            })
+            .collect();
+        self.record_state.emit_layer_changes(moved);
+    }
+
+    pub fn update_artboard_field(
+        &mut self,
+        artboard_id: Uuid,
+        field: ArtboardField,
+        new_value_str: &str,
+    ) -> Result<(), String> {
+        let mut page = self.page_setup();
+        field.apply(&mut page, artboard_id, new_value_str)?;
+
+        self.update_page(|current| *current = page);
+
+        Ok(())
+    }
+
+    pub fn update_dpi(&mut self, new_value_str: &str) -> Result<(), String> {
+        let mut page = self.page_setup();
+        page.set_dpi(new_value_str)?;
+
+        self.update_page(|current| *current = page);
+
+        Ok(())
+    }
+
+    /// The document and undo history as plain data, for the autosave
+    pub fn recovery_file(&self) -> RecoveryFile {
+        let history = HistorySnapshot::capture(&self.record.lock_or_recover());
+
+        RecoveryFile {
+            document: self.document_snapshot(),
+            history,
+        }
+    }
+
+    pub fn document_snapshot(&self) -> DocumentSnapshot {
+        let editor = self.editor.lock_or_recover();
+        let strokes = self.strokes.lock_or_recover();
+        let images = self.images.lock_or_recover();
+        let masks = self.masks.lock_or_recover();
+        let blends = self.blends.lock_or_recover();
+        let effects = self.effects.lock_or_recover();
+        let adjustments = self.adjustments.lock_or_recover();
+
+        DocumentSnapshot::capture(
+            &editor,
+            &strokes,
+            &images,
+            &masks,
+            &blends,
+            &effects,
+            &adjustments,
+            &self.page_setup(),
+        )
+    }
+
+    /// Where the canvas is looking
+    pub fn view(&self) -> View {
+        View::from_editor(&self.editor.lock_or_recover())
+    }
+
+    /// Pans or zooms the canvas. The view isn't part of the document, so this isn't undoable.
+    // Must not be called while the editor is locked
+    pub fn update_view(&self, queue: &wgpu::Queue, update: impl FnOnce(&mut View)) {
+        let mut view = self.view();
+        update(&mut view);
+
+        {
+            let mut editor = self.editor.lock_or_recover();
+            let Some(mut camera) = editor.camera else {
+                return;
+            };
+
+            camera.zoom = view.zoom;
+            camera.position.x = view.position[0];
+            camera.position.y = view.position[1];
+
+            editor.camera = Some(camera);
+            editor.update_camera_binding(queue);
+        }
+
+        let _ = self.events.send(EditorEvent::CameraChanged);
+        self.invalidator.invalidate(Invalidation::Camera);
+    }
+
+    /// The box around every artboard and everything drawn, off them too
+    pub fn content_bounds(&self) -> Option<([f32; 2], [f32; 2])> {
+        let artboards = {
+            let page = self.page.lock_or_recover();
+            page.artboards
+                .iter()
+                .map(|artboard| page.bounds(artboard))
+                .collect::<Vec<_>>()
+        };
+        let layers = {
+            let editor = self.editor.lock_or_recover();
+            let strokes = self.strokes.lock_or_recover();
+            let images = self.images.lock_or_recover();
+
+            editor
+                .layer_list
+                .iter()
+                .filter_map(|layer_id| self.layer_bounds(&editor, &strokes, &images, *layer_id))
+                .collect::<Vec<_>>()
+        };
+
+        union_bounds(artboards.into_iter().chain(layers))
+    }
+
+    /// Whether keys are meant for the canvas, the cursor is over it and nothing is being typed
+    // Must not be called while the editor is locked
+    pub fn canvas_has_focus(&self) -> bool {
+        !self.text_input_focused
+            && self
+                .view()
+                .on_canvas([self.last_cursor.x, self.last_cursor.y])
+    }
+
+    pub fn zoom_to_fit(&self, queue: &wgpu::Queue) {
+        if let Some(bounds) = self.content_bounds() {
+            self.update_view(queue, |view| view.fit(bounds));
+        }
+    }
+
+    pub fn zoom_to_selection(&self, queue: &wgpu::Queue) -> Result<(), String> {
+        let selected = if self.polygon_selected {
+            Some(self.selected_polygon_id)
+        } else {
+            self.selected_stroke_id.or(self.selected_image_id)
+        };
+        let layer_id = selected.ok_or("Nothing is selected")?;
+
+        let bounds = {
+            let editor = self.editor.lock_or_recover();
+            let strokes = self.strokes.lock_or_recover();
+            let images = self.images.lock_or_recover();
+            self.layer_bounds(&editor, &strokes, &images, layer_id)
+        }
+        .ok_or("Couldn't find selected layer")?;
+
+        self.update_view(queue, |view| view.fit(bounds));
+
+        Ok(())
+    }
+
+    // a polygon's box, the box around a stroke's samples, or where a photo is placed
+    fn layer_bounds(
+        &self,
+        editor: &Editor,
+        strokes: &StrokeLayers,
+        images: &ImageLayers,
+        layer_id: Uuid,
+    ) -> Option<([f32; 2], [f32; 2])> {
+        editor
+            .polygons
+            .iter()
+            .find(|polygon| polygon.id == layer_id)
+            .map(|polygon| {
+                let config = polygon.to_config();
+                let (width, height) = config.dimensions;
+                let [x, y] = [config.position.x, config.position.y];
+
+                ([x, y], [x + width, y + height])
+            })
+            .or_else(|| strokes.get(layer_id).and_then(StrokeLayer::bounds))
+            .or_else(|| images.get(layer_id).map(ImageLayer::bounds))
+    }
+
+    /// Saves one artboard, or all of them, as SVGs in the export directory
+    pub fn export_artboards(&self, artboard_id: Option<Uuid>) -> Result<Vec<PathBuf>, String> {
+        let metadata = self.preferences.lock_or_recover().photo_metadata;
+        export_artboards(&self.document_snapshot(), artboard_id, metadata)
+    }
+
+    /// Replaces the document and undo history with an autosaved one
+    // Must not be called while the editor is locked
+    pub fn restore_recovery(
+        &mut self,
+        file: RecoveryFile,
+        device: &wgpu::Device,
+        window_size: &WindowSize,
+    ) -> Result<(), String> {
+        let RecoveryFile { document, history } = file;
+        let head = history.head.min(history.edits.len());
+        let replaced = self.scene_layers();
+
+        // first, so lengths the history puts in the inputs are in the page's unit
+        self.update_page(|page| *page = document.page.clone());
+
+        let mut edits = {
+            let mut editor = self.editor.lock_or_recover();
+
+            editor.polygons.clear();
+            editor.layer_list.clear();
+
+            let polygons = document
+                .polygons
+                .iter()
+                .map(|polygon| polygon.build(&mut editor, device, window_size))
+                .collect::<Result<Vec<_>, String>>()?;
+
+            let mut builder = LayerBuilder {
+                editor: &mut editor,
+                device,
+                window_size,
+            };
+            let edits = history
+                .edits
+                .into_iter()
+                .map(|edit| edit.into_edit(&mut builder))
+                .collect::<Result<Vec<SceneEdit>, String>>()?;
+
+            editor.polygons = polygons;
+            editor.layer_list = document.layer_list;
+
+            let mut strokes = self.strokes.lock_or_recover();
+            strokes.clear();
+            for mut layer in document.strokes {
+                layer.rebuild(window_size);
+                strokes.insert(layer);
+            }
+
+            // photos are decoded from their files again
+            let mut images = self.images.lock_or_recover();
+            images.clear();
+            for layer in document.images {
+                images.insert(layer.restored(window_size));
+            }
+
+            let mut masks = self.masks.lock_or_recover();
+            masks.clear();
+            for (layer_id, state) in document.masks {
+                masks.set_state(layer_id, state);
+            }
+
+            let mut blends = self.blends.lock_or_recover();
+            blends.clear();
+            for (layer_id, blend) in document.blends {
+                blends.set(layer_id, blend);
+            }
+
+            let mut effects = self.effects.lock_or_recover();
+            effects.clear();
+            for (layer_id, stack) in document.effects {
+                effects.set(layer_id, stack);
+            }
+
+            let mut adjustments = self.adjustments.lock_or_recover();
+            adjustments.clear();
+            for (layer_id, stack) in document.adjustments {
+                adjustments.set(layer_id, stack);
+            }
+
+            edits
+        };
+
+        // the record can only take edits by applying them, so step back to where the
+        // history starts and apply it all again, then undo what had been undone
+        for edit in edits[..head].iter_mut().rev() {
+            edit.undo(&mut self.record_state);
+        }
+
+        let mut record = Record::new();
+        for edit in edits {
+            record.edit(&mut self.record_state, edit);
+        }
+        for _ in head..record.head() {
+            record.undo(&mut self.record_state);
+        }
+
+        self.emit_history_changed(&record);
+        *self.record.lock_or_recover() = record;
+
+        self.mask_editing = None;
+        let _ = self.events.send(EditorEvent::SelectionChanged(None));
+        let _ = self.events.send(EditorEvent::StrokeSelectionChanged(None));
+        let _ = self.events.send(EditorEvent::ImageSelectionChanged(None));
+
+        // the whole list is swapped, along with anything replaying the history listed
+        let restored = self.scene_layers();
+        let changes = replaced
+            .iter()
+            .chain(&restored)
+            .map(|layer| LayerChange::Removed {
+                id: layer.instance_id,
+                kind: layer.instance_kind,
+            })
+            .chain(
+                restored
+                    .iter()
+                    .enumerate()
+                    .map(|(index, layer)| LayerChange::Added {
+                        index,
+                        layer: layer.clone(),
+                    }),
+            )
+            .collect();
+        self.record_state.emit_layer_changes(changes);
+        // the recovered shapes can share ids and sizes with the ones they replace
+        self.invalidator.scene_replaced();
 
         Ok(())
     }
 
     pub fn undo(&mut self) {
-        let mut record = self.record.lock().unwrap();
+        let mut record = self.record.lock_or_recover();
 
         if record.undo(&mut self.record_state).is_some() {
             println!("Undo successful");
             // println!("record cannB... {:?}", self.record.head());
         }
+
+        self.emit_history_changed(&record);
     }
 
     pub fn redo(&mut self) {
-        let mut record = self.record.lock().unwrap();
+        let mut record = self.record.lock_or_recover();
 
         if record.redo(&mut self.record_state).is_some() {
             println!("Redo successful");
         }
+
+        self.emit_history_changed(&record);
     }
 }
@@ -0,0 +1,329 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use strum_macros::EnumIter;
+use uuid::Uuid;
+
+// the luminosity weights the Color and Luminosity modes are defined with
+const LUM: [f32; 3] = [0.3, 0.59, 0.11];
+
+/// How a layer's colours combine with what's under it. Matches the modes of the
+/// same name in CSS and most paint programs.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash, EnumIter, Serialize, Deserialize)]
+pub enum BlendMode {
+    #[default]
+    Normal,
+    Multiply,
+    Screen,
+    Overlay,
+    Darken,
+    Lighten,
+    Difference,
+    Color,
+    Luminosity,
+}
+
+impl BlendMode {
+    pub fn label(&self) -> &'static str {
+        match self {
+            BlendMode::Normal => "Normal",
+            BlendMode::Multiply => "Multiply",
+            BlendMode::Screen => "Screen",
+            BlendMode::Overlay => "Overlay",
+            BlendMode::Darken => "Darken",
+            BlendMode::Lighten => "Lighten",
+            BlendMode::Difference => "Difference",
+            BlendMode::Color => "Color",
+            BlendMode::Luminosity => "Luminosity",
+        }
+    }
+
+    /// What the composite shader switches on, keep in step with shaders/composite.wgsl
+    pub fn shader_index(&self) -> u32 {
+        *self as u32
+    }
+
+    /// Mixes a backdrop colour with a source colour, both unpremultiplied
+    pub fn blend(&self, backdrop: [f32; 3], source: [f32; 3]) -> [f32; 3] {
+        let per_channel = |f: fn(f32, f32) -> f32| [0, 1, 2].map(|i| f(backdrop[i], source[i]));
+
+        match self {
+            BlendMode::Normal => source,
+            BlendMode::Multiply => per_channel(|b, s| b * s),
+            BlendMode::Screen => per_channel(screen),
+            BlendMode::Overlay => per_channel(|b, s| {
+                // hard light with the layers swapped
+                if b <= 0.5 {
+                    2.0 * b * s
+                } else {
+                    screen(s, 2.0 * b - 1.0)
+                }
+            }),
+            BlendMode::Darken => per_channel(f32::min),
+            BlendMode::Lighten => per_channel(f32::max),
+            BlendMode::Difference => per_channel(|b, s| (b - s).abs()),
+            BlendMode::Color => set_lum(source, lum(backdrop)),
+            BlendMode::Luminosity => set_lum(backdrop, lum(source)),
+        }
+    }
+}
+
+fn screen(b: f32, s: f32) -> f32 {
+    b + s - b * s
+}
+
+fn lum(c: [f32; 3]) -> f32 {
+    c[0] * LUM[0] + c[1] * LUM[1] + c[2] * LUM[2]
+}
+
+// Gives the colour a new luminosity, pulling it back into range without shifting its hue
+fn set_lum(c: [f32; 3], l: f32) -> [f32; 3] {
+    let d = l - lum(c);
+    let c = c.map(|channel| channel + d);
+
+    let l = lum(c);
+    let min = c[0].min(c[1]).min(c[2]);
+    let max = c[0].max(c[1]).max(c[2]);
+
+    if min < 0.0 {
+        c.map(|channel| l + (channel - l) * l / (l - min).max(f32::EPSILON))
+    } else if max > 1.0 {
+        c.map(|channel| l + (channel - l) * (1.0 - l) / (max - l).max(f32::EPSILON))
+    } else {
+        c
+    }
+}
+
+/// A layer's blend mode and opacity. Opacity fades the layer as a whole, on top of the
+/// alpha its shapes are drawn with.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LayerBlend {
+    pub mode: BlendMode,
+    // 0.0 - 1.0
+    pub opacity: f32,
+}
+
+impl Default for LayerBlend {
+    fn default() -> Self {
+        LayerBlend {
+            mode: BlendMode::Normal,
+            opacity: 1.0,
+        }
+    }
+}
+
+impl LayerBlend {
+    /// Whether the layer can be drawn straight onto the canvas with the pipeline's blend state
+    pub fn is_plain(&self) -> bool {
+        self.mode == BlendMode::Normal && self.opacity >= 1.0
+    }
+
+    /// The CPU reference for the composite shader. Both colours are premultiplied RGBA
+    /// in the space shapes are drawn in; the result is premultiplied too.
+    pub fn composite(&self, backdrop: [f32; 4], source: [f32; 4]) -> [f32; 4] {
+        let source_alpha = source[3] * self.opacity.clamp(0.0, 1.0);
+        if source_alpha <= 0.0 {
+            return backdrop;
+        }
+
+        let backdrop_alpha = backdrop[3];
+        let unpremultiply = |c: [f32; 4]| {
+            let alpha = c[3].max(f32::EPSILON);
+            [c[0] / alpha, c[1] / alpha, c[2] / alpha]
+        };
+        let (cb, cs) = (unpremultiply(backdrop), unpremultiply(source));
+
+        // where there's no backdrop the source shows as it is
+        let blended = self.mode.blend(cb, cs);
+        let mixed = [0, 1, 2].map(|i| (1.0 - backdrop_alpha) * cs[i] + backdrop_alpha * blended[i]);
+
+        let [r, g, b] =
+            [0, 1, 2].map(|i| source_alpha * mixed[i] + (1.0 - source_alpha) * backdrop[i]);
+
+        [
+            r,
+            g,
+            b,
+            source_alpha + backdrop_alpha * (1.0 - source_alpha),
+        ]
+    }
+
+    /// Composites straight alpha RGBA8 layer pixels onto a straight alpha RGBA8 backdrop
+    pub fn composite_rgba8(&self, backdrop: &mut [u8], layer: &[u8]) {
+        let premultiply = |pixel: &[u8]| {
+            let alpha = pixel[3] as f32 / 255.0;
+            [
+                pixel[0] as f32 / 255.0 * alpha,
+                pixel[1] as f32 / 255.0 * alpha,
+                pixel[2] as f32 / 255.0 * alpha,
+                alpha,
+            ]
+        };
+
+        for (to, from) in backdrop.chunks_exact_mut(4).zip(layer.chunks_exact(4)) {
+            let [r, g, b, a] = self.composite(premultiply(to), premultiply(from));
+            let alpha = a.max(f32::EPSILON);
+
+            to.copy_from_slice(&[
+                ((r / alpha).clamp(0.0, 1.0) * 255.0).round() as u8,
+                ((g / alpha).clamp(0.0, 1.0) * 255.0).round() as u8,
+                ((b / alpha).clamp(0.0, 1.0) * 255.0).round() as u8,
+                (a.clamp(0.0, 1.0) * 255.0).round() as u8,
+            ]);
+        }
+    }
+}
+
+/// Blend modes and opacities for every layer, by layer id. Layers without an entry
+/// are Normal at full opacity.
+#[derive(Debug, Default)]
+pub struct LayerBlends {
+    blends: HashMap<Uuid, LayerBlend>,
+}
+
+// Lock after the layer masks, never before
+pub type SharedLayerBlends = Arc<Mutex<LayerBlends>>;
+
+impl LayerBlends {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, layer_id: Uuid) -> LayerBlend {
+        self.blends.get(&layer_id).copied().unwrap_or_default()
+    }
+
+    pub fn set(&mut self, layer_id: Uuid, blend: LayerBlend) {
+        if blend == LayerBlend::default() {
+            self.blends.remove(&layer_id);
+        } else {
+            self.blends.insert(layer_id, blend);
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Uuid, LayerBlend)> + '_ {
+        self.blends
+            .iter()
+            .map(|(layer_id, blend)| (*layer_id, *blend))
+    }
+
+    pub fn clear(&mut self) {
+        self.blends.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use strum::IntoEnumIterator;
+
+    use super::*;
+
+    fn assert_close<const N: usize>(actual: [f32; N], expected: [f32; N]) {
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-5, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn each_mode_blends_to_known_values() {
+        let backdrop = [0.25, 0.5, 0.75];
+        let source = [0.5, 0.5, 0.5];
+        let expected = |mode| match mode {
+            BlendMode::Normal => [0.5, 0.5, 0.5],
+            BlendMode::Multiply => [0.125, 0.25, 0.375],
+            BlendMode::Screen => [0.625, 0.75, 0.875],
+            BlendMode::Overlay => [0.25, 0.5, 0.75],
+            BlendMode::Darken => [0.25, 0.5, 0.5],
+            BlendMode::Lighten => [0.5, 0.5, 0.75],
+            BlendMode::Difference => [0.25, 0.0, 0.25],
+            // grey takes the backdrop's luminosity, 0.4525
+            BlendMode::Color => [0.4525, 0.4525, 0.4525],
+            BlendMode::Luminosity => [0.2975, 0.5475, 0.7975],
+        };
+
+        for mode in BlendMode::iter() {
+            assert_close(mode.blend(backdrop, source), expected(mode));
+        }
+    }
+
+    #[test]
+    fn overlay_screens_over_a_light_backdrop() {
+        assert_close(
+            BlendMode::Overlay.blend([0.25, 0.75, 1.0], [0.25, 0.25, 0.25]),
+            [0.125, 0.625, 1.0],
+        );
+    }
+
+    #[test]
+    fn colours_past_white_are_pulled_back_into_range() {
+        // red at the luminosity of white is white
+        assert_close(
+            BlendMode::Color.blend([1.0, 1.0, 1.0], [1.0, 0.0, 0.0]),
+            [1.0, 1.0, 1.0],
+        );
+        assert_close(
+            BlendMode::Luminosity.blend([1.0, 0.0, 0.0], [0.0, 0.0, 0.0]),
+            [0.0, 0.0, 0.0],
+        );
+    }
+
+    #[test]
+    fn the_shader_indices_follow_the_modes() {
+        let indices: Vec<u32> = BlendMode::iter().map(|mode| mode.shader_index()).collect();
+
+        assert_eq!(indices, (0..9).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn opacity_fades_the_blended_colour() {
+        let blend = LayerBlend {
+            mode: BlendMode::Multiply,
+            opacity: 0.5,
+        };
+
+        assert_close(
+            blend.composite([0.5, 0.5, 0.5, 1.0], [1.0, 0.0, 0.0, 1.0]),
+            [0.5, 0.25, 0.25, 1.0],
+        );
+    }
+
+    #[test]
+    fn without_a_backdrop_the_source_shows_as_it_is() {
+        let blend = LayerBlend {
+            mode: BlendMode::Screen,
+            opacity: 1.0,
+        };
+
+        assert_close(
+            blend.composite([0.0, 0.0, 0.0, 0.0], [0.25, 0.0, 0.0, 0.5]),
+            [0.25, 0.0, 0.0, 0.5],
+        );
+    }
+
+    #[test]
+    fn a_transparent_layer_leaves_the_backdrop() {
+        let blend = LayerBlend {
+            mode: BlendMode::Difference,
+            opacity: 0.0,
+        };
+        let backdrop = [0.1, 0.2, 0.3, 0.4];
+
+        assert_eq!(blend.composite(backdrop, [1.0, 1.0, 1.0, 1.0]), backdrop);
+    }
+
+    #[test]
+    fn rgba8_pixels_are_composited_in_place() {
+        let mut backdrop = vec![128, 128, 128, 255, 128, 128, 128, 255];
+        let layer = vec![255, 0, 0, 255, 255, 0, 0, 0];
+
+        LayerBlend {
+            mode: BlendMode::Difference,
+            opacity: 1.0,
+        }
+        .composite_rgba8(&mut backdrop, &layer);
+
+        // the second layer pixel is transparent
+        assert_eq!(backdrop, vec![127, 128, 128, 255, 128, 128, 128, 255]);
+    }
+}
@@ -0,0 +1,553 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+
+use common_vector::basic::{Point, WindowSize};
+use common_vector::guideline::point_to_ndc;
+use common_vector::vertex::Vertex;
+use geo::{LineString, MultiPolygon, TriangulateEarcut};
+use serde::{Deserialize, Serialize};
+use strum::IntoEnumIterator;
+use strum_macros::EnumIter;
+use uuid::Uuid;
+
+use super::masks::{clip_mesh, Coverage};
+use crate::brush::eraser::to_geo_polygon;
+
+// miters on sharp corners are capped at this many times the offset
+const MITER_LIMIT: f32 = 4.0;
+// a shape can't be shrunk past this fraction of its half width
+const MAX_SHRINK: f32 = 0.95;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, EnumIter, Serialize, Deserialize)]
+pub enum EffectKind {
+    DropShadow,
+    InnerShadow,
+    LayerBlur,
+    OuterGlow,
+}
+
+impl EffectKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            EffectKind::DropShadow => "Drop Shadow",
+            EffectKind::InnerShadow => "Inner Shadow",
+            EffectKind::LayerBlur => "Layer Blur",
+            EffectKind::OuterGlow => "Outer Glow",
+        }
+    }
+
+    /// Whether it's drawn under the shape rather than on or instead of it
+    pub fn is_behind(&self) -> bool {
+        matches!(self, EffectKind::DropShadow | EffectKind::OuterGlow)
+    }
+
+    /// A blur only has a radius, the rest are positioned and coloured too
+    pub fn fields(&self) -> Vec<EffectField> {
+        match self {
+            EffectKind::LayerBlur => vec![EffectField::Blur],
+            _ => EffectField::iter().collect(),
+        }
+    }
+}
+
+/// One entry in a polygon's effects stack. Distances are in window pixels.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ShapeEffect {
+    pub kind: EffectKind,
+    pub enabled: bool,
+    pub offset: (f32, f32),
+    // how far the edge fades out over
+    pub blur: f32,
+    // grows the shape before it's blurred, shrinks it when negative
+    pub spread: f32,
+    // 0.0 - 1.0
+    pub color: [f32; 3],
+    pub opacity: f32,
+}
+
+impl ShapeEffect {
+    pub fn new(kind: EffectKind) -> Self {
+        let (offset, blur, color, opacity) = match kind {
+            EffectKind::DropShadow => ((0.0, 4.0), 8.0, [0.0, 0.0, 0.0], 0.25),
+            EffectKind::InnerShadow => ((0.0, 2.0), 4.0, [0.0, 0.0, 0.0], 0.25),
+            EffectKind::LayerBlur => ((0.0, 0.0), 4.0, [0.0, 0.0, 0.0], 1.0),
+            EffectKind::OuterGlow => ((0.0, 0.0), 12.0, [1.0, 0.9, 0.5], 0.6),
+        };
+
+        ShapeEffect {
+            kind,
+            enabled: true,
+            offset,
+            blur,
+            spread: 0.0,
+            color,
+            opacity,
+        }
+    }
+}
+
+/// What the properties panel edits on an effect
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, EnumIter)]
+pub enum EffectField {
+    OffsetX,
+    OffsetY,
+    Blur,
+    Spread,
+    Red,
+    Green,
+    Blue,
+    Opacity,
+}
+
+impl EffectField {
+    pub fn label(&self) -> &'static str {
+        match self {
+            EffectField::OffsetX => "X:",
+            EffectField::OffsetY => "Y:",
+            EffectField::Blur => "Blur:",
+            EffectField::Spread => "Spread:",
+            EffectField::Red => "Red:",
+            EffectField::Green => "Green:",
+            EffectField::Blue => "Blue:",
+            EffectField::Opacity => "Opacity (%):",
+        }
+    }
+
+    /// The name the field's input signal is registered under
+    pub fn signal_name(&self, index: usize) -> String {
+        let field = match self {
+            EffectField::OffsetX => "offset_x",
+            EffectField::OffsetY => "offset_y",
+            EffectField::Blur => "blur",
+            EffectField::Spread => "spread",
+            EffectField::Red => "red",
+            EffectField::Green => "green",
+            EffectField::Blue => "blue",
+            EffectField::Opacity => "opacity",
+        };
+
+        format!("effect_{}_{}", index, field)
+    }
+
+    /// The value as it's entered: colours as 0-255 and opacity as a percentage
+    pub fn read(&self, effect: &ShapeEffect) -> f32 {
+        match self {
+            EffectField::OffsetX => effect.offset.0,
+            EffectField::OffsetY => effect.offset.1,
+            EffectField::Blur => effect.blur,
+            EffectField::Spread => effect.spread,
+            EffectField::Red => (effect.color[0] * 255.0).round(),
+            EffectField::Green => (effect.color[1] * 255.0).round(),
+            EffectField::Blue => (effect.color[2] * 255.0).round(),
+            EffectField::Opacity => (effect.opacity * 100.0).round(),
+        }
+    }
+
+    pub fn apply(&self, effect: &mut ShapeEffect, value: f32) {
+        match self {
+            EffectField::OffsetX => effect.offset.0 = value,
+            EffectField::OffsetY => effect.offset.1 = value,
+            EffectField::Blur => effect.blur = value.max(0.0),
+            EffectField::Spread => effect.spread = value,
+            EffectField::Red => effect.color[0] = (value / 255.0).clamp(0.0, 1.0),
+            EffectField::Green => effect.color[1] = (value / 255.0).clamp(0.0, 1.0),
+            EffectField::Blue => effect.color[2] = (value / 255.0).clamp(0.0, 1.0),
+            EffectField::Opacity => effect.opacity = (value / 100.0).clamp(0.0, 1.0),
+        }
+    }
+}
+
+/// Effects stacks for every polygon, by layer id. Like masks, a stack outlives its layer
+/// so undoing a delete brings the effects back too.
+#[derive(Debug, Default)]
+pub struct ShapeEffects {
+    stacks: HashMap<Uuid, Vec<ShapeEffect>>,
+    // bumped on every change so effect geometry gets built again
+    generation: u64,
+}
+
+// Lock after the layer blends, never before
+pub type SharedShapeEffects = Arc<Mutex<ShapeEffects>>;
+
+impl ShapeEffects {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn get(&self, layer_id: Uuid) -> &[ShapeEffect] {
+        self.stacks
+            .get(&layer_id)
+            .map(|stack| stack.as_slice())
+            .unwrap_or_default()
+    }
+
+    pub fn set(&mut self, layer_id: Uuid, stack: Vec<ShapeEffect>) {
+        if stack.is_empty() {
+            self.stacks.remove(&layer_id);
+        } else {
+            self.stacks.insert(layer_id, stack);
+        }
+
+        self.generation += 1;
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Uuid, &[ShapeEffect])> {
+        self.stacks
+            .iter()
+            .map(|(layer_id, stack)| (*layer_id, stack.as_slice()))
+    }
+
+    pub fn clear(&mut self) {
+        self.stacks.clear();
+        self.generation += 1;
+    }
+}
+
+fn signed_area(outline: &[[f32; 2]]) -> f32 {
+    outline
+        .iter()
+        .zip(outline.iter().cycle().skip(1))
+        .map(|(a, b)| a[0] * b[1] - b[0] * a[1])
+        .sum::<f32>()
+        / 2.0
+}
+
+// Closing points and repeats make for zero length edges, which have no normal
+fn clean_outline(outline: &[[f32; 2]]) -> Vec<[f32; 2]> {
+    let mut cleaned: Vec<[f32; 2]> = Vec::with_capacity(outline.len());
+
+    for point in outline {
+        let repeated = cleaned
+            .last()
+            .is_some_and(|last| (last[0] - point[0]).hypot(last[1] - point[1]) < 1e-3);
+
+        if !repeated {
+            cleaned.push(*point);
+        }
+    }
+
+    while cleaned.len() > 1 {
+        let (first, last) = (cleaned[0], cleaned[cleaned.len() - 1]);
+        if (first[0] - last[0]).hypot(first[1] - last[1]) >= 1e-3 {
+            break;
+        }
+        cleaned.pop();
+    }
+
+    cleaned
+}
+
+/// The outline pushed out along its normals, or pulled in when the distance is negative.
+/// Every point moves, so the result lines up point for point with the original.
+pub fn offset_outline(outline: &[[f32; 2]], distance: f32) -> Vec<[f32; 2]> {
+    let count = outline.len();
+    if count < 3 || distance == 0.0 {
+        return outline.to_vec();
+    }
+
+    let (min, max) = outline.iter().fold(
+        ([f32::MAX, f32::MAX], [f32::MIN, f32::MIN]),
+        |(min, max), point| {
+            (
+                [min[0].min(point[0]), min[1].min(point[1])],
+                [max[0].max(point[0]), max[1].max(point[1])],
+            )
+        },
+    );
+    // pulled in too far the outline turns inside out
+    let limit = (max[0] - min[0]).min(max[1] - min[1]) / 2.0 * MAX_SHRINK;
+    let distance = distance.max(-limit);
+
+    let outward = signed_area(outline).signum();
+    let normal = |a: [f32; 2], b: [f32; 2]| {
+        let (dx, dy) = (b[0] - a[0], b[1] - a[1]);
+        let length = dx.hypot(dy).max(f32::EPSILON);
+        [outward * dy / length, -outward * dx / length]
+    };
+
+    (0..count)
+        .map(|i| {
+            let (previous, point, next) = (
+                outline[(i + count - 1) % count],
+                outline[i],
+                outline[(i + 1) % count],
+            );
+            let (n0, n1) = (normal(previous, point), normal(point, next));
+
+            let bisector = [n0[0] + n1[0], n0[1] + n1[1]];
+            let length = bisector[0].hypot(bisector[1]);
+
+            let direction = if length < 1e-6 {
+                // the outline doubles back on itself here
+                n0
+            } else {
+                let miter = [bisector[0] / length, bisector[1] / length];
+                let cos = (miter[0] * n0[0] + miter[1] * n0[1]).max(1.0 / MITER_LIMIT);
+                [miter[0] / cos, miter[1] / cos]
+            };
+
+            [
+                point[0] + direction[0] * distance,
+                point[1] + direction[1] * distance,
+            ]
+        })
+        .collect()
+}
+
+fn shift(outline: &[[f32; 2]], offset: (f32, f32)) -> Vec<[f32; 2]> {
+    outline
+        .iter()
+        .map(|[x, y]| [x + offset.0, y + offset.1])
+        .collect()
+}
+
+fn with_alpha(color: [f32; 3], alpha: f32) -> [f32; 4] {
+    [color[0], color[1], color[2], alpha]
+}
+
+// Builds in window pixels, the mesh is moved into NDC once it's done
+#[derive(Default)]
+struct MeshBuilder {
+    vertices: Vec<Vertex>,
+    indices: Vec<u32>,
+}
+
+impl MeshBuilder {
+    fn push_vertex(&mut self, [x, y]: [f32; 2], color: [f32; 4]) -> u32 {
+        self.vertices.push(Vertex {
+            position: [x, y, 0.0],
+            tex_coords: [0.0, 0.0],
+            color,
+        });
+        self.vertices.len() as u32 - 1
+    }
+
+    fn fill(&mut self, outline: &[[f32; 2]], color: [f32; 4]) {
+        let polygon = geo::Polygon::new(
+            LineString::from(
+                outline
+                    .iter()
+                    .map(|[x, y]| (*x as f64, *y as f64))
+                    .collect::<Vec<_>>(),
+            ),
+            vec![],
+        );
+        let triangulated = polygon.earcut_triangles_raw();
+        let offset = self.vertices.len() as u32;
+
+        for xy in triangulated.vertices.chunks_exact(2) {
+            self.push_vertex([xy[0] as f32, xy[1] as f32], color);
+        }
+        self.indices.extend(
+            triangulated
+                .triangle_indices
+                .iter()
+                .map(|index| *index as u32 + offset),
+        );
+    }
+
+    // The band between two outlines that line up point for point, colours fading across it
+    fn ring(
+        &mut self,
+        inner: &[[f32; 2]],
+        inner_color: [f32; 4],
+        outer: &[[f32; 2]],
+        outer_color: [f32; 4],
+    ) {
+        let count = inner.len().min(outer.len());
+        if count < 3 {
+            return;
+        }
+
+        let start = self.vertices.len() as u32;
+
+        for i in 0..count {
+            self.push_vertex(inner[i], inner_color);
+            self.push_vertex(outer[i], outer_color);
+        }
+
+        for i in 0..count as u32 {
+            let next = (i + 1) % count as u32;
+            let (a, b) = (start + i * 2, start + i * 2 + 1);
+            let (c, d) = (start + next * 2, start + next * 2 + 1);
+
+            self.indices.extend_from_slice(&[a, b, d, a, d, c]);
+        }
+    }
+
+    // A filled outline with its edge faded out over the blur, centred on the edge
+    fn feathered(&mut self, outline: &[[f32; 2]], spread: f32, blur: f32, color: [f32; 4]) {
+        let inner = offset_outline(outline, spread - blur / 2.0);
+        let outer = offset_outline(outline, spread + blur / 2.0);
+
+        self.fill(&inner, color);
+        if blur > 0.0 {
+            self.ring(&inner, color, &outer, [color[0], color[1], color[2], 0.0]);
+        }
+    }
+
+    fn finish(self, window_size: &WindowSize) -> (Vec<Vertex>, Vec<u32>) {
+        let vertices = self
+            .vertices
+            .into_iter()
+            .map(|vertex| {
+                let ndc = point_to_ndc(
+                    Point {
+                        x: vertex.position[0],
+                        y: vertex.position[1],
+                    },
+                    window_size,
+                );
+
+                Vertex {
+                    position: [ndc.x, ndc.y, vertex.position[2]],
+                    ..vertex
+                }
+            })
+            .collect();
+
+        (vertices, self.indices)
+    }
+}
+
+/// The effect's geometry for a shape with this outline (window pixels) and fill.
+/// A layer blur is drawn instead of the shape, the others along with it.
+pub fn effect_mesh(
+    effect: &ShapeEffect,
+    outline: &[[f32; 2]],
+    fill: [f32; 4],
+    window_size: &WindowSize,
+) -> (Vec<Vertex>, Vec<u32>) {
+    let outline = clean_outline(outline);
+    let mut mesh = MeshBuilder::default();
+
+    if outline.len() < 3 {
+        return mesh.finish(window_size);
+    }
+
+    let color = with_alpha(effect.color, effect.opacity);
+
+    match effect.kind {
+        EffectKind::DropShadow | EffectKind::OuterGlow => {
+            mesh.feathered(
+                &shift(&outline, effect.offset),
+                effect.spread,
+                effect.blur,
+                color,
+            );
+        }
+        EffectKind::LayerBlur => {
+            mesh.feathered(&outline, 0.0, effect.blur, fill);
+        }
+        EffectKind::InnerShadow => {
+            // everything outside the shifted shape casts the shadow, cut back to the shape
+            let hole = offset_outline(&shift(&outline, effect.offset), -effect.spread);
+            let edge = offset_outline(&hole, effect.blur / 2.0);
+            let extent =
+                effect.offset.0.hypot(effect.offset.1) + effect.spread.abs() + effect.blur + 2.0;
+
+            mesh.ring(
+                &offset_outline(&hole, -effect.blur / 2.0),
+                with_alpha(effect.color, 0.0),
+                &edge,
+                color,
+            );
+            mesh.ring(&edge, color, &offset_outline(&edge, extent), color);
+
+            let shape = Coverage(MultiPolygon::new(vec![to_geo_polygon(&outline)]));
+            let (vertices, indices) = clip_mesh(&mesh.vertices, &mesh.indices, &shape);
+            mesh = MeshBuilder { vertices, indices };
+        }
+    }
+
+    mesh.finish(window_size)
+}
+
+fn svg_color(color: [f32; 3]) -> String {
+    let [r, g, b] = color.map(|channel| (channel.clamp(0.0, 1.0) * 255.0).round() as u8);
+    format!("rgb({},{},{})", r, g, b)
+}
+
+/// The stack as an SVG filter, for a shape to reference with filter="url(#filter_id)".
+/// None when no effect is enabled.
+pub fn svg_filter(filter_id: &str, effects: &[ShapeEffect]) -> Option<String> {
+    let enabled: Vec<&ShapeEffect> = effects.iter().filter(|effect| effect.enabled).collect();
+    if enabled.is_empty() {
+        return None;
+    }
+
+    let mut primitives = String::new();
+    let mut behind = Vec::new();
+    let mut front = Vec::new();
+    let mut source = "SourceGraphic".to_string();
+
+    for (index, effect) in enabled.iter().enumerate() {
+        let result = format!("effect{}", index);
+        // the mesh fades out over the blur, which a deviation of half of it matches closely
+        let deviation = effect.blur / 2.0;
+        let (dx, dy) = effect.offset;
+
+        let _ = match effect.kind {
+            EffectKind::DropShadow | EffectKind::OuterGlow => writeln!(
+                primitives,
+                r#"    <feMorphology in="SourceAlpha" operator="{}" radius="{}" result="{result}_spread"/>
+    <feGaussianBlur in="{result}_spread" stdDeviation="{deviation}"/>
+    <feOffset dx="{dx}" dy="{dy}" result="{result}_shape"/>
+    <feFlood flood-color="{}" flood-opacity="{}"/>
+    <feComposite in2="{result}_shape" operator="in" result="{result}"/>"#,
+                if effect.spread < 0.0 {
+                    "erode"
+                } else {
+                    "dilate"
+                },
+                effect.spread.abs(),
+                svg_color(effect.color),
+                effect.opacity,
+            ),
+            EffectKind::InnerShadow => writeln!(
+                primitives,
+                r#"    <feMorphology in="SourceAlpha" operator="{}" radius="{}" result="{result}_spread"/>
+    <feGaussianBlur in="{result}_spread" stdDeviation="{deviation}"/>
+    <feOffset dx="{dx}" dy="{dy}" result="{result}_hole"/>
+    <feComposite in="SourceAlpha" in2="{result}_hole" operator="arithmetic" k2="1" k3="-1" result="{result}_shape"/>
+    <feFlood flood-color="{}" flood-opacity="{}"/>
+    <feComposite in2="{result}_shape" operator="in" result="{result}"/>"#,
+                if effect.spread < 0.0 {
+                    "dilate"
+                } else {
+                    "erode"
+                },
+                effect.spread.abs(),
+                svg_color(effect.color),
+                effect.opacity,
+            ),
+            EffectKind::LayerBlur => writeln!(
+                primitives,
+                r#"    <feGaussianBlur in="{source}" stdDeviation="{deviation}" result="{result}"/>"#,
+            ),
+        };
+
+        match effect.kind {
+            EffectKind::LayerBlur => source = result,
+            kind if kind.is_behind() => behind.push(result),
+            _ => front.push(result),
+        }
+    }
+
+    let merged = behind
+        .iter()
+        .chain(std::iter::once(&source))
+        .chain(front.iter())
+        .map(|input| format!("      <feMergeNode in=\"{}\"/>\n", input))
+        .collect::<String>();
+
+    Some(format!(
+        "<filter id=\"{}\" x=\"-50%\" y=\"-50%\" width=\"200%\" height=\"200%\" color-interpolation-filters=\"sRGB\">\n{}    <feMerge>\n{}    </feMerge>\n</filter>",
+        filter_id, primitives, merged
+    ))
+}
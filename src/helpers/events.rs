@@ -0,0 +1,148 @@
+use std::sync::{Arc, Mutex};
+
+use common_vector::editor::{LayersUpdateHandler, PolygonClickHandler};
+use common_vector::polygon::PolygonConfig;
+use crossbeam::channel::{unbounded, Receiver, Sender};
+use floem::ext_event::{register_ext_trigger, ExtSendTrigger};
+use floem::reactive::{create_effect, create_rw_signal, RwSignal, SignalUpdate, SignalWith};
+use uuid::Uuid;
+
+use crate::photo::filters::Filter;
+use crate::photo::scopes::Histogram;
+
+use crate::renderer::batch::BatchItemId;
+
+use super::layers::LayerChange;
+use super::locking::LockRecover;
+use super::redraw::Invalidator;
+
+pub enum EditorEvent {
+    // None when the selection is cleared
+    SelectionChanged(Option<(Uuid, PolygonConfig)>),
+    PolygonAdded(PolygonConfig),
+    PolygonRemoved(Uuid),
+    PolygonUpdated {
+        polygon_id: Uuid,
+        field_name: String,
+    },
+    // None when the stroke is deselected or deleted
+    StrokeSelectionChanged(Option<Uuid>),
+    StrokeUpdated {
+        stroke_id: Uuid,
+        field_name: String,
+    },
+    // None when the photo is deselected or deleted
+    ImageSelectionChanged(Option<Uuid>),
+    LayersChanged(Vec<LayerChange>),
+    // a layer's adjustment stack changed
+    AdjustmentsChanged(Uuid),
+    // a photo's crop changed, or it started or stopped being cropped on the canvas
+    CropChanged(Uuid),
+    // a photo's filters changed, or one started or stopped being previewed on it
+    FiltersChanged(Uuid),
+    // a filter finished baking on the worker, into a copy of the source it started from
+    FilterBaked {
+        image_id: Uuid,
+        filter: Filter,
+        source: Arc<Vec<u8>>,
+        baked: Arc<Vec<u8>>,
+    },
+    // the scopes finished counting the canvas as it was last read back
+    HistogramUpdated(Histogram),
+    // a layer's mask or clipping changed, or its mask started or stopped being painted on
+    MaskChanged(Uuid),
+    // a layer's blend mode or opacity changed
+    BlendChanged(Uuid),
+    // a polygon's effects stack changed
+    EffectsChanged(Uuid),
+    // the asset library moved or something was saved to it
+    AssetsChanged,
+    // something was changed in the settings panel
+    PreferencesChanged,
+    // the page was resized or measured in another unit
+    PageChanged,
+    CameraChanged,
+    HistoryChanged {
+        can_undo: bool,
+        can_redo: bool,
+    },
+}
+
+pub type EditorEventSender = Sender<EditorEvent>;
+pub type EditorEventReceiver = Receiver<EditorEvent>;
+
+pub fn create_event_channel() -> (EditorEventSender, EditorEventReceiver) {
+    unbounded()
+}
+
+/// Drains the channel on the UI thread and publishes each batch to the returned signal.
+/// The UI thread is only woken when something was sent, so an idle editor costs nothing.
+pub fn create_event_signal(receiver: EditorEventReceiver) -> RwSignal<Vec<EditorEvent>> {
+    let events = create_rw_signal(Vec::new());
+    let trigger = ExtSendTrigger::new();
+    let pending = Arc::new(Mutex::new(Vec::new()));
+
+    create_effect({
+        let pending = Arc::clone(&pending);
+        move |_| {
+            trigger.track();
+
+            let batch: Vec<EditorEvent> = pending.lock_or_recover().drain(..).collect();
+
+            if !batch.is_empty() {
+                events.set(batch);
+            }
+        }
+    });
+
+    std::thread::spawn(move || {
+        while let Ok(event) = receiver.recv() {
+            pending.lock_or_recover().push(event);
+            register_ext_trigger(trigger);
+        }
+    });
+
+    events
+}
+
+/// Runs the handler for every event in every batch, for as long as the calling view lives
+pub fn subscribe(events: RwSignal<Vec<EditorEvent>>, handler: impl Fn(&EditorEvent) + 'static) {
+    create_effect(move |subscribed: Option<()>| {
+        events.with(|batch| {
+            // the first run only tracks the signal, the batch it holds was sent before we subscribed
+            if subscribed.is_some() {
+                batch.iter().for_each(|event| handler(event));
+            }
+        });
+    });
+}
+
+// The editor still calls its handler fields, so point them at the channel once on startup
+pub fn polygon_click_handler(
+    sender: EditorEventSender,
+    invalidator: Invalidator,
+) -> Arc<PolygonClickHandler> {
+    Arc::new(move || {
+        let sender = sender.clone();
+        let invalidator = invalidator.clone();
+        Some(
+            Box::new(move |polygon_id: Uuid, polygon_data: PolygonConfig| {
+                // the editor drags whatever was clicked until the button comes up
+                invalidator.drag_started(BatchItemId::Polygon(polygon_id));
+                let _ = sender.send(EditorEvent::SelectionChanged(Some((
+                    polygon_id,
+                    polygon_data,
+                ))));
+            }) as Box<dyn FnMut(Uuid, PolygonConfig) + Send>,
+        )
+    })
+}
+
+pub fn layers_update_handler(sender: EditorEventSender) -> Arc<LayersUpdateHandler> {
+    Arc::new(move || {
+        let sender = sender.clone();
+        Some(Box::new(move |polygon_data: PolygonConfig| {
+            let _ = sender.send(EditorEvent::PolygonAdded(polygon_data));
+        }) as Box<dyn FnMut(PolygonConfig) + Send>)
+    })
+}
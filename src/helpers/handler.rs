@@ -8,6 +8,8 @@ use common_vector::editor::{Editor, Viewport};
 use common_vector::polygon::{Polygon, PolygonConfig};
 use floem_renderer::gpu_resources::GpuResources;
 
+use super::locking::LockRecover;
+
 struct Handler {
     button_handler: RefCell<Option<Box<dyn Fn(MutexGuard<'_, Editor>) + Send + 'static>>>,
 }
@@ -28,7 +30,7 @@ struct Handler {
 //     ) {
 //         let handler = Box::new(move |mut editor: MutexGuard<'_, Editor>| {
 //             println!("Button clicked, attempting to add polygon...");
-//             let viewport = viewport.lock().unwrap();
+//             let viewport = viewport.lock_or_recover();
 //             let window_size = WindowSize {
 //                 width: viewport.width as u32,
 //                 height: viewport.height as u32,
@@ -0,0 +1,328 @@
+use common_vector::editor::Editor;
+use common_vector::polygon::PolygonConfig;
+use uuid::Uuid;
+
+use crate::brush::strokes::{StrokeLayer, StrokeLayers};
+use crate::document::page::PageSetup;
+use crate::helpers::masks::{LayerLinks, LayerMasks};
+use crate::photo::images::{ImageLayer, ImageLayers};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LayerKind {
+    Polygon,
+    Stroke,
+    Image,
+    // Path,
+    // Text,
+    // Group,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Layer {
+    pub instance_id: Uuid,
+    pub instance_name: String,
+    pub instance_kind: LayerKind,
+    // the mask and clipping shown alongside it
+    pub links: LayerLinks,
+    // the artboard its center sits on, which Scene section it's listed in
+    pub artboard: Option<Uuid>,
+}
+
+impl Layer {
+    pub fn from_polygon_config(config: &PolygonConfig) -> Self {
+        Layer {
+            instance_id: config.id,
+            instance_name: config.name.clone(),
+            instance_kind: LayerKind::Polygon,
+            links: LayerLinks::default(),
+            artboard: None,
+        }
+    }
+
+    pub fn from_stroke(stroke: &StrokeLayer) -> Self {
+        Layer {
+            instance_id: stroke.id,
+            instance_name: stroke.name.clone(),
+            instance_kind: LayerKind::Stroke,
+            links: LayerLinks::default(),
+            artboard: None,
+        }
+    }
+
+    pub fn from_image(image: &ImageLayer) -> Self {
+        Layer {
+            instance_id: image.id,
+            instance_name: image.name.clone(),
+            instance_kind: LayerKind::Image,
+            links: LayerLinks::default(),
+            artboard: None,
+        }
+    }
+}
+
+/// A single change to the editor's layer list, applied in order by the Scene list
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LayerChange {
+    Added { index: usize, layer: Layer },
+    Removed { id: Uuid, kind: LayerKind },
+    Renamed { id: Uuid, name: String },
+    Linked { id: Uuid, links: LayerLinks },
+    Moved { id: Uuid, artboard: Option<Uuid> },
+    Reordered { order: Vec<Uuid> },
+}
+
+/// The Scene list's view of one layer, None when it isn't in the editor's layer list
+pub fn editor_layer(
+    editor: &Editor,
+    strokes: &StrokeLayers,
+    images: &ImageLayers,
+    masks: &LayerMasks,
+    page: &PageSetup,
+    layer_id: Uuid,
+) -> Option<Layer> {
+    if !editor.layer_list.contains(&layer_id) {
+        return None;
+    }
+
+    editor
+        .polygons
+        .iter()
+        .find(|polygon| polygon.id == layer_id)
+        .map(|polygon| {
+            let config = polygon.to_config();
+            let (width, height) = config.dimensions;
+            let center = [
+                config.position.x + width / 2.0,
+                config.position.y + height / 2.0,
+            ];
+
+            Layer {
+                artboard: page.artboard_at(center),
+                ..Layer::from_polygon_config(&config)
+            }
+        })
+        .or_else(|| {
+            strokes.get(layer_id).map(|stroke| Layer {
+                artboard: stroke.bounds().and_then(|(min, max)| {
+                    page.artboard_at([(min[0] + max[0]) / 2.0, (min[1] + max[1]) / 2.0])
+                }),
+                ..Layer::from_stroke(stroke)
+            })
+        })
+        .or_else(|| {
+            images.get(layer_id).map(|image| {
+                let (min, max) = image.bounds();
+
+                Layer {
+                    artboard: page.artboard_at([(min[0] + max[0]) / 2.0, (min[1] + max[1]) / 2.0]),
+                    ..Layer::from_image(image)
+                }
+            })
+        })
+        .map(|layer| Layer {
+            links: masks.links(layer_id),
+            ..layer
+        })
+}
+
+/// Builds the layer list from the editor, which is the single source of truth for order
+pub fn editor_layers(
+    editor: &Editor,
+    strokes: &StrokeLayers,
+    images: &ImageLayers,
+    masks: &LayerMasks,
+    page: &PageSetup,
+) -> Vec<Layer> {
+    editor
+        .layer_list
+        .iter()
+        .filter_map(|layer_id| editor_layer(editor, strokes, images, masks, page, *layer_id))
+        .collect()
+}
+
+pub fn apply_layer_change(layers: &mut Vec<Layer>, change: &LayerChange) {
+    match change {
+        LayerChange::Added { index, layer } => {
+            // guard against the same add arriving twice
+            if layers.iter().any(|l| l.instance_id == layer.instance_id) {
+                return;
+            }
+            let index = (*index).min(layers.len());
+            layers.insert(index, layer.clone());
+        }
+        LayerChange::Removed { id, .. } => {
+            layers.retain(|l| l.instance_id != *id);
+        }
+        LayerChange::Renamed { id, name } => {
+            if let Some(layer) = layers.iter_mut().find(|l| l.instance_id == *id) {
+                layer.instance_name = name.clone();
+            }
+        }
+        LayerChange::Linked { id, links } => {
+            if let Some(layer) = layers.iter_mut().find(|l| l.instance_id == *id) {
+                layer.links = *links;
+            }
+        }
+        LayerChange::Moved { id, artboard } => {
+            if let Some(layer) = layers.iter_mut().find(|l| l.instance_id == *id) {
+                layer.artboard = *artboard;
+            }
+        }
+        LayerChange::Reordered { order } => {
+            layers.sort_by_key(|l| {
+                order
+                    .iter()
+                    .position(|id| *id == l.instance_id)
+                    .unwrap_or(usize::MAX)
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layer(name: &str) -> Layer {
+        Layer {
+            instance_id: Uuid::new_v4(),
+            instance_name: name.to_string(),
+            instance_kind: LayerKind::Polygon,
+            links: LayerLinks::default(),
+            artboard: None,
+        }
+    }
+
+    fn names(layers: &[Layer]) -> Vec<&str> {
+        layers.iter().map(|l| l.instance_name.as_str()).collect()
+    }
+
+    #[test]
+    fn added_layers_go_where_they_were_put() {
+        let mut layers = vec![layer("a"), layer("b")];
+
+        apply_layer_change(
+            &mut layers,
+            &LayerChange::Added {
+                index: 1,
+                layer: layer("c"),
+            },
+        );
+        // past the end is on top
+        apply_layer_change(
+            &mut layers,
+            &LayerChange::Added {
+                index: 10,
+                layer: layer("d"),
+            },
+        );
+
+        assert_eq!(names(&layers), vec!["a", "c", "b", "d"]);
+    }
+
+    #[test]
+    fn adding_twice_is_ignored() {
+        let mut layers = vec![layer("a")];
+        let change = LayerChange::Added {
+            index: 0,
+            layer: layer("b"),
+        };
+
+        apply_layer_change(&mut layers, &change);
+        apply_layer_change(&mut layers, &change);
+
+        assert_eq!(names(&layers), vec!["b", "a"]);
+    }
+
+    #[test]
+    fn removing_then_undoing_puts_the_layer_back() {
+        let mut layers = vec![layer("a"), layer("b"), layer("c")];
+        let removed = layers[1].clone();
+
+        apply_layer_change(
+            &mut layers,
+            &LayerChange::Removed {
+                id: removed.instance_id,
+                kind: LayerKind::Polygon,
+            },
+        );
+        assert_eq!(names(&layers), vec!["a", "c"]);
+
+        apply_layer_change(
+            &mut layers,
+            &LayerChange::Added {
+                index: 1,
+                layer: removed,
+            },
+        );
+        assert_eq!(names(&layers), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn changes_to_a_layer_keep_its_place() {
+        let mut layers = vec![layer("a"), layer("b")];
+        let id = layers[0].instance_id;
+        let artboard = Uuid::new_v4();
+        let links = LayerLinks {
+            masked: true,
+            clipped: false,
+        };
+
+        apply_layer_change(
+            &mut layers,
+            &LayerChange::Renamed {
+                id,
+                name: "renamed".to_string(),
+            },
+        );
+        apply_layer_change(&mut layers, &LayerChange::Linked { id, links });
+        apply_layer_change(
+            &mut layers,
+            &LayerChange::Moved {
+                id,
+                artboard: Some(artboard),
+            },
+        );
+
+        assert_eq!(names(&layers), vec!["renamed", "b"]);
+        assert_eq!(layers[0].links, links);
+        assert_eq!(layers[0].artboard, Some(artboard));
+    }
+
+    #[test]
+    fn reordering_follows_the_editor_order() {
+        let mut layers = vec![layer("a"), layer("b"), layer("c")];
+        let order = vec![
+            layers[2].instance_id,
+            layers[0].instance_id,
+            layers[1].instance_id,
+        ];
+
+        apply_layer_change(&mut layers, &LayerChange::Reordered { order });
+
+        assert_eq!(names(&layers), vec!["c", "a", "b"]);
+    }
+
+    #[test]
+    fn changes_to_missing_layers_are_ignored() {
+        let mut layers = vec![layer("a")];
+        let id = Uuid::new_v4();
+
+        apply_layer_change(
+            &mut layers,
+            &LayerChange::Removed {
+                id,
+                kind: LayerKind::Stroke,
+            },
+        );
+        apply_layer_change(
+            &mut layers,
+            &LayerChange::Renamed {
+                id,
+                name: "b".to_string(),
+            },
+        );
+
+        assert_eq!(names(&layers), vec!["a"]);
+    }
+}
@@ -0,0 +1,17 @@
+use std::sync::{Mutex, MutexGuard};
+
+/// Locking that outlives a panic. A thread that panics while holding a lock poisons it,
+/// and unwrapping every later lock would take the whole app down with it, so the data
+/// is used as the panicking thread left it instead.
+pub trait LockRecover<T> {
+    fn lock_or_recover(&self) -> MutexGuard<'_, T>;
+}
+
+impl<T> LockRecover<T> for Mutex<T> {
+    fn lock_or_recover(&self) -> MutexGuard<'_, T> {
+        self.lock().unwrap_or_else(|poisoned| {
+            println!("Recovering a lock poisoned by a panic");
+            poisoned.into_inner()
+        })
+    }
+}
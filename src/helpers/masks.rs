@@ -0,0 +1,666 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use common_vector::basic::{Point, WindowSize};
+use common_vector::guideline::point_to_ndc;
+use common_vector::polygon::Polygon;
+use common_vector::vertex::Vertex;
+use geo::{
+    Area, BooleanOps, BoundingRect, Contains, Intersects, LineString, MapCoords, MultiPolygon,
+    Rect, TriangulateEarcut,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::brush::eraser::{polygon_outline, tapered_capsule, to_geo_polygon};
+use crate::brush::smoothing::catmull_rom;
+use crate::brush::strokes::StrokeLayer;
+use crate::photo::images::ImageLayer;
+
+// triangles thinner than this draw nothing, so they aren't worth clipping
+const MIN_TRIANGLE_AREA: f64 = 1e-12;
+
+/// A painted mask, one level per pixel over a rectangle of the scene. 255 shows the layer,
+/// 0 hides it and anything between lets part of it through. Everything outside the
+/// rectangle shows, so it only grows to cover what has been painted out.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RasterMask {
+    // top left, in window pixels
+    pub origin: [i32; 2],
+    pub width: u32,
+    pub height: u32,
+    // shared with the edits that hold older versions, so painting replaces it
+    #[serde(
+        serialize_with = "serialize_runs",
+        deserialize_with = "deserialize_runs"
+    )]
+    pub levels: Arc<Vec<u8>>,
+}
+
+impl RasterMask {
+    pub fn is_empty(&self) -> bool {
+        self.levels.is_empty()
+    }
+
+    /// The level at a point in window pixels
+    pub fn level(&self, x: f32, y: f32) -> u8 {
+        let (x, y) = (
+            x.floor() as i64 - self.origin[0] as i64,
+            y.floor() as i64 - self.origin[1] as i64,
+        );
+        if x < 0 || y < 0 || x >= self.width as i64 || y >= self.height as i64 {
+            return 255;
+        }
+
+        self.levels[y as usize * self.width as usize + x as usize]
+    }
+
+    /// Paints a round tip along the path, each point with its own radius. Brushes bring the
+    /// layer back and the eraser hides it, by as much as the strength.
+    pub fn paint(&mut self, path: &[([f32; 2], f32)], strength: f32, reveal: bool) {
+        let Some((min, max)) = path_bounds(path) else {
+            return;
+        };
+
+        // bringing back what was never hidden changes nothing
+        if reveal {
+            if self.is_empty() {
+                return;
+            }
+        } else {
+            self.grow(min, max);
+        }
+
+        let left = min[0].max(self.origin[0]);
+        let top = min[1].max(self.origin[1]);
+        let right = max[0].min(self.origin[0] + self.width as i32);
+        let bottom = max[1].min(self.origin[1] + self.height as i32);
+        if left >= right || top >= bottom {
+            return;
+        }
+
+        let strength = strength.clamp(0.0, 1.0);
+        let width = self.width as usize;
+        let origin = self.origin;
+        let levels = Arc::make_mut(&mut self.levels);
+
+        for y in top..bottom {
+            for x in left..right {
+                let coverage = path_coverage(path, [x as f32 + 0.5, y as f32 + 0.5]) * strength;
+                if coverage <= 0.0 {
+                    continue;
+                }
+
+                let at = (y - origin[1]) as usize * width + (x - origin[0]) as usize;
+                let level = levels[at] as f32 / 255.0;
+                let level = if reveal {
+                    level + (1.0 - level) * coverage
+                } else {
+                    level * (1.0 - coverage)
+                };
+                levels[at] = (level * 255.0).round() as u8;
+            }
+        }
+    }
+
+    // Makes room for the rectangle, keeping what's painted. New pixels show the layer.
+    fn grow(&mut self, min: [i32; 2], max: [i32; 2]) {
+        let (left, top, right, bottom) = if self.is_empty() {
+            (min[0], min[1], max[0], max[1])
+        } else {
+            (
+                min[0].min(self.origin[0]),
+                min[1].min(self.origin[1]),
+                max[0].max(self.origin[0] + self.width as i32),
+                max[1].max(self.origin[1] + self.height as i32),
+            )
+        };
+        let (width, height) = ((right - left) as u32, (bottom - top) as u32);
+
+        if [left, top] == self.origin && (width, height) == (self.width, self.height) {
+            return;
+        }
+
+        let mut levels = vec![255; width as usize * height as usize];
+        for row in 0..self.height as usize {
+            let from = row * self.width as usize;
+            let to = (row + (self.origin[1] - top) as usize) * width as usize
+                + (self.origin[0] - left) as usize;
+            levels[to..to + self.width as usize]
+                .copy_from_slice(&self.levels[from..from + self.width as usize]);
+        }
+
+        *self = RasterMask {
+            origin: [left, top],
+            width,
+            height,
+            levels: Arc::new(levels),
+        };
+    }
+}
+
+// the whole pixels the tip touches, with a pixel to spare for the soft edge
+fn path_bounds(path: &[([f32; 2], f32)]) -> Option<([i32; 2], [i32; 2])> {
+    path.iter().fold(None, |bounds, ([x, y], radius)| {
+        let (min, max) = (
+            [
+                (x - radius).floor() as i32 - 1,
+                (y - radius).floor() as i32 - 1,
+            ],
+            [
+                (x + radius).ceil() as i32 + 1,
+                (y + radius).ceil() as i32 + 1,
+            ],
+        );
+        Some(match bounds {
+            Some((low, high)) => (
+                [min[0].min(low[0]), min[1].min(low[1])],
+                [max[0].max(high[0]), max[1].max(high[1])],
+            ),
+            None => (min, max),
+        })
+    })
+}
+
+// How much of the pixel around the point the tip covers, softened over one pixel
+fn path_coverage(path: &[([f32; 2], f32)], point: [f32; 2]) -> f32 {
+    let edge = |([ax, ay], ra): ([f32; 2], f32), ([bx, by], rb): ([f32; 2], f32)| {
+        let (dx, dy) = (bx - ax, by - ay);
+        let length = dx * dx + dy * dy;
+        let t = if length <= f32::EPSILON {
+            0.0
+        } else {
+            (((point[0] - ax) * dx + (point[1] - ay) * dy) / length).clamp(0.0, 1.0)
+        };
+        let (cx, cy) = (ax + dx * t, ay + dy * t);
+        let distance = ((point[0] - cx).powi(2) + (point[1] - cy).powi(2)).sqrt();
+
+        (ra + (rb - ra) * t) - distance + 0.5
+    };
+
+    let inside = match path {
+        [] => return 0.0,
+        [only] => edge(*only, *only),
+        _ => path
+            .windows(2)
+            .map(|pair| edge(pair[0], pair[1]))
+            .fold(f32::MIN, f32::max),
+    };
+
+    inside.clamp(0.0, 1.0)
+}
+
+// Painted masks are mostly one level, so they're saved as runs of (level, count)
+fn serialize_runs<S: serde::Serializer>(
+    levels: &Arc<Vec<u8>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    encode_runs(levels).serialize(serializer)
+}
+
+fn deserialize_runs<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Arc<Vec<u8>>, D::Error> {
+    let runs = Vec::<(u8, u32)>::deserialize(deserializer)?;
+    Ok(Arc::new(decode_runs(&runs)))
+}
+
+fn encode_runs(levels: &[u8]) -> Vec<(u8, u32)> {
+    let mut runs: Vec<(u8, u32)> = Vec::new();
+
+    for level in levels {
+        match runs.last_mut() {
+            Some((last, count)) if last == level => *count += 1,
+            _ => runs.push((*level, 1)),
+        }
+    }
+
+    runs
+}
+
+fn decode_runs(runs: &[(u8, u32)]) -> Vec<u8> {
+    runs.iter()
+        .flat_map(|(level, count)| std::iter::repeat(*level).take(*count as usize))
+        .collect()
+}
+
+/// What hides parts of a layer without touching the layer itself, in window pixels.
+/// Both parts are optional: a fresh mask shows everything.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LayerMask {
+    // the vector mask, only what's inside it shows
+    pub shape: Option<MultiPolygon<f64>>,
+    // the raster mask, painted out with the eraser and back in with a brush
+    #[serde(default)]
+    pub painted: RasterMask,
+    pub enabled: bool,
+}
+
+impl Default for LayerMask {
+    fn default() -> Self {
+        LayerMask {
+            shape: None,
+            painted: RasterMask::default(),
+            enabled: true,
+        }
+    }
+}
+
+impl LayerMask {
+    /// The part of an outline the vector mask lets through. The painted levels are
+    /// applied per pixel as the layer is drawn.
+    pub fn visible(&self, outline: &MultiPolygon<f64>) -> MultiPolygon<f64> {
+        match &self.shape {
+            Some(shape) => outline.intersection(shape),
+            None => outline.clone(),
+        }
+    }
+}
+
+/// Everything that changes how a layer is masked, so an edit can swap it as a whole
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MaskState {
+    pub mask: Option<LayerMask>,
+    // clipped to the first unclipped layer below it
+    pub clipped: bool,
+}
+
+/// What the Scene list shows next to a layer
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub struct LayerLinks {
+    pub masked: bool,
+    pub clipped: bool,
+}
+
+/// Masks and clipping for every layer, by layer id. Stacking order lives in the
+/// editor's layer list, so a layer that leaves the scene and comes back keeps its mask.
+#[derive(Debug, Default)]
+pub struct LayerMasks {
+    states: HashMap<Uuid, MaskState>,
+    // bumped on every change so masked layers get clipped again
+    generation: u64,
+}
+
+// Lock after the stroke layers, never before
+pub type SharedLayerMasks = Arc<Mutex<LayerMasks>>;
+
+impl LayerMasks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The painted levels the layer is drawn through, None when there are none or the
+    /// mask is turned off
+    pub fn painted(&self, layer_id: Uuid) -> Option<&RasterMask> {
+        self.get(layer_id)
+            .filter(|mask| mask.enabled)
+            .map(|mask| &mask.painted)
+            .filter(|painted| !painted.is_empty())
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn get(&self, layer_id: Uuid) -> Option<&LayerMask> {
+        self.states.get(&layer_id)?.mask.as_ref()
+    }
+
+    pub fn is_clipped(&self, layer_id: Uuid) -> bool {
+        self.states
+            .get(&layer_id)
+            .is_some_and(|state| state.clipped)
+    }
+
+    pub fn state(&self, layer_id: Uuid) -> MaskState {
+        self.states.get(&layer_id).cloned().unwrap_or_default()
+    }
+
+    pub fn set_state(&mut self, layer_id: Uuid, state: MaskState) {
+        if state == MaskState::default() {
+            self.states.remove(&layer_id);
+        } else {
+            self.states.insert(layer_id, state);
+        }
+
+        self.generation += 1;
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Uuid, &MaskState)> {
+        self.states
+            .iter()
+            .map(|(layer_id, state)| (*layer_id, state))
+    }
+
+    pub fn clear(&mut self) {
+        self.states.clear();
+        self.generation += 1;
+    }
+
+    pub fn links(&self, layer_id: Uuid) -> LayerLinks {
+        LayerLinks {
+            masked: self.get(layer_id).is_some(),
+            clipped: self.is_clipped(layer_id),
+        }
+    }
+}
+
+/// A layer's geometry, borrowed from the scene
+#[derive(Copy, Clone)]
+pub enum LayerShape<'a> {
+    Polygon(&'a Polygon),
+    Stroke(&'a StrokeLayer),
+    Image(&'a ImageLayer),
+}
+
+impl LayerShape<'_> {
+    /// The area the layer covers, in window pixels
+    pub fn outline(&self) -> MultiPolygon<f64> {
+        match self {
+            LayerShape::Polygon(polygon) => {
+                MultiPolygon::new(vec![to_geo_polygon(&polygon_outline(&polygon.to_config()))])
+            }
+            LayerShape::Stroke(layer) => {
+                let settings = &layer.settings;
+                let path: Vec<([f32; 2], f32)> = catmull_rom(&layer.samples)
+                    .iter()
+                    .map(|sample| {
+                        let radius =
+                            settings.size / 2.0 * settings.pressure_curve.size_scale(sample);
+                        ([sample.x, sample.y], radius)
+                    })
+                    .collect();
+
+                sweep(&path)
+            }
+            LayerShape::Image(image) => MultiPolygon::new(vec![to_geo_polygon(&image.outline())]),
+        }
+    }
+}
+
+/// The area covered by a round tip moving along the path, each point with its own radius
+pub fn sweep(path: &[([f32; 2], f32)]) -> MultiPolygon<f64> {
+    let capsules: Vec<geo::Polygon<f64>> = match path {
+        [] => vec![],
+        [(point, radius)] => vec![tapered_capsule(*point, *radius, *point, *radius)],
+        _ => path
+            .windows(2)
+            .map(|pair| tapered_capsule(pair[0].0, pair[0].1, pair[1].0, pair[1].1))
+            .collect(),
+    };
+
+    union_all(capsules)
+}
+
+// Halves keep the shapes being merged small, one at a time gets slow on long strokes
+fn union_all(mut polygons: Vec<geo::Polygon<f64>>) -> MultiPolygon<f64> {
+    if polygons.len() <= 1 {
+        return MultiPolygon::new(polygons);
+    }
+
+    let second = polygons.split_off(polygons.len() / 2);
+    union_all(polygons).union(&union_all(second))
+}
+
+/// Which part of a masked layer still draws
+#[derive(Debug, Clone, PartialEq)]
+pub struct Coverage(pub MultiPolygon<f64>);
+
+impl Coverage {
+    /// The same region in the normalized device coordinates vertices are stored in
+    pub fn to_ndc(&self, window_size: &WindowSize) -> Coverage {
+        let convert = |region: &MultiPolygon<f64>| {
+            region.map_coords(|coord| {
+                let ndc = point_to_ndc(
+                    Point {
+                        x: coord.x as f32,
+                        y: coord.y as f32,
+                    },
+                    window_size,
+                );
+                geo::Coord {
+                    x: ndc.x as f64,
+                    y: ndc.y as f64,
+                }
+            })
+        };
+
+        Coverage(convert(&self.0))
+    }
+}
+
+/// Everything a layer is masked by
+pub struct MaskSource<'a> {
+    pub mask: Option<&'a LayerMask>,
+    // the layer it's clipped to, with that layer's own mask
+    pub base: Option<(LayerShape<'a>, Option<&'a LayerMask>)>,
+}
+
+impl MaskSource<'_> {
+    /// None when no shape cuts the layer. The painted levels aren't part of it, they're
+    /// sampled per pixel by the scene pipeline.
+    pub fn coverage(&self) -> Option<Coverage> {
+        let base = self
+            .base
+            .as_ref()
+            .map(|(shape, base_mask)| (shape.outline(), *base_mask));
+
+        clip_region(self.mask, base).map(Coverage)
+    }
+}
+
+/// What's left of a layer once its vector mask and the visible part of the layer it's
+/// clipped to have cut it, in the same space as the outlines. None when nothing cuts it,
+/// a disabled mask counts as no mask.
+pub fn clip_region(
+    mask: Option<&LayerMask>,
+    base: Option<(MultiPolygon<f64>, Option<&LayerMask>)>,
+) -> Option<MultiPolygon<f64>> {
+    let mask = mask.filter(|mask| mask.enabled);
+
+    let base = base.map(
+        |(outline, base_mask)| match base_mask.filter(|mask| mask.enabled) {
+            Some(base_mask) => base_mask.visible(&outline),
+            None => outline,
+        },
+    );
+
+    match (mask.and_then(|mask| mask.shape.clone()), base) {
+        (Some(shape), Some(base)) => Some(shape.intersection(&base)),
+        (shape, base) => shape.or(base),
+    }
+}
+
+fn overlaps(a: &Rect<f64>, b: &Rect<f64>) -> bool {
+    a.min().x <= b.max().x
+        && a.max().x >= b.min().x
+        && a.min().y <= b.max().y
+        && a.max().y >= b.min().y
+}
+
+// The vertex at (x, y) inside the triangle, with its colour blended from the corners
+fn interpolate(corners: &[Vertex; 3], x: f32, y: f32) -> Vertex {
+    let [a, b, c] = corners.map(|v| v.position);
+    let denominator = (b[1] - c[1]) * (a[0] - c[0]) + (c[0] - b[0]) * (a[1] - c[1]);
+
+    let (wa, wb) = if denominator.abs() <= f32::EPSILON {
+        (1.0, 0.0)
+    } else {
+        (
+            ((b[1] - c[1]) * (x - c[0]) + (c[0] - b[0]) * (y - c[1])) / denominator,
+            ((c[1] - a[1]) * (x - c[0]) + (a[0] - c[0]) * (y - c[1])) / denominator,
+        )
+    };
+    let wc = 1.0 - wa - wb;
+    let blend = |a: f32, b: f32, c: f32| a * wa + b * wb + c * wc;
+
+    Vertex {
+        position: [x, y, a[2]],
+        tex_coords: [0, 1].map(|i| {
+            blend(
+                corners[0].tex_coords[i],
+                corners[1].tex_coords[i],
+                corners[2].tex_coords[i],
+            )
+        }),
+        color: [0, 1, 2, 3].map(|i| {
+            blend(
+                corners[0].color[i],
+                corners[1].color[i],
+                corners[2].color[i],
+            )
+        }),
+    }
+}
+
+/// Cuts a triangle mesh down to the coverage. Coverage must be in the same space as the
+/// vertices. Triangles entirely on one side are kept or dropped whole, only those on an
+/// edge of the mask are cut and triangulated again.
+pub fn clip_mesh(
+    vertices: &[Vertex],
+    indices: &[u32],
+    coverage: &Coverage,
+) -> (Vec<Vertex>, Vec<u32>) {
+    let region = &coverage.0;
+    let bounds = region.bounding_rect();
+
+    let mut clipped_vertices = Vec::new();
+    let mut clipped_indices = Vec::new();
+    // whole triangles keep sharing their vertices
+    let mut remapped: Vec<Option<u32>> = vec![None; vertices.len()];
+
+    for triangle in indices.chunks_exact(3) {
+        let corners = [0, 1, 2].map(|i| vertices[triangle[i] as usize]);
+        let shape = geo::Polygon::new(
+            LineString::from(
+                corners
+                    .iter()
+                    .map(|v| (v.position[0] as f64, v.position[1] as f64))
+                    .collect::<Vec<_>>(),
+            ),
+            vec![],
+        );
+
+        if shape.unsigned_area() < MIN_TRIANGLE_AREA {
+            continue;
+        }
+
+        let touches = match (&bounds, shape.bounding_rect()) {
+            (Some(bounds), Some(rect)) => overlaps(bounds, &rect) && region.intersects(&shape),
+            _ => false,
+        };
+
+        let whole = if !touches {
+            Some(false)
+        } else if region.contains(&shape) {
+            Some(true)
+        } else {
+            None
+        };
+
+        match whole {
+            Some(true) => {
+                for index in triangle {
+                    let slot = &mut remapped[*index as usize];
+                    let new_index = *slot.get_or_insert_with(|| {
+                        clipped_vertices.push(vertices[*index as usize]);
+                        clipped_vertices.len() as u32 - 1
+                    });
+                    clipped_indices.push(new_index);
+                }
+            }
+            Some(false) => {}
+            None => {
+                let shape = MultiPolygon::new(vec![shape]);
+                let pieces = shape.intersection(region);
+
+                for piece in &pieces {
+                    let triangulated = piece.earcut_triangles_raw();
+                    let offset = clipped_vertices.len() as u32;
+
+                    clipped_vertices.extend(
+                        triangulated
+                            .vertices
+                            .chunks_exact(2)
+                            .map(|xy| interpolate(&corners, xy[0] as f32, xy[1] as f32)),
+                    );
+                    clipped_indices.extend(
+                        triangulated
+                            .triangle_indices
+                            .iter()
+                            .map(|index| *index as u32 + offset),
+                    );
+                }
+            }
+        }
+    }
+
+    (clipped_vertices, clipped_indices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_eraser_hides_and_a_brush_brings_back() {
+        let mut painted = RasterMask::default();
+        let dab = [([10.0, 10.0], 3.0)];
+
+        painted.paint(&dab, 1.0, false);
+        assert_eq!(painted.level(10.0, 10.0), 0);
+        // outside the tip, and outside the rectangle, the layer still shows
+        assert_eq!(painted.level(10.0, 15.5), 255);
+        assert_eq!(painted.level(100.0, 100.0), 255);
+
+        painted.paint(&dab, 0.5, true);
+        assert_eq!(painted.level(10.0, 10.0), 128);
+    }
+
+    #[test]
+    fn edges_of_the_tip_are_partly_covered() {
+        let mut painted = RasterMask::default();
+        painted.paint(&[([0.0, 0.0], 2.25), ([20.0, 0.0], 2.25)], 1.0, false);
+
+        let edge = painted.level(10.0, 2.0);
+        assert!(edge > 0 && edge < 255, "{}", edge);
+        assert_eq!(painted.level(10.0, 0.0), 0);
+    }
+
+    #[test]
+    fn revealing_an_unpainted_mask_leaves_it_empty() {
+        let mut painted = RasterMask::default();
+        painted.paint(&[([10.0, 10.0], 3.0)], 1.0, true);
+
+        assert!(painted.is_empty());
+    }
+
+    #[test]
+    fn growing_keeps_what_was_painted() {
+        let mut painted = RasterMask::default();
+        painted.paint(&[([10.0, 10.0], 2.0)], 1.0, false);
+        let before = painted.clone();
+
+        painted.paint(&[([40.0, -20.0], 2.0)], 1.0, false);
+
+        assert!(painted.width > before.width && painted.height > before.height);
+        assert_eq!(painted.level(10.0, 10.0), 0);
+        assert_eq!(painted.level(40.0, -20.0), 0);
+        assert_eq!(painted.level(25.0, 0.0), 255);
+        // the edit holding the old levels still has them
+        assert_eq!(before.level(40.0, -20.0), 255);
+    }
+
+    #[test]
+    fn levels_are_saved_as_runs() {
+        let levels = vec![255, 255, 255, 0, 0, 128];
+
+        assert_eq!(encode_runs(&levels), vec![(255, 3), (0, 2), (128, 1)]);
+        assert_eq!(decode_runs(&encode_runs(&levels)), levels);
+
+        let mut painted = RasterMask::default();
+        painted.paint(&[([5.0, 5.0], 2.0)], 1.0, false);
+        let json = serde_json::to_string(&painted).unwrap();
+        assert_eq!(serde_json::from_str::<RasterMask>(&json).unwrap(), painted);
+    }
+}
@@ -1 +1,11 @@
+pub mod blending;
+pub mod effects;
+pub mod events;
 pub mod handler;
+pub mod layers;
+pub mod locking;
+pub mod masks;
+pub mod navigation;
+pub mod preferences;
+pub mod redraw;
+pub mod snapping;
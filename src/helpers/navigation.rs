@@ -0,0 +1,415 @@
+use common_vector::basic::string_to_f32;
+use common_vector::editor::Editor;
+
+use super::locking::LockRecover;
+
+pub const MIN_ZOOM: f32 = 0.05;
+pub const MAX_ZOOM: f32 = 64.0;
+// each notch of a mouse wheel
+pub const WHEEL_ZOOM_STEP: f32 = 1.1;
+// trackpads report pinches as small pixel deltas
+const PINCH_ZOOM_SPEED: f32 = 0.01;
+// the panels cover the left of the window, so fitting centers in what's left
+const PANELS_WIDTH: f32 = 420.0;
+// room left around whatever is zoomed to
+const FIT_MARGIN: f32 = 40.0;
+// room left around the scene in the navigator, as a share of its larger side
+const OVERVIEW_PADDING: f32 = 0.05;
+
+/// Where the camera is looking, copied out of and back into the editor's camera.
+/// A scene point lands on screen at (point - center - position) * zoom + center, with
+/// the center being the middle of the window.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct View {
+    pub zoom: f32,
+    // in scene pixels
+    pub position: [f32; 2],
+    // the window, in screen pixels
+    pub size: [f32; 2],
+}
+
+impl View {
+    /// Where the editor's camera is looking, for callers that already hold the editor
+    pub fn from_editor(editor: &Editor) -> View {
+        // the panels can mount before the canvas has a camera
+        let (zoom, position) = editor.camera.map_or((1.0, [0.0, 0.0]), |camera| {
+            (camera.zoom, [camera.position.x, camera.position.y])
+        });
+        let viewport = editor.viewport.lock_or_recover();
+
+        View {
+            zoom,
+            position,
+            size: [viewport.width, viewport.height],
+        }
+    }
+
+    fn center(&self) -> [f32; 2] {
+        [self.size[0] / 2.0, self.size[1] / 2.0]
+    }
+
+    pub fn screen_to_scene(&self, [x, y]: [f32; 2]) -> [f32; 2] {
+        let [cx, cy] = self.center();
+
+        [
+            (x - cx) / self.zoom + cx + self.position[0],
+            (y - cy) / self.zoom + cy + self.position[1],
+        ]
+    }
+
+    /// Moves the scene along with a drag of this many screen pixels
+    pub fn pan(&mut self, dx: f32, dy: f32) {
+        self.position[0] -= dx / self.zoom;
+        self.position[1] -= dy / self.zoom;
+    }
+
+    /// Zooms while keeping the scene point under the anchor where it is on screen
+    pub fn zoom_around(&mut self, zoom: f32, anchor: [f32; 2]) {
+        let [x, y] = self.screen_to_scene(anchor);
+        let [cx, cy] = self.center();
+
+        self.zoom = zoom.clamp(MIN_ZOOM, MAX_ZOOM);
+        self.position = [
+            x - cx - (anchor[0] - cx) / self.zoom,
+            y - cy - (anchor[1] - cy) / self.zoom,
+        ];
+    }
+
+    /// Zooms around the middle of the canvas, as the commands and typed zooms do
+    pub fn zoom_centered(&mut self, zoom: f32) {
+        let (min, max) = self.canvas();
+        self.zoom_around(zoom, [(min[0] + max[0]) / 2.0, (min[1] + max[1]) / 2.0]);
+    }
+
+    /// A pinch reported as a scroll of this many pixels
+    pub fn pinch(&mut self, delta: f32, anchor: [f32; 2]) {
+        self.zoom_around(self.zoom * (delta * PINCH_ZOOM_SPEED).exp(), anchor);
+    }
+
+    /// A trackpad pinch as the platform reports it, a delta of 0.1 grows the view by about
+    /// a tenth and pinching back by the same amount undoes it
+    pub fn magnify(&mut self, delta: f32, anchor: [f32; 2]) {
+        self.zoom_around(self.zoom * delta.exp(), anchor);
+    }
+
+    /// Zooms and pans so the bounds fill the canvas
+    pub fn fit(&mut self, (min, max): ([f32; 2], [f32; 2])) {
+        let (canvas_min, canvas_max) = self.canvas();
+        let available = [
+            (canvas_max[0] - canvas_min[0] - FIT_MARGIN * 2.0).max(1.0),
+            (canvas_max[1] - canvas_min[1] - FIT_MARGIN * 2.0).max(1.0),
+        ];
+        let size = [(max[0] - min[0]).max(1.0), (max[1] - min[1]).max(1.0)];
+
+        let zoom = (available[0] / size[0]).min(available[1] / size[1]);
+        let middle = [(min[0] + max[0]) / 2.0, (min[1] + max[1]) / 2.0];
+        let canvas_middle = [
+            (canvas_min[0] + canvas_max[0]) / 2.0,
+            (canvas_min[1] + canvas_max[1]) / 2.0,
+        ];
+        let [cx, cy] = self.center();
+
+        self.zoom = zoom.clamp(MIN_ZOOM, MAX_ZOOM);
+        self.position = [
+            middle[0] - cx - (canvas_middle[0] - cx) / self.zoom,
+            middle[1] - cy - (canvas_middle[1] - cy) / self.zoom,
+        ];
+    }
+
+    // the part of the window the panels leave uncovered
+    fn canvas(&self) -> ([f32; 2], [f32; 2]) {
+        let left = if self.size[0] > PANELS_WIDTH * 2.0 {
+            PANELS_WIDTH
+        } else {
+            0.0
+        };
+
+        ([left, 0.0], self.size)
+    }
+
+    /// Whether a point on screen is over the canvas rather than the panels
+    pub fn on_canvas(&self, [x, y]: [f32; 2]) -> bool {
+        let (min, max) = self.canvas();
+        x >= min[0] && x < max[0] && y >= min[1] && y < max[1]
+    }
+
+    /// The part of the scene the canvas shows
+    pub fn visible(&self) -> ([f32; 2], [f32; 2]) {
+        let (min, max) = self.canvas();
+        (self.screen_to_scene(min), self.screen_to_scene(max))
+    }
+
+    /// Moves what the canvas looks at by this much of the scene
+    pub fn shift(&mut self, dx: f32, dy: f32) {
+        self.position[0] += dx;
+        self.position[1] += dy;
+    }
+
+    /// Moves the canvas so the scene point is in the middle of it
+    pub fn center_on(&mut self, [x, y]: [f32; 2]) {
+        let (min, max) = self.visible();
+        self.shift(x - (min[0] + max[0]) / 2.0, y - (min[1] + max[1]) / 2.0);
+    }
+
+    pub fn percent(&self) -> String {
+        format!("{}%", (self.zoom * 100.0).round())
+    }
+}
+
+/// A zoom as it's typed, e.g. "150" or "150%"
+pub fn parse_zoom(text: &str) -> Result<f32, String> {
+    let percent = string_to_f32(text.trim().trim_end_matches('%').trim())
+        .map_err(|_| "Couldn't convert string to f32")?;
+
+    if percent <= 0.0 {
+        return Err("Zoom must be above 0%".to_string());
+    }
+
+    Ok((percent / 100.0).clamp(MIN_ZOOM, MAX_ZOOM))
+}
+
+/// The smallest box around all of them
+pub fn union_bounds(
+    bounds: impl IntoIterator<Item = ([f32; 2], [f32; 2])>,
+) -> Option<([f32; 2], [f32; 2])> {
+    bounds
+        .into_iter()
+        .reduce(|(min, max), (other_min, other_max)| {
+            (
+                [min[0].min(other_min[0]), min[1].min(other_min[1])],
+                [max[0].max(other_max[0]), max[1].max(other_max[1])],
+            )
+        })
+}
+
+/// Maps the scene into the navigator, with the extent centered in it like an svg viewBox
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Overview {
+    pub extent: ([f32; 2], [f32; 2]),
+    // the navigator, in screen pixels
+    pub size: [f32; 2],
+}
+
+impl Overview {
+    /// Covers everything drawn and what the canvas shows, with some room around it
+    pub fn new(
+        content: Option<([f32; 2], [f32; 2])>,
+        visible: ([f32; 2], [f32; 2]),
+        size: [f32; 2],
+    ) -> Self {
+        let (min, max) = union_bounds(content.into_iter().chain(Some(visible))).unwrap_or(visible);
+        let padding = (max[0] - min[0]).max(max[1] - min[1]) * OVERVIEW_PADDING;
+
+        Overview {
+            extent: (
+                [min[0] - padding, min[1] - padding],
+                [max[0] + padding, max[1] + padding],
+            ),
+            size,
+        }
+    }
+
+    fn scale(&self) -> f32 {
+        let ([left, top], [right, bottom]) = self.extent;
+        (self.size[0] / (right - left).max(1.0)).min(self.size[1] / (bottom - top).max(1.0))
+    }
+
+    fn offset(&self) -> [f32; 2] {
+        let ([left, top], [right, bottom]) = self.extent;
+        let scale = self.scale();
+
+        [
+            (self.size[0] - (right - left) * scale) / 2.0 - left * scale,
+            (self.size[1] - (bottom - top) * scale) / 2.0 - top * scale,
+        ]
+    }
+
+    pub fn to_panel(&self, [x, y]: [f32; 2]) -> [f32; 2] {
+        let scale = self.scale();
+        let offset = self.offset();
+
+        [x * scale + offset[0], y * scale + offset[1]]
+    }
+
+    pub fn to_scene(&self, [x, y]: [f32; 2]) -> [f32; 2] {
+        let scale = self.scale();
+        let offset = self.offset();
+
+        [(x - offset[0]) / scale, (y - offset[1]) / scale]
+    }
+
+    /// A drag across the navigator, in scene pixels
+    pub fn scene_distance(&self, distance: f32) -> f32 {
+        distance / self.scale()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: [f32; 2], b: [f32; 2]) -> bool {
+        (a[0] - b[0]).abs() < 1e-3 && (a[1] - b[1]).abs() < 1e-3
+    }
+
+    fn view() -> View {
+        View {
+            zoom: 1.0,
+            position: [0.0, 0.0],
+            size: [1200.0, 800.0],
+        }
+    }
+
+    #[test]
+    fn at_100_percent_screen_and_scene_line_up() {
+        assert!(close(
+            view().screen_to_scene([300.0, 200.0]),
+            [300.0, 200.0]
+        ));
+    }
+
+    #[test]
+    fn zooming_keeps_the_anchor_in_place() {
+        let mut view = view();
+        let anchor = [900.0, 150.0];
+        let before = view.screen_to_scene(anchor);
+
+        view.zoom_around(2.5, anchor);
+        assert_eq!(view.zoom, 2.5);
+        assert!(close(view.screen_to_scene(anchor), before));
+
+        view.zoom_around(0.3, [10.0, 700.0]);
+        view.zoom_around(4.0, anchor);
+        let again = view.screen_to_scene(anchor);
+        view.pinch(-50.0, anchor);
+        assert!(close(view.screen_to_scene(anchor), again));
+    }
+
+    #[test]
+    fn magnifying_back_and_forth_returns_to_the_same_view() {
+        let mut view = view();
+        let anchor = [700.0, 300.0];
+        let before = view.screen_to_scene(anchor);
+
+        view.magnify(0.25, anchor);
+        assert!(view.zoom > 1.0);
+        assert!(close(view.screen_to_scene(anchor), before));
+
+        view.magnify(-0.25, anchor);
+        assert!((view.zoom - 1.0).abs() < 1e-5);
+        assert!(close(view.screen_to_scene(anchor), before));
+    }
+
+    #[test]
+    fn the_panels_are_not_part_of_the_canvas() {
+        let view = view();
+
+        assert!(!view.on_canvas([100.0, 300.0]));
+        assert!(view.on_canvas([700.0, 300.0]));
+        assert!(!view.on_canvas([1300.0, 300.0]));
+    }
+
+    #[test]
+    fn zoom_is_clamped() {
+        let mut view = view();
+
+        view.zoom_around(1000.0, [0.0, 0.0]);
+        assert_eq!(view.zoom, MAX_ZOOM);
+        view.zoom_around(0.0, [0.0, 0.0]);
+        assert_eq!(view.zoom, MIN_ZOOM);
+    }
+
+    #[test]
+    fn panning_follows_the_drag() {
+        let mut view = view();
+        view.zoom_around(2.0, [600.0, 400.0]);
+        let grabbed = view.screen_to_scene([500.0, 500.0]);
+
+        view.pan(30.0, -20.0);
+
+        assert!(close(view.screen_to_scene([530.0, 480.0]), grabbed));
+    }
+
+    #[test]
+    fn fitting_centers_the_bounds_in_the_canvas() {
+        let mut view = view();
+        view.fit(([0.0, 0.0], [200.0, 100.0]));
+
+        // 780 x 800 is left of the panels, less the margins
+        assert!((view.zoom - 700.0 / 200.0).abs() < 1e-4);
+
+        let (min, max) = view.canvas();
+        assert!(close(
+            view.screen_to_scene([(min[0] + max[0]) / 2.0, (min[1] + max[1]) / 2.0]),
+            [100.0, 50.0]
+        ));
+    }
+
+    #[test]
+    fn centering_moves_the_visible_middle() {
+        let mut view = view();
+        view.center_on([-300.0, 1000.0]);
+
+        let (min, max) = view.visible();
+        assert!(close(
+            [(min[0] + max[0]) / 2.0, (min[1] + max[1]) / 2.0],
+            [-300.0, 1000.0]
+        ));
+    }
+
+    #[test]
+    fn typed_zooms_are_percentages() {
+        assert_eq!(parse_zoom("150"), Ok(1.5));
+        assert_eq!(parse_zoom(" 50 % "), Ok(0.5));
+        assert_eq!(parse_zoom("100000%"), Ok(MAX_ZOOM));
+        assert!(parse_zoom("0").is_err());
+        assert!(parse_zoom("big").is_err());
+        assert_eq!(view().percent(), "100%");
+    }
+
+    #[test]
+    fn union_covers_every_box() {
+        assert_eq!(union_bounds(Vec::new()), None);
+        assert_eq!(
+            union_bounds(vec![
+                ([0.0, 10.0], [5.0, 20.0]),
+                ([-5.0, 15.0], [3.0, 30.0]),
+            ]),
+            Some(([-5.0, 10.0], [5.0, 30.0]))
+        );
+    }
+
+    #[test]
+    fn the_overview_round_trips() {
+        let overview = Overview::new(
+            Some(([0.0, 0.0], [1000.0, 500.0])),
+            ([200.0, 100.0], [400.0, 300.0]),
+            [240.0, 160.0],
+        );
+
+        for point in [[0.0, 0.0], [1000.0, 500.0], [321.0, -12.0]] {
+            assert!(close(overview.to_scene(overview.to_panel(point)), point));
+        }
+
+        // the scene is wider than the panel, so it fills the width and is centered down it
+        let (left, right) = (
+            overview.to_panel(overview.extent.0),
+            overview.to_panel(overview.extent.1),
+        );
+        assert!(close([left[0], right[0]], [0.0, 240.0]));
+        assert!((left[1] + right[1] - 160.0).abs() < 1e-3);
+        assert!((overview.scene_distance(240.0) - 1100.0).abs() < 1e-2);
+    }
+
+    #[test]
+    fn the_overview_includes_what_the_canvas_shows() {
+        let visible = ([-500.0, -500.0], [-100.0, -100.0]);
+        let overview = Overview::new(Some(([0.0, 0.0], [100.0, 100.0])), visible, [240.0, 160.0]);
+
+        assert!(overview.extent.0[0] < -500.0 && overview.extent.1[0] > 100.0);
+        assert_eq!(
+            Overview::new(None, visible, [240.0, 160.0]).extent.0,
+            [-520.0, -520.0]
+        );
+    }
+}
@@ -0,0 +1,289 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use strum::IntoEnumIterator;
+use strum_macros::EnumIter;
+
+use crate::photo::metadata::ExportMetadata;
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash, EnumIter, Serialize, Deserialize)]
+pub enum Unit {
+    #[default]
+    Px,
+    Mm,
+    In,
+    Pt,
+}
+
+impl Unit {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Unit::Px => "px",
+            Unit::Mm => "mm",
+            Unit::In => "in",
+            Unit::Pt => "pt",
+        }
+    }
+
+    /// The unit a length ends in, e.g. the "mm" of "10mm"
+    pub fn from_suffix(suffix: &str) -> Option<Unit> {
+        Unit::iter().find(|unit| unit.label().eq_ignore_ascii_case(suffix))
+    }
+
+    // pixels are tied to inches by the dpi, the rest have a fixed size
+    fn per_inch(&self, dpi: f32) -> f32 {
+        match self {
+            Unit::Px => dpi,
+            Unit::Mm => 25.4,
+            Unit::In => 1.0,
+            Unit::Pt => 72.0,
+        }
+    }
+
+    pub fn to_px(&self, value: f32, dpi: f32) -> f32 {
+        value * dpi / self.per_inch(dpi)
+    }
+
+    pub fn from_px(&self, px: f32, dpi: f32) -> f32 {
+        px * self.per_inch(dpi) / dpi
+    }
+}
+
+/// Multisampling for the canvas. 1 and 4 are the only counts every adapter supports.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash, EnumIter, Serialize, Deserialize)]
+pub enum SampleCount {
+    Off,
+    #[default]
+    Four,
+}
+
+impl SampleCount {
+    pub fn count(&self) -> u32 {
+        match self {
+            SampleCount::Off => 1,
+            SampleCount::Four => 4,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            SampleCount::Off => "Off",
+            SampleCount::Four => "4x",
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash, EnumIter, Serialize, Deserialize)]
+pub enum Theme {
+    #[default]
+    Light,
+    Dark,
+}
+
+impl Theme {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Theme::Light => "Light",
+            Theme::Dark => "Dark",
+        }
+    }
+}
+
+/// Settings that belong to the user rather than the document, kept between sessions
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+// fields added later are filled in when an older file is read
+#[serde(default)]
+pub struct Preferences {
+    // for new shapes
+    pub fill: [f32; 4],
+    pub stroke: [f32; 4],
+    pub stroke_thickness: f32,
+    // what new documents are measured in
+    pub units: Unit,
+    pub background: [f32; 3],
+    pub sample_count: SampleCount,
+    // 0 turns autosave off
+    pub autosave_minutes: f32,
+    // how close a dragged point has to get to another shape's point to land on it, in pixels
+    pub snap_distance: f32,
+    // how far outside a stroke a click still selects it, in pixels
+    pub pick_tolerance: f32,
+    pub theme: Theme,
+    // whether photos take their camera details and location along when exported
+    pub photo_metadata: ExportMetadata,
+}
+
+impl Default for Preferences {
+    fn default() -> Self {
+        Preferences {
+            fill: [1.0, 1.0, 1.0, 1.0],
+            stroke: [1.0, 1.0, 1.0, 1.0],
+            stroke_thickness: 2.0,
+            units: Unit::Px,
+            background: [1.0, 1.0, 1.0],
+            sample_count: SampleCount::Four,
+            autosave_minutes: 2.0,
+            snap_distance: 6.0,
+            pick_tolerance: 3.0,
+            theme: Theme::Light,
+            photo_metadata: ExportMetadata::Preserve,
+        }
+    }
+}
+
+impl Preferences {
+    pub fn file_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("sensor").join("preferences.json"))
+    }
+
+    /// The saved preferences, or the defaults if there aren't any yet or they can't be read
+    pub fn load() -> Self {
+        let Some(path) = Self::file_path() else {
+            return Preferences::default();
+        };
+
+        match fs::read_to_string(&path) {
+            Ok(text) => serde_json::from_str(&text).unwrap_or_else(|error| {
+                println!("Couldn't read preferences, using defaults: {}", error);
+                Preferences::default()
+            }),
+            Err(_) => Preferences::default(),
+        }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = Self::file_path().ok_or("Couldn't find config directory")?;
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|_| "Couldn't create config directory")?;
+        }
+
+        let text =
+            serde_json::to_string_pretty(self).map_err(|_| "Couldn't serialize preferences")?;
+        fs::write(&path, text).map_err(|_| "Couldn't write preferences")?;
+
+        Ok(())
+    }
+
+    pub fn background_color(&self) -> wgpu::Color {
+        let [r, g, b] = self.background.map(|channel| channel as f64);
+        wgpu::Color { r, g, b, a: 1.0 }
+    }
+}
+
+/// What the settings panel edits as a number
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, EnumIter)]
+pub enum PreferenceField {
+    FillRed,
+    FillGreen,
+    FillBlue,
+    StrokeRed,
+    StrokeGreen,
+    StrokeBlue,
+    StrokeThickness,
+    BackgroundRed,
+    BackgroundGreen,
+    BackgroundBlue,
+    AutosaveMinutes,
+    SnapDistance,
+    PickTolerance,
+}
+
+impl PreferenceField {
+    pub fn label(&self) -> &'static str {
+        match self {
+            PreferenceField::FillRed
+            | PreferenceField::StrokeRed
+            | PreferenceField::BackgroundRed => "Red:",
+            PreferenceField::FillGreen
+            | PreferenceField::StrokeGreen
+            | PreferenceField::BackgroundGreen => "Green:",
+            PreferenceField::FillBlue
+            | PreferenceField::StrokeBlue
+            | PreferenceField::BackgroundBlue => "Blue:",
+            PreferenceField::StrokeThickness => "Thickness:",
+            PreferenceField::AutosaveMinutes => "Autosave (min):",
+            PreferenceField::SnapDistance => "Snap (px):",
+            PreferenceField::PickTolerance => "Pick (px):",
+        }
+    }
+
+    /// The name the field's input signal is registered under
+    pub fn signal_name(&self) -> String {
+        format!("preference_{:?}", self).to_lowercase()
+    }
+
+    fn channel(&self) -> Option<(ColorTarget, usize)> {
+        match self {
+            PreferenceField::FillRed => Some((ColorTarget::Fill, 0)),
+            PreferenceField::FillGreen => Some((ColorTarget::Fill, 1)),
+            PreferenceField::FillBlue => Some((ColorTarget::Fill, 2)),
+            PreferenceField::StrokeRed => Some((ColorTarget::Stroke, 0)),
+            PreferenceField::StrokeGreen => Some((ColorTarget::Stroke, 1)),
+            PreferenceField::StrokeBlue => Some((ColorTarget::Stroke, 2)),
+            PreferenceField::BackgroundRed => Some((ColorTarget::Background, 0)),
+            PreferenceField::BackgroundGreen => Some((ColorTarget::Background, 1)),
+            PreferenceField::BackgroundBlue => Some((ColorTarget::Background, 2)),
+            _ => None,
+        }
+    }
+
+    /// The value as it's entered, colours as 0-255
+    pub fn read(&self, preferences: &Preferences) -> f32 {
+        if let Some((target, index)) = self.channel() {
+            return (target.channels(preferences)[index] * 255.0).round();
+        }
+
+        match self {
+            PreferenceField::StrokeThickness => preferences.stroke_thickness,
+            PreferenceField::AutosaveMinutes => preferences.autosave_minutes,
+            PreferenceField::SnapDistance => preferences.snap_distance,
+            PreferenceField::PickTolerance => preferences.pick_tolerance,
+            _ => 0.0,
+        }
+    }
+
+    pub fn apply(&self, preferences: &mut Preferences, value: f32) {
+        if let Some((target, index)) = self.channel() {
+            target.channels_mut(preferences)[index] = (value / 255.0).clamp(0.0, 1.0);
+            return;
+        }
+
+        match self {
+            PreferenceField::StrokeThickness => preferences.stroke_thickness = value.max(0.0),
+            PreferenceField::AutosaveMinutes => preferences.autosave_minutes = value.max(0.0),
+            PreferenceField::SnapDistance => preferences.snap_distance = value.max(0.0),
+            PreferenceField::PickTolerance => preferences.pick_tolerance = value.max(0.0),
+            _ => {}
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+enum ColorTarget {
+    Fill,
+    Stroke,
+    Background,
+}
+
+impl ColorTarget {
+    fn channels<'a>(&self, preferences: &'a Preferences) -> &'a [f32] {
+        match self {
+            ColorTarget::Fill => &preferences.fill,
+            ColorTarget::Stroke => &preferences.stroke,
+            ColorTarget::Background => &preferences.background,
+        }
+    }
+
+    fn channels_mut<'a>(&self, preferences: &'a mut Preferences) -> &'a mut [f32] {
+        match self {
+            ColorTarget::Fill => &mut preferences.fill,
+            ColorTarget::Stroke => &mut preferences.stroke,
+            ColorTarget::Background => &mut preferences.background,
+        }
+    }
+}
+
+pub type SharedPreferences = Arc<Mutex<Preferences>>;
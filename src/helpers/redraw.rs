@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use floem_winit::window::Window;
+
+use crate::renderer::batch::BatchItemId;
+
+use super::locking::LockRecover;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Invalidation {
+    Scene,
+    Camera,
+    Hover,
+    Overlay,
+    Resize,
+}
+
+/// Counts the edits made to each shape, so the batch only uploads what an edit touched
+#[derive(Default)]
+struct Revisions {
+    // bumped when every shape is rebuilt at once, e.g. for a new window size
+    everything: u64,
+    shapes: HashMap<BatchItemId, u64>,
+    // the shape under the cursor since the button went down, moved by the editor itself
+    dragged: Option<BatchItemId>,
+}
+
+/// The one place the canvas is asked to redraw. Requests made before the next frame
+/// starts are coalesced into a single redraw, and nothing is requested while idle.
+#[derive(Clone, Default)]
+pub struct Invalidator {
+    window: Arc<Mutex<Option<Arc<Window>>>>,
+    pending: Arc<AtomicBool>,
+    // bumped whenever what the canvas shows changes, the hover and overlay aside
+    generation: Arc<AtomicU64>,
+    revisions: Arc<Mutex<Revisions>>,
+}
+
+impl Invalidator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_window(&self, window: Option<Arc<Window>>) {
+        *self.window.lock_or_recover() = window;
+        // draw the first frame once there is somewhere to draw it
+        self.invalidate(Invalidation::Resize);
+    }
+
+    pub fn invalidate(&self, reason: Invalidation) {
+        if !matches!(reason, Invalidation::Hover | Invalidation::Overlay) {
+            self.generation.fetch_add(1, Ordering::AcqRel);
+        }
+        // the editor rebuilds every shape for the new window size
+        if reason == Invalidation::Resize {
+            self.revisions.lock_or_recover().everything += 1;
+        }
+
+        // nothing is latched until there's a window, or its first frame would never come
+        let window = self.window.lock_or_recover();
+        let Some(window) = window.as_ref() else {
+            return;
+        };
+
+        if self.pending.swap(true, Ordering::AcqRel) {
+            return;
+        }
+
+        window.request_redraw();
+    }
+
+    /// Compared by the scopes, which only read the canvas back once it has changed
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+
+    /// Every edit that rebuilds a shape's geometry goes through here
+    pub fn shape_changed(&self, id: BatchItemId) {
+        *self
+            .revisions
+            .lock_or_recover()
+            .shapes
+            .entry(id)
+            .or_default() += 1;
+        self.invalidate(Invalidation::Scene);
+    }
+
+    /// For when the whole scene is swapped out, e.g. by a recovered document
+    pub fn scene_replaced(&self) {
+        self.revisions.lock_or_recover().everything += 1;
+        self.invalidate(Invalidation::Scene);
+    }
+
+    /// The editor moves a clicked shape along with the cursor on its own, see drag_moved
+    pub fn drag_started(&self, id: BatchItemId) {
+        self.revisions.lock_or_recover().dragged = Some(id);
+    }
+
+    pub fn drag_moved(&self) {
+        let dragged = self.revisions.lock_or_recover().dragged;
+        if let Some(id) = dragged {
+            self.shape_changed(id);
+        }
+    }
+
+    pub fn drag_finished(&self) {
+        self.revisions.lock_or_recover().dragged = None;
+    }
+
+    /// Only ever goes up, so a slot is uploaded again whenever it differs from the last frame
+    pub fn revision(&self, id: BatchItemId) -> u64 {
+        let revisions = self.revisions.lock_or_recover();
+        revisions.everything + revisions.shapes.get(&id).copied().unwrap_or(0)
+    }
+
+    // Called by the render callback, anything invalidated after this gets another frame
+    pub fn frame_started(&self) -> bool {
+        self.pending.swap(false, Ordering::AcqRel)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+
+    #[test]
+    fn nothing_is_pending_without_a_window() {
+        let invalidator = Invalidator::new();
+
+        invalidator.invalidate(Invalidation::Scene);
+
+        assert!(!invalidator.frame_started());
+    }
+
+    #[test]
+    fn revisions_count_edits_per_shape() {
+        let invalidator = Invalidator::new();
+        let (a, b) = (
+            BatchItemId::Polygon(Uuid::new_v4()),
+            BatchItemId::Polygon(Uuid::new_v4()),
+        );
+
+        invalidator.shape_changed(a);
+        assert_eq!((invalidator.revision(a), invalidator.revision(b)), (1, 0));
+
+        invalidator.invalidate(Invalidation::Resize);
+        assert_eq!((invalidator.revision(a), invalidator.revision(b)), (2, 1));
+    }
+
+    #[test]
+    fn generations_skip_the_hover_and_overlay() {
+        let invalidator = Invalidator::new();
+
+        invalidator.invalidate(Invalidation::Hover);
+        invalidator.invalidate(Invalidation::Overlay);
+        assert_eq!(invalidator.generation(), 0);
+
+        invalidator.invalidate(Invalidation::Scene);
+        invalidator.invalidate(Invalidation::Camera);
+        assert_eq!(invalidator.generation(), 2);
+    }
+
+    #[test]
+    fn drags_bump_the_dragged_shape_until_released() {
+        let invalidator = Invalidator::new();
+        let id = BatchItemId::Polygon(Uuid::new_v4());
+
+        invalidator.drag_moved();
+        invalidator.drag_started(id);
+        invalidator.drag_moved();
+        invalidator.drag_moved();
+        invalidator.drag_finished();
+        invalidator.drag_moved();
+
+        assert_eq!(invalidator.revision(id), 2);
+    }
+}
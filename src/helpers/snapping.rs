@@ -0,0 +1,56 @@
+use common_vector::basic::Point;
+use common_vector::polygon::PolygonConfig;
+
+use crate::brush::eraser::polygon_outline;
+
+/// The dragged polygon's new points, with any that moved pulled onto the nearest point of
+/// another polygon within the distance. Points are normalized to the polygon's dimensions,
+/// the same as the editor keeps them.
+pub fn snap_points(
+    config: &PolygonConfig,
+    old_points: &[Point],
+    new_points: &[Point],
+    polygons: &[PolygonConfig],
+    distance: f32,
+) -> Vec<Point> {
+    let (width, height) = config.dimensions;
+    if distance <= 0.0 || width <= 0.0 || height <= 0.0 {
+        return new_points.to_vec();
+    }
+
+    let targets: Vec<[f32; 2]> = polygons
+        .iter()
+        .filter(|other| other.id != config.id)
+        .flat_map(polygon_outline)
+        .collect();
+
+    new_points
+        .iter()
+        .enumerate()
+        .map(|(index, point)| {
+            let moved = old_points
+                .get(index)
+                .map_or(true, |old| old.x != point.x || old.y != point.y);
+
+            let x = config.position.x + point.x * width;
+            let y = config.position.y + point.y * height;
+
+            let nearest = targets
+                .iter()
+                .map(|target| (target, (target[0] - x).hypot(target[1] - y)))
+                .filter(|(_, gap)| *gap <= distance)
+                .min_by(|a, b| a.1.total_cmp(&b.1));
+
+            match nearest {
+                Some((target, _)) if moved => Point {
+                    x: (target[0] - config.position.x) / width,
+                    y: (target[1] - config.position.y) / height,
+                },
+                _ => Point {
+                    x: point.x,
+                    y: point.y,
+                },
+            }
+        })
+        .collect()
+}
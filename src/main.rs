@@ -2,6 +2,8 @@ use std::borrow::{Borrow, BorrowMut};
 use std::rc::{Rc, Weak};
 use std::sync::{Arc, Mutex, MutexGuard};
 
+use brush::strokes::{SharedStrokeLayers, StrokeLayers};
+use brush::{rebuild_stroke, ActiveStroke, BrushTool};
 use bytemuck::Contiguous;
 use cgmath::Vector4;
 use common_vector::basic::{
@@ -9,17 +11,41 @@ use common_vector::basic::{
 };
 use common_vector::camera::{Camera, CameraBinding};
 use common_vector::dot::draw_dot;
-use common_vector::editor::{self, size_to_ndc, visualize_ray_intersection, Editor, Viewport};
+use common_vector::editor::{
+    self, size_to_ndc, visualize_ray_intersection, ControlMode, Editor, PolygonProperty, Viewport,
+};
 use common_vector::guideline::{create_guide_line_buffers, point_to_ndc};
 use common_vector::polygon::{Polygon, PolygonConfig};
-use common_vector::vertex::Vertex;
-use editor_state::{EditorState, PolygonEdit, RecordState};
+use document::page::{PageSetup, SharedPageSetup};
+use document::recovery::AutosaveSession;
+use editor_state::{EditorState, PolygonEdit, RecordState, SceneEdit};
 use floem::common::{nav_button, option_button, small_button};
 use floem::kurbo::Size;
 use floem::window::WindowConfig;
 use floem_renderer::gpu_resources::{self, GpuResources};
 use floem_winit::dpi::{LogicalSize, PhysicalSize};
 use floem_winit::event::{ElementState, KeyEvent, Modifiers, MouseButton, MouseScrollDelta};
+use helpers::blending::{LayerBlends, SharedLayerBlends};
+use helpers::effects::{ShapeEffects, SharedShapeEffects};
+use helpers::events::{
+    create_event_channel, layers_update_handler, polygon_click_handler, EditorEvent,
+    EditorEventSender,
+};
+use helpers::locking::LockRecover;
+use helpers::masks::{LayerMasks, SharedLayerMasks};
+use helpers::navigation::{self, WHEEL_ZOOM_STEP};
+use helpers::preferences::{Preferences, SharedPreferences};
+use helpers::redraw::{Invalidation, Invalidator};
+use helpers::snapping::snap_points;
+use photo::adjustments::{LayerAdjustments, SharedLayerAdjustments};
+use photo::images::{ImageLayers, SharedImageLayers};
+use photo::scopes::ScopeWorker;
+use renderer::batch::{scene_items, BatchItemId, EffectCache, MaskCache, SceneBatch};
+use renderer::canvas::{create_scene_pipeline, SingleSampleCanvas, WINDOW_SAMPLE_COUNT};
+use renderer::compositor::{composite_runs, Compositor};
+use renderer::images::ImageTextures;
+use renderer::overlay::OverlayRenderer;
+use renderer::readback::CanvasReadback;
 use uuid::Uuid;
 use views::app::app_view;
 // use winit::{event_loop, window};
@@ -31,8 +57,13 @@ use floem::{Application, CustomRenderCallback};
 use floem::{GpuHelper, View, WindowHandle};
 use undo::{Edit, Record};
 
+mod assets;
+mod brush;
+mod document;
 mod editor_state;
 mod helpers;
+mod photo;
+mod renderer;
 mod views;
 
 // // Define an enum for our dropdown options
@@ -128,7 +159,28 @@ type RenderCallback<'a> = dyn for<'b> Fn(
         Option<Arc<wgpu::TextureView>>,
     ) + 'a;
 
-fn create_render_callback<'a>() -> Box<RenderCallback<'a>> {
+fn create_render_callback<'a>(
+    invalidator: Invalidator,
+    strokes: SharedStrokeLayers,
+    images: SharedImageLayers,
+    adjustments: SharedLayerAdjustments,
+    masks: SharedLayerMasks,
+    blends: SharedLayerBlends,
+    effects: SharedShapeEffects,
+    events: EditorEventSender,
+    preferences: SharedPreferences,
+    page: SharedPageSetup,
+) -> Box<RenderCallback<'a>> {
+    let batch: Mutex<Option<SceneBatch>> = Mutex::new(None);
+    let mask_cache: Mutex<MaskCache> = Mutex::new(MaskCache::new());
+    let compositor: Mutex<Option<Compositor>> = Mutex::new(None);
+    let single_sample: Mutex<Option<SingleSampleCanvas>> = Mutex::new(None);
+    let effect_cache: Mutex<EffectCache> = Mutex::new(EffectCache::new());
+    let overlay: Mutex<Option<OverlayRenderer>> = Mutex::new(None);
+    let image_textures: Mutex<Option<ImageTextures>> = Mutex::new(None);
+    let readback: Mutex<Option<CanvasReadback>> = Mutex::new(None);
+    let scopes = ScopeWorker::new();
+
     Box::new(
         move |mut encoder: wgpu::CommandEncoder,
               frame: wgpu::SurfaceTexture,
@@ -137,6 +189,8 @@ fn create_render_callback<'a>() -> Box<RenderCallback<'a>> {
               //   window_handle: &WindowHandle
               gpu_resources: &Arc<GpuResources>,
               engine_handle: &EngineHandle| {
+            invalidator.frame_started();
+
             // let mut handle = window_handle.borrow();
             let mut editor = get_sensor_editor(engine_handle);
             // let mut engine = editor
@@ -147,11 +201,211 @@ fn create_render_callback<'a>() -> Box<RenderCallback<'a>> {
 
             // if let Some(gpu_resources) = &handle.gpu_resources {
             {
+                // let editor = handle
+                //     .user_editor
+                //     .as_ref()
+                //     .expect("Couldn't get user editor")
+                //     .lock()
+                //     .unwrap();
+                let editor = get_sensor_editor(engine_handle);
+                // does this freeze?
+                let editor = editor
+                    .as_ref()
+                    .expect("Couldn't get user engine")
+                    .lock_or_recover();
+
+                let camera_binding = editor
+                    .camera_binding
+                    .as_ref()
+                    .expect("Couldn't get camera binding");
+
+                // camera_binding.update(&gpu_resources.queue, &editor.camera);
+                // editor.update_camera_binding(&gpu_resources.queue);
+
+                // polygons, brush strokes and photos share one set of buffers, drawn in layer order
+                let mut batch = batch.lock_or_recover();
+                let batch = batch.get_or_insert_with(|| SceneBatch::new(&gpu_resources.device));
+
+                let viewport = editor.viewport.lock_or_recover();
+                let window_size = WindowSize {
+                    width: viewport.width as u32,
+                    height: viewport.height as u32,
+                };
+
+                let (background, sample_count) = {
+                    let preferences = preferences.lock_or_recover();
+                    (
+                        preferences.background_color(),
+                        preferences.sample_count.count(),
+                    )
+                };
+
+                // the window's targets are multisampled, so without it the canvas is drawn
+                // straight into the frame with a pipeline and depth target of its own
+                let mut single_sample = single_sample.lock_or_recover();
+                if sample_count == WINDOW_SAMPLE_COUNT {
+                    *single_sample = None;
+                } else {
+                    single_sample
+                        .get_or_insert_with(|| {
+                            SingleSampleCanvas::new(
+                                &gpu_resources.device,
+                                &camera_binding.bind_group_layout,
+                            )
+                        })
+                        .prepare(&gpu_resources.device, &window_size);
+                }
+                let single_sample = single_sample.as_ref();
+
+                let render_pipeline = match single_sample {
+                    Some(canvas) => &canvas.pipeline,
+                    None => engine_handle
+                        .render_pipeline
+                        .as_ref()
+                        .expect("Couldn't fetch render pipeline"),
+                };
+
+                let strokes = strokes.lock_or_recover();
+                let images = images.lock_or_recover();
+                let mut image_textures = image_textures.lock_or_recover();
+                let textures = image_textures.get_or_insert_with(|| {
+                    ImageTextures::new(&gpu_resources.device, &gpu_resources.queue)
+                });
+                let masks = masks.lock_or_recover();
+                // photos and painted masks are only uploaded again once they've been replaced
+                textures.prepare(
+                    &gpu_resources.device,
+                    &gpu_resources.queue,
+                    &images,
+                    &masks,
+                    &window_size,
+                );
+                let textures = &*textures;
+                let blends = blends.lock_or_recover();
+                let mut effect_cache = effect_cache.lock_or_recover();
+                effect_cache.update(
+                    &editor,
+                    &effects.lock_or_recover(),
+                    &invalidator,
+                    &window_size,
+                );
+                let mut mask_cache = mask_cache.lock_or_recover();
+                // masked layers are cut down on the CPU, the pipeline has no way to sample a mask
+                let items = mask_cache.resolve(
+                    scene_items(
+                        &editor,
+                        &strokes,
+                        &images,
+                        &masks,
+                        &effect_cache,
+                        &invalidator,
+                    ),
+                    &window_size,
+                );
+                batch.prepare(&gpu_resources.device, &gpu_resources.queue, &items);
+                let runs = composite_runs(&items, &blends, &adjustments.lock_or_recover());
+                let crop_guides = images.crop_guides();
+                drop(items);
+                drop(mask_cache);
+                drop(effect_cache);
+                drop(blends);
+                drop(masks);
+                drop(images);
+                drop(strokes);
+
+                // blend modes, layer opacity and adjustments need each layer drawn on its own, so
+                // frames are put together offscreen before the canvas pass begins
+                let mut compositor = compositor.lock_or_recover();
+                if compositor
+                    .as_ref()
+                    .is_some_and(|compositor| compositor.sample_count() != sample_count)
+                {
+                    *compositor = None;
+                }
+                if let Some(runs) = &runs {
+                    let compositor = compositor.get_or_insert_with(|| {
+                        Compositor::new(&gpu_resources.device, sample_count)
+                    });
+
+                    compositor.render(
+                        &gpu_resources.device,
+                        &gpu_resources.queue,
+                        &mut encoder,
+                        render_pipeline,
+                        &camera_binding.bind_group,
+                        batch,
+                        textures,
+                        runs,
+                        &window_size,
+                        background,
+                    );
+                }
+
+                // the scopes count what the canvas shows, read back a copy at a time
+                let mut readback = readback.lock_or_recover();
+                let readback = readback.get_or_insert_with(|| {
+                    CanvasReadback::new(&gpu_resources.device, &camera_binding.bind_group_layout)
+                });
+                if let Some((pixels, width, _)) = readback.poll(&gpu_resources.device) {
+                    let events = events.clone();
+                    scopes.update(Arc::new(pixels), width, move |histogram| {
+                        let _ = events.send(EditorEvent::HistogramUpdated(histogram));
+                    });
+                }
+                let generation = invalidator.generation();
+                if readback.wants_copy(generation) {
+                    match (
+                        &runs,
+                        compositor.as_ref().and_then(Compositor::canvas_texture),
+                    ) {
+                        (Some(_), Some(texture)) => readback.copy_texture(
+                            &gpu_resources.device,
+                            &mut encoder,
+                            texture,
+                            generation,
+                        ),
+                        _ => readback.copy_scene(
+                            &gpu_resources.device,
+                            &mut encoder,
+                            &camera_binding.bind_group,
+                            batch,
+                            textures,
+                            &window_size,
+                            background,
+                            generation,
+                        ),
+                    }
+                }
+                // the copy comes back over the next frames, which have to be asked for
+                if readback.in_flight() {
+                    invalidator.invalidate(Invalidation::Overlay);
+                }
+
+                let gpu_helper = engine_handle
+                    .gpu_helper
+                    .as_ref()
+                    .expect("Couldn't get gpu helper")
+                    .lock_or_recover();
+                let depth_view: &wgpu::TextureView = match single_sample {
+                    Some(canvas) => canvas.depth_view(),
+                    // This is the depth texture view
+                    None => gpu_helper
+                        .depth_view
+                        .as_ref()
+                        .expect("Couldn't fetch depth view"),
+                };
+
                 let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                     label: None,
                     color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                        view: &view,
-                        resolve_target: Some(&resolve_view),
+                        view: match single_sample {
+                            Some(_) => &resolve_view,
+                            None => &view,
+                        },
+                        resolve_target: match single_sample {
+                            Some(_) => None,
+                            None => Some(&resolve_view),
+                        },
                         ops: wgpu::Operations {
                             // load: wgpu::LoadOp::Clear(wgpu::Color {
                             //     // grey background
@@ -167,21 +421,13 @@ fn create_render_callback<'a>() -> Box<RenderCallback<'a>> {
                             // load: wgpu::LoadOp::Clear(wgpu::Color::WHITE),
                             // load: wgpu::LoadOp::Load,
                             // store: wgpu::StoreOp::Store,
-                            load: wgpu::LoadOp::Clear(wgpu::Color::WHITE),
+                            load: wgpu::LoadOp::Clear(background),
                             store: wgpu::StoreOp::Store,
                         },
                     })],
                     // depth_stencil_attachment: None,
                     depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                        view: &engine_handle
-                            .gpu_helper
-                            .as_ref()
-                            .expect("Couldn't get gpu helper")
-                            .lock()
-                            .unwrap()
-                            .depth_view
-                            .as_ref()
-                            .expect("Couldn't fetch depth view"), // This is the depth texture view
+                        view: depth_view,
                         depth_ops: Some(wgpu::Operations {
                             load: wgpu::LoadOp::Clear(1.0), // Clear to max depth
                             store: wgpu::StoreOp::Store,
@@ -198,118 +444,45 @@ fn create_render_callback<'a>() -> Box<RenderCallback<'a>> {
                 // render_pass.set_viewport(100.0, 100.0, 200.0, 200.0, 0.0, 1.0);
                 // render_pass.set_scissor_rect(100, 100, 200, 200);
 
-                render_pass.set_pipeline(
-                    &engine_handle
-                        .render_pipeline
-                        .as_ref()
-                        .expect("Couldn't fetch render pipeline"),
-                );
-
-                // let editor = handle
-                //     .user_editor
-                //     .as_ref()
-                //     .expect("Couldn't get user editor")
-                //     .lock()
-                //     .unwrap();
-                let editor = get_sensor_editor(engine_handle);
-                // does this freeze?
-                let editor = editor
-                    .as_ref()
-                    .expect("Couldn't get user engine")
-                    .lock()
-                    .unwrap();
-
-                let camera_binding = editor
-                    .camera_binding
-                    .as_ref()
-                    .expect("Couldn't get camera binding");
-
-                // camera_binding.update(&gpu_resources.queue, &editor.camera);
-                // editor.update_camera_binding(&gpu_resources.queue);
-
-                render_pass.set_bind_group(0, &camera_binding.bind_group, &[]);
-
-                for (poly_index, polygon) in editor.polygons.iter().enumerate() {
-                    render_pass.set_vertex_buffer(0, polygon.vertex_buffer.slice(..));
-                    render_pass.set_index_buffer(
-                        polygon.index_buffer.slice(..),
-                        wgpu::IndexFormat::Uint32,
-                    );
-                    render_pass.draw_indexed(0..polygon.indices.len() as u32, 0, 0..1);
-                }
-
-                // for now render just the active brush stroke
-                for (stroke_index, stroke) in editor.brush_strokes.iter().enumerate() {
-                    // Only render if both buffers are initialized
-                    if let (Some(vertex_buffer), Some(index_buffer)) =
-                        (&stroke.vertex_buffer, &stroke.index_buffer)
-                    {
-                        render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
-                        render_pass
-                            .set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint32);
-                        render_pass.draw_indexed(0..stroke.indices.len() as u32, 0, 0..1);
+                match (&runs, compositor.as_ref()) {
+                    (Some(_), Some(compositor)) => compositor.present(&mut render_pass),
+                    _ => {
+                        render_pass.set_pipeline(render_pipeline);
+                        render_pass.set_bind_group(0, &camera_binding.bind_group, &[]);
+                        batch.draw(&mut render_pass, textures);
                     }
                 }
 
-                let viewport = editor.viewport.lock().unwrap();
-                let window_size = WindowSize {
-                    width: viewport.width as u32,
-                    height: viewport.height as u32,
-                };
-
                 // println!("Render size {:?}", window_size);
 
-                let camera = editor.camera.expect("Couldn't get camera");
+                let mut overlay = overlay.lock_or_recover();
+                let overlay =
+                    overlay.get_or_insert_with(|| OverlayRenderer::new(&gpu_resources.device));
 
-                let ndc_position = point_to_ndc(editor.last_top_left, &window_size);
-                let (vertices, indices, vertex_buffer, index_buffer) = draw_dot(
+                let artboard_bounds = {
+                    let page = page.lock_or_recover();
+                    page.artboards
+                        .iter()
+                        .map(|artboard| page.bounds(artboard))
+                        .collect()
+                };
+
+                // buffers are only rewritten when the dots, guide lines, crop guides or
+                // artboards have moved
+                overlay.prepare(
                     &gpu_resources.device,
+                    &gpu_resources.queue,
+                    &editor,
+                    crop_guides,
+                    artboard_bounds,
                     &window_size,
-                    Point {
-                        x: ndc_position.x,
-                        y: ndc_position.y,
-                    },
-                    rgb_to_wgpu(47, 131, 222, 1.0),
-                    &camera,
-                ); // Green dot
-
-                render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
-                render_pass.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint32);
-                render_pass.draw_indexed(0..indices.len() as u32, 0, 0..1);
-
-                if let Some(edge_point) = editor.hover_point {
-                    let ndc_position = point_to_ndc(edge_point.point, &window_size);
-                    let (vertices, indices, vertex_buffer, index_buffer) = draw_dot(
-                        &gpu_resources.device,
-                        &window_size,
-                        Point {
-                            x: ndc_position.x,
-                            y: ndc_position.y,
-                        },
-                        rgb_to_wgpu(47, 131, 222, 1.0),
-                        &camera,
-                    ); // Green dot
-
-                    render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
-                    render_pass.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint32);
-                    render_pass.draw_indexed(0..indices.len() as u32, 0, 0..1);
-                }
-
-                // Draw guide lines
-                for guide_line in &editor.guide_lines {
-                    let (vertices, indices, vertex_buffer, index_buffer) =
-                        create_guide_line_buffers(
-                            &gpu_resources.device,
-                            &window_size,
-                            guide_line.start,
-                            guide_line.end,
-                            rgb_to_wgpu(47, 131, 222, 1.0), // Blue color for guide lines
-                        );
-
-                    render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
-                    render_pass.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint32);
-                    render_pass.draw_indexed(0..indices.len() as u32, 0, 0..1);
-                }
+                );
+                // presenting a composited frame leaves its own pipeline bound
+                render_pass.set_pipeline(render_pipeline);
+                render_pass.set_bind_group(0, &camera_binding.bind_group, &[]);
+                // the overlay isn't textured, but the pipeline samples whatever is bound
+                render_pass.set_bind_group(1, textures.bind_group(None), &[]);
+                overlay.draw(&mut render_pass);
             }
 
             // let command_buffer = encoder.finish();
@@ -327,18 +500,46 @@ fn create_render_callback<'a>() -> Box<RenderCallback<'a>> {
 }
 
 fn handle_cursor_moved(
+    editor_state: Arc<Mutex<EditorState>>,
     editor: std::sync::Arc<Mutex<common_vector::editor::Editor>>,
     gpu_resources: std::sync::Arc<GpuResources>,
     // window_size: WindowSize,
     viewport: std::sync::Arc<Mutex<Viewport>>,
+    invalidator: Invalidator,
 ) -> Option<Box<dyn Fn(f64, f64, f64, f64)>> {
     Some(Box::new(
         move |positionX: f64, positionY: f64, logPosX: f64, logPoxY: f64| {
-            let mut editor = editor.lock().unwrap();
-            let viewport = viewport.lock().unwrap();
-            let window_size = WindowSize {
-                width: viewport.width as u32,
-                height: viewport.height as u32,
+            let mut editor_state = editor_state.lock_or_recover();
+            let cursor = Point {
+                x: positionX as f32,
+                y: positionY as f32,
+            };
+
+            // the view follows the drag instead of the editor
+            if let Some(from) = editor_state.pan_from {
+                editor_state.pan_from = Some(cursor);
+                editor_state.last_cursor = cursor;
+                editor_state.update_view(&gpu_resources.queue, |view| {
+                    view.pan(cursor.x - from.x, cursor.y - from.y)
+                });
+                return;
+            }
+
+            // the straighten line follows the cursor instead of the editor
+            let scene_cursor = editor_state.view().screen_to_scene([cursor.x, cursor.y]);
+            if editor_state.drag_straighten(scene_cursor) {
+                editor_state.last_cursor = cursor;
+                return;
+            }
+
+            let mut editor = editor.lock_or_recover();
+            // let go of straight away, the view reads it again through the editor
+            let window_size = {
+                let viewport = viewport.lock_or_recover();
+                WindowSize {
+                    width: viewport.width as u32,
+                    height: viewport.height as u32,
+                }
             };
             // println!("window size {:?}", window_size);
             // println!("positions {:?} {:?}", positionX, positionY);
@@ -348,6 +549,33 @@ fn handle_cursor_moved(
                 positionX as f32,
                 positionY as f32,
             );
+
+            editor_state.last_cursor = Point {
+                x: positionX as f32,
+                y: positionY as f32,
+            };
+
+            let settings = editor_state.stroke_settings();
+            if let Some(active) = editor_state.active_stroke.as_mut() {
+                // strokes are kept in the scene, wherever the view has moved it
+                let [x, y] =
+                    navigation::View::from_editor(&editor).screen_to_scene([cursor.x, cursor.y]);
+                active.record(x, y);
+                rebuild_stroke(
+                    &mut editor,
+                    &gpu_resources.device,
+                    &gpu_resources.queue,
+                    &window_size,
+                    &settings,
+                    active,
+                );
+                invalidator.shape_changed(BatchItemId::Stroke(active.stroke_index));
+            }
+
+            // a shape being dragged has been moved by the editor
+            invalidator.drag_moved();
+            // hover dots, guide lines and drags all follow the cursor
+            invalidator.invalidate(Invalidation::Hover);
             // TODO: need callback for when cursor is done moving, then add translation to undo stack
         },
     ))
@@ -359,17 +587,63 @@ fn handle_mouse_input(
     gpu_resources: std::sync::Arc<GpuResources>,
     // window_size: WindowSize,
     viewport: std::sync::Arc<Mutex<Viewport>>,
-    record: Arc<Mutex<Record<PolygonEdit>>>,
+    record: Arc<Mutex<Record<SceneEdit>>>,
+    events: EditorEventSender,
+    invalidator: Invalidator,
 ) -> Option<Box<dyn Fn(MouseButton, ElementState)>> {
     Some(Box::new(move |button, state| {
+        let mut editor_state = editor_state.lock_or_recover();
+
+        // the middle button, or the left with space held, drags the view around
+        let pan_button = button == MouseButton::Middle
+            || (button == MouseButton::Left && editor_state.space_held);
+        match state {
+            ElementState::Pressed if pan_button => {
+                editor_state.pan_from = Some(editor_state.last_cursor);
+                return;
+            }
+            ElementState::Released
+                if editor_state.pan_from.is_some()
+                    && matches!(button, MouseButton::Left | MouseButton::Middle) =>
+            {
+                editor_state.pan_from = None;
+                return;
+            }
+            _ => {}
+        }
+
+        // while a photo is being cropped, dragging draws the line to straighten it along
+        if button == MouseButton::Left {
+            let cursor = editor_state
+                .view()
+                .screen_to_scene([editor_state.last_cursor.x, editor_state.last_cursor.y]);
+            let handled = match state {
+                ElementState::Pressed => editor_state.begin_straighten(cursor),
+                ElementState::Released => editor_state.finish_straighten(),
+            };
+
+            if handled {
+                return;
+            }
+        }
+
         let mut editor_orig = Arc::clone(&editor);
-        let mut editor = editor.lock().unwrap();
-        let viewport = viewport.lock().unwrap();
-        let window_size = WindowSize {
-            width: viewport.width as u32,
-            height: viewport.height as u32,
+        let mut editor = editor.lock_or_recover();
+        // let go of straight away, the view reads it again through the editor
+        let window_size = {
+            let viewport = viewport.lock_or_recover();
+            WindowSize {
+                width: viewport.width as u32,
+                height: viewport.height as u32,
+            }
         };
         if button == MouseButton::Left {
+            let brush_mode = matches!(editor.control_mode, ControlMode::Brush);
+            let [cursor_x, cursor_y] = navigation::View::from_editor(&editor)
+                .screen_to_scene([editor_state.last_cursor.x, editor_state.last_cursor.y]);
+            let stroke_index = editor.brush_strokes.len();
+            let mut finished_stroke = None;
+
             let edit_config = match state {
                 ElementState::Pressed => editor.handle_mouse_down(
                     // mouse_position.0,
@@ -377,16 +651,102 @@ fn handle_mouse_input(
                     &window_size,
                     &gpu_resources.device,
                 ),
-                ElementState::Released => editor.handle_mouse_up(),
+                ElementState::Released => {
+                    invalidator.drag_finished();
+                    editor.handle_mouse_up()
+                }
             };
 
+            if brush_mode {
+                match state {
+                    ElementState::Pressed => {
+                        let mut active = ActiveStroke::new(
+                            stroke_index,
+                            editor_state.stroke_settings().smoothing,
+                        );
+                        active.record(cursor_x, cursor_y);
+                        editor_state.active_stroke = Some(active);
+                    }
+                    ElementState::Released => {
+                        // the finished stroke leaves the editor, to become a layer or cut into others
+                        if let Some(active) = editor_state.active_stroke.take() {
+                            if active.stroke_index < editor.brush_strokes.len() {
+                                editor.brush_strokes.remove(active.stroke_index);
+                            }
+                            finished_stroke = Some((active, editor_state.brush_tool));
+                        }
+                    }
+                }
+            } else if state == ElementState::Pressed {
+                // the editor only hit tests polygons, strokes and photos are checked here
+                let tolerance = editor_state.preferences.lock_or_recover().pick_tolerance;
+                let hit = editor_state.strokes.lock_or_recover().hit_test(
+                    &editor.layer_list,
+                    cursor_x,
+                    cursor_y,
+                    tolerance,
+                );
+                if let Some(stroke_id) = hit {
+                    let _ = events.send(EditorEvent::StrokeSelectionChanged(Some(stroke_id)));
+                } else if let Some(image_id) = editor_state.images.lock_or_recover().hit_test(
+                    &editor.layer_list,
+                    cursor_x,
+                    cursor_y,
+                ) {
+                    let _ = events.send(EditorEvent::ImageSelectionChanged(Some(image_id)));
+                }
+            }
+
+            // the editor doesn't snap, so points dropped near another polygon's points are
+            // pulled onto them before the edit is recorded
+            let edit_config = edit_config.map(|mut edit_config| {
+                if let (
+                    ElementState::Released,
+                    PolygonProperty::Points(old_points),
+                    PolygonProperty::Points(new_points),
+                ) = (state, &edit_config.old_value, &edit_config.new_value)
+                {
+                    let polygons: Vec<PolygonConfig> = editor
+                        .polygons
+                        .iter()
+                        .map(|polygon| polygon.to_config())
+                        .collect();
+
+                    if let Some(config) = polygons
+                        .iter()
+                        .find(|config| config.id == edit_config.polygon_id)
+                    {
+                        let snapped = snap_points(
+                            config,
+                            old_points,
+                            new_points,
+                            &polygons,
+                            editor_state.preferences.lock_or_recover().snap_distance,
+                        );
+                        edit_config.new_value = PolygonProperty::Points(snapped);
+                    }
+                }
+
+                edit_config
+            });
+
             drop(editor);
 
+            match finished_stroke {
+                Some((active, BrushTool::Paint)) => {
+                    editor_state.finish_stroke(active, &window_size)
+                }
+                Some((active, BrushTool::Erase)) => {
+                    editor_state.finish_erase(active, &gpu_resources.device, &window_size)
+                }
+                None => {}
+            }
+
+            invalidator.invalidate(Invalidation::Scene);
+
             if (edit_config.is_some()) {
                 let edit_config = edit_config.expect("Couldn't get polygon edit config");
 
-                let mut editor_state = editor_state.lock().unwrap();
-
                 let edit = PolygonEdit {
                     polygon_id: edit_config.polygon_id,
                     old_value: edit_config.old_value,
@@ -398,10 +758,22 @@ fn handle_mouse_input(
                 let mut record_state = RecordState {
                     editor: editor_orig,
                     // record: Arc::clone(&record),
+                    events: events.clone(),
+                    invalidator: invalidator.clone(),
+                    strokes: Arc::clone(&editor_state.strokes),
+                    images: Arc::clone(&editor_state.images),
+                    adjustments: Arc::clone(&editor_state.adjustments),
+                    filter_worker: editor_state.record_state.filter_worker.clone(),
+                    masks: Arc::clone(&editor_state.masks),
+                    blends: Arc::clone(&editor_state.blends),
+                    effects: Arc::clone(&editor_state.effects),
+                    page: Arc::clone(&editor_state.page),
                 };
 
-                let mut record = record.lock().unwrap();
-                record.edit(&mut record_state, edit);
+                let mut record = record.lock_or_recover();
+                record.edit(&mut record_state, edit.into());
+
+                editor_state.emit_history_changed(&record);
             }
         }
     }))
@@ -409,20 +781,24 @@ fn handle_mouse_input(
 
 fn handle_window_resize(
     editor: std::sync::Arc<Mutex<common_vector::editor::Editor>>,
+    strokes: SharedStrokeLayers,
+    images: SharedImageLayers,
     gpu_resources: std::sync::Arc<GpuResources>,
     // window_size: WindowSize, // need newest window size
     gpu_helper: std::sync::Arc<Mutex<GpuHelper>>,
     viewport: std::sync::Arc<Mutex<Viewport>>,
+    events: EditorEventSender,
+    invalidator: Invalidator,
 ) -> Option<Box<dyn FnMut(PhysicalSize<u32>, LogicalSize<f64>)>> {
     Some(Box::new(move |size, logical_size| {
-        let mut editor = editor.lock().unwrap();
+        let mut editor = editor.lock_or_recover();
 
         let window_size = WindowSize {
             width: size.width,
             height: size.height,
         };
 
-        let mut viewport = viewport.lock().unwrap();
+        let mut viewport = viewport.lock_or_recover();
 
         viewport.width = size.width as f32;
         viewport.height = size.height as f32;
@@ -433,50 +809,46 @@ fn handle_window_resize(
         camera.window_size.height = size.height;
 
         editor.update_date_from_window_resize(&window_size, &gpu_resources.device);
+        strokes.lock_or_recover().rebuild_all(&window_size);
+        images.lock_or_recover().rebuild_all(&window_size);
 
         gpu_helper
-            .lock()
-            .unwrap()
+            .lock_or_recover()
             .recreate_depth_view(&gpu_resources, size.width, size.height);
+
+        let _ = events.send(EditorEvent::CameraChanged);
+        invalidator.invalidate(Invalidation::Resize);
     }))
 }
 
 fn handle_mouse_wheel(
-    editor: std::sync::Arc<Mutex<common_vector::editor::Editor>>,
+    editor_state: Arc<Mutex<EditorState>>,
     gpu_resources: std::sync::Arc<GpuResources>,
-    // window_size: WindowSize, // need newest window size
-    // gpu_helper: std::sync::Arc<Mutex<GpuHelper>>,
-    viewport: std::sync::Arc<Mutex<Viewport>>,
 ) -> Option<Box<dyn FnMut(MouseScrollDelta)>> {
     Some(Box::new(move |delta: MouseScrollDelta| {
-        let mut editor = editor.lock().unwrap();
-
-        // let window_size = WindowSize {
-        //     width: size.width,
-        //     height: size.height,
-        // };
-
-        // let mut viewport = viewport.lock().unwrap();
-
-        // viewport.width = size.width as f32;
-        // viewport.height = size.height as f32;
-        let mouse_pos = Point {
-            x: editor.global_top_left.x,
-            y: editor.global_top_left.y,
-        };
+        let editor_state = editor_state.lock_or_recover();
+        let cursor = [editor_state.last_cursor.x, editor_state.last_cursor.y];
 
         match delta {
             MouseScrollDelta::LineDelta(_x, y) => {
                 // y is positive for scrolling up/away from user
                 // negative for scrolling down/toward user
-                // let zoom_factor = if y > 0.0 { 1.1 } else { 0.9 };
-                editor.handle_wheel(y, mouse_pos, &gpu_resources.queue);
+                editor_state.update_view(&gpu_resources.queue, |view| {
+                    view.zoom_around(view.zoom * WHEEL_ZOOM_STEP.powf(y), cursor)
+                });
             }
+            // pinch gestures are handled in app_view, this is the fallback for trackpads that
+            // pinch as a scroll with control held
+            MouseScrollDelta::PixelDelta(pos) if editor_state.current_modifiers.control_key() => {
+                editor_state.update_view(&gpu_resources.queue, |view| {
+                    view.pinch(pos.y as f32, cursor)
+                });
+            }
+            // and pan with two fingers
             MouseScrollDelta::PixelDelta(pos) => {
-                // Convert pixel delta if needed
-                let y = pos.y as f32;
-                // let zoom_factor = if y > 0.0 { 1.1 } else { 0.9 };
-                editor.handle_wheel(y, mouse_pos, &gpu_resources.queue);
+                editor_state.update_view(&gpu_resources.queue, |view| {
+                    view.pan(pos.x as f32, pos.y as f32)
+                });
             }
         }
     }))
@@ -489,7 +861,7 @@ fn handle_modifiers_changed(
     viewport: std::sync::Arc<Mutex<Viewport>>,
 ) -> Option<Box<dyn FnMut(Modifiers)>> {
     Some(Box::new(move |modifiers: Modifiers| {
-        let mut editor_state = editor_state.lock().unwrap();
+        let mut editor_state = editor_state.lock_or_recover();
         println!("modifiers changed");
         let modifier_state = modifiers.state();
         editor_state.current_modifiers = modifier_state;
@@ -506,12 +878,20 @@ fn handle_keyboard_input(
     viewport: std::sync::Arc<Mutex<Viewport>>,
 ) -> Option<Box<dyn FnMut(KeyEvent)>> {
     Some(Box::new(move |event: KeyEvent| {
+        if event.logical_key == Key::Named(NamedKey::Space) {
+            let mut editor_state = editor_state.lock_or_recover();
+            // spaces typed into the panels don't pan, letting go always stops panning
+            editor_state.space_held =
+                event.state == ElementState::Pressed && editor_state.canvas_has_focus();
+            return;
+        }
+
         if event.state != ElementState::Pressed {
             return;
         }
 
-        let mut editor_state = editor_state.lock().unwrap();
-        // let editor: MutexGuard<'_, Editor> = editor_state.editor.lock().unwrap();
+        let mut editor_state = editor_state.lock_or_recover();
+        // let editor: MutexGuard<'_, Editor> = editor_state.editor.lock_or_recover();
         // Check for Ctrl+Z (undo)
         let modifiers = editor_state.current_modifiers;
 
@@ -531,6 +911,31 @@ fn handle_keyboard_input(
                     editor_state.redo(); // Ctrl+Y
                 }
             }
+            Key::Character(c) if c == SmolStr::new("0") && modifiers.control_key() => {
+                editor_state.zoom_to_fit(&gpu_resources.queue); // Ctrl+0
+            }
+            Key::Character(c) if c == SmolStr::new("1") && modifiers.control_key() => {
+                // Ctrl+1 shows the scene at its actual size
+                editor_state.update_view(&gpu_resources.queue, |view| view.zoom_centered(1.0));
+            }
+            Key::Character(c) if c == SmolStr::new("2") && modifiers.control_key() => {
+                let _ = editor_state.zoom_to_selection(&gpu_resources.queue); // Ctrl+2
+            }
+            Key::Character(c)
+                if (c == SmolStr::new("=") || c == SmolStr::new("+"))
+                    && modifiers.control_key() =>
+            {
+                // Ctrl+= zooms in
+                editor_state.update_view(&gpu_resources.queue, |view| {
+                    view.zoom_centered(view.zoom * 2.0)
+                });
+            }
+            Key::Character(c) if c == SmolStr::new("-") && modifiers.control_key() => {
+                // Ctrl+- zooms out
+                editor_state.update_view(&gpu_resources.queue, |view| {
+                    view.zoom_centered(view.zoom / 2.0)
+                });
+            }
             _ => {}
         }
     }))
@@ -566,6 +971,18 @@ async fn main() {
 
     let mut editor = Arc::new(Mutex::new(Editor::new(viewport.clone())));
 
+    let (events_tx, events_rx) = create_event_channel();
+    let invalidator = Invalidator::new();
+
+    {
+        let mut editor = editor.lock_or_recover();
+        editor.handle_polygon_click = Some(polygon_click_handler(
+            events_tx.clone(),
+            invalidator.clone(),
+        ));
+        editor.handle_layers_update = Some(layers_update_handler(events_tx.clone()));
+    }
+
     let cloned_viewport = Arc::clone(&viewport);
     let cloned_viewport2 = Arc::clone(&viewport);
     let cloned_viewport3 = Arc::clone(&viewport);
@@ -584,7 +1001,6 @@ async fn main() {
     // let cloned8 = Arc::clone(&editor);
     // let cloned9 = Arc::clone(&editor);
     // let cloned10 = Arc::clone(&editor);
-    let cloned11 = Arc::clone(&editor);
     let cloned12 = Arc::clone(&editor);
     let cloned13 = Arc::clone(&editor);
 
@@ -592,11 +1008,40 @@ async fn main() {
 
     let record_2 = Arc::clone(&record);
 
-    let editor_state = Arc::new(Mutex::new(EditorState::new(cloned4, record)));
+    let strokes: SharedStrokeLayers = Arc::new(Mutex::new(StrokeLayers::new()));
+    let images: SharedImageLayers = Arc::new(Mutex::new(ImageLayers::new()));
+    let adjustments: SharedLayerAdjustments = Arc::new(Mutex::new(LayerAdjustments::new()));
+    let masks: SharedLayerMasks = Arc::new(Mutex::new(LayerMasks::new()));
+    let blends: SharedLayerBlends = Arc::new(Mutex::new(LayerBlends::new()));
+    let effects: SharedShapeEffects = Arc::new(Mutex::new(ShapeEffects::new()));
+    let preferences: SharedPreferences = Arc::new(Mutex::new(Preferences::load()));
+    // new documents start on a page measured in the preferred unit
+    let page: SharedPageSetup = Arc::new(Mutex::new(PageSetup::new(
+        preferences.lock_or_recover().units,
+    )));
+
+    let editor_state = Arc::new(Mutex::new(EditorState::new(
+        cloned4,
+        record,
+        events_tx.clone(),
+        invalidator.clone(),
+        Arc::clone(&strokes),
+        Arc::clone(&images),
+        Arc::clone(&adjustments),
+        Arc::clone(&masks),
+        Arc::clone(&blends),
+        Arc::clone(&effects),
+        Arc::clone(&preferences),
+        Arc::clone(&page),
+    )));
 
     let state_2 = Arc::clone(&editor_state);
     let state_3 = Arc::clone(&editor_state);
     let state_4 = Arc::clone(&editor_state);
+    let state_5 = Arc::clone(&editor_state);
+
+    let session = AutosaveSession::new();
+    let app_session = session.clone();
 
     let (mut app, window_id) = app.window(
         move |_| {
@@ -605,6 +1050,8 @@ async fn main() {
                 Arc::clone(&editor),
                 Arc::clone(&gpu_helper),
                 Arc::clone(&viewport),
+                events_rx.clone(),
+                app_session.clone(),
             )
         },
         Some(
@@ -627,7 +1074,18 @@ async fn main() {
             .expect("Couldn't get window handle");
 
         // Create and set the render callback
-        let render_callback = create_render_callback();
+        let render_callback = create_render_callback(
+            invalidator.clone(),
+            Arc::clone(&strokes),
+            Arc::clone(&images),
+            Arc::clone(&adjustments),
+            Arc::clone(&masks),
+            Arc::clone(&blends),
+            Arc::clone(&effects),
+            events_tx.clone(),
+            Arc::clone(&preferences),
+            Arc::clone(&page),
+        );
 
         // window_handle.set_render_callback(render_callback);
         window_handle.set_encode_callback(render_callback);
@@ -648,8 +1106,8 @@ async fn main() {
 
                 println!("Initializing pipeline...");
 
-                // let mut editor = cloned11.lock().unwrap();
-                let mut editor = cloned5.lock().unwrap();
+                // let mut editor = cloned11.lock_or_recover();
+                let mut editor = cloned5.lock_or_recover();
 
                 let camera = Camera::new(window_size);
                 let camera_binding = CameraBinding::new(&gpu_resources.device);
@@ -668,122 +1126,22 @@ async fn main() {
                         ..Default::default()
                     });
 
-                gpu_cloned.lock().unwrap().recreate_depth_view(
+                gpu_cloned.lock_or_recover().recreate_depth_view(
                     &gpu_resources,
                     window_size.width,
                     window_size.height,
                 );
 
-                let depth_stencil_state = wgpu::DepthStencilState {
-                    format: wgpu::TextureFormat::Depth24Plus,
-                    depth_write_enabled: true,
-                    depth_compare: wgpu::CompareFunction::Less,
-                    stencil: wgpu::StencilState::default(),
-                    bias: wgpu::DepthBiasState::default(),
-                };
-
                 let camera_binding = editor
                     .camera_binding
                     .as_ref()
                     .expect("Couldn't get camera binding");
 
-                // Define the layouts
-                let pipeline_layout =
-                    gpu_resources
-                        .device
-                        .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                            label: Some("Pipeline Layout"),
-                            // bind_group_layouts: &[&bind_group_layout],
-                            bind_group_layouts: &[&camera_binding.bind_group_layout], // No bind group layouts
-                            push_constant_ranges: &[],
-                        });
-
-                // Load the shaders
-                let shader_module_vert_primary =
-                    gpu_resources
-                        .device
-                        .create_shader_module(wgpu::ShaderModuleDescriptor {
-                            label: Some("Primary Vert Shader"),
-                            source: wgpu::ShaderSource::Wgsl(
-                                include_str!("shaders/vert_primary.wgsl").into(),
-                            ),
-                        });
-
-                let shader_module_frag_primary =
-                    gpu_resources
-                        .device
-                        .create_shader_module(wgpu::ShaderModuleDescriptor {
-                            label: Some("Primary Frag Shader"),
-                            source: wgpu::ShaderSource::Wgsl(
-                                include_str!("shaders/frag_primary.wgsl").into(),
-                            ),
-                        });
-
-                // let swapchain_capabilities = gpu_resources
-                //     .surface
-                //     .get_capabilities(&gpu_resources.adapter);
-                // let swapchain_format = swapchain_capabilities.formats[0]; // Choosing the first available format
-                let swapchain_format = wgpu::TextureFormat::Bgra8UnormSrgb; // hardcode for now
-
-                // Configure the render pipeline
-                let render_pipeline =
-                    gpu_resources
-                        .device
-                        .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                            label: Some("Common Vector Primary Render Pipeline"),
-                            layout: Some(&pipeline_layout),
-                            multiview: None,
-                            cache: None,
-                            vertex: wgpu::VertexState {
-                                module: &shader_module_vert_primary,
-                                entry_point: "vs_main", // name of the entry point in your vertex shader
-                                buffers: &[Vertex::desc()], // Make sure your Vertex::desc() matches your vertex structure
-                                compilation_options: wgpu::PipelineCompilationOptions::default(),
-                            },
-                            fragment: Some(wgpu::FragmentState {
-                                module: &shader_module_frag_primary,
-                                entry_point: "fs_main", // name of the entry point in your fragment shader
-                                targets: &[Some(wgpu::ColorTargetState {
-                                    format: swapchain_format,
-                                    // blend: Some(wgpu::BlendState::REPLACE),
-                                    blend: Some(wgpu::BlendState {
-                                        color: wgpu::BlendComponent {
-                                            src_factor: wgpu::BlendFactor::SrcAlpha,
-                                            dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
-                                            operation: wgpu::BlendOperation::Add,
-                                        },
-                                        alpha: wgpu::BlendComponent {
-                                            src_factor: wgpu::BlendFactor::One,
-                                            dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
-                                            operation: wgpu::BlendOperation::Add,
-                                        },
-                                    }),
-                                    write_mask: wgpu::ColorWrites::ALL,
-                                })],
-                                compilation_options: wgpu::PipelineCompilationOptions::default(),
-                            }),
-                            // primitive: wgpu::PrimitiveState::default(),
-                            // depth_stencil: None,
-                            // multisample: wgpu::MultisampleState::default(),
-                            primitive: wgpu::PrimitiveState {
-                                conservative: false,
-                                topology: wgpu::PrimitiveTopology::TriangleList, // how vertices are assembled into geometric primitives
-                                // strip_index_format: Some(wgpu::IndexFormat::Uint32),
-                                strip_index_format: None,
-                                front_face: wgpu::FrontFace::Ccw, // Counter-clockwise is considered the front face
-                                // none cull_mode
-                                cull_mode: None,
-                                polygon_mode: wgpu::PolygonMode::Fill,
-                                // Other properties such as conservative rasterization can be set here
-                                unclipped_depth: false,
-                            },
-                            depth_stencil: Some(depth_stencil_state), // Optional, only if you are using depth testing
-                            multisample: wgpu::MultisampleState {
-                                count: 4, // effect performance
-                                mask: !0,
-                                alpha_to_coverage_enabled: false,
-                            },
-                        });
+                let render_pipeline = create_scene_pipeline(
+                    &gpu_resources.device,
+                    &camera_binding.bind_group_layout,
+                    WINDOW_SAMPLE_COUNT,
+                );
 
                 // window_handle.render_pipeline = Some(render_pipeline);
                 // window_handle.depth_view = gpu_helper.depth_view;
@@ -791,9 +1149,11 @@ async fn main() {
                 println!("Initialized...");
 
                 window_handle.handle_cursor_moved = handle_cursor_moved(
+                    state_5.clone(),
                     cloned2.clone(),
                     gpu_resources.clone(),
                     cloned_viewport.clone(),
+                    invalidator.clone(),
                 );
                 window_handle.handle_mouse_input = handle_mouse_input(
                     state_4.clone(),
@@ -801,15 +1161,21 @@ async fn main() {
                     gpu_resources.clone(),
                     cloned_viewport2.clone(),
                     record_2.clone(),
+                    events_tx.clone(),
+                    invalidator.clone(),
                 );
                 window_handle.handle_window_resized = handle_window_resize(
                     cloned7,
+                    Arc::clone(&strokes),
+                    Arc::clone(&images),
                     gpu_resources.clone(),
                     gpu_cloned3,
                     cloned_viewport3.clone(),
+                    events_tx.clone(),
+                    invalidator.clone(),
                 );
                 window_handle.handle_mouse_wheel =
-                    handle_mouse_wheel(cloned11, gpu_resources.clone(), cloned_viewport3.clone());
+                    handle_mouse_wheel(state_4.clone(), gpu_resources.clone());
                 window_handle.handle_modifiers_changed = handle_modifiers_changed(
                     state_3,
                     gpu_resources.clone(),
@@ -880,11 +1246,12 @@ async fn main() {
 
                 editor.update_camera_binding(&gpu_resources.queue);
 
-                gpu_clonsed2.lock().unwrap().gpu_resources = Some(Arc::clone(&gpu_resources));
+                gpu_clonsed2.lock_or_recover().gpu_resources = Some(Arc::clone(&gpu_resources));
                 editor.gpu_resources = Some(Arc::clone(&gpu_resources));
                 window_handle.gpu_resources = Some(gpu_resources);
                 // window_handle.gpu_helper = Some(gpu_clonsed2);
                 editor.window = window_handle.window.clone();
+                invalidator.set_window(window_handle.window.clone());
                 window_handle.engine_handle = Some(EngineHandle {
                     render_pipeline: Some(render_pipeline),
                     user_editor: Some(Box::new(cloned)),
@@ -901,4 +1268,9 @@ async fn main() {
     }
 
     app.run();
+
+    // a clean exit leaves nothing to recover
+    if let Some(session) = session {
+        session.end();
+    }
 }
@@ -0,0 +1,889 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use strum_macros::EnumIter;
+use uuid::Uuid;
+
+// Rec. 709 weights, for luminance in encoded values
+const LUMA: [f32; 3] = [0.2126, 0.7152, 0.0722];
+// how far full temperature or tint moves a channel
+const WHITE_BALANCE_RANGE: f32 = 0.3;
+// how far full highlights or shadows pull a tone toward white or black
+const TONE_RANGE: f32 = 0.5;
+// where a fresh curve's points sit, end points included
+const CURVE_INPUTS: [f32; 5] = [0.0, 0.25, 0.5, 0.75, 1.0];
+
+/// A single photo adjustment. Values are 0 at rest unless noted, so a fresh
+/// adjustment leaves the image as it was.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Adjustment {
+    // in stops
+    Exposure(f32),
+    // -1.0 - 1.0
+    Contrast(f32),
+    // -1.0 - 1.0 each, positive lifts
+    HighlightsShadows {
+        highlights: f32,
+        shadows: f32,
+    },
+    // -1.0 - 1.0 each, positive is warmer and more magenta
+    WhiteBalance {
+        temperature: f32,
+        tint: f32,
+    },
+    // -1.0 - 1.0 each, vibrance favours colours that aren't saturated yet
+    Saturation {
+        saturation: f32,
+        vibrance: f32,
+    },
+    // input and output points are 0.0 - 1.0, gamma is 1.0 at rest
+    Levels {
+        input_black: f32,
+        input_white: f32,
+        gamma: f32,
+        output_black: f32,
+        output_white: f32,
+    },
+    // points are [input, output], 0.0 - 1.0, sorted by input
+    Curves(Vec<[f32; 2]>),
+}
+
+impl Adjustment {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Adjustment::Exposure(_) => "Exposure",
+            Adjustment::Contrast(_) => "Contrast",
+            Adjustment::HighlightsShadows { .. } => "Highlights / Shadows",
+            Adjustment::WhiteBalance { .. } => "White Balance",
+            Adjustment::Saturation { .. } => "Saturation",
+            Adjustment::Levels { .. } => "Levels",
+            Adjustment::Curves(_) => "Curves",
+        }
+    }
+
+    /// One of every adjustment, at rest
+    pub fn all() -> Vec<Adjustment> {
+        vec![
+            Adjustment::Exposure(0.0),
+            Adjustment::Contrast(0.0),
+            Adjustment::HighlightsShadows {
+                highlights: 0.0,
+                shadows: 0.0,
+            },
+            Adjustment::WhiteBalance {
+                temperature: 0.0,
+                tint: 0.0,
+            },
+            Adjustment::Saturation {
+                saturation: 0.0,
+                vibrance: 0.0,
+            },
+            Adjustment::levels(),
+            Adjustment::curves(),
+        ]
+    }
+
+    /// What the properties panel edits on it
+    pub fn fields(&self) -> Vec<AdjustmentField> {
+        match self {
+            Adjustment::Exposure(_) => vec![AdjustmentField::Exposure],
+            Adjustment::Contrast(_) => vec![AdjustmentField::Contrast],
+            Adjustment::HighlightsShadows { .. } => {
+                vec![AdjustmentField::Highlights, AdjustmentField::Shadows]
+            }
+            Adjustment::WhiteBalance { .. } => {
+                vec![AdjustmentField::Temperature, AdjustmentField::Tint]
+            }
+            Adjustment::Saturation { .. } => {
+                vec![AdjustmentField::Saturation, AdjustmentField::Vibrance]
+            }
+            Adjustment::Levels { .. } => vec![
+                AdjustmentField::InputBlack,
+                AdjustmentField::InputWhite,
+                AdjustmentField::Gamma,
+                AdjustmentField::OutputBlack,
+                AdjustmentField::OutputWhite,
+            ],
+            // only the points a fresh curve has can be typed in
+            Adjustment::Curves(points) => [
+                AdjustmentField::CurveBlack,
+                AdjustmentField::CurveShadows,
+                AdjustmentField::CurveMidtones,
+                AdjustmentField::CurveHighlights,
+                AdjustmentField::CurveWhite,
+            ]
+            .into_iter()
+            .filter(|field| {
+                field
+                    .curve_point()
+                    .is_some_and(|index| index < points.len())
+            })
+            .collect(),
+        }
+    }
+
+    pub fn levels() -> Self {
+        Adjustment::Levels {
+            input_black: 0.0,
+            input_white: 1.0,
+            gamma: 1.0,
+            output_black: 0.0,
+            output_white: 1.0,
+        }
+    }
+
+    pub fn curves() -> Self {
+        Adjustment::Curves(CURVE_INPUTS.map(|input| [input, input]).to_vec())
+    }
+
+    /// Adjusts one pixel. Colours are sRGB encoded, 0.0 - 1.0.
+    pub fn apply(&self, rgb: [f32; 3]) -> [f32; 3] {
+        let adjusted = match self {
+            Adjustment::Exposure(stops) => {
+                // exposure is light, so it scales linear values
+                let scale = 2f32.powf(*stops);
+                rgb.map(|c| to_srgb(to_linear(c) * scale))
+            }
+            Adjustment::Contrast(amount) => {
+                let factor = 4f32.powf(amount.clamp(-1.0, 1.0));
+                rgb.map(|c| (c - 0.5) * factor + 0.5)
+            }
+            Adjustment::HighlightsShadows {
+                highlights,
+                shadows,
+            } => highlights_shadows(rgb, *highlights, *shadows),
+            Adjustment::WhiteBalance { temperature, tint } => {
+                let temperature = temperature.clamp(-1.0, 1.0) * WHITE_BALANCE_RANGE;
+                let tint = tint.clamp(-1.0, 1.0) * WHITE_BALANCE_RANGE;
+                let gains = [1.0 + temperature, 1.0 - tint, 1.0 - temperature];
+
+                [0, 1, 2].map(|i| to_srgb(to_linear(rgb[i]) * gains[i]))
+            }
+            Adjustment::Saturation {
+                saturation,
+                vibrance,
+            } => {
+                let gray = luminance(rgb);
+                let max = rgb.iter().cloned().fold(f32::MIN, f32::max);
+                let min = rgb.iter().cloned().fold(f32::MAX, f32::min);
+
+                let factor = (1.0 + saturation.clamp(-1.0, 1.0))
+                    * (1.0 + vibrance.clamp(-1.0, 1.0) * (1.0 - (max - min)));
+
+                rgb.map(|c| gray + (c - gray) * factor)
+            }
+            Adjustment::Levels {
+                input_black,
+                input_white,
+                gamma,
+                output_black,
+                output_white,
+            } => {
+                let range = (input_white - input_black).max(f32::EPSILON);
+                let exponent = 1.0 / gamma.max(0.01);
+
+                rgb.map(|c| {
+                    let v = ((c - input_black) / range).clamp(0.0, 1.0).powf(exponent);
+                    output_black + v * (output_white - output_black)
+                })
+            }
+            Adjustment::Curves(points) => rgb.map(|c| evaluate_curve(points, c)),
+        };
+
+        adjusted.map(|c| c.clamp(0.0, 1.0))
+    }
+}
+
+pub fn to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn to_srgb(c: f32) -> f32 {
+    let c = c.max(0.0);
+
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+pub fn luminance(rgb: [f32; 3]) -> f32 {
+    rgb[0] * LUMA[0] + rgb[1] * LUMA[1] + rgb[2] * LUMA[2]
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+// Moves the luminance of dark and bright tones separately, keeping the colour
+fn highlights_shadows(rgb: [f32; 3], highlights: f32, shadows: f32) -> [f32; 3] {
+    let l = luminance(rgb);
+
+    let pull = |amount: f32, mask: f32| {
+        let amount = amount.clamp(-1.0, 1.0) * TONE_RANGE * mask;
+        // lifting heads toward white, lowering toward black
+        if amount > 0.0 {
+            amount * (1.0 - l)
+        } else {
+            amount * l
+        }
+    };
+
+    let shifted = l
+        + pull(shadows, 1.0 - smoothstep(0.0, 0.5, l))
+        + pull(highlights, smoothstep(0.5, 1.0, l));
+
+    if l <= f32::EPSILON {
+        return [shifted; 3];
+    }
+
+    rgb.map(|c| c * shifted / l)
+}
+
+/// Monotone cubic through the curve's points, so the curve never overshoots them
+pub fn evaluate_curve(points: &[[f32; 2]], x: f32) -> f32 {
+    match points {
+        [] => return x,
+        [only] => return only[1],
+        _ => {}
+    }
+
+    let last = points.len() - 1;
+    if x <= points[0][0] {
+        return points[0][1];
+    }
+    if x >= points[last][0] {
+        return points[last][1];
+    }
+
+    let slopes: Vec<f32> = points
+        .windows(2)
+        .map(|pair| (pair[1][1] - pair[0][1]) / (pair[1][0] - pair[0][0]).max(f32::EPSILON))
+        .collect();
+
+    // Fritsch–Carlson tangents
+    let tangent = |i: usize| -> f32 {
+        if i == 0 {
+            slopes[0]
+        } else if i == last {
+            slopes[last - 1]
+        } else if slopes[i - 1] * slopes[i] <= 0.0 {
+            0.0
+        } else {
+            let (a, b) = (slopes[i - 1], slopes[i]);
+            3.0 * a * b / (2.0 * a.max(b) + a.min(b))
+        }
+    };
+
+    let i = points
+        .windows(2)
+        .position(|pair| x < pair[1][0])
+        .unwrap_or(last - 1);
+
+    let ([x0, y0], [x1, y1]) = (points[i], points[i + 1]);
+    let h = (x1 - x0).max(f32::EPSILON);
+    let t = (x - x0) / h;
+    let (t2, t3) = (t * t, t * t * t);
+
+    (2.0 * t3 - 3.0 * t2 + 1.0) * y0
+        + (t3 - 2.0 * t2 + t) * h * tangent(i)
+        + (-2.0 * t3 + 3.0 * t2) * y1
+        + (t3 - t2) * h * tangent(i + 1)
+}
+
+/// What the properties panel edits on an adjustment
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, EnumIter)]
+pub enum AdjustmentField {
+    Exposure,
+    Contrast,
+    Highlights,
+    Shadows,
+    Temperature,
+    Tint,
+    Saturation,
+    Vibrance,
+    InputBlack,
+    InputWhite,
+    Gamma,
+    OutputBlack,
+    OutputWhite,
+    // outputs of a fresh curve's points, bottom to top
+    CurveBlack,
+    CurveShadows,
+    CurveMidtones,
+    CurveHighlights,
+    CurveWhite,
+}
+
+impl AdjustmentField {
+    pub fn label(&self) -> &'static str {
+        match self {
+            AdjustmentField::Exposure => "Stops:",
+            AdjustmentField::Contrast => "Contrast:",
+            AdjustmentField::Highlights => "Highlights:",
+            AdjustmentField::Shadows => "Shadows:",
+            AdjustmentField::Temperature => "Temperature:",
+            AdjustmentField::Tint => "Tint:",
+            AdjustmentField::Saturation => "Saturation:",
+            AdjustmentField::Vibrance => "Vibrance:",
+            AdjustmentField::InputBlack => "In Black:",
+            AdjustmentField::InputWhite => "In White:",
+            AdjustmentField::Gamma => "Gamma:",
+            AdjustmentField::OutputBlack => "Out Black:",
+            AdjustmentField::OutputWhite => "Out White:",
+            AdjustmentField::CurveBlack => "Black:",
+            AdjustmentField::CurveShadows => "Shadows:",
+            AdjustmentField::CurveMidtones => "Midtones:",
+            AdjustmentField::CurveHighlights => "Highlights:",
+            AdjustmentField::CurveWhite => "White:",
+        }
+    }
+
+    /// The name the field's input signal is registered under. Entries are named by id
+    /// rather than position, so inputs keep following them when the stack is reordered.
+    pub fn signal_name(&self, entry_id: Uuid) -> String {
+        format!("adjustment_{}_{:?}", entry_id, self)
+    }
+
+    fn curve_point(&self) -> Option<usize> {
+        match self {
+            AdjustmentField::CurveBlack => Some(0),
+            AdjustmentField::CurveShadows => Some(1),
+            AdjustmentField::CurveMidtones => Some(2),
+            AdjustmentField::CurveHighlights => Some(3),
+            AdjustmentField::CurveWhite => Some(4),
+            _ => None,
+        }
+    }
+
+    /// The value as it's entered, None when the adjustment doesn't have the field
+    pub fn read(&self, adjustment: &Adjustment) -> Option<f32> {
+        match (self, adjustment) {
+            (AdjustmentField::Exposure, Adjustment::Exposure(stops)) => Some(*stops),
+            (AdjustmentField::Contrast, Adjustment::Contrast(amount)) => Some(*amount),
+            (AdjustmentField::Highlights, Adjustment::HighlightsShadows { highlights, .. }) => {
+                Some(*highlights)
+            }
+            (AdjustmentField::Shadows, Adjustment::HighlightsShadows { shadows, .. }) => {
+                Some(*shadows)
+            }
+            (AdjustmentField::Temperature, Adjustment::WhiteBalance { temperature, .. }) => {
+                Some(*temperature)
+            }
+            (AdjustmentField::Tint, Adjustment::WhiteBalance { tint, .. }) => Some(*tint),
+            (AdjustmentField::Saturation, Adjustment::Saturation { saturation, .. }) => {
+                Some(*saturation)
+            }
+            (AdjustmentField::Vibrance, Adjustment::Saturation { vibrance, .. }) => Some(*vibrance),
+            (AdjustmentField::InputBlack, Adjustment::Levels { input_black, .. }) => {
+                Some(*input_black)
+            }
+            (AdjustmentField::InputWhite, Adjustment::Levels { input_white, .. }) => {
+                Some(*input_white)
+            }
+            (AdjustmentField::Gamma, Adjustment::Levels { gamma, .. }) => Some(*gamma),
+            (AdjustmentField::OutputBlack, Adjustment::Levels { output_black, .. }) => {
+                Some(*output_black)
+            }
+            (AdjustmentField::OutputWhite, Adjustment::Levels { output_white, .. }) => {
+                Some(*output_white)
+            }
+            (field, Adjustment::Curves(points)) => field
+                .curve_point()
+                .and_then(|index| points.get(index))
+                .map(|point| point[1]),
+            _ => None,
+        }
+    }
+
+    pub fn apply(&self, adjustment: &mut Adjustment, value: f32) {
+        let amount = value.clamp(-1.0, 1.0);
+        let level = value.clamp(0.0, 1.0);
+
+        match (self, adjustment) {
+            (AdjustmentField::Exposure, Adjustment::Exposure(stops)) => *stops = value,
+            (AdjustmentField::Contrast, Adjustment::Contrast(contrast)) => *contrast = amount,
+            (AdjustmentField::Highlights, Adjustment::HighlightsShadows { highlights, .. }) => {
+                *highlights = amount
+            }
+            (AdjustmentField::Shadows, Adjustment::HighlightsShadows { shadows, .. }) => {
+                *shadows = amount
+            }
+            (AdjustmentField::Temperature, Adjustment::WhiteBalance { temperature, .. }) => {
+                *temperature = amount
+            }
+            (AdjustmentField::Tint, Adjustment::WhiteBalance { tint, .. }) => *tint = amount,
+            (AdjustmentField::Saturation, Adjustment::Saturation { saturation, .. }) => {
+                *saturation = amount
+            }
+            (AdjustmentField::Vibrance, Adjustment::Saturation { vibrance, .. }) => {
+                *vibrance = amount
+            }
+            (AdjustmentField::InputBlack, Adjustment::Levels { input_black, .. }) => {
+                *input_black = level
+            }
+            (AdjustmentField::InputWhite, Adjustment::Levels { input_white, .. }) => {
+                *input_white = level
+            }
+            (AdjustmentField::Gamma, Adjustment::Levels { gamma, .. }) => *gamma = value.max(0.01),
+            (AdjustmentField::OutputBlack, Adjustment::Levels { output_black, .. }) => {
+                *output_black = level
+            }
+            (AdjustmentField::OutputWhite, Adjustment::Levels { output_white, .. }) => {
+                *output_white = level
+            }
+            (field, Adjustment::Curves(points)) => {
+                if let Some(point) = field.curve_point().and_then(|index| points.get_mut(index)) {
+                    point[1] = level;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AdjustmentEntry {
+    pub id: Uuid,
+    pub adjustment: Adjustment,
+    pub enabled: bool,
+}
+
+/// The adjustments on a layer, applied top to bottom over the untouched original
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AdjustmentStack {
+    entries: Vec<AdjustmentEntry>,
+}
+
+impl AdjustmentStack {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn entries(&self) -> &[AdjustmentEntry] {
+        &self.entries
+    }
+
+    /// Whether anything in it would change a pixel
+    pub fn is_active(&self) -> bool {
+        self.entries.iter().any(|entry| entry.enabled)
+    }
+
+    pub fn get(&self, id: Uuid) -> Option<&AdjustmentEntry> {
+        self.entries.iter().find(|entry| entry.id == id)
+    }
+
+    pub fn position(&self, id: Uuid) -> Option<usize> {
+        self.entries.iter().position(|entry| entry.id == id)
+    }
+
+    // at the bottom unless a position is given
+    pub fn insert(&mut self, entry: AdjustmentEntry, position: Option<usize>) {
+        let position = position
+            .unwrap_or(self.entries.len())
+            .min(self.entries.len());
+
+        self.entries.insert(position, entry);
+    }
+
+    pub fn add(&mut self, adjustment: Adjustment) -> Uuid {
+        let id = Uuid::new_v4();
+
+        self.insert(
+            AdjustmentEntry {
+                id,
+                adjustment,
+                enabled: true,
+            },
+            None,
+        );
+
+        id
+    }
+
+    pub fn remove(&mut self, id: Uuid) -> Option<(AdjustmentEntry, usize)> {
+        let position = self.position(id)?;
+        Some((self.entries.remove(position), position))
+    }
+
+    /// Moves the adjustment to a new position, returning where it was
+    pub fn move_to(&mut self, id: Uuid, position: usize) -> Option<usize> {
+        let (entry, previous) = self.remove(id)?;
+        self.insert(entry, Some(position));
+
+        Some(previous)
+    }
+
+    /// Replaces the adjustment's values, returning the old ones
+    pub fn update(&mut self, id: Uuid, adjustment: Adjustment) -> Option<Adjustment> {
+        let entry = self.entries.iter_mut().find(|entry| entry.id == id)?;
+        Some(std::mem::replace(&mut entry.adjustment, adjustment))
+    }
+
+    pub fn set_enabled(&mut self, id: Uuid, enabled: bool) -> Option<bool> {
+        let entry = self.entries.iter_mut().find(|entry| entry.id == id)?;
+        Some(std::mem::replace(&mut entry.enabled, enabled))
+    }
+
+    pub fn apply(&self, rgb: [f32; 3]) -> [f32; 3] {
+        self.entries
+            .iter()
+            .filter(|entry| entry.enabled)
+            .fold(rgb, |rgb, entry| entry.adjustment.apply(rgb))
+    }
+
+    /// The CPU reference: adjusts a copy of RGBA8 pixels, alpha is left alone.
+    /// GPU paths should match this to within rounding.
+    pub fn apply_rgba8(&self, pixels: &[u8]) -> Vec<u8> {
+        let mut adjusted = pixels.to_vec();
+
+        for pixel in adjusted.chunks_exact_mut(4) {
+            let rgb = self.apply([0, 1, 2].map(|i| pixel[i] as f32 / 255.0));
+
+            for i in 0..3 {
+                pixel[i] = (rgb[i] * 255.0).round() as u8;
+            }
+        }
+
+        adjusted
+    }
+
+    /// The stack baked into a size³ RGBA8 lookup table, red fastest and blue slowest.
+    /// The composite shader samples it between grid points, so it only has to agree
+    /// with `apply` where the grid lands.
+    pub fn lut(&self, size: usize) -> Vec<u8> {
+        let size = size.max(2);
+        let step = 1.0 / (size - 1) as f32;
+        let mut table = Vec::with_capacity(size * size * size * 4);
+
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    let rgb = self.apply([r as f32 * step, g as f32 * step, b as f32 * step]);
+
+                    table.extend(rgb.map(|c| (c * 255.0).round() as u8));
+                    table.push(255);
+                }
+            }
+        }
+
+        table
+    }
+}
+
+/// Adjustment stacks for every layer, by layer id. Like effects, a stack outlives its
+/// layer so undoing a delete brings the adjustments back too.
+#[derive(Debug, Default)]
+pub struct LayerAdjustments {
+    stacks: HashMap<Uuid, AdjustmentStack>,
+}
+
+// Lock after the shape effects, never before
+pub type SharedLayerAdjustments = Arc<Mutex<LayerAdjustments>>;
+
+impl LayerAdjustments {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, layer_id: Uuid) -> AdjustmentStack {
+        self.stacks.get(&layer_id).cloned().unwrap_or_default()
+    }
+
+    /// The layer's stack, if it would change anything
+    pub fn active(&self, layer_id: Uuid) -> Option<&AdjustmentStack> {
+        self.stacks.get(&layer_id).filter(|stack| stack.is_active())
+    }
+
+    pub fn set(&mut self, layer_id: Uuid, stack: AdjustmentStack) {
+        if stack.entries().is_empty() {
+            self.stacks.remove(&layer_id);
+        } else {
+            self.stacks.insert(layer_id, stack);
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Uuid, &AdjustmentStack)> {
+        self.stacks
+            .iter()
+            .map(|(layer_id, stack)| (*layer_id, stack))
+    }
+
+    pub fn clear(&mut self) {
+        self.stacks.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: [f32; 3], expected: [f32; 3]) {
+        for i in 0..3 {
+            assert!(
+                (actual[i] - expected[i]).abs() < 1e-3,
+                "{:?} != {:?}",
+                actual,
+                expected
+            );
+        }
+    }
+
+    fn stack(adjustments: Vec<Adjustment>) -> AdjustmentStack {
+        let mut stack = AdjustmentStack::new();
+        for adjustment in adjustments {
+            stack.add(adjustment);
+        }
+        stack
+    }
+
+    #[test]
+    fn adjustments_at_rest_change_nothing() {
+        let colors = [[0.0; 3], [1.0; 3], [0.2, 0.5, 0.8], [0.9, 0.1, 0.4]];
+
+        for adjustment in Adjustment::all() {
+            for color in colors {
+                assert_close(adjustment.apply(color), color);
+            }
+        }
+    }
+
+    #[test]
+    fn exposure_scales_linear_light() {
+        // one stop up doubles the light, which is less than double the encoded value
+        assert_close(Adjustment::Exposure(1.0).apply([0.5; 3]), [0.6858; 3]);
+        assert_close(Adjustment::Exposure(-1.0).apply([0.5; 3]), [0.3613; 3]);
+        assert_close(Adjustment::Exposure(4.0).apply([0.9; 3]), [1.0; 3]);
+    }
+
+    #[test]
+    fn contrast_pivots_on_mid_grey() {
+        // half contrast doubles the distance from grey
+        let adjusted = Adjustment::Contrast(0.5).apply([0.5, 0.625, 0.25]);
+        assert_close(adjusted, [0.5, 0.75, 0.0]);
+
+        let flattened = Adjustment::Contrast(-0.5).apply([0.5, 0.9, 0.1]);
+        assert_close(flattened, [0.5, 0.7, 0.3]);
+    }
+
+    #[test]
+    fn highlights_and_shadows_move_their_own_tones() {
+        let lifted = Adjustment::HighlightsShadows {
+            highlights: 0.0,
+            shadows: 1.0,
+        };
+        assert!(lifted.apply([0.1; 3])[0] > 0.1);
+        // highlights are left where they were
+        assert_close(lifted.apply([0.9; 3]), [0.9; 3]);
+
+        let lowered = Adjustment::HighlightsShadows {
+            highlights: -1.0,
+            shadows: 0.0,
+        };
+        assert!(lowered.apply([0.9; 3])[0] < 0.9);
+        assert_close(lowered.apply([0.1; 3]), [0.1; 3]);
+    }
+
+    #[test]
+    fn white_balance_warms_and_tints() {
+        let warmer = Adjustment::WhiteBalance {
+            temperature: 1.0,
+            tint: 0.0,
+        }
+        .apply([0.5; 3]);
+        assert!(warmer[0] > 0.5 && warmer[2] < 0.5);
+        assert!((warmer[1] - 0.5).abs() < 1e-3);
+
+        let magenta = Adjustment::WhiteBalance {
+            temperature: 0.0,
+            tint: 1.0,
+        }
+        .apply([0.5; 3]);
+        assert!(magenta[1] < 0.5);
+        assert!((magenta[0] - 0.5).abs() < 1e-3);
+    }
+
+    #[test]
+    fn saturation_at_minus_one_is_grey() {
+        let color = [0.8, 0.4, 0.2];
+        let grey = Adjustment::Saturation {
+            saturation: -1.0,
+            vibrance: 0.0,
+        }
+        .apply(color);
+
+        assert_close(grey, [luminance(color); 3]);
+    }
+
+    #[test]
+    fn vibrance_favours_muted_colours() {
+        let vibrance = Adjustment::Saturation {
+            saturation: 0.0,
+            vibrance: 1.0,
+        };
+        let spread = |rgb: [f32; 3]| rgb[0] - rgb[2];
+
+        let muted = [0.55, 0.5, 0.45];
+        let vivid = [0.95, 0.5, 0.05];
+
+        let muted_gain = spread(vibrance.apply(muted)) / spread(muted);
+        let vivid_gain = spread(vibrance.apply(vivid)) / spread(vivid);
+        assert!(muted_gain > vivid_gain);
+    }
+
+    #[test]
+    fn levels_remap_the_input_range() {
+        let levels = Adjustment::Levels {
+            input_black: 0.2,
+            input_white: 0.8,
+            gamma: 1.0,
+            output_black: 0.1,
+            output_white: 0.9,
+        };
+
+        assert_close(levels.apply([0.2, 0.5, 0.8]), [0.1, 0.5, 0.9]);
+        // past the input points is clipped to the output ones
+        assert_close(levels.apply([0.0, 0.1, 1.0]), [0.1, 0.1, 0.9]);
+
+        let mut brighter = Adjustment::levels();
+        AdjustmentField::Gamma.apply(&mut brighter, 2.0);
+        assert_close(brighter.apply([0.25; 3]), [0.5; 3]);
+    }
+
+    #[test]
+    fn curves_pass_through_their_points_without_overshooting() {
+        let points = vec![[0.0, 0.0], [0.25, 0.4], [0.5, 0.5], [1.0, 1.0]];
+
+        for point in &points {
+            assert!((evaluate_curve(&points, point[0]) - point[1]).abs() < 1e-5);
+        }
+
+        // monotone points make a monotone curve
+        let samples: Vec<f32> = (0..=100)
+            .map(|i| evaluate_curve(&points, i as f32 / 100.0))
+            .collect();
+        assert!(samples.windows(2).all(|pair| pair[1] >= pair[0] - 1e-6));
+
+        // ends are held flat past the first and last points
+        assert_eq!(evaluate_curve(&[[0.2, 0.1], [0.8, 0.9]], 0.0), 0.1);
+        assert_eq!(evaluate_curve(&[[0.2, 0.1], [0.8, 0.9]], 1.0), 0.9);
+    }
+
+    #[test]
+    fn stack_applies_top_to_bottom_and_skips_disabled() {
+        let mut adjustments = stack(vec![Adjustment::Exposure(1.0), Adjustment::Contrast(0.5)]);
+        let exposure_first = adjustments.apply([0.4; 3]);
+
+        let contrast_id = adjustments.entries()[1].id;
+        assert_eq!(adjustments.move_to(contrast_id, 0), Some(1));
+        let contrast_first = adjustments.apply([0.4; 3]);
+        assert!((exposure_first[0] - contrast_first[0]).abs() > 0.01);
+
+        let expected = Adjustment::Exposure(1.0).apply([0.4; 3]);
+        assert_eq!(adjustments.set_enabled(contrast_id, false), Some(true));
+        assert_close(adjustments.apply([0.4; 3]), expected);
+        assert!(adjustments.is_active());
+
+        let exposure_id = adjustments.entries()[1].id;
+        adjustments.set_enabled(exposure_id, false);
+        assert!(!adjustments.is_active());
+    }
+
+    #[test]
+    fn stack_edits_report_what_they_replaced() {
+        let mut adjustments = stack(vec![Adjustment::Exposure(1.0)]);
+        let id = adjustments.entries()[0].id;
+
+        assert_eq!(
+            adjustments.update(id, Adjustment::Exposure(2.0)),
+            Some(Adjustment::Exposure(1.0))
+        );
+
+        let (entry, position) = adjustments.remove(id).unwrap();
+        assert_eq!(position, 0);
+        assert_eq!(entry.adjustment, Adjustment::Exposure(2.0));
+        assert!(adjustments.entries().is_empty());
+        assert_eq!(adjustments.update(id, Adjustment::Exposure(0.0)), None);
+    }
+
+    #[test]
+    fn rgba8_keeps_alpha() {
+        let adjustments = stack(vec![Adjustment::Saturation {
+            saturation: -1.0,
+            vibrance: 0.0,
+        }]);
+        let adjusted = adjustments.apply_rgba8(&[255, 0, 0, 128, 0, 0, 255, 7]);
+
+        // Rec. 709 red and blue
+        assert_eq!(adjusted, vec![54, 54, 54, 128, 18, 18, 18, 7]);
+    }
+
+    #[test]
+    fn lut_matches_the_stack_on_its_grid() {
+        let adjustments = stack(vec![Adjustment::Exposure(0.5), Adjustment::Contrast(0.2)]);
+        let size = 5;
+        let table = adjustments.lut(size);
+        assert_eq!(table.len(), size * size * size * 4);
+
+        // red fastest, then green, then blue
+        let (r, g, b) = (1, 2, 4);
+        let index = ((b * size + g) * size + r) * 4;
+        let grid = [r, g, b].map(|i| i as f32 / (size - 1) as f32);
+        let expected = adjustments.apply(grid).map(|c| (c * 255.0).round() as u8);
+
+        assert_eq!(&table[index..index + 3], expected.as_slice());
+        assert_eq!(table[index + 3], 255);
+
+        // nothing to adjust gives the corners of the colour cube
+        let identity = AdjustmentStack::new().lut(2);
+        assert_eq!(&identity[0..4], &[0, 0, 0, 255]);
+        assert_eq!(&identity[4..8], &[255, 0, 0, 255]);
+        assert_eq!(&identity[28..32], &[255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn fields_read_back_what_they_apply() {
+        for mut adjustment in Adjustment::all() {
+            for field in adjustment.fields() {
+                field.apply(&mut adjustment, 0.75);
+                assert_eq!(field.read(&adjustment), Some(0.75), "{:?}", field);
+            }
+        }
+
+        // amounts are clamped, fields of other adjustments are ignored
+        let mut contrast = Adjustment::Contrast(0.0);
+        AdjustmentField::Contrast.apply(&mut contrast, 3.0);
+        AdjustmentField::Gamma.apply(&mut contrast, 3.0);
+        assert_eq!(contrast, Adjustment::Contrast(1.0));
+        assert_eq!(AdjustmentField::Gamma.read(&contrast), None);
+    }
+
+    #[test]
+    fn layer_adjustments_drop_empty_stacks() {
+        let mut layers = LayerAdjustments::new();
+        let layer_id = Uuid::new_v4();
+
+        let mut adjustments = stack(vec![Adjustment::Exposure(1.0)]);
+        layers.set(layer_id, adjustments.clone());
+        assert!(layers.active(layer_id).is_some());
+
+        let entry_id = adjustments.entries()[0].id;
+        adjustments.set_enabled(entry_id, false);
+        layers.set(layer_id, adjustments.clone());
+        // kept for the panel, but there's nothing to draw
+        assert!(layers.active(layer_id).is_none());
+        assert_eq!(layers.iter().count(), 1);
+
+        adjustments.remove(entry_id);
+        layers.set(layer_id, adjustments);
+        assert_eq!(layers.iter().count(), 0);
+        assert_eq!(layers.get(layer_id), AdjustmentStack::new());
+    }
+}
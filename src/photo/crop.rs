@@ -0,0 +1,385 @@
+use serde::{Deserialize, Serialize};
+use strum_macros::EnumIter;
+use uuid::Uuid;
+
+// steps when shrinking a crop to fit inside a rotated image
+const FIT_STEPS: usize = 24;
+// straightening is for fixing a tilted horizon, not turning the photo over
+const MAX_STRAIGHTEN: f32 = 45.0;
+// lines drawn shorter than this, in scene pixels, are taken as a click
+pub const MIN_STRAIGHTEN_LENGTH: f32 = 4.0;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, EnumIter)]
+pub enum AspectPreset {
+    Free,
+    Original,
+    Square,
+    Portrait,
+    Wide,
+}
+
+impl AspectPreset {
+    pub fn label(&self) -> &'static str {
+        match self {
+            AspectPreset::Free => "Free",
+            AspectPreset::Original => "Original",
+            AspectPreset::Square => "1:1",
+            AspectPreset::Portrait => "4:5",
+            AspectPreset::Wide => "16:9",
+        }
+    }
+
+    /// Width over height in pixels, None when any shape goes
+    pub fn ratio(&self, image_width: u32, image_height: u32) -> Option<f32> {
+        match self {
+            AspectPreset::Free => None,
+            AspectPreset::Original => Some(image_width as f32 / image_height.max(1) as f32),
+            AspectPreset::Square => Some(1.0),
+            AspectPreset::Portrait => Some(4.0 / 5.0),
+            AspectPreset::Wide => Some(16.0 / 9.0),
+        }
+    }
+}
+
+/// A crop kept as parameters on the layer, so the original pixels are never touched.
+/// The image is flipped, then turned about its centre, then cut to the rect.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CropParams {
+    // x, y, width, height as fractions of the image size
+    pub rect: [f32; 4],
+    // degrees, clockwise
+    pub rotation: f32,
+    pub flip_horizontal: bool,
+    pub flip_vertical: bool,
+}
+
+impl Default for CropParams {
+    fn default() -> Self {
+        CropParams {
+            rect: [0.0, 0.0, 1.0, 1.0],
+            rotation: 0.0,
+            flip_horizontal: false,
+            flip_vertical: false,
+        }
+    }
+}
+
+impl CropParams {
+    pub fn is_identity(&self) -> bool {
+        *self == CropParams::default()
+    }
+
+    /// The crop in image pixels, x, y, width and height
+    pub fn pixel_rect(&self, width: u32, height: u32) -> [f32; 4] {
+        let (w, h) = (width as f32, height as f32);
+        [
+            self.rect[0] * w,
+            self.rect[1] * h,
+            self.rect[2] * w,
+            self.rect[3] * h,
+        ]
+    }
+
+    fn set_pixel_rect(&mut self, rect: [f32; 4], width: u32, height: u32) {
+        let (w, h) = (width.max(1) as f32, height.max(1) as f32);
+        self.rect = [rect[0] / w, rect[1] / h, rect[2] / w, rect[3] / h];
+    }
+
+    /// Narrows the crop to the preset's shape, keeping its centre
+    pub fn apply_preset(&mut self, preset: AspectPreset, width: u32, height: u32) {
+        let Some(ratio) = preset.ratio(width, height) else {
+            return;
+        };
+
+        let [x, y, w, h] = self.pixel_rect(width, height);
+        let (new_w, new_h) = if w / h.max(f32::EPSILON) > ratio {
+            (h * ratio, h)
+        } else {
+            (w, w / ratio)
+        };
+
+        self.set_pixel_rect(
+            [x + (w - new_w) / 2.0, y + (h - new_h) / 2.0, new_w, new_h],
+            width,
+            height,
+        );
+    }
+
+    pub fn rotate_by(&mut self, degrees: f32, width: u32, height: u32) {
+        self.rotation = (self.rotation + degrees).rem_euclid(360.0);
+        self.fit_rotation(width, height);
+    }
+
+    /// Levels the image so the line drawn along the horizon, or an upright, ends up straight
+    pub fn straighten(&mut self, line: [[f32; 2]; 2], width: u32, height: u32) {
+        let angle = straighten_angle(line);
+        self.rotation = (self.rotation + angle).rem_euclid(360.0);
+        self.fit_rotation(width, height);
+    }
+
+    pub fn flip(&mut self, horizontal: bool) {
+        if horizontal {
+            self.flip_horizontal = !self.flip_horizontal;
+        } else {
+            self.flip_vertical = !self.flip_vertical;
+        }
+    }
+
+    /// Where a point in the turned frame lands on the original image, in pixels
+    pub fn source_point(&self, x: f32, y: f32, width: u32, height: u32) -> [f32; 2] {
+        let (w, h) = (width as f32, height as f32);
+        let (cx, cy) = (w / 2.0, h / 2.0);
+        let (sin, cos) = (-self.rotation.to_radians()).sin_cos();
+
+        let (dx, dy) = (x - cx, y - cy);
+        let (mut sx, mut sy) = (cx + dx * cos - dy * sin, cy + dx * sin + dy * cos);
+
+        if self.flip_horizontal {
+            sx = w - sx;
+        }
+        if self.flip_vertical {
+            sy = h - sy;
+        }
+
+        [sx, sy]
+    }
+
+    fn fits(&self, rect: [f32; 4], width: u32, height: u32) -> bool {
+        let [x, y, w, h] = rect;
+        let tolerance = 0.01;
+
+        [[x, y], [x + w, y], [x, y + h], [x + w, y + h]]
+            .iter()
+            .all(|[cx, cy]| {
+                let [sx, sy] = self.source_point(*cx, *cy, width, height);
+                sx >= -tolerance
+                    && sy >= -tolerance
+                    && sx <= width as f32 + tolerance
+                    && sy <= height as f32 + tolerance
+            })
+    }
+
+    /// Shrinks the crop about its centre until no empty corners show after turning
+    pub fn fit_rotation(&mut self, width: u32, height: u32) {
+        let [x, y, w, h] = self.pixel_rect(width, height);
+        if self.fits([x, y, w, h], width, height) {
+            return;
+        }
+
+        let (mut cx, mut cy) = (x + w / 2.0, y + h / 2.0);
+        if !self.fits([cx, cy, 0.0, 0.0], width, height) {
+            (cx, cy) = (width as f32 / 2.0, height as f32 / 2.0);
+        }
+
+        let (mut low, mut high) = (0.0, 1.0);
+        for _ in 0..FIT_STEPS {
+            let scale = (low + high) / 2.0;
+            let rect = [
+                cx - w * scale / 2.0,
+                cy - h * scale / 2.0,
+                w * scale,
+                h * scale,
+            ];
+
+            if self.fits(rect, width, height) {
+                low = scale;
+            } else {
+                high = scale;
+            }
+        }
+
+        self.set_pixel_rect(
+            [cx - w * low / 2.0, cy - h * low / 2.0, w * low, h * low],
+            width,
+            height,
+        );
+    }
+
+    /// The rule of thirds guides across the crop, as line segments in image pixels
+    pub fn thirds(&self, width: u32, height: u32) -> Vec<[[f32; 2]; 2]> {
+        let [x, y, w, h] = self.pixel_rect(width, height);
+
+        [1.0, 2.0]
+            .iter()
+            .flat_map(|third: &f32| {
+                let (gx, gy) = (x + w * third / 3.0, y + h * third / 3.0);
+                [[[gx, y], [gx, y + h]], [[x, gy], [x + w, gy]]]
+            })
+            .collect()
+    }
+
+    /// The CPU reference: the cropped RGBA8 pixels with their width and height.
+    /// Anything outside the original comes out transparent.
+    pub fn render_rgba8(&self, pixels: &[u8], width: u32, height: u32) -> (Vec<u8>, u32, u32) {
+        let [x, y, w, h] = self.pixel_rect(width, height);
+        let (out_width, out_height) = (w.round().max(1.0) as u32, h.round().max(1.0) as u32);
+
+        let mut cropped = vec![0; (out_width * out_height * 4) as usize];
+
+        for oy in 0..out_height {
+            for ox in 0..out_width {
+                let [sx, sy] =
+                    self.source_point(x + ox as f32 + 0.5, y + oy as f32 + 0.5, width, height);
+                let to = ((oy * out_width + ox) * 4) as usize;
+
+                cropped[to..to + 4].copy_from_slice(&sample_bilinear(
+                    pixels,
+                    width,
+                    height,
+                    sx - 0.5,
+                    sy - 0.5,
+                ));
+            }
+        }
+
+        (cropped, out_width, out_height)
+    }
+}
+
+/// The photo whose crop is being changed on the canvas, with the line being dragged
+/// across it to straighten it, in scene pixels
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CropTool {
+    pub image_id: Uuid,
+    pub straighten: Option<[[f32; 2]; 2]>,
+}
+
+/// The turn that makes the line level, or upright if it's closer to vertical
+pub fn straighten_angle(line: [[f32; 2]; 2]) -> f32 {
+    let [[x0, y0], [x1, y1]] = line;
+    let angle = (y1 - y0).atan2(x1 - x0).to_degrees();
+
+    // nearest multiple of 90 degrees is where the line should end up
+    let target = (angle / 90.0).round() * 90.0;
+
+    (target - angle).clamp(-MAX_STRAIGHTEN, MAX_STRAIGHTEN)
+}
+
+fn sample_bilinear(pixels: &[u8], width: u32, height: u32, x: f32, y: f32) -> [u8; 4] {
+    let (w, h) = (width as i64, height as i64);
+    let (x0, y0) = (x.floor() as i64, y.floor() as i64);
+    let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+
+    let texel = |px: i64, py: i64| -> [f32; 4] {
+        if px < 0 || py < 0 || px >= w || py >= h {
+            return [0.0; 4];
+        }
+        let at = ((py * w + px) * 4) as usize;
+        [0, 1, 2, 3].map(|i| pixels[at + i] as f32)
+    };
+
+    let (a, b) = (texel(x0, y0), texel(x0 + 1, y0));
+    let (c, d) = (texel(x0, y0 + 1), texel(x0 + 1, y0 + 1));
+
+    [0, 1, 2, 3].map(|i| {
+        let top = a[i] + (b[i] - a[i]) * fx;
+        let bottom = c[i] + (d[i] - c[i]) * fx;
+        (top + (bottom - top) * fy).round().clamp(0.0, 255.0) as u8
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-2
+    }
+
+    #[test]
+    fn straightening_levels_a_tilted_horizon() {
+        let angle = straighten_angle([[0.0, 0.0], [100.0, 10.0]]);
+        assert!(close(angle, -(0.1f32).atan().to_degrees()));
+
+        // drawn right to left, it's the same horizon
+        let reversed = straighten_angle([[100.0, 10.0], [0.0, 0.0]]);
+        assert!(close(reversed, angle));
+    }
+
+    #[test]
+    fn straightening_stands_an_upright_up() {
+        let angle = straighten_angle([[0.0, 0.0], [10.0, 100.0]]);
+        assert!(close(angle, (0.1f32).atan().to_degrees()));
+        assert_eq!(straighten_angle([[5.0, 0.0], [5.0, 50.0]]), 0.0);
+    }
+
+    #[test]
+    fn straightening_turns_at_most_45_degrees() {
+        assert!(close(
+            straighten_angle([[0.0, 0.0], [10.0, 10.0]]).abs(),
+            45.0
+        ));
+    }
+
+    #[test]
+    fn quarter_turns_of_a_square_keep_the_whole_image() {
+        let mut crop = CropParams::default();
+        crop.rotate_by(90.0, 100, 100);
+
+        assert_eq!(crop.rotation, 90.0);
+        assert_eq!(crop.rect, [0.0, 0.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn turning_a_wide_image_shrinks_the_crop_to_fit() {
+        let mut crop = CropParams::default();
+        crop.rotate_by(90.0, 200, 100);
+
+        let [x, y, w, h] = crop.rect;
+        assert!(close(w, 0.5) && close(h, 0.5));
+        assert!(close(x + w / 2.0, 0.5) && close(y + h / 2.0, 0.5));
+    }
+
+    #[test]
+    fn fitting_leaves_no_empty_corners() {
+        let mut crop = CropParams::default();
+        crop.straighten([[0.0, 0.0], [100.0, 10.0]], 300, 200);
+
+        let rect = crop.pixel_rect(300, 200);
+        assert!(crop.fits(rect, 300, 200));
+        assert!(crop.rect[2] < 1.0 && crop.rect[2] > 0.7);
+        // the aspect is kept while shrinking
+        assert!(close(crop.rect[2], crop.rect[3]));
+    }
+
+    #[test]
+    fn presets_keep_the_centre() {
+        let mut crop = CropParams::default();
+        crop.apply_preset(AspectPreset::Square, 200, 100);
+
+        assert_eq!(crop.pixel_rect(200, 100), [50.0, 0.0, 100.0, 100.0]);
+    }
+
+    #[test]
+    fn thirds_split_the_crop() {
+        let crop = CropParams {
+            rect: [0.25, 0.0, 0.5, 1.0],
+            ..CropParams::default()
+        };
+        let lines = crop.thirds(120, 90);
+
+        assert_eq!(lines.len(), 4);
+        assert!(lines.contains(&[[50.0, 0.0], [50.0, 90.0]]));
+        assert!(lines.contains(&[[30.0, 60.0], [90.0, 60.0]]));
+    }
+
+    #[test]
+    fn rendering_crops_and_flips() {
+        // a 2 x 1 image, red then blue
+        let pixels = [255, 0, 0, 255, 0, 0, 255, 255];
+
+        let mut crop = CropParams::default();
+        assert_eq!(crop.render_rgba8(&pixels, 2, 1), (pixels.to_vec(), 2, 1));
+
+        crop.flip(true);
+        let (flipped, _, _) = crop.render_rgba8(&pixels, 2, 1);
+        assert_eq!(flipped, [0, 0, 255, 255, 255, 0, 0, 255]);
+
+        crop.flip(true);
+        crop.rect = [0.5, 0.0, 0.5, 1.0];
+        assert_eq!(
+            crop.render_rgba8(&pixels, 2, 1),
+            (vec![0, 0, 255, 255], 1, 1)
+        );
+    }
+}
@@ -0,0 +1,643 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crossbeam::channel::{unbounded, Sender};
+use serde::{Deserialize, Serialize};
+use strum_macros::EnumIter;
+use uuid::Uuid;
+
+use crate::helpers::locking::LockRecover;
+
+// median windows grow quadratically, past this they stop being interactive
+const MAX_MEDIAN_RADIUS: u32 = 8;
+// high pass results sit around mid grey
+const HIGH_PASS_MIDPOINT: f32 = 128.0;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, EnumIter)]
+pub enum FilterKind {
+    GaussianBlur,
+    UnsharpMask,
+    Median,
+    HighPass,
+}
+
+impl FilterKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            FilterKind::GaussianBlur => "Gaussian Blur",
+            FilterKind::UnsharpMask => "Unsharp Mask",
+            FilterKind::Median => "Median",
+            FilterKind::HighPass => "High Pass",
+        }
+    }
+
+    pub fn default_filter(&self) -> Filter {
+        match self {
+            FilterKind::GaussianBlur => Filter::GaussianBlur { radius: 4.0 },
+            FilterKind::UnsharpMask => Filter::UnsharpMask {
+                radius: 2.0,
+                amount: 0.8,
+                threshold: 2.0,
+            },
+            FilterKind::Median => Filter::Median { radius: 1 },
+            FilterKind::HighPass => Filter::HighPass { radius: 6.0 },
+        }
+    }
+}
+
+/// A raster filter with its parameters. Radii are in pixels.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Filter {
+    GaussianBlur {
+        radius: f32,
+    },
+    // amount is how much of the detail is added back, threshold is in 0 - 255 levels
+    UnsharpMask {
+        radius: f32,
+        amount: f32,
+        threshold: f32,
+    },
+    // also the noise reduction, it keeps edges where a blur would soften them
+    Median {
+        radius: u32,
+    },
+    HighPass {
+        radius: f32,
+    },
+}
+
+/// Whether a filter bakes into the layer's pixels or stays on it as an effect
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FilterMode {
+    Destructive,
+    Effect,
+}
+
+/// What the properties panel edits on a filter
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, EnumIter)]
+pub enum FilterField {
+    Radius,
+    Amount,
+    Threshold,
+}
+
+impl FilterField {
+    pub fn label(&self) -> &'static str {
+        match self {
+            FilterField::Radius => "Radius (px):",
+            FilterField::Amount => "Amount:",
+            FilterField::Threshold => "Threshold:",
+        }
+    }
+
+    /// The value as it's entered, None when the filter doesn't have the field
+    pub fn read(&self, filter: &Filter) -> Option<f32> {
+        match (self, filter) {
+            (FilterField::Radius, Filter::GaussianBlur { radius })
+            | (FilterField::Radius, Filter::UnsharpMask { radius, .. })
+            | (FilterField::Radius, Filter::HighPass { radius }) => Some(*radius),
+            (FilterField::Radius, Filter::Median { radius }) => Some(*radius as f32),
+            (FilterField::Amount, Filter::UnsharpMask { amount, .. }) => Some(*amount),
+            (FilterField::Threshold, Filter::UnsharpMask { threshold, .. }) => Some(*threshold),
+            _ => None,
+        }
+    }
+
+    pub fn apply(&self, filter: &mut Filter, value: f32) {
+        let value = value.max(0.0);
+
+        match (self, filter) {
+            (FilterField::Radius, Filter::GaussianBlur { radius })
+            | (FilterField::Radius, Filter::UnsharpMask { radius, .. })
+            | (FilterField::Radius, Filter::HighPass { radius }) => *radius = value,
+            (FilterField::Radius, Filter::Median { radius }) => {
+                *radius = (value.round() as u32).min(MAX_MEDIAN_RADIUS)
+            }
+            (FilterField::Amount, Filter::UnsharpMask { amount, .. }) => *amount = value,
+            (FilterField::Threshold, Filter::UnsharpMask { threshold, .. }) => {
+                *threshold = value.min(255.0)
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The filters on an image layer. Baked ones are already in the layer's source pixels and
+/// can't be taken off again, they're only kept so the source can be made again from the file.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LayerFilters {
+    pub baked: Vec<Filter>,
+    // run over the source every time it changes, in order
+    pub effects: Vec<Filter>,
+}
+
+/// Shared with a running filter, which gives up at the next row once it's set
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl Filter {
+    pub fn fields(&self) -> Vec<FilterField> {
+        match self {
+            Filter::UnsharpMask { .. } => vec![
+                FilterField::Radius,
+                FilterField::Amount,
+                FilterField::Threshold,
+            ],
+            _ => vec![FilterField::Radius],
+        }
+    }
+
+    pub fn kind(&self) -> FilterKind {
+        match self {
+            Filter::GaussianBlur { .. } => FilterKind::GaussianBlur,
+            Filter::UnsharpMask { .. } => FilterKind::UnsharpMask,
+            Filter::Median { .. } => FilterKind::Median,
+            Filter::HighPass { .. } => FilterKind::HighPass,
+        }
+    }
+
+    /// The CPU implementation over RGBA8 pixels, None if it was cancelled part way
+    pub fn apply_rgba8(
+        &self,
+        pixels: &[u8],
+        width: u32,
+        height: u32,
+        cancel: &CancelToken,
+    ) -> Option<Vec<u8>> {
+        let image = Image {
+            pixels,
+            width: width as usize,
+            height: height as usize,
+        };
+
+        match *self {
+            Filter::GaussianBlur { radius } => gaussian_blur(&image, radius, cancel),
+            Filter::UnsharpMask {
+                radius,
+                amount,
+                threshold,
+            } => {
+                let blurred = gaussian_blur(&image, radius, cancel)?;
+
+                Some(combine(pixels, &blurred, |original, blur| {
+                    let detail = original - blur;
+                    if detail.abs() < threshold {
+                        original
+                    } else {
+                        original + detail * amount
+                    }
+                }))
+            }
+            Filter::Median { radius } => median(&image, radius.min(MAX_MEDIAN_RADIUS), cancel),
+            Filter::HighPass { radius } => {
+                let blurred = gaussian_blur(&image, radius, cancel)?;
+
+                Some(combine(pixels, &blurred, |original, blur| {
+                    HIGH_PASS_MIDPOINT + original - blur
+                }))
+            }
+        }
+    }
+}
+
+struct Image<'a> {
+    pixels: &'a [u8],
+    width: usize,
+    height: usize,
+}
+
+// Runs the colour channels of two images through f, keeping the first image's alpha
+fn combine(original: &[u8], other: &[u8], f: impl Fn(f32, f32) -> f32) -> Vec<u8> {
+    original
+        .chunks_exact(4)
+        .zip(other.chunks_exact(4))
+        .flat_map(|(a, b)| {
+            [
+                f(a[0] as f32, b[0] as f32).round().clamp(0.0, 255.0) as u8,
+                f(a[1] as f32, b[1] as f32).round().clamp(0.0, 255.0) as u8,
+                f(a[2] as f32, b[2] as f32).round().clamp(0.0, 255.0) as u8,
+                a[3],
+            ]
+        })
+        .collect()
+}
+
+fn gaussian_kernel(radius: f32) -> Vec<f32> {
+    let sigma = (radius / 2.0).max(0.1);
+    let reach = radius.ceil().max(1.0) as i32;
+
+    let weights: Vec<f32> = (-reach..=reach)
+        .map(|i| (-(i * i) as f32 / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let total: f32 = weights.iter().sum();
+
+    weights.iter().map(|w| w / total).collect()
+}
+
+/// Runs the filters one after another, None if it was cancelled part way
+pub fn apply_chain(
+    filters: &[Filter],
+    pixels: &[u8],
+    width: u32,
+    height: u32,
+    cancel: &CancelToken,
+) -> Option<Vec<u8>> {
+    let mut filtered = pixels.to_vec();
+
+    for filter in filters {
+        filtered = filter.apply_rgba8(&filtered, width, height, cancel)?;
+    }
+
+    Some(filtered)
+}
+
+// Separable, one pass across and one down, with edges clamped. Colour is weighted by alpha
+// while it's spread, so transparent pixels don't bleed their colour into the edges.
+fn gaussian_blur(image: &Image, radius: f32, cancel: &CancelToken) -> Option<Vec<u8>> {
+    if radius <= 0.0 {
+        return Some(image.pixels.to_vec());
+    }
+
+    let kernel = gaussian_kernel(radius);
+    let reach = (kernel.len() / 2) as i64;
+    let (w, h) = (image.width, image.height);
+
+    let pass = |source: &[f32], horizontal: bool| -> Option<Vec<f32>> {
+        let mut out = vec![0.0; source.len()];
+
+        for y in 0..h {
+            if cancel.is_cancelled() {
+                return None;
+            }
+
+            for x in 0..w {
+                let mut sum = [0.0; 4];
+
+                for (k, weight) in kernel.iter().enumerate() {
+                    let offset = k as i64 - reach;
+                    let (sx, sy) = if horizontal {
+                        ((x as i64 + offset).clamp(0, w as i64 - 1) as usize, y)
+                    } else {
+                        (x, (y as i64 + offset).clamp(0, h as i64 - 1) as usize)
+                    };
+
+                    let at = (sy * w + sx) * 4;
+                    for c in 0..4 {
+                        sum[c] += source[at + c] * weight;
+                    }
+                }
+
+                out[(y * w + x) * 4..(y * w + x) * 4 + 4].copy_from_slice(&sum);
+            }
+        }
+
+        Some(out)
+    };
+
+    let source: Vec<f32> = image
+        .pixels
+        .chunks_exact(4)
+        .flat_map(|pixel| {
+            let alpha = pixel[3] as f32 / 255.0;
+            [
+                pixel[0] as f32 * alpha,
+                pixel[1] as f32 * alpha,
+                pixel[2] as f32 * alpha,
+                pixel[3] as f32,
+            ]
+        })
+        .collect();
+    let across = pass(&source, true)?;
+    let down = pass(&across, false)?;
+
+    Some(
+        down.chunks_exact(4)
+            .flat_map(|pixel| {
+                let alpha = pixel[3] / 255.0;
+                let unpremultiply = |v: f32| {
+                    if alpha > 0.0 {
+                        (v / alpha).round().clamp(0.0, 255.0) as u8
+                    } else {
+                        0
+                    }
+                };
+
+                [
+                    unpremultiply(pixel[0]),
+                    unpremultiply(pixel[1]),
+                    unpremultiply(pixel[2]),
+                    pixel[3].round().clamp(0.0, 255.0) as u8,
+                ]
+            })
+            .collect(),
+    )
+}
+
+fn median(image: &Image, radius: u32, cancel: &CancelToken) -> Option<Vec<u8>> {
+    if radius == 0 {
+        return Some(image.pixels.to_vec());
+    }
+
+    let (w, h) = (image.width as i64, image.height as i64);
+    let r = radius as i64;
+    let mut out = image.pixels.to_vec();
+    let mut window = Vec::with_capacity(((2 * r + 1) * (2 * r + 1)) as usize);
+
+    for y in 0..h {
+        if cancel.is_cancelled() {
+            return None;
+        }
+
+        for x in 0..w {
+            for c in 0..3 {
+                window.clear();
+
+                for wy in (y - r).max(0)..=(y + r).min(h - 1) {
+                    for wx in (x - r).max(0)..=(x + r).min(w - 1) {
+                        window.push(image.pixels[((wy * w + wx) * 4 + c) as usize]);
+                    }
+                }
+
+                let middle = window.len() / 2;
+                out[((y * w + x) * 4 + c) as usize] = *window.select_nth_unstable(middle).1;
+            }
+        }
+    }
+
+    Some(out)
+}
+
+// one run waiting for the worker, dropped if it's cancelled before it starts
+struct FilterRequest {
+    key: Uuid,
+    filters: Vec<Filter>,
+    pixels: Arc<Vec<u8>>,
+    width: u32,
+    height: u32,
+    cancel: CancelToken,
+    on_done: Box<dyn FnOnce(Vec<u8>) + Send>,
+}
+
+/// Runs filters off the UI thread, one at a time on a thread that lasts as long as the
+/// worker. Only the latest run for each key is finished, e.g. a layer's live preview.
+#[derive(Clone)]
+pub struct FilterWorker {
+    sender: Sender<FilterRequest>,
+    running: Arc<Mutex<HashMap<Uuid, CancelToken>>>,
+}
+
+impl FilterWorker {
+    pub fn new() -> Self {
+        let (sender, receiver) = unbounded::<FilterRequest>();
+        let running: Arc<Mutex<HashMap<Uuid, CancelToken>>> = Arc::default();
+
+        // ends once every handle on the worker has been dropped
+        std::thread::spawn({
+            let running = Arc::clone(&running);
+            move || {
+                for request in receiver {
+                    run_request(request, &running);
+                }
+            }
+        });
+
+        Self { sender, running }
+    }
+
+    /// Queues the filters over the pixels, cancelling whatever was last queued under the
+    /// key. on_done only runs if this run finishes uncancelled, on the worker's thread.
+    pub fn run(
+        &self,
+        key: Uuid,
+        filters: Vec<Filter>,
+        pixels: Arc<Vec<u8>>,
+        width: u32,
+        height: u32,
+        on_done: impl FnOnce(Vec<u8>) + Send + 'static,
+    ) {
+        let cancel = CancelToken::new();
+        if let Some(previous) = self.running.lock_or_recover().insert(key, cancel.clone()) {
+            previous.cancel();
+        }
+
+        let _ = self.sender.send(FilterRequest {
+            key,
+            filters,
+            pixels,
+            width,
+            height,
+            cancel,
+            on_done: Box::new(on_done),
+        });
+    }
+
+    pub fn cancel(&self, key: Uuid) {
+        if let Some(previous) = self.running.lock_or_recover().remove(&key) {
+            previous.cancel();
+        }
+    }
+}
+
+// Runs on the worker's thread
+fn run_request(request: FilterRequest, running: &Mutex<HashMap<Uuid, CancelToken>>) {
+    if !request.cancel.is_cancelled() {
+        let filtered = apply_chain(
+            &request.filters,
+            &request.pixels,
+            request.width,
+            request.height,
+            &request.cancel,
+        );
+
+        // cancelled after the last row is still cancelled
+        if let Some(filtered) = filtered.filter(|_| !request.cancel.is_cancelled()) {
+            (request.on_done)(filtered);
+        }
+    }
+
+    // forget the run unless another has been queued under the key since
+    let mut running = running.lock_or_recover();
+    if running
+        .get(&request.key)
+        .is_some_and(|cancel| Arc::ptr_eq(&cancel.0, &request.cancel.0))
+    {
+        running.remove(&request.key);
+    }
+}
+
+impl Default for FilterWorker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    use super::*;
+
+    fn run(filter: Filter, pixels: &[u8], width: u32, height: u32) -> Vec<u8> {
+        filter
+            .apply_rgba8(pixels, width, height, &CancelToken::new())
+            .expect("Couldn't run filter")
+    }
+
+    fn flat(width: u32, height: u32, pixel: [u8; 4]) -> Vec<u8> {
+        pixel.repeat((width * height) as usize)
+    }
+
+    #[test]
+    fn blurring_a_flat_image_changes_nothing() {
+        let pixels = flat(5, 4, [40, 120, 200, 255]);
+
+        assert_eq!(
+            run(Filter::GaussianBlur { radius: 3.0 }, &pixels, 5, 4),
+            pixels
+        );
+    }
+
+    #[test]
+    fn blur_spreads_a_dot() {
+        let mut pixels = flat(5, 1, [0, 0, 0, 255]);
+        pixels[8..12].copy_from_slice(&[255, 255, 255, 255]);
+
+        let blurred = run(Filter::GaussianBlur { radius: 1.0 }, &pixels, 5, 1);
+
+        assert!(blurred[8] < 255 && blurred[4] > 0 && blurred[12] > 0);
+        assert_eq!(blurred[4], blurred[12]);
+        assert_eq!(blurred[0], blurred[16]);
+    }
+
+    #[test]
+    fn transparent_pixels_dont_darken_a_blurred_edge() {
+        // opaque red beside transparent black
+        let mut pixels = flat(4, 1, [0, 0, 0, 0]);
+        pixels[..8].copy_from_slice(&[255, 0, 0, 255, 255, 0, 0, 255]);
+
+        let blurred = run(Filter::GaussianBlur { radius: 2.0 }, &pixels, 4, 1);
+
+        for pixel in blurred.chunks_exact(4).filter(|pixel| pixel[3] > 0) {
+            assert_eq!(pixel[..3], [255, 0, 0]);
+        }
+        // the edge fades out instead
+        assert!(blurred[8 + 3] > 0 && blurred[8 + 3] < 255);
+    }
+
+    #[test]
+    fn median_removes_a_speck() {
+        let mut pixels = flat(3, 3, [10, 10, 10, 255]);
+        pixels[16..20].copy_from_slice(&[250, 250, 250, 255]);
+
+        assert_eq!(
+            run(Filter::Median { radius: 1 }, &pixels, 3, 3),
+            flat(3, 3, [10, 10, 10, 255])
+        );
+    }
+
+    #[test]
+    fn high_pass_of_a_flat_image_is_mid_grey() {
+        let pixels = flat(3, 2, [200, 30, 90, 255]);
+
+        assert_eq!(
+            run(Filter::HighPass { radius: 2.0 }, &pixels, 3, 2),
+            flat(3, 2, [128, 128, 128, 255])
+        );
+    }
+
+    #[test]
+    fn unsharp_mask_steepens_an_edge() {
+        let mut pixels = flat(4, 1, [100, 100, 100, 255]);
+        pixels[8..].copy_from_slice(&[200, 200, 200, 255, 200, 200, 200, 255]);
+        let filter = Filter::UnsharpMask {
+            radius: 1.0,
+            amount: 1.0,
+            threshold: 0.0,
+        };
+
+        let sharpened = run(filter, &pixels, 4, 1);
+
+        assert!(sharpened[4] < 100 && sharpened[8] > 200);
+    }
+
+    #[test]
+    fn cancelled_filters_give_nothing_back() {
+        let cancel = CancelToken::new();
+        cancel.cancel();
+
+        let pixels = flat(4, 4, [0, 0, 0, 255]);
+        assert!(Filter::GaussianBlur { radius: 1.0 }
+            .apply_rgba8(&pixels, 4, 4, &cancel)
+            .is_none());
+    }
+
+    #[test]
+    fn fields_are_clamped_to_what_the_filter_takes() {
+        let mut filter = FilterKind::Median.default_filter();
+        FilterField::Radius.apply(&mut filter, 40.0);
+        FilterField::Amount.apply(&mut filter, 2.0);
+
+        assert_eq!(
+            filter,
+            Filter::Median {
+                radius: MAX_MEDIAN_RADIUS
+            }
+        );
+        assert_eq!(FilterField::Amount.read(&filter), None);
+    }
+
+    #[test]
+    fn worker_only_finishes_the_latest_run() {
+        let worker = FilterWorker::new();
+        let pixels = Arc::new(flat(4, 4, [10, 20, 30, 255]));
+        let (sender, receiver) = mpsc::channel();
+
+        // holds the worker up until both runs below are queued
+        let (release, held) = mpsc::channel::<()>();
+        worker.run(
+            Uuid::new_v4(),
+            Vec::new(),
+            Arc::clone(&pixels),
+            4,
+            4,
+            move |_| {
+                let _ = held.recv();
+            },
+        );
+
+        let key = Uuid::new_v4();
+        for radius in [8.0, 1.0] {
+            let sender = sender.clone();
+            worker.run(
+                key,
+                vec![Filter::GaussianBlur { radius }],
+                Arc::clone(&pixels),
+                4,
+                4,
+                move |_| {
+                    let _ = sender.send(radius);
+                },
+            );
+        }
+        let _ = release.send(());
+
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(10)), Ok(1.0));
+        assert!(receiver.recv_timeout(Duration::from_millis(100)).is_err());
+    }
+}
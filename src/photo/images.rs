@@ -0,0 +1,648 @@
+use std::fs;
+use std::io::Cursor;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use common_vector::basic::{Point, WindowSize};
+use common_vector::guideline::point_to_ndc;
+use common_vector::vertex::Vertex;
+use image::codecs::jpeg::JpegEncoder;
+use image::{ImageFormat, RgbImage, RgbaImage};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::crop::{CropParams, CropTool};
+use super::filters::{apply_chain, CancelToken, LayerFilters};
+use super::metadata::{
+    apply_orientation, export_metadata, read_metadata, ExportMetadata, PhotoMetadata,
+};
+
+// photos are placed no bigger than this along their longer side, in scene pixels
+const MAX_PLACED_SIZE: f32 = 800.0;
+const EXPORT_JPEG_QUALITY: u8 = 90;
+
+/// A photo as decoded from its file, already turned upright
+#[derive(Debug, Clone)]
+pub struct DecodedImage {
+    // RGBA8, rows top to bottom
+    pub pixels: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+    pub metadata: PhotoMetadata,
+}
+
+/// Decodes any of the formats photos can be imported from. Metadata is only found in
+/// JPEGs, anything else comes back upright with none.
+pub fn decode_image(bytes: &[u8]) -> Result<DecodedImage, String> {
+    let decoded = image::load_from_memory(bytes)
+        .map_err(|_| "Couldn't decode image")?
+        .to_rgba8();
+    let (width, height) = decoded.dimensions();
+
+    let metadata = read_metadata(bytes);
+    let (pixels, width, height) =
+        apply_orientation(decoded.as_raw(), width, height, metadata.orientation)?;
+
+    Ok(DecodedImage {
+        pixels,
+        width,
+        height,
+        metadata,
+    })
+}
+
+/// An imported photo, drawn as a textured rectangle. Only the file, placement, crop and
+/// filters are serialized, so a loaded layer has to be decoded again with reload.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageLayer {
+    pub id: Uuid,
+    pub name: String,
+    pub path: PathBuf,
+    // where the whole photo would sit uncropped, top left corner in scene pixels
+    pub position: [f32; 2],
+    pub size: [f32; 2],
+    // only the part inside the crop is drawn, the pixels are kept whole
+    #[serde(default)]
+    pub crop: CropParams,
+    #[serde(default)]
+    pub filters: LayerFilters,
+    // upright RGBA8 with the baked filters in it, what the effects run over
+    #[serde(skip)]
+    pub source: Arc<Vec<u8>>,
+    // the source once the effects have run, shared with the texture it's uploaded to
+    #[serde(skip)]
+    pub pixels: Arc<Vec<u8>>,
+    #[serde(skip)]
+    pub width: u32,
+    #[serde(skip)]
+    pub height: u32,
+    #[serde(skip)]
+    pub metadata: PhotoMetadata,
+    #[serde(skip)]
+    pub vertices: Vec<Vertex>,
+    #[serde(skip)]
+    pub indices: Vec<u32>,
+    // bumped on every rebuild so the scene batch knows to rewrite the slot
+    #[serde(skip)]
+    pub generation: u64,
+}
+
+impl ImageLayer {
+    /// Reads and decodes the file, placed with its middle at the given scene point
+    pub fn open(path: PathBuf, center: [f32; 2], window_size: &WindowSize) -> Result<Self, String> {
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "Image".to_string());
+
+        let mut layer = ImageLayer {
+            id: Uuid::new_v4(),
+            name,
+            path,
+            position: center,
+            size: [0.0, 0.0],
+            crop: CropParams::default(),
+            filters: LayerFilters::default(),
+            source: Arc::new(Vec::new()),
+            pixels: Arc::new(Vec::new()),
+            width: 0,
+            height: 0,
+            metadata: PhotoMetadata::default(),
+            vertices: Vec::new(),
+            indices: Vec::new(),
+            generation: 0,
+        };
+
+        layer.reload(window_size)?;
+
+        let scale = (MAX_PLACED_SIZE / layer.width.max(layer.height) as f32).min(1.0);
+        layer.size = [layer.width as f32 * scale, layer.height as f32 * scale];
+        layer.position = [
+            center[0] - layer.size[0] / 2.0,
+            center[1] - layer.size[1] / 2.0,
+        ];
+        layer.rebuild(window_size);
+
+        Ok(layer)
+    }
+
+    /// Decodes the file again. The filters are run here rather than on the worker, as the
+    /// layer is being loaded anyway.
+    pub fn reload(&mut self, window_size: &WindowSize) -> Result<(), String> {
+        let bytes = fs::read(&self.path).map_err(|_| "Couldn't read image")?;
+        let decoded = decode_image(&bytes)?;
+
+        self.width = decoded.width;
+        self.height = decoded.height;
+        self.metadata = decoded.metadata;
+        self.filter_decoded(decoded.pixels)?;
+        self.rebuild(window_size);
+
+        Ok(())
+    }
+
+    // Bakes the baked filters into the decoded pixels again, then runs the effects over them
+    fn filter_decoded(&mut self, decoded: Vec<u8>) -> Result<(), String> {
+        let cancel = CancelToken::new();
+        let source = if self.filters.baked.is_empty() {
+            decoded
+        } else {
+            apply_chain(
+                &self.filters.baked,
+                &decoded,
+                self.width,
+                self.height,
+                &cancel,
+            )
+            .ok_or("Couldn't filter image")?
+        };
+
+        self.source = Arc::new(source);
+        self.pixels = if self.filters.effects.is_empty() {
+            Arc::clone(&self.source)
+        } else {
+            let filtered = apply_chain(
+                &self.filters.effects,
+                &self.source,
+                self.width,
+                self.height,
+                &cancel,
+            );
+            Arc::new(filtered.ok_or("Couldn't filter image")?)
+        };
+
+        Ok(())
+    }
+
+    /// Decodes a layer that was loaded without its pixels. A photo whose file has gone
+    /// stays in the document, drawn as nothing until the file is back.
+    pub fn restored(mut self, window_size: &WindowSize) -> Self {
+        if let Err(error) = self.reload(window_size) {
+            println!("{} {}", error, self.path.display());
+        }
+
+        self
+    }
+
+    pub fn rebuild(&mut self, window_size: &WindowSize) {
+        self.generation += 1;
+
+        if self.pixels.is_empty() {
+            self.vertices.clear();
+            self.indices.clear();
+            return;
+        }
+
+        // the quad covers the crop, and its corners sample wherever the turn and flips
+        // put them on the original, so cropping never touches the texture
+        let [x, y, width, height] = self.crop.pixel_rect(self.width, self.height);
+        let corners = [
+            [x, y],
+            [x + width, y],
+            [x + width, y + height],
+            [x, y + height],
+        ];
+
+        self.vertices = corners
+            .iter()
+            .map(|[x, y]| {
+                let [scene_x, scene_y] = self.to_scene([*x, *y]);
+                let ndc = point_to_ndc(
+                    Point {
+                        x: scene_x,
+                        y: scene_y,
+                    },
+                    window_size,
+                );
+                let [source_x, source_y] = self.crop.source_point(*x, *y, self.width, self.height);
+
+                Vertex {
+                    position: [ndc.x, ndc.y, 0.0],
+                    tex_coords: [source_x / self.width as f32, source_y / self.height as f32],
+                    // the texture is multiplied by this, so white leaves it as it is
+                    color: [1.0, 1.0, 1.0, 1.0],
+                }
+            })
+            .collect();
+        self.indices = vec![0, 1, 2, 0, 2, 3];
+    }
+
+    // a point on the photo in its pixels, in scene pixels
+    fn to_scene(&self, [x, y]: [f32; 2]) -> [f32; 2] {
+        [
+            self.position[0] + x / self.width.max(1) as f32 * self.size[0],
+            self.position[1] + y / self.height.max(1) as f32 * self.size[1],
+        ]
+    }
+
+    /// The rectangle the cropped photo covers, in scene pixels
+    pub fn bounds(&self) -> ([f32; 2], [f32; 2]) {
+        let [x, y, width, height] = self.crop.rect;
+        let min = [
+            self.position[0] + x * self.size[0],
+            self.position[1] + y * self.size[1],
+        ];
+
+        (
+            min,
+            [
+                min[0] + width * self.size[0],
+                min[1] + height * self.size[1],
+            ],
+        )
+    }
+
+    /// The photo as it's drawn, cropped, turned and filtered, encoded as a PNG
+    pub fn encode_png(&self) -> Result<Vec<u8>, String> {
+        if self.pixels.is_empty() {
+            return Err("Couldn't encode image, it isn't loaded".to_string());
+        }
+
+        let (pixels, width, height) = self
+            .crop
+            .render_rgba8(&self.pixels, self.width, self.height);
+        let image = RgbaImage::from_raw(width, height, pixels).ok_or("Couldn't encode image")?;
+
+        let mut bytes = Cursor::new(Vec::new());
+        image
+            .write_to(&mut bytes, ImageFormat::Png)
+            .map_err(|_| "Couldn't encode image")?;
+
+        Ok(bytes.into_inner())
+    }
+
+    /// The photo as it's drawn, for an export, with its media type. JPEGs stay JPEGs so they
+    /// can keep or lose the original file's metadata, anything else is a PNG.
+    pub fn encode_export(
+        &self,
+        original: &[u8],
+        metadata: ExportMetadata,
+    ) -> Result<(&'static str, Vec<u8>), String> {
+        if !matches!(image::guess_format(original), Ok(ImageFormat::Jpeg)) {
+            return Ok(("image/png", self.encode_png()?));
+        }
+
+        if self.pixels.is_empty() {
+            return Err("Couldn't encode image, it isn't loaded".to_string());
+        }
+
+        let (pixels, width, height) = self
+            .crop
+            .render_rgba8(&self.pixels, self.width, self.height);
+        // JPEGs have no alpha, straightened corners are filled in white
+        let flattened = pixels
+            .chunks_exact(4)
+            .flat_map(|pixel| {
+                let alpha = pixel[3] as u32;
+                [0, 1, 2].map(|channel| {
+                    ((pixel[channel] as u32 * alpha + 255 * (255 - alpha)) / 255) as u8
+                })
+            })
+            .collect();
+        let image = RgbImage::from_raw(width, height, flattened).ok_or("Couldn't encode image")?;
+
+        let mut bytes = Vec::new();
+        JpegEncoder::new_with_quality(&mut bytes, EXPORT_JPEG_QUALITY)
+            .encode_image(&image)
+            .map_err(|_| "Couldn't encode image")?;
+
+        Ok(("image/jpeg", export_metadata(original, &bytes, metadata)))
+    }
+
+    /// The edges of the crop and its rule of thirds, in scene pixels
+    pub fn crop_guides(&self) -> Vec<([f32; 2], [f32; 2])> {
+        let outline = self.outline();
+
+        (0..outline.len())
+            .map(|side| (outline[side], outline[(side + 1) % outline.len()]))
+            .chain(
+                self.crop
+                    .thirds(self.width, self.height)
+                    .into_iter()
+                    .map(|[start, end]| (self.to_scene(start), self.to_scene(end))),
+            )
+            .collect()
+    }
+
+    /// The corners clockwise from the top left, in scene pixels
+    pub fn outline(&self) -> Vec<[f32; 2]> {
+        let (min, max) = self.bounds();
+
+        vec![min, [max[0], min[1]], max, [min[0], max[1]]]
+    }
+
+    pub fn hit_test(&self, x: f32, y: f32) -> bool {
+        let (min, max) = self.bounds();
+
+        x >= min[0] && x <= max[0] && y >= min[1] && y <= max[1]
+    }
+}
+
+/// Every imported photo in the document. Stacking order lives in the editor's layer list.
+#[derive(Debug, Default)]
+pub struct ImageLayers {
+    layers: Vec<ImageLayer>,
+    // kept with the photos so the canvas can draw its guides
+    pub crop_tool: Option<CropTool>,
+}
+
+// Lock after the stroke layers, never before
+pub type SharedImageLayers = Arc<Mutex<ImageLayers>>;
+
+impl ImageLayers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, id: Uuid) -> Option<&ImageLayer> {
+        self.layers.iter().find(|layer| layer.id == id)
+    }
+
+    pub fn get_mut(&mut self, id: Uuid) -> Option<&mut ImageLayer> {
+        self.layers.iter_mut().find(|layer| layer.id == id)
+    }
+
+    pub fn clear(&mut self) {
+        self.layers.clear();
+        self.crop_tool = None;
+    }
+
+    pub fn insert(&mut self, layer: ImageLayer) {
+        self.layers.push(layer);
+    }
+
+    pub fn remove(&mut self, id: Uuid) -> Option<ImageLayer> {
+        let index = self.layers.iter().position(|layer| layer.id == id)?;
+        Some(self.layers.remove(index))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ImageLayer> {
+        self.layers.iter()
+    }
+
+    // like strokes, image vertices are in window space
+    pub fn rebuild_all(&mut self, window_size: &WindowSize) {
+        for layer in &mut self.layers {
+            layer.rebuild(window_size);
+        }
+    }
+
+    /// What the overlay draws while a photo is being cropped, in scene pixels
+    pub fn crop_guides(&self) -> Vec<([f32; 2], [f32; 2])> {
+        let Some(tool) = self.crop_tool else {
+            return Vec::new();
+        };
+
+        let mut guides = self
+            .get(tool.image_id)
+            .map(|layer| layer.crop_guides())
+            .unwrap_or_default();
+        guides.extend(tool.straighten.map(|[start, end]| (start, end)));
+
+        guides
+    }
+
+    /// The topmost photo under the cursor, checked in reverse stacking order
+    pub fn hit_test(&self, layer_list: &[Uuid], x: f32, y: f32) -> Option<Uuid> {
+        layer_list
+            .iter()
+            .rev()
+            .filter_map(|id| self.get(*id))
+            .find(|layer| layer.hit_test(x, y))
+            .map(|layer| layer.id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use image::{ImageFormat, Rgba, RgbaImage};
+
+    use super::*;
+    use crate::photo::filters::Filter;
+
+    // red on the left, blue on the right
+    fn two_tone(width: u32, height: u32) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, _| {
+            if x < width / 2 {
+                Rgba([255, 0, 0, 255])
+            } else {
+                Rgba([0, 0, 255, 255])
+            }
+        })
+    }
+
+    fn encode(image: &RgbaImage, format: ImageFormat) -> Vec<u8> {
+        let mut bytes = Cursor::new(Vec::new());
+        match format {
+            // JPEGs have no alpha
+            ImageFormat::Jpeg => image::DynamicImage::ImageRgba8(image.clone())
+                .to_rgb8()
+                .write_to(&mut bytes, format),
+            _ => image.write_to(&mut bytes, format),
+        }
+        .expect("Couldn't encode image");
+
+        bytes.into_inner()
+    }
+
+    // An EXIF block saying the camera was turned a quarter clockwise
+    fn turned_exif() -> Vec<u8> {
+        let mut exif = b"Exif\0\0II".to_vec();
+        exif.extend_from_slice(&42u16.to_le_bytes());
+        exif.extend_from_slice(&8u32.to_le_bytes());
+        exif.extend_from_slice(&1u16.to_le_bytes());
+        // orientation, a short, one of them, 6
+        exif.extend_from_slice(&0x0112u16.to_le_bytes());
+        exif.extend_from_slice(&3u16.to_le_bytes());
+        exif.extend_from_slice(&1u32.to_le_bytes());
+        exif.extend_from_slice(&[6, 0, 0, 0]);
+        exif.extend_from_slice(&0u32.to_le_bytes());
+
+        exif
+    }
+
+    fn with_segment(jpeg: &[u8], marker: u8, payload: &[u8]) -> Vec<u8> {
+        let mut file = jpeg[..2].to_vec();
+        file.extend_from_slice(&[0xFF, marker]);
+        file.extend_from_slice(&(payload.len() as u16 + 2).to_be_bytes());
+        file.extend_from_slice(payload);
+        file.extend_from_slice(&jpeg[2..]);
+
+        file
+    }
+
+    fn pixel(decoded: &DecodedImage, x: u32, y: u32) -> [u8; 4] {
+        let at = ((y * decoded.width + x) * 4) as usize;
+        decoded.pixels[at..at + 4]
+            .try_into()
+            .expect("Couldn't read pixel")
+    }
+
+    #[test]
+    fn png_decodes_as_it_is() {
+        let decoded =
+            decode_image(&encode(&two_tone(4, 2), ImageFormat::Png)).expect("Couldn't decode");
+
+        assert_eq!((decoded.width, decoded.height), (4, 2));
+        assert_eq!(pixel(&decoded, 0, 0), [255, 0, 0, 255]);
+        assert_eq!(pixel(&decoded, 3, 1), [0, 0, 255, 255]);
+        assert_eq!(decoded.metadata.orientation, 1);
+        assert!(decoded.metadata.is_empty());
+    }
+
+    #[test]
+    fn jpeg_is_turned_upright_on_import() {
+        let jpeg = encode(&two_tone(16, 8), ImageFormat::Jpeg);
+        let decoded =
+            decode_image(&with_segment(&jpeg, 0xE1, &turned_exif())).expect("Couldn't decode");
+
+        assert_eq!(decoded.metadata.orientation, 6);
+        // a quarter clockwise puts what was on the left at the top
+        assert_eq!((decoded.width, decoded.height), (8, 16));
+        let (top, bottom) = (pixel(&decoded, 4, 2), pixel(&decoded, 4, 13));
+        assert!(top[0] > 200 && top[2] < 60, "{:?}", top);
+        assert!(bottom[2] > 200 && bottom[0] < 60, "{:?}", bottom);
+    }
+
+    #[test]
+    fn garbage_is_an_error() {
+        assert!(decode_image(b"not an image").is_err());
+    }
+
+    fn placed(position: [f32; 2], size: [f32; 2]) -> ImageLayer {
+        ImageLayer {
+            id: Uuid::new_v4(),
+            name: "Photo".to_string(),
+            path: PathBuf::from("photo.png"),
+            position,
+            size,
+            crop: CropParams::default(),
+            filters: LayerFilters::default(),
+            source: Arc::new(Vec::new()),
+            pixels: Arc::new(Vec::new()),
+            width: 0,
+            height: 0,
+            metadata: PhotoMetadata::default(),
+            vertices: Vec::new(),
+            indices: Vec::new(),
+            generation: 0,
+        }
+    }
+
+    #[test]
+    fn topmost_photo_is_hit() {
+        let below = placed([0.0, 0.0], [100.0, 100.0]);
+        let above = placed([50.0, 50.0], [100.0, 100.0]);
+        let order = vec![below.id, above.id];
+
+        let mut layers = ImageLayers::new();
+        layers.insert(below.clone());
+        layers.insert(above.clone());
+
+        assert_eq!(layers.hit_test(&order, 75.0, 75.0), Some(above.id));
+        assert_eq!(layers.hit_test(&order, 25.0, 25.0), Some(below.id));
+        assert_eq!(layers.hit_test(&order, 175.0, 25.0), None);
+    }
+
+    #[test]
+    fn crops_sample_the_turned_photo() {
+        let mut layer = placed([0.0, 0.0], [40.0, 20.0]);
+        layer.pixels = Arc::new(vec![255; 4 * 2 * 4]);
+        (layer.width, layer.height) = (4, 2);
+        layer.crop.rect = [0.5, 0.0, 0.5, 1.0];
+        layer.crop.flip(true);
+        layer.rebuild(&WindowSize {
+            width: 100,
+            height: 100,
+        });
+
+        assert_eq!(layer.bounds(), ([20.0, 0.0], [40.0, 20.0]));
+        assert!(layer.hit_test(30.0, 10.0) && !layer.hit_test(10.0, 10.0));
+        // flipped, the right half of the frame shows the left half of the photo
+        let tex_coords: Vec<[f32; 2]> = layer.vertices.iter().map(|v| v.tex_coords).collect();
+        assert_eq!(
+            tex_coords,
+            vec![[0.5, 0.0], [0.0, 0.0], [0.0, 1.0], [0.5, 1.0]]
+        );
+    }
+
+    #[test]
+    fn exports_encode_the_cropped_pixels() {
+        let mut layer = placed([0.0, 0.0], [40.0, 20.0]);
+        layer.pixels = Arc::new(two_tone(4, 2).into_raw());
+        (layer.width, layer.height) = (4, 2);
+        layer.crop.rect = [0.5, 0.0, 0.5, 1.0];
+
+        let png = layer.encode_png().expect("Couldn't encode image");
+        let decoded = decode_image(&png).expect("Couldn't decode");
+
+        // only the blue half is left
+        assert_eq!((decoded.width, decoded.height), (2, 2));
+        assert!(decoded.pixels.chunks(4).all(|p| p == [0, 0, 255, 255]));
+        assert!(placed([0.0, 0.0], [1.0, 1.0]).encode_png().is_err());
+    }
+
+    #[test]
+    fn jpeg_exports_keep_or_strip_the_metadata() {
+        let jpeg = encode(&two_tone(16, 8), ImageFormat::Jpeg);
+        let original = with_segment(&jpeg, 0xE1, &turned_exif());
+        let has_exif = |bytes: &[u8]| bytes.windows(6).any(|window| window == b"Exif\0\0");
+
+        // already turned upright on import
+        let mut layer = placed([0.0, 0.0], [80.0, 160.0]);
+        layer.pixels = Arc::new(two_tone(8, 16).into_raw());
+        (layer.width, layer.height) = (8, 16);
+
+        let (media_type, kept) = layer
+            .encode_export(&original, ExportMetadata::Preserve)
+            .expect("Couldn't encode image");
+        assert_eq!(media_type, "image/jpeg");
+        assert!(has_exif(&kept));
+        // the pixels are written as they're drawn, so nothing turns them again
+        let decoded = decode_image(&kept).expect("Couldn't decode");
+        assert_eq!(decoded.metadata.orientation, 1);
+        assert_eq!((decoded.width, decoded.height), (8, 16));
+
+        let (_, stripped) = layer
+            .encode_export(&original, ExportMetadata::Strip)
+            .expect("Couldn't encode image");
+        assert!(!has_exif(&stripped));
+        assert_eq!(decode_image(&stripped).expect("Couldn't decode").width, 8);
+
+        // other formats have no metadata to carry
+        let png = encode(&two_tone(8, 16), ImageFormat::Png);
+        let (media_type, _) = layer
+            .encode_export(&png, ExportMetadata::Preserve)
+            .expect("Couldn't encode image");
+        assert_eq!(media_type, "image/png");
+    }
+
+    #[test]
+    fn baked_filters_change_the_source_pixels() {
+        // grey with a white speck in the middle
+        let grey = [10, 10, 10, 255].repeat(9);
+        let mut decoded = grey.clone();
+        decoded[16..20].copy_from_slice(&[250, 250, 250, 255]);
+
+        let mut layer = placed([0.0, 0.0], [30.0, 30.0]);
+        (layer.width, layer.height) = (3, 3);
+        layer.filters.baked.push(Filter::Median { radius: 1 });
+        layer
+            .filter_decoded(decoded.clone())
+            .expect("Couldn't filter image");
+
+        assert_eq!(*layer.source, grey);
+        assert!(Arc::ptr_eq(&layer.source, &layer.pixels));
+
+        // effects run over the baked pixels, which stay as they are
+        layer.filters.effects.push(Filter::HighPass { radius: 1.0 });
+        layer
+            .filter_decoded(decoded)
+            .expect("Couldn't filter image");
+
+        assert_eq!(*layer.source, grey);
+        assert_eq!(*layer.pixels, [128, 128, 128, 255].repeat(9));
+    }
+}
@@ -0,0 +1,1030 @@
+use serde::{Deserialize, Serialize};
+use strum_macros::EnumIter;
+
+// JPEG markers
+const SOI: u8 = 0xD8;
+const SOS: u8 = 0xDA;
+const EOI: u8 = 0xD9;
+const APP0: u8 = 0xE0;
+const APP1: u8 = 0xE1;
+const APP13: u8 = 0xED;
+
+const EXIF_HEADER: &[u8] = b"Exif\0\0";
+const XMP_HEADER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+const PHOTOSHOP_HEADER: &[u8] = b"Photoshop 3.0\0";
+// the Photoshop resource that carries IPTC records
+const IPTC_RESOURCE: u16 = 0x0404;
+
+// TIFF tags
+const TAG_MAKE: u16 = 0x010F;
+const TAG_MODEL: u16 = 0x0110;
+const TAG_ORIENTATION: u16 = 0x0112;
+const TAG_DATE_TIME: u16 = 0x0132;
+const TAG_EXIF_IFD: u16 = 0x8769;
+const TAG_GPS_IFD: u16 = 0x8825;
+const TAG_EXPOSURE_TIME: u16 = 0x829A;
+const TAG_F_NUMBER: u16 = 0x829D;
+const TAG_ISO: u16 = 0x8827;
+const TAG_DATE_TIME_ORIGINAL: u16 = 0x9003;
+const TAG_FOCAL_LENGTH: u16 = 0x920A;
+const TAG_LENS_MODEL: u16 = 0xA434;
+const TAG_GPS_LATITUDE_REF: u16 = 0x0001;
+const TAG_GPS_LATITUDE: u16 = 0x0002;
+const TAG_GPS_LONGITUDE_REF: u16 = 0x0003;
+const TAG_GPS_LONGITUDE: u16 = 0x0004;
+
+// IPTC datasets in record 2, with the label they're shown under
+const IPTC_FIELDS: [(u8, &str); 7] = [
+    (5, "Title"),
+    (25, "Keywords"),
+    (80, "Author"),
+    (90, "City"),
+    (101, "Country"),
+    (116, "Copyright"),
+    (120, "Caption"),
+];
+
+// XMP properties, with the label they're shown under
+const XMP_FIELDS: [(&str, &str); 6] = [
+    ("dc:title", "Title"),
+    ("dc:creator", "Creator"),
+    ("dc:description", "Description"),
+    ("dc:rights", "Rights"),
+    ("xmp:Rating", "Rating"),
+    ("xmp:CreatorTool", "Software"),
+];
+
+/// What a photo says about itself, read from its EXIF, IPTC and XMP blocks
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PhotoMetadata {
+    pub camera_make: Option<String>,
+    pub camera_model: Option<String>,
+    pub lens: Option<String>,
+    // seconds
+    pub exposure_time: Option<f64>,
+    pub f_number: Option<f64>,
+    pub iso: Option<u32>,
+    // millimetres
+    pub focal_length: Option<f64>,
+    pub taken_at: Option<String>,
+    // degrees, south and west are negative
+    pub gps: Option<(f64, f64)>,
+    // EXIF orientation, 1 - 8, 1 is upright
+    pub orientation: u16,
+    pub iptc: Vec<(String, String)>,
+    pub xmp: Vec<(String, String)>,
+}
+
+impl PhotoMetadata {
+    pub fn is_empty(&self) -> bool {
+        *self
+            == PhotoMetadata {
+                orientation: self.orientation,
+                ..PhotoMetadata::default()
+            }
+    }
+
+    /// Label and value pairs, in the order the metadata section shows them
+    pub fn fields(&self) -> Vec<(String, String)> {
+        let mut fields = Vec::new();
+        let mut push = |name: &str, value: Option<String>| {
+            if let Some(value) = value {
+                fields.push((name.to_string(), value));
+            }
+        };
+
+        let camera = match (&self.camera_make, &self.camera_model) {
+            // models usually repeat the make
+            (Some(make), Some(model)) if model.starts_with(make.as_str()) => Some(model.clone()),
+            (Some(make), Some(model)) => Some(format!("{} {}", make, model)),
+            (make, model) => make.clone().or(model.clone()),
+        };
+
+        push("Camera", camera);
+        push("Lens", self.lens.clone());
+        push(
+            "Exposure",
+            self.exposure_time.map(|seconds| {
+                if seconds > 0.0 && seconds < 1.0 {
+                    format!("1/{} s", (1.0 / seconds).round())
+                } else {
+                    format!("{} s", seconds)
+                }
+            }),
+        );
+        push("Aperture", self.f_number.map(|f| format!("f/{:.1}", f)));
+        push("ISO", self.iso.map(|iso| iso.to_string()));
+        push(
+            "Focal Length",
+            self.focal_length.map(|mm| format!("{} mm", mm.round())),
+        );
+        push("Date", self.taken_at.clone());
+        push(
+            "GPS",
+            self.gps
+                .map(|(lat, long)| format!("{:.5}, {:.5}", lat, long)),
+        );
+
+        fields.extend(self.iptc.iter().cloned());
+        fields.extend(self.xmp.iter().cloned());
+
+        fields
+    }
+}
+
+/// Whether exported files carry the original's metadata
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash, EnumIter, Serialize, Deserialize)]
+pub enum ExportMetadata {
+    #[default]
+    Preserve,
+    Strip,
+}
+
+impl ExportMetadata {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ExportMetadata::Preserve => "Keep",
+            ExportMetadata::Strip => "Strip",
+        }
+    }
+}
+
+struct Segment<'a> {
+    marker: u8,
+    // the whole segment, marker and length included
+    bytes: &'a [u8],
+    payload: &'a [u8],
+}
+
+// The segments before the image data, which is where all the metadata lives
+fn segments(jpeg: &[u8]) -> Vec<Segment<'_>> {
+    let mut segments = Vec::new();
+
+    if jpeg.len() < 2 || jpeg[0] != 0xFF || jpeg[1] != SOI {
+        return segments;
+    }
+
+    let mut offset = 2;
+    while offset + 4 <= jpeg.len() && jpeg[offset] == 0xFF {
+        let marker = jpeg[offset + 1];
+        if marker == SOS || marker == EOI {
+            break;
+        }
+
+        let length = u16::from_be_bytes([jpeg[offset + 2], jpeg[offset + 3]]) as usize;
+        let end = offset + 2 + length;
+        if length < 2 || end > jpeg.len() {
+            break;
+        }
+
+        segments.push(Segment {
+            marker,
+            bytes: &jpeg[offset..end],
+            payload: &jpeg[offset + 4..end],
+        });
+
+        offset = end;
+    }
+
+    segments
+}
+
+fn is_metadata(segment: &Segment) -> bool {
+    match segment.marker {
+        APP1 => segment.payload.starts_with(EXIF_HEADER) || segment.payload.starts_with(XMP_HEADER),
+        APP13 => segment.payload.starts_with(PHOTOSHOP_HEADER),
+        _ => false,
+    }
+}
+
+/// Reads whatever metadata a JPEG carries. Anything unreadable is left out rather than failing.
+pub fn read_metadata(jpeg: &[u8]) -> PhotoMetadata {
+    let mut metadata = PhotoMetadata {
+        orientation: 1,
+        ..PhotoMetadata::default()
+    };
+
+    for segment in segments(jpeg) {
+        match segment.marker {
+            APP1 if segment.payload.starts_with(EXIF_HEADER) => {
+                if let Some(tiff) = Tiff::new(&segment.payload[EXIF_HEADER.len()..]) {
+                    read_exif(&tiff, &mut metadata);
+                }
+            }
+            APP1 if segment.payload.starts_with(XMP_HEADER) => {
+                let xml = String::from_utf8_lossy(&segment.payload[XMP_HEADER.len()..]);
+                metadata.xmp = read_xmp(&xml);
+            }
+            APP13 if segment.payload.starts_with(PHOTOSHOP_HEADER) => {
+                metadata.iptc = read_iptc(&segment.payload[PHOTOSHOP_HEADER.len()..]);
+            }
+            _ => {}
+        }
+    }
+
+    metadata
+}
+
+/// The JPEG without its EXIF, IPTC and XMP blocks. Colour profiles are kept.
+pub fn strip_metadata(jpeg: &[u8]) -> Vec<u8> {
+    let mut stripped = jpeg.to_vec();
+
+    // removed back to front so earlier offsets stay put
+    for segment in segments(jpeg).iter().rev().filter(|s| is_metadata(s)) {
+        let start = segment.bytes.as_ptr() as usize - jpeg.as_ptr() as usize;
+        stripped.drain(start..start + segment.bytes.len());
+    }
+
+    stripped
+}
+
+/// Copies the original's metadata into a freshly encoded JPEG. The pixels were turned
+/// upright on import, so the copied orientation is reset to 1.
+pub fn copy_metadata(original: &[u8], encoded: &[u8]) -> Vec<u8> {
+    let blocks: Vec<Vec<u8>> = segments(original)
+        .iter()
+        .filter(|segment| is_metadata(segment))
+        .map(|segment| {
+            let mut bytes = segment.bytes.to_vec();
+            if segment.marker == APP1 && segment.payload.starts_with(EXIF_HEADER) {
+                reset_orientation(&mut bytes[4 + EXIF_HEADER.len()..]);
+            }
+            bytes
+        })
+        .collect();
+
+    let encoded = strip_metadata(encoded);
+
+    // after the JFIF header if there is one, which has to come first
+    let insert_at = segments(&encoded)
+        .first()
+        .filter(|segment| segment.marker == APP0)
+        .map(|segment| 2 + segment.bytes.len())
+        .unwrap_or(2)
+        .min(encoded.len());
+
+    let mut result = Vec::with_capacity(encoded.len() + blocks.iter().map(Vec::len).sum::<usize>());
+    result.extend_from_slice(&encoded[..insert_at]);
+    for block in blocks {
+        result.extend_from_slice(&block);
+    }
+    result.extend_from_slice(&encoded[insert_at..]);
+
+    result
+}
+
+pub fn export_metadata(original: &[u8], encoded: &[u8], mode: ExportMetadata) -> Vec<u8> {
+    match mode {
+        ExportMetadata::Preserve => copy_metadata(original, encoded),
+        ExportMetadata::Strip => strip_metadata(encoded),
+    }
+}
+
+struct Entry {
+    tag: u16,
+    kind: u16,
+    count: u32,
+    // where the value is, either inline in the entry or elsewhere in the block
+    offset: usize,
+}
+
+struct Tiff<'a> {
+    data: &'a [u8],
+    little_endian: bool,
+}
+
+impl<'a> Tiff<'a> {
+    fn new(data: &'a [u8]) -> Option<Self> {
+        let little_endian = match data.get(..2)? {
+            b"II" => true,
+            b"MM" => false,
+            _ => return None,
+        };
+
+        let tiff = Tiff {
+            data,
+            little_endian,
+        };
+
+        (tiff.u16(2)? == 42).then_some(tiff)
+    }
+
+    fn u16(&self, offset: usize) -> Option<u16> {
+        let bytes: [u8; 2] = self.data.get(offset..offset + 2)?.try_into().ok()?;
+        Some(if self.little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        })
+    }
+
+    fn u32(&self, offset: usize) -> Option<u32> {
+        let bytes: [u8; 4] = self.data.get(offset..offset + 4)?.try_into().ok()?;
+        Some(if self.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    }
+
+    fn first_ifd(&self) -> Option<usize> {
+        self.u32(4).map(|offset| offset as usize)
+    }
+
+    fn entries(&self, ifd: usize) -> Vec<Entry> {
+        let Some(count) = self.u16(ifd) else {
+            return Vec::new();
+        };
+
+        (0..count as usize)
+            .filter_map(|i| {
+                let at = ifd + 2 + i * 12;
+                let (tag, kind, count) = (self.u16(at)?, self.u16(at + 2)?, self.u32(at + 4)?);
+
+                let size = match kind {
+                    1 | 2 | 6 | 7 => 1,
+                    3 | 8 => 2,
+                    4 | 9 | 11 => 4,
+                    5 | 10 | 12 => 8,
+                    _ => return None,
+                } * count as usize;
+
+                let offset = if size <= 4 {
+                    at + 8
+                } else {
+                    self.u32(at + 8)? as usize
+                };
+
+                (offset + size <= self.data.len()).then_some(Entry {
+                    tag,
+                    kind,
+                    count,
+                    offset,
+                })
+            })
+            .collect()
+    }
+
+    fn ascii(&self, entry: &Entry) -> Option<String> {
+        let bytes = self
+            .data
+            .get(entry.offset..entry.offset + entry.count as usize)?;
+        let text = String::from_utf8_lossy(bytes)
+            .trim_end_matches('\0')
+            .trim()
+            .to_string();
+
+        (!text.is_empty()).then_some(text)
+    }
+
+    fn integer(&self, entry: &Entry) -> Option<u32> {
+        match entry.kind {
+            1 | 7 => self.data.get(entry.offset).map(|b| *b as u32),
+            3 => self.u16(entry.offset).map(u32::from),
+            4 => self.u32(entry.offset),
+            _ => None,
+        }
+    }
+
+    fn rational(&self, entry: &Entry, index: usize) -> Option<f64> {
+        if index >= entry.count as usize {
+            return None;
+        }
+
+        let at = entry.offset + index * 8;
+        let (numerator, denominator) = (self.u32(at)?, self.u32(at + 4)?);
+
+        let (numerator, denominator) = match entry.kind {
+            5 => (numerator as f64, denominator as f64),
+            10 => (numerator as i32 as f64, denominator as i32 as f64),
+            _ => return None,
+        };
+
+        (denominator != 0.0).then(|| numerator / denominator)
+    }
+
+    // degrees, minutes and seconds
+    fn coordinate(&self, entry: &Entry) -> Option<f64> {
+        Some(
+            self.rational(entry, 0)?
+                + self.rational(entry, 1).unwrap_or(0.0) / 60.0
+                + self.rational(entry, 2).unwrap_or(0.0) / 3600.0,
+        )
+    }
+}
+
+fn read_exif(tiff: &Tiff, metadata: &mut PhotoMetadata) {
+    let Some(ifd0) = tiff.first_ifd() else {
+        return;
+    };
+
+    let mut exif_ifd = None;
+    let mut gps_ifd = None;
+
+    for entry in tiff.entries(ifd0) {
+        match entry.tag {
+            TAG_MAKE => metadata.camera_make = tiff.ascii(&entry),
+            TAG_MODEL => metadata.camera_model = tiff.ascii(&entry),
+            TAG_ORIENTATION => {
+                metadata.orientation = tiff
+                    .integer(&entry)
+                    .filter(|o| (1..=8).contains(o))
+                    .unwrap_or(1) as u16
+            }
+            TAG_DATE_TIME => metadata.taken_at = metadata.taken_at.take().or(tiff.ascii(&entry)),
+            TAG_EXIF_IFD => exif_ifd = tiff.integer(&entry),
+            TAG_GPS_IFD => gps_ifd = tiff.integer(&entry),
+            _ => {}
+        }
+    }
+
+    for entry in exif_ifd
+        .map(|ifd| tiff.entries(ifd as usize))
+        .unwrap_or_default()
+    {
+        match entry.tag {
+            TAG_EXPOSURE_TIME => metadata.exposure_time = tiff.rational(&entry, 0),
+            TAG_F_NUMBER => metadata.f_number = tiff.rational(&entry, 0),
+            TAG_ISO => metadata.iso = tiff.integer(&entry),
+            TAG_FOCAL_LENGTH => metadata.focal_length = tiff.rational(&entry, 0),
+            TAG_LENS_MODEL => metadata.lens = tiff.ascii(&entry),
+            // when the shutter fired beats when the file was last written
+            TAG_DATE_TIME_ORIGINAL => {
+                metadata.taken_at = tiff.ascii(&entry).or(metadata.taken_at.take())
+            }
+            _ => {}
+        }
+    }
+
+    let gps_entries = gps_ifd
+        .map(|ifd| tiff.entries(ifd as usize))
+        .unwrap_or_default();
+    let find = |tag: u16| gps_entries.iter().find(|entry| entry.tag == tag);
+
+    let latitude = find(TAG_GPS_LATITUDE).and_then(|entry| tiff.coordinate(entry));
+    let longitude = find(TAG_GPS_LONGITUDE).and_then(|entry| tiff.coordinate(entry));
+    let is_ref = |tag: u16, negative: &str| {
+        find(tag).and_then(|entry| tiff.ascii(entry)).as_deref() == Some(negative)
+    };
+
+    if let (Some(latitude), Some(longitude)) = (latitude, longitude) {
+        metadata.gps = Some((
+            if is_ref(TAG_GPS_LATITUDE_REF, "S") {
+                -latitude
+            } else {
+                latitude
+            },
+            if is_ref(TAG_GPS_LONGITUDE_REF, "W") {
+                -longitude
+            } else {
+                longitude
+            },
+        ));
+    }
+}
+
+// Writes 1 over the orientation tag in an EXIF block, if it has one
+fn reset_orientation(exif: &mut [u8]) {
+    let position = {
+        let Some(tiff) = Tiff::new(exif) else {
+            return;
+        };
+        let Some(ifd0) = tiff.first_ifd() else {
+            return;
+        };
+
+        tiff.entries(ifd0)
+            .into_iter()
+            .find(|entry| entry.tag == TAG_ORIENTATION && entry.kind == 3)
+            .map(|entry| (entry.offset, tiff.little_endian))
+    };
+
+    if let Some((offset, little_endian)) = position {
+        let one = if little_endian {
+            1u16.to_le_bytes()
+        } else {
+            1u16.to_be_bytes()
+        };
+        exif[offset..offset + 2].copy_from_slice(&one);
+    }
+}
+
+// Photoshop image resources, looking for the IPTC records inside them
+fn read_iptc(resources: &[u8]) -> Vec<(String, String)> {
+    let mut offset = 0;
+
+    while offset + 12 <= resources.len() && &resources[offset..offset + 4] == b"8BIM" {
+        let id = u16::from_be_bytes([resources[offset + 4], resources[offset + 5]]);
+
+        // the name is a pascal string, padded to an even length
+        let name_length = resources[offset + 6] as usize;
+        let name_end = offset + 6 + ((name_length + 2) & !1);
+
+        let Some(size) = resources
+            .get(name_end..name_end + 4)
+            .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as usize)
+        else {
+            break;
+        };
+
+        let data_start = name_end + 4;
+        let Some(data) = resources.get(data_start..data_start + size) else {
+            break;
+        };
+
+        if id == IPTC_RESOURCE {
+            return read_iptc_records(data);
+        }
+
+        offset = data_start + ((size + 1) & !1);
+    }
+
+    Vec::new()
+}
+
+fn read_iptc_records(data: &[u8]) -> Vec<(String, String)> {
+    let mut values: Vec<(u8, String)> = Vec::new();
+    let mut offset = 0;
+
+    while offset + 5 <= data.len() && data[offset] == 0x1C {
+        let (record, dataset) = (data[offset + 1], data[offset + 2]);
+        let length = u16::from_be_bytes([data[offset + 3], data[offset + 4]]) as usize;
+
+        let Some(value) = data.get(offset + 5..offset + 5 + length) else {
+            break;
+        };
+
+        if record == 2 {
+            values.push((dataset, String::from_utf8_lossy(value).trim().to_string()));
+        }
+
+        offset += 5 + length;
+    }
+
+    IPTC_FIELDS
+        .iter()
+        .filter_map(|(dataset, name)| {
+            // keywords repeat, one dataset each
+            let joined = values
+                .iter()
+                .filter(|(d, value)| d == dataset && !value.is_empty())
+                .map(|(_, value)| value.as_str())
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            (!joined.is_empty()).then(|| (name.to_string(), joined))
+        })
+        .collect()
+}
+
+fn read_xmp(xml: &str) -> Vec<(String, String)> {
+    XMP_FIELDS
+        .iter()
+        .filter_map(|(property, name)| {
+            xmp_value(xml, property).map(|value| (name.to_string(), value))
+        })
+        .collect()
+}
+
+// Properties are either attributes on the description or elements, often wrapping an rdf list
+fn xmp_value(xml: &str, property: &str) -> Option<String> {
+    let attribute = format!("{}=\"", property);
+    if let Some(start) = xml.find(&attribute) {
+        let rest = &xml[start + attribute.len()..];
+        return rest.find('"').map(|end| rest[..end].trim().to_string());
+    }
+
+    let open = format!("<{}", property);
+    let close = format!("</{}>", property);
+
+    let start = xml.find(&open)?;
+    let body_start = start + xml[start..].find('>')? + 1;
+    let body = &xml[body_start..body_start + xml[body_start..].find(&close)?];
+
+    // the text between tags, one value per list item
+    let mut values = Vec::new();
+    let mut text = String::new();
+    let mut in_tag = false;
+
+    for c in body.chars() {
+        match c {
+            '<' => {
+                in_tag = true;
+                if !text.trim().is_empty() {
+                    values.push(text.trim().to_string());
+                }
+                text.clear();
+            }
+            '>' => in_tag = false,
+            c if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    if !text.trim().is_empty() {
+        values.push(text.trim().to_string());
+    }
+
+    (!values.is_empty()).then(|| values.join(", "))
+}
+
+/// Turns RGBA8 pixels upright according to their EXIF orientation.
+/// Returns the new pixels with their width and height, which swap for quarter turns.
+/// Fails if there are fewer pixels than the size says.
+pub fn apply_orientation(
+    pixels: &[u8],
+    width: u32,
+    height: u32,
+    orientation: u16,
+) -> Result<(Vec<u8>, u32, u32), String> {
+    let (w, h) = (width as usize, height as usize);
+
+    if pixels.len() < w * h * 4 {
+        return Err("Couldn't orient image, its pixels are shorter than its size".to_string());
+    }
+
+    if orientation <= 1 || orientation > 8 {
+        return Ok((pixels[..w * h * 4].to_vec(), width, height));
+    }
+
+    let (new_width, new_height) = if orientation >= 5 { (h, w) } else { (w, h) };
+    let mut oriented = vec![0; new_width * new_height * 4];
+
+    for y in 0..h {
+        for x in 0..w {
+            let (dx, dy) = match orientation {
+                // mirrored
+                2 => (w - 1 - x, y),
+                // upside down
+                3 => (w - 1 - x, h - 1 - y),
+                // mirrored and upside down
+                4 => (x, h - 1 - y),
+                // mirrored and turned
+                5 => (y, x),
+                // turned a quarter clockwise
+                6 => (h - 1 - y, x),
+                // mirrored and turned the other way
+                7 => (h - 1 - y, w - 1 - x),
+                // turned a quarter anticlockwise
+                _ => (y, w - 1 - x),
+            };
+
+            let from = (y * w + x) * 4;
+            let to = (dy * new_width + dx) * 4;
+            oriented[to..to + 4].copy_from_slice(&pixels[from..from + 4]);
+        }
+    }
+
+    Ok((oriented, new_width as u32, new_height as u32))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    enum Value {
+        Ascii(&'static str),
+        Short(u16),
+        Rationals(Vec<(u32, u32)>),
+        // the offset of another directory in the same block
+        Ifd(usize),
+    }
+
+    // A TIFF block with the directories one after another and longer values after them
+    fn tiff(little_endian: bool, ifds: &[Vec<(u16, Value)>]) -> Vec<u8> {
+        let u16_bytes = |value: u16| {
+            if little_endian {
+                value.to_le_bytes()
+            } else {
+                value.to_be_bytes()
+            }
+        };
+        let u32_bytes = |value: u32| {
+            if little_endian {
+                value.to_le_bytes()
+            } else {
+                value.to_be_bytes()
+            }
+        };
+
+        let mut offsets = Vec::new();
+        let mut data_start = 8;
+        for ifd in ifds {
+            offsets.push(data_start);
+            data_start += 2 + ifd.len() * 12 + 4;
+        }
+
+        let mut block = Vec::new();
+        block.extend_from_slice(if little_endian { b"II" } else { b"MM" });
+        block.extend_from_slice(&u16_bytes(42));
+        block.extend_from_slice(&u32_bytes(8));
+
+        let mut data = Vec::new();
+        for ifd in ifds {
+            block.extend_from_slice(&u16_bytes(ifd.len() as u16));
+
+            for (tag, value) in ifd {
+                let (kind, count, bytes) = match value {
+                    Value::Ascii(text) => (2, text.len() + 1, [text.as_bytes(), &[0]].concat()),
+                    Value::Short(short) => (3, 1, u16_bytes(*short).to_vec()),
+                    Value::Rationals(values) => (
+                        5,
+                        values.len(),
+                        values
+                            .iter()
+                            .flat_map(|(n, d)| [u32_bytes(*n), u32_bytes(*d)].concat())
+                            .collect(),
+                    ),
+                    Value::Ifd(index) => (4, 1, u32_bytes(offsets[*index] as u32).to_vec()),
+                };
+
+                block.extend_from_slice(&u16_bytes(*tag));
+                block.extend_from_slice(&u16_bytes(kind));
+                block.extend_from_slice(&u32_bytes(count as u32));
+
+                if bytes.len() <= 4 {
+                    let mut inline = bytes;
+                    inline.resize(4, 0);
+                    block.extend_from_slice(&inline);
+                } else {
+                    block.extend_from_slice(&u32_bytes((data_start + data.len()) as u32));
+                    data.extend_from_slice(&bytes);
+                }
+            }
+
+            block.extend_from_slice(&u32_bytes(0));
+        }
+        block.extend_from_slice(&data);
+
+        block
+    }
+
+    fn camera_exif(little_endian: bool) -> Vec<u8> {
+        let block = tiff(
+            little_endian,
+            &[
+                vec![
+                    (TAG_MAKE, Value::Ascii("Canon")),
+                    (TAG_MODEL, Value::Ascii("Canon EOS R5")),
+                    (TAG_ORIENTATION, Value::Short(6)),
+                    (TAG_EXIF_IFD, Value::Ifd(1)),
+                    (TAG_GPS_IFD, Value::Ifd(2)),
+                ],
+                vec![
+                    (TAG_EXPOSURE_TIME, Value::Rationals(vec![(1, 250)])),
+                    (TAG_F_NUMBER, Value::Rationals(vec![(28, 10)])),
+                    (TAG_ISO, Value::Short(400)),
+                    (TAG_DATE_TIME_ORIGINAL, Value::Ascii("2024:05:01 10:30:00")),
+                ],
+                vec![
+                    (TAG_GPS_LATITUDE_REF, Value::Ascii("N")),
+                    (
+                        TAG_GPS_LATITUDE,
+                        Value::Rationals(vec![(51, 1), (30, 1), (0, 1)]),
+                    ),
+                    (TAG_GPS_LONGITUDE_REF, Value::Ascii("W")),
+                    (
+                        TAG_GPS_LONGITUDE,
+                        Value::Rationals(vec![(0, 1), (7, 1), (30, 1)]),
+                    ),
+                ],
+            ],
+        );
+
+        [EXIF_HEADER, &block].concat()
+    }
+
+    fn jpeg(segments: &[(u8, &[u8])]) -> Vec<u8> {
+        let mut jpeg = vec![0xFF, SOI];
+
+        for (marker, payload) in segments {
+            jpeg.extend_from_slice(&[0xFF, *marker]);
+            jpeg.extend_from_slice(&(payload.len() as u16 + 2).to_be_bytes());
+            jpeg.extend_from_slice(payload);
+        }
+
+        // an empty scan, nothing after it is read
+        jpeg.extend_from_slice(&[0xFF, SOS, 0, 2, 0xFF, EOI]);
+
+        jpeg
+    }
+
+    fn expected_camera() -> PhotoMetadata {
+        PhotoMetadata {
+            camera_make: Some("Canon".to_string()),
+            camera_model: Some("Canon EOS R5".to_string()),
+            exposure_time: Some(1.0 / 250.0),
+            f_number: Some(2.8),
+            iso: Some(400),
+            taken_at: Some("2024:05:01 10:30:00".to_string()),
+            gps: Some((51.5, -0.125)),
+            orientation: 6,
+            ..PhotoMetadata::default()
+        }
+    }
+
+    #[test]
+    fn little_endian_exif_is_read() {
+        let exif = camera_exif(true);
+        let metadata = read_metadata(&jpeg(&[(APP1, &exif)]));
+
+        assert_eq!(metadata, expected_camera());
+    }
+
+    #[test]
+    fn big_endian_exif_reads_the_same() {
+        let exif = camera_exif(false);
+        let metadata = read_metadata(&jpeg(&[(APP1, &exif)]));
+
+        assert_eq!(metadata, expected_camera());
+    }
+
+    #[test]
+    fn fields_are_labelled_for_the_panel() {
+        let fields = expected_camera().fields();
+        let field = |name: &str| {
+            fields
+                .iter()
+                .find(|(label, _)| label == name)
+                .map(|(_, value)| value.as_str())
+        };
+
+        // the model already says who made it
+        assert_eq!(field("Camera"), Some("Canon EOS R5"));
+        assert_eq!(field("Exposure"), Some("1/250 s"));
+        assert_eq!(field("Aperture"), Some("f/2.8"));
+        assert_eq!(field("GPS"), Some("51.50000, -0.12500"));
+    }
+
+    #[test]
+    fn truncated_segment_is_skipped() {
+        let exif = camera_exif(true);
+        let mut file = jpeg(&[(APP1, &exif)]);
+        // cut off partway through the EXIF block
+        file.truncate(40);
+
+        let metadata = read_metadata(&file);
+
+        assert!(metadata.is_empty());
+        assert_eq!(metadata.orientation, 1);
+    }
+
+    #[test]
+    fn segment_length_past_the_end_is_skipped() {
+        let exif = camera_exif(true);
+        let mut file = jpeg(&[(APP1, &exif)]);
+        // the length claims more than the file holds
+        file[4..6].copy_from_slice(&u16::MAX.to_be_bytes());
+
+        assert!(read_metadata(&file).is_empty());
+        assert_eq!(strip_metadata(&file), file);
+    }
+
+    #[test]
+    fn truncated_directory_keeps_what_fits() {
+        let exif = camera_exif(false);
+        // the directory ends halfway through the orientation entry
+        let cut = EXIF_HEADER.len() + 8 + 2 + 2 * 12 + 6;
+        let metadata = read_metadata(&jpeg(&[(APP1, &exif[..cut])]));
+
+        // the make and model point past the cut, so nothing readable is left
+        assert_eq!(metadata.camera_make, None);
+        assert_eq!(metadata.orientation, 1);
+    }
+
+    #[test]
+    fn iptc_and_xmp_are_read() {
+        let records: Vec<u8> = [(25, "sea"), (25, "boat"), (80, "Ada")]
+            .iter()
+            .flat_map(|(dataset, value)| {
+                let mut record = vec![0x1C, 2, *dataset];
+                record.extend_from_slice(&(value.len() as u16).to_be_bytes());
+                record.extend_from_slice(value.as_bytes());
+                record
+            })
+            .collect();
+
+        let mut photoshop = PHOTOSHOP_HEADER.to_vec();
+        photoshop.extend_from_slice(b"8BIM");
+        photoshop.extend_from_slice(&IPTC_RESOURCE.to_be_bytes());
+        // an empty name, padded to two bytes
+        photoshop.extend_from_slice(&[0, 0]);
+        photoshop.extend_from_slice(&(records.len() as u32).to_be_bytes());
+        photoshop.extend_from_slice(&records);
+
+        let xmp = [
+            XMP_HEADER,
+            b"<rdf:Description xmp:Rating=\"4\"><dc:title><rdf:Alt><rdf:li>Harbour</rdf:li></rdf:Alt></dc:title></rdf:Description>",
+        ]
+        .concat();
+
+        let metadata = read_metadata(&jpeg(&[(APP13, &photoshop), (APP1, &xmp)]));
+
+        assert_eq!(
+            metadata.iptc,
+            vec![
+                ("Keywords".to_string(), "sea, boat".to_string()),
+                ("Author".to_string(), "Ada".to_string()),
+            ]
+        );
+        assert_eq!(
+            metadata.xmp,
+            vec![
+                ("Title".to_string(), "Harbour".to_string()),
+                ("Rating".to_string(), "4".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn strip_keeps_the_jfif_header() {
+        let exif = camera_exif(true);
+        let file = jpeg(&[(APP0, b"JFIF\0"), (APP1, &exif)]);
+
+        let stripped = strip_metadata(&file);
+
+        assert_eq!(stripped, jpeg(&[(APP0, b"JFIF\0")]));
+        assert!(read_metadata(&stripped).is_empty());
+    }
+
+    #[test]
+    fn copied_metadata_is_upright() {
+        for little_endian in [true, false] {
+            let exif = camera_exif(little_endian);
+            let original = jpeg(&[(APP1, &exif)]);
+            let encoded = jpeg(&[(APP0, b"JFIF\0")]);
+
+            let copied = read_metadata(&export_metadata(
+                &original,
+                &encoded,
+                ExportMetadata::Preserve,
+            ));
+
+            assert_eq!(
+                copied,
+                PhotoMetadata {
+                    orientation: 1,
+                    ..expected_camera()
+                }
+            );
+        }
+    }
+
+    // a 3 by 2 image, each pixel's red channel is its index
+    //   0 1 2
+    //   3 4 5
+    fn numbered() -> Vec<u8> {
+        (0..6).flat_map(|index| [index, 0, 0, 255]).collect()
+    }
+
+    fn reds(pixels: &[u8]) -> Vec<u8> {
+        pixels.chunks(4).map(|pixel| pixel[0]).collect()
+    }
+
+    #[test]
+    fn every_orientation_turns_upright() {
+        let cases: [(u16, [u8; 6], (u32, u32)); 8] = [
+            (1, [0, 1, 2, 3, 4, 5], (3, 2)),
+            // mirrored
+            (2, [2, 1, 0, 5, 4, 3], (3, 2)),
+            // upside down
+            (3, [5, 4, 3, 2, 1, 0], (3, 2)),
+            // mirrored and upside down
+            (4, [3, 4, 5, 0, 1, 2], (3, 2)),
+            // mirrored across the diagonal
+            (5, [0, 3, 1, 4, 2, 5], (2, 3)),
+            // a quarter clockwise
+            (6, [3, 0, 4, 1, 5, 2], (2, 3)),
+            // mirrored across the other diagonal
+            (7, [5, 2, 4, 1, 3, 0], (2, 3)),
+            // a quarter anticlockwise
+            (8, [2, 5, 1, 4, 0, 3], (2, 3)),
+        ];
+
+        for (orientation, expected, size) in cases {
+            let (pixels, width, height) =
+                apply_orientation(&numbered(), 3, 2, orientation).expect("Couldn't orient pixels");
+
+            assert_eq!(reds(&pixels), expected, "orientation {}", orientation);
+            assert_eq!((width, height), size, "orientation {}", orientation);
+        }
+    }
+
+    #[test]
+    fn unknown_orientation_is_left_alone() {
+        let (pixels, width, height) =
+            apply_orientation(&numbered(), 3, 2, 9).expect("Couldn't orient pixels");
+
+        assert_eq!(pixels, numbered());
+        assert_eq!((width, height), (3, 2));
+    }
+
+    #[test]
+    fn too_few_pixels_is_an_error() {
+        let pixels = numbered();
+
+        for orientation in 1..=8 {
+            assert!(apply_orientation(&pixels[..20], 3, 2, orientation).is_err());
+        }
+        assert!(apply_orientation(&pixels, 3, 3, 6).is_err());
+    }
+}
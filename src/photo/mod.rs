@@ -0,0 +1,6 @@
+pub mod adjustments;
+pub mod crop;
+pub mod filters;
+pub mod images;
+pub mod metadata;
+pub mod scopes;
@@ -0,0 +1,389 @@
+use std::sync::{Arc, Mutex};
+
+use crossbeam::channel::{unbounded, Sender};
+
+use crate::helpers::locking::LockRecover;
+
+use super::filters::CancelToken;
+
+// rows between cancel checks, scopes run over every pixel of the canvas
+const CANCEL_CHECK_ROWS: usize = 16;
+
+/// Counts per level for each channel and for luminance
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    pub red: [u32; 256],
+    pub green: [u32; 256],
+    pub blue: [u32; 256],
+    pub luminance: [u32; 256],
+    // transparent pixels aren't counted
+    pub total: u32,
+    // pixels with any channel at 0, or at 255
+    pub clipped_shadows: u32,
+    pub clipped_highlights: u32,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Histogram {
+            red: [0; 256],
+            green: [0; 256],
+            blue: [0; 256],
+            luminance: [0; 256],
+            total: 0,
+            clipped_shadows: 0,
+            clipped_highlights: 0,
+        }
+    }
+}
+
+/// How much of the image has lost detail at either end, as fractions of its pixels
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Clipping {
+    pub shadows: f32,
+    pub highlights: f32,
+}
+
+// Rec. 709 luma in integer steps, weights sum to 256
+fn luma(r: u8, g: u8, b: u8) -> u8 {
+    ((54 * r as u32 + 183 * g as u32 + 19 * b as u32) >> 8) as u8
+}
+
+impl Histogram {
+    pub fn from_rgba8(pixels: &[u8], width: u32, cancel: &CancelToken) -> Option<Self> {
+        let mut histogram = Histogram::default();
+
+        let row_length = (width as usize * 4 * CANCEL_CHECK_ROWS).max(4);
+
+        for rows in pixels.chunks(row_length) {
+            if cancel.is_cancelled() {
+                return None;
+            }
+
+            for pixel in rows.chunks_exact(4) {
+                let (r, g, b) = (pixel[0], pixel[1], pixel[2]);
+                if pixel[3] == 0 {
+                    continue;
+                }
+
+                histogram.red[r as usize] += 1;
+                histogram.green[g as usize] += 1;
+                histogram.blue[b as usize] += 1;
+                histogram.luminance[luma(r, g, b) as usize] += 1;
+                histogram.total += 1;
+
+                if r == 0 || g == 0 || b == 0 {
+                    histogram.clipped_shadows += 1;
+                }
+                if r == 255 || g == 255 || b == 255 {
+                    histogram.clipped_highlights += 1;
+                }
+            }
+        }
+
+        Some(histogram)
+    }
+
+    pub fn clipping(&self) -> Clipping {
+        let total = self.total.max(1) as f32;
+
+        Clipping {
+            shadows: self.clipped_shadows as f32 / total,
+            highlights: self.clipped_highlights as f32 / total,
+        }
+    }
+
+    /// The channel's counts grouped into fewer bins and scaled to 0.0 - 1.0 against the
+    /// tallest bin of any channel, ready to draw as bars
+    pub fn bins(&self, channel: &[u32; 256], count: usize) -> Vec<f32> {
+        let count = count.clamp(1, 256);
+        let group = |counts: &[u32; 256]| -> Vec<u32> {
+            (0..count)
+                .map(|bin| {
+                    let (start, end) = (bin * 256 / count, (bin + 1) * 256 / count);
+                    counts[start..end].iter().sum()
+                })
+                .collect()
+        };
+
+        let tallest = [&self.red, &self.green, &self.blue, &self.luminance]
+            .iter()
+            .flat_map(|counts| group(counts))
+            .max()
+            .unwrap_or(0)
+            .max(1) as f32;
+
+        group(channel)
+            .iter()
+            .map(|count| *count as f32 / tallest)
+            .collect()
+    }
+}
+
+/// Luminance levels down each column of the image, left to right
+pub fn waveform(
+    pixels: &[u8],
+    width: u32,
+    height: u32,
+    columns: usize,
+    cancel: &CancelToken,
+) -> Option<Vec<[u32; 256]>> {
+    let columns = columns.clamp(1, width.max(1) as usize);
+    let mut scope = vec![[0; 256]; columns];
+
+    for y in 0..height as usize {
+        if y % CANCEL_CHECK_ROWS == 0 && cancel.is_cancelled() {
+            return None;
+        }
+
+        for x in 0..width as usize {
+            let at = (y * width as usize + x) * 4;
+            let pixel = &pixels[at..at + 4];
+            if pixel[3] == 0 {
+                continue;
+            }
+
+            let column = x * columns / width as usize;
+            scope[column][luma(pixel[0], pixel[1], pixel[2]) as usize] += 1;
+        }
+    }
+
+    Some(scope)
+}
+
+/// Hue and saturation as a size by size grid of counts, neutral grey in the middle
+pub fn vectorscope(pixels: &[u8], size: usize, cancel: &CancelToken) -> Option<Vec<u32>> {
+    let size = size.max(2);
+    let mut scope = vec![0; size * size];
+
+    for (i, pixel) in pixels.chunks_exact(4).enumerate() {
+        if i % 4096 == 0 && cancel.is_cancelled() {
+            return None;
+        }
+        if pixel[3] == 0 {
+            continue;
+        }
+
+        let (r, g, b) = (
+            pixel[0] as f32 / 255.0,
+            pixel[1] as f32 / 255.0,
+            pixel[2] as f32 / 255.0,
+        );
+        let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+        // each is -0.5 - 0.5
+        let (cb, cr) = ((b - y) / 1.8556, (r - y) / 1.5748);
+
+        let x = (((cb + 0.5) * size as f32) as usize).min(size - 1);
+        let row = (((0.5 - cr) * size as f32) as usize).min(size - 1);
+        scope[row * size + x] += 1;
+    }
+
+    Some(scope)
+}
+
+// one pass waiting for the worker, dropped if a newer image came in before it started
+struct ScopeRequest {
+    pixels: Arc<Vec<u8>>,
+    width: u32,
+    cancel: CancelToken,
+    on_done: Box<dyn FnOnce(Histogram) + Send>,
+}
+
+/// Keeps scopes current without blocking the UI, on a thread that lasts as long as the
+/// worker. A new image cancels the pass still running.
+pub struct ScopeWorker {
+    sender: Sender<ScopeRequest>,
+    current: Mutex<Option<CancelToken>>,
+}
+
+impl ScopeWorker {
+    pub fn new() -> Self {
+        let (sender, receiver) = unbounded::<ScopeRequest>();
+
+        // ends once the worker is dropped
+        std::thread::spawn(move || {
+            for request in receiver {
+                if request.cancel.is_cancelled() {
+                    continue;
+                }
+
+                let histogram =
+                    Histogram::from_rgba8(&request.pixels, request.width, &request.cancel);
+                if let Some(histogram) = histogram.filter(|_| !request.cancel.is_cancelled()) {
+                    (request.on_done)(histogram);
+                }
+            }
+        });
+
+        Self {
+            sender,
+            current: Mutex::new(None),
+        }
+    }
+
+    pub fn update(
+        &self,
+        pixels: Arc<Vec<u8>>,
+        width: u32,
+        on_done: impl FnOnce(Histogram) + Send + 'static,
+    ) {
+        let cancel = CancelToken::new();
+        if let Some(previous) = self.current.lock_or_recover().replace(cancel.clone()) {
+            previous.cancel();
+        }
+
+        let _ = self.sender.send(ScopeRequest {
+            pixels,
+            width,
+            cancel,
+            on_done: Box::new(on_done),
+        });
+    }
+
+    pub fn cancel(&self) {
+        if let Some(cancel) = self.current.lock_or_recover().take() {
+            cancel.cancel();
+        }
+    }
+}
+
+impl Default for ScopeWorker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for ScopeWorker {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    use super::*;
+
+    fn histogram(pixels: &[u8]) -> Histogram {
+        Histogram::from_rgba8(pixels, (pixels.len() / 4) as u32, &CancelToken::new()).unwrap()
+    }
+
+    #[test]
+    fn levels_are_counted_per_channel() {
+        let h = histogram(&[10, 20, 30, 255, 10, 200, 30, 255]);
+
+        assert_eq!(h.total, 2);
+        assert_eq!(h.red[10], 2);
+        assert_eq!((h.green[20], h.green[200]), (1, 1));
+        assert_eq!(h.blue[30], 2);
+        assert_eq!(h.luminance.iter().sum::<u32>(), 2);
+    }
+
+    #[test]
+    fn luminance_weights_green_most() {
+        assert_eq!(luma(255, 255, 255), 255);
+        assert_eq!(luma(0, 0, 0), 0);
+        assert!(luma(0, 255, 0) > luma(255, 0, 0));
+        assert!(luma(255, 0, 0) > luma(0, 0, 255));
+
+        let h = histogram(&[0, 255, 0, 255]);
+        assert_eq!(h.luminance[luma(0, 255, 0) as usize], 1);
+    }
+
+    #[test]
+    fn transparent_pixels_are_skipped() {
+        let h = histogram(&[255, 255, 255, 0, 1, 2, 3, 1]);
+
+        assert_eq!(h.total, 1);
+        assert_eq!(h.red[255], 0);
+    }
+
+    #[test]
+    fn clipping_counts_either_end() {
+        let h = histogram(&[
+            0, 100, 100, 255, //
+            100, 255, 100, 255, //
+            0, 0, 255, 255, //
+            100, 100, 100, 255,
+        ]);
+
+        assert_eq!((h.clipped_shadows, h.clipped_highlights), (2, 2));
+        assert_eq!(
+            h.clipping(),
+            Clipping {
+                shadows: 0.5,
+                highlights: 0.5,
+            }
+        );
+        assert_eq!(Histogram::default().clipping().shadows, 0.0);
+    }
+
+    #[test]
+    fn bins_are_scaled_to_the_tallest() {
+        let h = histogram(&[0, 0, 0, 255, 0, 0, 0, 255, 255, 128, 0, 255]);
+
+        // blue has all three pixels in its darkest bin, the tallest of any channel
+        assert_eq!(h.bins(&h.blue, 4), vec![1.0, 0.0, 0.0, 0.0]);
+        assert_eq!(h.bins(&h.red, 4), vec![2.0 / 3.0, 0.0, 0.0, 1.0 / 3.0]);
+        assert_eq!(h.bins(&h.green, 1000).len(), 256);
+    }
+
+    #[test]
+    fn cancelled_passes_return_nothing() {
+        let cancel = CancelToken::new();
+        cancel.cancel();
+
+        assert!(Histogram::from_rgba8(&[0; 16], 2, &cancel).is_none());
+        assert!(waveform(&[0; 16], 2, 2, 2, &cancel).is_none());
+        assert!(vectorscope(&[0; 16], 8, &cancel).is_none());
+    }
+
+    #[test]
+    fn waveform_sorts_pixels_into_columns() {
+        let pixels = [
+            0, 0, 0, 255, 255, 255, 255, 255, 255, 255, 255, 255, 0, 0, 0, 0,
+        ];
+        let scope = waveform(&pixels, 2, 2, 2, &CancelToken::new()).unwrap();
+
+        assert_eq!((scope[0][0], scope[0][255]), (1, 1));
+        assert_eq!((scope[1][255], scope[1][0]), (1, 0));
+    }
+
+    #[test]
+    fn the_worker_only_finishes_the_latest_image() {
+        let worker = ScopeWorker::new();
+        let (sender, receiver) = mpsc::channel();
+
+        // holds the thread until both images are queued, so the first is cancelled waiting
+        let (started, running) = mpsc::channel::<()>();
+        let (release, blocked) = mpsc::channel::<()>();
+        worker.update(Arc::new(vec![0, 0, 0, 255]), 1, move |_| {
+            let _ = started.send(());
+            let _ = blocked.recv();
+        });
+        let _ = running.recv_timeout(Duration::from_secs(5));
+        let first = sender.clone();
+        worker.update(Arc::new(vec![0, 0, 0, 255]), 1, move |histogram| {
+            let _ = first.send(("first", histogram.total));
+        });
+        worker.update(Arc::new(vec![255; 8]), 2, move |histogram| {
+            let _ = sender.send(("second", histogram.total));
+        });
+        let _ = release.send(());
+
+        assert_eq!(
+            receiver.recv_timeout(Duration::from_secs(5)),
+            Ok(("second", 2))
+        );
+        assert!(receiver.recv_timeout(Duration::from_millis(100)).is_err());
+    }
+
+    #[test]
+    fn greys_land_in_the_middle_of_the_vectorscope() {
+        let scope = vectorscope(&[128, 128, 128, 255], 8, &CancelToken::new()).unwrap();
+
+        assert_eq!(scope[4 * 8 + 4], 1);
+    }
+}
@@ -0,0 +1,613 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+
+use common_vector::basic::WindowSize;
+use common_vector::editor::Editor;
+use common_vector::polygon::Polygon;
+use common_vector::vertex::Vertex;
+use uuid::Uuid;
+
+use crate::brush::eraser::polygon_outline;
+use crate::brush::strokes::StrokeLayers;
+use crate::helpers::effects::{effect_mesh, EffectKind, ShapeEffects};
+use crate::helpers::masks::{clip_mesh, LayerMasks, LayerShape, MaskSource};
+use crate::helpers::redraw::Invalidator;
+use crate::photo::images::ImageLayers;
+
+use super::images::ImageTextures;
+
+const VERTEX_SIZE: u64 = std::mem::size_of::<Vertex>() as u64;
+const INDEX_SIZE: u64 = std::mem::size_of::<u32>() as u64;
+
+// so small edits (an extra point, a rounder corner) don't force a relayout
+const MIN_SLOT_VERTICES: u32 = 16;
+const MIN_SLOT_INDICES: u32 = 48;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum BatchItemId {
+    Polygon(Uuid),
+    StrokeLayer(Uuid),
+    Image(Uuid),
+    // the stroke still being drawn, which lives in the editor until it's finished
+    Stroke(usize),
+    // a polygon's shadow or glow, by its place in the effects stack
+    Effect(Uuid, usize),
+}
+
+/// Changes whenever an edit rebuilds a shape's geometry, as counted by the Invalidator.
+/// Stroke and image layers count their own rebuilds in their generation.
+/// Masked layers also change with their masks and whatever they're clipped to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Revision {
+    edit: u64,
+    generation: u64,
+    vertex_count: usize,
+    index_count: usize,
+    mask: u64,
+}
+
+pub struct BatchItem<'a> {
+    pub id: BatchItemId,
+    pub revision: Revision,
+    pub vertices: &'a [Vertex],
+    pub indices: &'a [u32],
+    // clipped on the CPU before it's uploaded, see MaskCache
+    pub mask: Option<MaskSource<'a>>,
+}
+
+/// Shapes in stacking order, as the render pass should draw them
+pub fn scene_items<'a>(
+    editor: &'a Editor,
+    strokes: &'a StrokeLayers,
+    images: &'a ImageLayers,
+    masks: &'a LayerMasks,
+    effects: &'a EffectCache,
+    invalidator: &Invalidator,
+) -> Vec<BatchItem<'a>> {
+    let mut items = Vec::new();
+
+    let polygon_item = |polygon: &'a Polygon| BatchItem {
+        id: BatchItemId::Polygon(polygon.id),
+        revision: Revision {
+            edit: invalidator.revision(BatchItemId::Polygon(polygon.id)),
+            generation: 0,
+            vertex_count: polygon.vertices.len(),
+            index_count: polygon.indices.len(),
+            mask: 0,
+        },
+        vertices: &polygon.vertices,
+        indices: &polygon.indices,
+        mask: None,
+    };
+
+    // polygons, finished strokes and photos interleave in the layer list
+    let mut layers: Vec<(Uuid, LayerShape<'a>, BatchItem<'a>)> = editor
+        .layer_list
+        .iter()
+        .filter_map(|layer_id| {
+            if let Some(polygon) = editor.polygons.iter().find(|p| p.id == *layer_id) {
+                Some((
+                    *layer_id,
+                    LayerShape::Polygon(polygon),
+                    polygon_item(polygon),
+                ))
+            } else if let Some(image) = images.get(*layer_id) {
+                Some((
+                    *layer_id,
+                    LayerShape::Image(image),
+                    BatchItem {
+                        id: BatchItemId::Image(image.id),
+                        revision: Revision {
+                            edit: 0,
+                            generation: image.generation,
+                            vertex_count: image.vertices.len(),
+                            index_count: image.indices.len(),
+                            mask: 0,
+                        },
+                        vertices: &image.vertices,
+                        indices: &image.indices,
+                        mask: None,
+                    },
+                ))
+            } else {
+                let stroke = strokes.get(*layer_id)?;
+
+                Some((
+                    *layer_id,
+                    LayerShape::Stroke(stroke),
+                    BatchItem {
+                        id: BatchItemId::StrokeLayer(stroke.id),
+                        revision: Revision {
+                            edit: 0,
+                            generation: stroke.generation,
+                            vertex_count: stroke.vertices.len(),
+                            index_count: stroke.indices.len(),
+                            mask: 0,
+                        },
+                        vertices: &stroke.vertices,
+                        indices: &stroke.indices,
+                        mask: None,
+                    },
+                ))
+            }
+        })
+        .collect();
+
+    for index in 0..layers.len() {
+        let (layer_id, _, item) = &layers[index];
+        let mask = masks.get(*layer_id);
+
+        // clipping groups stack on the first unclipped layer below them
+        let base = if masks.is_clipped(*layer_id) {
+            layers[..index]
+                .iter()
+                .rev()
+                .find(|(id, _, _)| !masks.is_clipped(*id))
+        } else {
+            None
+        };
+
+        if mask.is_none() && base.is_none() {
+            continue;
+        }
+
+        let mut hasher = DefaultHasher::new();
+        masks.generation().hash(&mut hasher);
+        base.map(|(_, _, base_item)| base_item.revision)
+            .hash(&mut hasher);
+
+        let revision = Revision {
+            mask: hasher.finish(),
+            ..item.revision
+        };
+        let mask = MaskSource {
+            mask,
+            base: base.map(|(base_id, shape, _)| (*shape, masks.get(*base_id))),
+        };
+
+        let (_, _, item) = &mut layers[index];
+        item.revision = revision;
+        item.mask = Some(mask);
+    }
+
+    for (layer_id, _, mut item) in layers {
+        let Some(built) = effects.layers.get(&layer_id) else {
+            items.push(item);
+            continue;
+        };
+
+        let effect_item = |mesh: &'a EffectMesh| BatchItem {
+            id: BatchItemId::Effect(layer_id, mesh.index),
+            revision: Revision {
+                edit: 0,
+                generation: built.revision,
+                vertex_count: mesh.vertices.len(),
+                index_count: mesh.indices.len(),
+                mask: 0,
+            },
+            vertices: &mesh.vertices,
+            indices: &mesh.indices,
+            mask: None,
+        };
+
+        items.extend(
+            built
+                .meshes
+                .iter()
+                .filter(|mesh| mesh.kind.is_behind())
+                .map(effect_item),
+        );
+
+        // a blurred shape is drawn feathered instead, still masked like the shape
+        if let Some(blur) = built
+            .meshes
+            .iter()
+            .find(|mesh| mesh.kind == EffectKind::LayerBlur)
+        {
+            item.revision.generation = built.revision;
+            item.revision.vertex_count = blur.vertices.len();
+            item.revision.index_count = blur.indices.len();
+            item.vertices = &blur.vertices;
+            item.indices = &blur.indices;
+        }
+        items.push(item);
+
+        items.extend(
+            built
+                .meshes
+                .iter()
+                .filter(|mesh| mesh.kind == EffectKind::InnerShadow)
+                .map(effect_item),
+        );
+    }
+
+    for polygon in editor
+        .polygons
+        .iter()
+        .filter(|p| !editor.layer_list.contains(&p.id))
+    {
+        items.push(polygon_item(polygon));
+    }
+
+    for (stroke_index, stroke) in editor.brush_strokes.iter().enumerate() {
+        // Only batch strokes whose geometry has been built
+        if stroke.vertex_buffer.is_none() {
+            continue;
+        }
+
+        items.push(BatchItem {
+            id: BatchItemId::Stroke(stroke_index),
+            revision: Revision {
+                edit: invalidator.revision(BatchItemId::Stroke(stroke_index)),
+                generation: 0,
+                vertex_count: stroke.vertices.len(),
+                index_count: stroke.indices.len(),
+                mask: 0,
+            },
+            vertices: &stroke.vertices,
+            indices: &stroke.indices,
+            mask: None,
+        });
+    }
+
+    items
+}
+
+/// Clipped geometry for masked layers. Clipping is too slow to redo every frame,
+/// so each layer is only clipped again when its revision changes.
+#[derive(Default)]
+pub struct MaskCache {
+    meshes: HashMap<BatchItemId, (Revision, Vec<Vertex>, Vec<u32>)>,
+}
+
+impl MaskCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Swaps each masked item's geometry for its clipped mesh
+    pub fn resolve<'a>(
+        &'a mut self,
+        items: Vec<BatchItem<'a>>,
+        window_size: &WindowSize,
+    ) -> Vec<BatchItem<'a>> {
+        self.meshes.retain(|id, _| {
+            items
+                .iter()
+                .any(|item| item.id == *id && item.mask.is_some())
+        });
+
+        for item in &items {
+            let Some(mask) = &item.mask else {
+                continue;
+            };
+
+            if matches!(self.meshes.get(&item.id), Some((revision, _, _)) if *revision == item.revision)
+            {
+                continue;
+            }
+
+            let (vertices, indices) = match mask.coverage() {
+                Some(coverage) => {
+                    clip_mesh(item.vertices, item.indices, &coverage.to_ndc(window_size))
+                }
+                None => (item.vertices.to_vec(), item.indices.to_vec()),
+            };
+
+            self.meshes
+                .insert(item.id, (item.revision, vertices, indices));
+        }
+
+        // nothing changes from here on, so the meshes can be lent for as long as the items
+        let this: &'a Self = self;
+
+        items
+            .into_iter()
+            .map(|item| match this.meshes.get(&item.id) {
+                Some((_, vertices, indices)) if item.mask.is_some() => BatchItem {
+                    vertices: vertices.as_slice(),
+                    indices: indices.as_slice(),
+                    ..item
+                },
+                _ => item,
+            })
+            .collect()
+    }
+}
+
+pub struct EffectMesh {
+    // place in the layer's effects stack
+    pub index: usize,
+    pub kind: EffectKind,
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+}
+
+pub struct EffectMeshes {
+    revision: u64,
+    pub meshes: Vec<EffectMesh>,
+}
+
+/// Geometry for polygon effects, built again only when the polygon, its effects or
+/// the window change
+#[derive(Default)]
+pub struct EffectCache {
+    layers: HashMap<Uuid, EffectMeshes>,
+}
+
+impl EffectCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update(
+        &mut self,
+        editor: &Editor,
+        effects: &ShapeEffects,
+        invalidator: &Invalidator,
+        window_size: &WindowSize,
+    ) {
+        let polygons: Vec<&Polygon> = editor
+            .polygons
+            .iter()
+            .filter(|polygon| editor.layer_list.contains(&polygon.id))
+            .filter(|polygon| effects.get(polygon.id).iter().any(|effect| effect.enabled))
+            .collect();
+
+        self.layers
+            .retain(|id, _| polygons.iter().any(|polygon| polygon.id == *id));
+
+        for polygon in polygons {
+            let mut hasher = DefaultHasher::new();
+            invalidator
+                .revision(BatchItemId::Polygon(polygon.id))
+                .hash(&mut hasher);
+            effects.generation().hash(&mut hasher);
+            (window_size.width, window_size.height).hash(&mut hasher);
+            let revision = hasher.finish();
+
+            if matches!(self.layers.get(&polygon.id), Some(built) if built.revision == revision) {
+                continue;
+            }
+
+            let config = polygon.to_config();
+            let outline = polygon_outline(&config);
+
+            let meshes = effects
+                .get(polygon.id)
+                .iter()
+                .enumerate()
+                .filter(|(_, effect)| effect.enabled)
+                .map(|(index, effect)| {
+                    let (vertices, indices) =
+                        effect_mesh(effect, &outline, config.fill, window_size);
+
+                    EffectMesh {
+                        index,
+                        kind: effect.kind,
+                        vertices,
+                        indices,
+                    }
+                })
+                .collect();
+
+            self.layers
+                .insert(polygon.id, EffectMeshes { revision, meshes });
+        }
+    }
+}
+
+struct BatchSlot {
+    id: BatchItemId,
+    revision: Revision,
+    vertex_offset: u32,
+    vertex_capacity: u32,
+    index_offset: u32,
+    index_capacity: u32,
+}
+
+impl BatchSlot {
+    // the layer whose photo and painted mask the slot is drawn with
+    fn layer(&self) -> Option<Uuid> {
+        match self.id {
+            BatchItemId::Polygon(id) | BatchItemId::StrokeLayer(id) | BatchItemId::Image(id) => {
+                Some(id)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Shared vertex and index buffers for the whole scene, with one slot per shape.
+/// Slots keep the stacking order, so everything between photos and painted masks draws
+/// in a single call.
+/// Unused index capacity is padded with degenerate triangles.
+pub struct SceneBatch {
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    vertex_capacity: u64,
+    index_capacity: u64,
+    slots: Vec<BatchSlot>,
+    index_count: u32,
+}
+
+impl SceneBatch {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let vertex_capacity = 1024;
+        let index_capacity = 3072;
+
+        Self {
+            vertex_buffer: create_vertex_buffer(device, vertex_capacity),
+            index_buffer: create_index_buffer(device, index_capacity),
+            vertex_capacity,
+            index_capacity,
+            slots: Vec::new(),
+            index_count: 0,
+        }
+    }
+
+    /// Uploads only the shapes that changed, or relays out everything if shapes
+    /// were added, removed, reordered or outgrew their slot
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, items: &[BatchItem]) {
+        let layout_matches = self.slots.len() == items.len()
+            && self.slots.iter().zip(items).all(|(slot, item)| {
+                slot.id == item.id
+                    && item.vertices.len() as u32 <= slot.vertex_capacity
+                    && item.indices.len() as u32 <= slot.index_capacity
+            });
+
+        if !layout_matches {
+            self.rebuild(device, queue, items);
+            return;
+        }
+
+        for (slot, item) in self.slots.iter_mut().zip(items) {
+            if slot.revision != item.revision {
+                write_slot(&self.vertex_buffer, &self.index_buffer, queue, slot, item);
+                slot.revision = item.revision;
+            }
+        }
+    }
+
+    fn rebuild(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, items: &[BatchItem]) {
+        self.slots.clear();
+
+        let mut vertex_offset = 0;
+        let mut index_offset = 0;
+
+        for item in items {
+            let vertex_capacity = slot_capacity(item.vertices.len() as u32, MIN_SLOT_VERTICES);
+            // keep whole triangles so padding stays degenerate
+            let index_capacity =
+                slot_capacity(item.indices.len() as u32, MIN_SLOT_INDICES).div_ceil(3) * 3;
+
+            self.slots.push(BatchSlot {
+                id: item.id,
+                revision: item.revision,
+                vertex_offset,
+                vertex_capacity,
+                index_offset,
+                index_capacity,
+            });
+
+            vertex_offset += vertex_capacity;
+            index_offset += index_capacity;
+        }
+
+        if vertex_offset as u64 > self.vertex_capacity {
+            self.vertex_capacity = (vertex_offset as u64).next_power_of_two();
+            self.vertex_buffer = create_vertex_buffer(device, self.vertex_capacity);
+        }
+
+        if index_offset as u64 > self.index_capacity {
+            self.index_capacity = (index_offset as u64).next_power_of_two();
+            self.index_buffer = create_index_buffer(device, self.index_capacity);
+        }
+
+        for (slot, item) in self.slots.iter().zip(items) {
+            write_slot(&self.vertex_buffer, &self.index_buffer, queue, slot, item);
+        }
+
+        self.index_count = index_offset;
+    }
+
+    pub fn draw(&self, render_pass: &mut wgpu::RenderPass, textures: &ImageTextures) {
+        if self.index_count == 0 {
+            return;
+        }
+
+        self.draw_slots(render_pass, textures, 0..self.slots.len());
+    }
+
+    /// Draws only some of the items. Slots are in the same order as the items they were
+    /// prepared from. Each photo or painted mask binds its own textures, so the draw is
+    /// split around them.
+    pub fn draw_slots(
+        &self,
+        render_pass: &mut wgpu::RenderPass,
+        textures: &ImageTextures,
+        slots: Range<usize>,
+    ) {
+        if slots.is_empty() || slots.end > self.slots.len() {
+            return;
+        }
+
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+
+        let mut start = slots.start;
+        while start < slots.end {
+            let key = textures.key(self.slots[start].layer());
+            let end = (start + 1..slots.end)
+                .find(|index| textures.key(self.slots[*index].layer()) != key)
+                .unwrap_or(slots.end);
+            let (first, last) = (&self.slots[start], &self.slots[end - 1]);
+
+            render_pass.set_bind_group(1, textures.bind_group(key), &[]);
+            // unused indices in a slot repeat its first vertex, so they draw nothing
+            render_pass.draw_indexed(
+                first.index_offset..last.index_offset + last.index_capacity,
+                0,
+                0..1,
+            );
+
+            start = end;
+        }
+    }
+}
+
+fn slot_capacity(len: u32, min: u32) -> u32 {
+    (len + len / 2).max(min)
+}
+
+fn write_slot(
+    vertex_buffer: &wgpu::Buffer,
+    index_buffer: &wgpu::Buffer,
+    queue: &wgpu::Queue,
+    slot: &BatchSlot,
+    item: &BatchItem,
+) {
+    if !item.vertices.is_empty() {
+        queue.write_buffer(
+            vertex_buffer,
+            slot.vertex_offset as u64 * VERTEX_SIZE,
+            bytemuck::cast_slice(item.vertices),
+        );
+    }
+
+    // indices become absolute so every slot can share one draw call
+    let mut indices: Vec<u32> = item
+        .indices
+        .iter()
+        .map(|index| index + slot.vertex_offset)
+        .collect();
+    indices.resize(slot.index_capacity as usize, slot.vertex_offset);
+
+    if !indices.is_empty() {
+        queue.write_buffer(
+            index_buffer,
+            slot.index_offset as u64 * INDEX_SIZE,
+            bytemuck::cast_slice(&indices),
+        );
+    }
+}
+
+fn create_vertex_buffer(device: &wgpu::Device, capacity: u64) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Scene Batch Vertex Buffer"),
+        size: capacity * VERTEX_SIZE,
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+fn create_index_buffer(device: &wgpu::Device, capacity: u64) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Scene Batch Index Buffer"),
+        size: capacity * INDEX_SIZE,
+        usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
@@ -0,0 +1,142 @@
+use common_vector::basic::WindowSize;
+use common_vector::vertex::Vertex;
+
+use super::images::create_image_bind_group_layout;
+
+// must match the swapchain
+const CANVAS_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Bgra8UnormSrgb;
+const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth24Plus;
+/// What the window's own color and depth targets are created with
+pub const WINDOW_SAMPLE_COUNT: u32 = 4;
+
+/// The pipeline polygons, strokes, photos and the overlay are drawn with. Photos are
+/// bound at group 1, see ImageTextures.
+pub fn create_scene_pipeline(
+    device: &wgpu::Device,
+    camera_bind_group_layout: &wgpu::BindGroupLayout,
+    sample_count: u32,
+) -> wgpu::RenderPipeline {
+    // wgpu shares layouts with the same entries, so ImageTextures' bind groups fit
+    let image_bind_group_layout = create_image_bind_group_layout(device);
+
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Pipeline Layout"),
+        bind_group_layouts: &[camera_bind_group_layout, &image_bind_group_layout],
+        push_constant_ranges: &[],
+    });
+
+    let shader_module_vert_primary = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Primary Vert Shader"),
+        source: wgpu::ShaderSource::Wgsl(include_str!("../shaders/vert_primary.wgsl").into()),
+    });
+
+    let shader_module_frag_primary = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Primary Frag Shader"),
+        source: wgpu::ShaderSource::Wgsl(include_str!("../shaders/frag_primary.wgsl").into()),
+    });
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Common Vector Primary Render Pipeline"),
+        layout: Some(&pipeline_layout),
+        multiview: None,
+        cache: None,
+        vertex: wgpu::VertexState {
+            module: &shader_module_vert_primary,
+            entry_point: "vs_main",
+            buffers: &[Vertex::desc()],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &shader_module_frag_primary,
+            entry_point: "fs_main",
+            targets: &[Some(wgpu::ColorTargetState {
+                format: CANVAS_FORMAT,
+                blend: Some(wgpu::BlendState {
+                    color: wgpu::BlendComponent {
+                        src_factor: wgpu::BlendFactor::SrcAlpha,
+                        dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                        operation: wgpu::BlendOperation::Add,
+                    },
+                    alpha: wgpu::BlendComponent {
+                        src_factor: wgpu::BlendFactor::One,
+                        dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                        operation: wgpu::BlendOperation::Add,
+                    },
+                }),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        }),
+        primitive: wgpu::PrimitiveState {
+            conservative: false,
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: None,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            unclipped_depth: false,
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: DEPTH_FORMAT,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState {
+            count: sample_count,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+    })
+}
+
+/// A pipeline and depth target for drawing the canvas straight into the frame, for when
+/// multisampling is turned off. The window's own targets are always multisampled.
+pub struct SingleSampleCanvas {
+    pub pipeline: wgpu::RenderPipeline,
+    depth: Option<((u32, u32), wgpu::TextureView)>,
+}
+
+impl SingleSampleCanvas {
+    pub fn new(device: &wgpu::Device, camera_bind_group_layout: &wgpu::BindGroupLayout) -> Self {
+        Self {
+            pipeline: create_scene_pipeline(device, camera_bind_group_layout, 1),
+            depth: None,
+        }
+    }
+
+    /// Creates the depth target again whenever the window changes size
+    pub fn prepare(&mut self, device: &wgpu::Device, window_size: &WindowSize) {
+        let size = (window_size.width.max(1), window_size.height.max(1));
+
+        if self.depth.as_ref().map(|(depth_size, _)| *depth_size) != Some(size) {
+            let view = device
+                .create_texture(&wgpu::TextureDescriptor {
+                    label: Some("Single Sample Canvas Depth"),
+                    size: wgpu::Extent3d {
+                        width: size.0,
+                        height: size.1,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: DEPTH_FORMAT,
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                    view_formats: &[],
+                })
+                .create_view(&wgpu::TextureViewDescriptor::default());
+
+            self.depth = Some((size, view));
+        }
+    }
+
+    pub fn depth_view(&self) -> &wgpu::TextureView {
+        &self
+            .depth
+            .as_ref()
+            .expect("Couldn't get canvas depth, prepare wasn't called")
+            .1
+    }
+}
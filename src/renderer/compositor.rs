@@ -0,0 +1,648 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use common_vector::basic::WindowSize;
+use uuid::Uuid;
+
+use super::batch::{BatchItem, BatchItemId, SceneBatch};
+use super::images::ImageTextures;
+use crate::helpers::blending::{BlendMode, LayerBlend, LayerBlends};
+use crate::photo::adjustments::{AdjustmentStack, LayerAdjustments};
+
+// must match the swapchain and the primary pipeline
+const CANVAS_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Bgra8UnormSrgb;
+const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth24Plus;
+// dynamic uniform offsets are aligned to this on every backend
+const UNIFORM_STRIDE: u64 = 256;
+const UNIFORM_SIZE: u64 = 16;
+// points along each side of an adjustment lookup table
+const LUT_SIZE: u32 = 33;
+
+/// Layers drawn offscreen together, then composited onto the canvas in one step
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompositeRun {
+    // batch slots, in stacking order
+    pub slots: Range<usize>,
+    pub blend: LayerBlend,
+    // the layer's adjustments, applied to its colours before they're blended
+    pub adjustments: Option<(Uuid, AdjustmentStack)>,
+}
+
+/// Groups the scene into runs. None when every layer is plain, so the canvas can be drawn
+/// in a single pass with the pipeline's own blend state.
+pub fn composite_runs(
+    items: &[BatchItem],
+    blends: &LayerBlends,
+    adjustments: &LayerAdjustments,
+) -> Option<Vec<CompositeRun>> {
+    let layer_blend = |item: &BatchItem| match item.id {
+        BatchItemId::Polygon(id) | BatchItemId::StrokeLayer(id) | BatchItemId::Image(id) => {
+            blends.get(id)
+        }
+        // effects fade with their layer, but always blend normally
+        BatchItemId::Effect(id, _) => LayerBlend {
+            mode: BlendMode::Normal,
+            ..blends.get(id)
+        },
+        // the stroke being drawn isn't a layer yet
+        BatchItemId::Stroke(_) => LayerBlend::default(),
+    };
+    // Everything is drawn at the same depth, so an effect sharing a pass with its shape
+    // would hide it. Effects get a pass of their own, which starts with a clear depth buffer.
+    let is_effect = |item: &BatchItem| matches!(item.id, BatchItemId::Effect(..));
+    // effects keep the colours they were given
+    let layer_adjustments = |item: &BatchItem| match item.id {
+        BatchItemId::Polygon(id) | BatchItemId::StrokeLayer(id) | BatchItemId::Image(id) => {
+            adjustments.active(id).map(|stack| (id, stack))
+        }
+        _ => None,
+    };
+    let is_plain = |item: &BatchItem| {
+        layer_blend(item).is_plain() && !is_effect(item) && layer_adjustments(item).is_none()
+    };
+
+    if items.iter().all(is_plain) {
+        return None;
+    }
+
+    let mut runs: Vec<CompositeRun> = Vec::new();
+
+    for (index, item) in items.iter().enumerate() {
+        match runs.last_mut() {
+            // plain layers next to each other can share a pass
+            Some(run) if is_plain(item) && is_plain(&items[run.slots.start]) => {
+                run.slots.end = index + 1
+            }
+            _ => runs.push(CompositeRun {
+                slots: index..index + 1,
+                blend: layer_blend(item),
+                adjustments: layer_adjustments(item).map(|(id, stack)| (id, stack.clone())),
+            }),
+        }
+    }
+
+    Some(runs)
+}
+
+struct Targets {
+    size: (u32, u32),
+    // layers are drawn multisampled like the canvas, then resolved so they can be read.
+    // None when multisampling is off and layers are drawn straight into layer_view.
+    msaa_view: Option<wgpu::TextureView>,
+    depth_view: wgpu::TextureView,
+    layer_view: wgpu::TextureView,
+    // the canvas so far, read from one while the next step is written to the other.
+    // Also copied from for the scopes, see CanvasReadback.
+    backdrops: [wgpu::Texture; 2],
+    backdrop_views: [wgpu::TextureView; 2],
+}
+
+/// The offscreen path for blend modes, layer opacity and adjustments. Each run is drawn
+/// into its own texture, then blended onto the canvas so far by shaders/composite.wgsl.
+pub struct Compositor {
+    bind_group_layout: wgpu::BindGroupLayout,
+    lut_layout: wgpu::BindGroupLayout,
+    lut_sampler: wgpu::Sampler,
+    // by layer, with the stack each was baked from
+    luts: HashMap<Uuid, (AdjustmentStack, wgpu::BindGroup)>,
+    // bound for runs without adjustments, which the shader then skips
+    identity_lut: Option<wgpu::BindGroup>,
+    composite_pipeline: wgpu::RenderPipeline,
+    present_pipeline: wgpu::RenderPipeline,
+    uniform_buffer: wgpu::Buffer,
+    // in runs
+    uniform_capacity: u64,
+    targets: Option<Targets>,
+    // one per backdrop, rebuilt with the targets or the uniform buffer
+    bind_groups: Option<[wgpu::BindGroup; 2]>,
+    // which backdrop holds the finished canvas
+    current: usize,
+    // the canvas pass's, which the layer passes and present have to match
+    sample_count: u32,
+}
+
+impl Compositor {
+    pub fn new(device: &wgpu::Device, sample_count: u32) -> Self {
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Composite Bind Group Layout"),
+            entries: &[
+                texture_entry(0),
+                texture_entry(1),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: wgpu::BufferSize::new(UNIFORM_SIZE),
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let lut_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Composite Lut Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D3,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        // the tables are sampled between their points, which is where trilinear comes in
+        let lut_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let composite_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Composite Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout, &lut_layout],
+            push_constant_ranges: &[],
+        });
+        // present only copies, so it has no table to bind
+        let present_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Composite Present Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Composite Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../shaders/composite.wgsl").into()),
+        });
+
+        let pipeline = |label, layout, entry_point, depth_stencil, sample_count| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(layout),
+                multiview: None,
+                cache: None,
+                vertex: wgpu::VertexState {
+                    module: &shader_module,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader_module,
+                    entry_point,
+                    // the shader does the blending itself
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: CANVAS_FORMAT,
+                        blend: Some(wgpu::BlendState::REPLACE),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil,
+                multisample: wgpu::MultisampleState {
+                    count: sample_count,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+            })
+        };
+
+        let composite_pipeline = pipeline(
+            "Composite Pipeline",
+            &composite_layout,
+            "fs_composite",
+            None,
+            1,
+        );
+        // drawn inside the canvas pass, so it has to fit that pass's attachments
+        let present_pipeline = pipeline(
+            "Composite Present Pipeline",
+            &present_layout,
+            "fs_present",
+            Some(wgpu::DepthStencilState {
+                format: DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Always,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            sample_count,
+        );
+
+        let uniform_capacity = 8;
+
+        Self {
+            bind_group_layout,
+            lut_layout,
+            lut_sampler,
+            luts: HashMap::new(),
+            identity_lut: None,
+            composite_pipeline,
+            present_pipeline,
+            uniform_buffer: create_uniform_buffer(device, uniform_capacity),
+            uniform_capacity,
+            targets: None,
+            bind_groups: None,
+            current: 0,
+            sample_count,
+        }
+    }
+
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
+    fn prepare_targets(&mut self, device: &wgpu::Device, window_size: &WindowSize, runs: usize) {
+        let size = (window_size.width.max(1), window_size.height.max(1));
+
+        if self.targets.as_ref().map(|targets| targets.size) != Some(size) {
+            let create = |label, format, sample_count, usage| {
+                device.create_texture(&wgpu::TextureDescriptor {
+                    label: Some(label),
+                    size: wgpu::Extent3d {
+                        width: size.0,
+                        height: size.1,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count,
+                    dimension: wgpu::TextureDimension::D2,
+                    format,
+                    usage,
+                    view_formats: &[],
+                })
+            };
+            let texture = |label, format, sample_count, usage| {
+                create(label, format, sample_count, usage)
+                    .create_view(&wgpu::TextureViewDescriptor::default())
+            };
+            let readable =
+                wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING;
+            let backdrop = |label| {
+                create(
+                    label,
+                    CANVAS_FORMAT,
+                    1,
+                    readable | wgpu::TextureUsages::COPY_SRC,
+                )
+            };
+            let backdrops = [
+                backdrop("Composite Backdrop A"),
+                backdrop("Composite Backdrop B"),
+            ];
+
+            self.targets = Some(Targets {
+                size,
+                msaa_view: (self.sample_count > 1).then(|| {
+                    texture(
+                        "Composite Layer Multisampled",
+                        CANVAS_FORMAT,
+                        self.sample_count,
+                        wgpu::TextureUsages::RENDER_ATTACHMENT,
+                    )
+                }),
+                depth_view: texture(
+                    "Composite Layer Depth",
+                    DEPTH_FORMAT,
+                    self.sample_count,
+                    wgpu::TextureUsages::RENDER_ATTACHMENT,
+                ),
+                layer_view: texture("Composite Layer", CANVAS_FORMAT, 1, readable),
+                backdrop_views: [
+                    backdrops[0].create_view(&wgpu::TextureViewDescriptor::default()),
+                    backdrops[1].create_view(&wgpu::TextureViewDescriptor::default()),
+                ],
+                backdrops,
+            });
+            self.bind_groups = None;
+        }
+
+        if runs as u64 > self.uniform_capacity {
+            self.uniform_capacity = (runs as u64).next_power_of_two();
+            self.uniform_buffer = create_uniform_buffer(device, self.uniform_capacity);
+            self.bind_groups = None;
+        }
+
+        if self.bind_groups.is_none() {
+            let targets = self
+                .targets
+                .as_ref()
+                .expect("Couldn't get composite targets");
+
+            let bind_group = |backdrop: &wgpu::TextureView| {
+                device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("Composite Bind Group"),
+                    layout: &self.bind_group_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::TextureView(backdrop),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::TextureView(&targets.layer_view),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                                buffer: &self.uniform_buffer,
+                                offset: 0,
+                                size: wgpu::BufferSize::new(UNIFORM_SIZE),
+                            }),
+                        },
+                    ],
+                })
+            };
+
+            self.bind_groups = Some([
+                bind_group(&targets.backdrop_views[0]),
+                bind_group(&targets.backdrop_views[1]),
+            ]);
+        }
+    }
+
+    // Bakes the tables for the runs' adjustments, keeping the ones that haven't changed
+    fn prepare_luts(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, runs: &[CompositeRun]) {
+        self.luts.retain(|layer_id, _| {
+            runs.iter()
+                .any(|run| matches!(&run.adjustments, Some((id, _)) if id == layer_id))
+        });
+
+        for (layer_id, stack) in runs.iter().filter_map(|run| run.adjustments.as_ref()) {
+            if self.luts.get(layer_id).map(|(baked, _)| baked) == Some(stack) {
+                continue;
+            }
+
+            let bind_group = create_lut(
+                device,
+                queue,
+                &self.lut_layout,
+                &self.lut_sampler,
+                &stack.lut(LUT_SIZE as usize),
+                LUT_SIZE,
+            );
+            self.luts.insert(*layer_id, (stack.clone(), bind_group));
+        }
+
+        if self.identity_lut.is_none() {
+            // two points a side is exact for a table that changes nothing
+            self.identity_lut = Some(create_lut(
+                device,
+                queue,
+                &self.lut_layout,
+                &self.lut_sampler,
+                &AdjustmentStack::new().lut(2),
+                2,
+            ));
+        }
+    }
+
+    /// Draws and composites every run offscreen. Has to be encoded before the canvas pass,
+    /// which then only needs present.
+    pub fn render(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        pipeline: &wgpu::RenderPipeline,
+        camera_bind_group: &wgpu::BindGroup,
+        batch: &SceneBatch,
+        textures: &ImageTextures,
+        runs: &[CompositeRun],
+        window_size: &WindowSize,
+        background: wgpu::Color,
+    ) {
+        self.prepare_targets(device, window_size, runs.len());
+        self.prepare_luts(device, queue, runs);
+
+        let targets = self
+            .targets
+            .as_ref()
+            .expect("Couldn't get composite targets");
+        let bind_groups = self
+            .bind_groups
+            .as_ref()
+            .expect("Couldn't get composite bind groups");
+        let identity_lut = self
+            .identity_lut
+            .as_ref()
+            .expect("Couldn't get identity lut");
+
+        for (index, run) in runs.iter().enumerate() {
+            let uniform = [
+                run.blend.mode.shader_index(),
+                run.blend.opacity.to_bits(),
+                run.adjustments.is_some() as u32,
+                0,
+            ];
+            queue.write_buffer(
+                &self.uniform_buffer,
+                index as u64 * UNIFORM_STRIDE,
+                bytemuck::cast_slice(&uniform),
+            );
+        }
+
+        // same as the canvas pass clears to
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Composite Clear Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &targets.backdrop_views[0],
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(background),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        let mut current = 0;
+
+        for (index, run) in runs.iter().enumerate() {
+            {
+                let mut layer_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("Composite Layer Pass"),
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: targets.msaa_view.as_ref().unwrap_or(&targets.layer_view),
+                        resolve_target: targets.msaa_view.as_ref().map(|_| &targets.layer_view),
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                            // only what's resolved is kept, unless there's nothing to resolve
+                            store: if targets.msaa_view.is_some() {
+                                wgpu::StoreOp::Discard
+                            } else {
+                                wgpu::StoreOp::Store
+                            },
+                        },
+                    })],
+                    depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                        view: &targets.depth_view,
+                        depth_ops: Some(wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(1.0),
+                            store: wgpu::StoreOp::Discard,
+                        }),
+                        stencil_ops: None,
+                    }),
+                    timestamp_writes: None,
+                    occlusion_query_set: None,
+                });
+
+                layer_pass.set_pipeline(pipeline);
+                layer_pass.set_bind_group(0, camera_bind_group, &[]);
+                batch.draw_slots(&mut layer_pass, textures, run.slots.clone());
+            }
+
+            // every pixel is written, so nothing needs loading
+            let mut composite_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Composite Blend Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &targets.backdrop_views[1 - current],
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+
+            composite_pass.set_pipeline(&self.composite_pipeline);
+            composite_pass.set_bind_group(
+                0,
+                &bind_groups[current],
+                &[(index as u64 * UNIFORM_STRIDE) as u32],
+            );
+            let lut = run
+                .adjustments
+                .as_ref()
+                .and_then(|(layer_id, _)| self.luts.get(layer_id))
+                .map_or(identity_lut, |(_, bind_group)| bind_group);
+            composite_pass.set_bind_group(1, lut, &[]);
+            composite_pass.draw(0..3, 0..1);
+
+            current = 1 - current;
+        }
+
+        self.current = current;
+    }
+
+    /// The composited canvas from the last render, None before the first
+    pub fn canvas_texture(&self) -> Option<&wgpu::Texture> {
+        self.targets
+            .as_ref()
+            .map(|targets| &targets.backdrops[self.current])
+    }
+
+    /// Copies the composited canvas into the canvas pass
+    pub fn present(&self, render_pass: &mut wgpu::RenderPass) {
+        let Some(bind_groups) = &self.bind_groups else {
+            return;
+        };
+
+        render_pass.set_pipeline(&self.present_pipeline);
+        render_pass.set_bind_group(0, &bind_groups[self.current], &[0]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+fn create_uniform_buffer(device: &wgpu::Device, capacity: u64) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Composite Uniform Buffer"),
+        size: capacity * UNIFORM_STRIDE,
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+// Uploads a size³ table laid out like AdjustmentStack::lut
+fn create_lut(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    layout: &wgpu::BindGroupLayout,
+    sampler: &wgpu::Sampler,
+    table: &[u8],
+    size: u32,
+) -> wgpu::BindGroup {
+    let extent = wgpu::Extent3d {
+        width: size,
+        height: size,
+        depth_or_array_layers: size,
+    };
+
+    // the table is in encoded values already, so it's stored as plain unorm
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Composite Lut"),
+        size: extent,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D3,
+        format: wgpu::TextureFormat::Rgba8Unorm,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+
+    queue.write_texture(
+        wgpu::ImageCopyTexture {
+            texture: &texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
+        },
+        table,
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(size * 4),
+            rows_per_image: Some(size),
+        },
+        extent,
+    );
+
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Composite Lut Bind Group"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+        ],
+    })
+}
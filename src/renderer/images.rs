@@ -0,0 +1,340 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use common_vector::basic::{Point, WindowSize};
+use common_vector::guideline::point_to_ndc;
+use uuid::Uuid;
+use wgpu::util::DeviceExt;
+
+use crate::helpers::masks::{LayerMasks, RasterMask};
+use crate::photo::images::ImageLayers;
+
+/// What the scene pipeline samples at group 1: a photo's pixels and the layer's painted
+/// mask, with where the mask sits. Every shape samples it, so anything that isn't a photo
+/// gets a single white texel and anything unmasked a mask that's turned off.
+pub fn create_image_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+        },
+        count: None,
+    };
+
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("Image Bind Group Layout"),
+        entries: &[
+            texture_entry(0),
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+            texture_entry(2),
+            wgpu::BindGroupLayoutEntry {
+                binding: 3,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ],
+    })
+}
+
+// The painted mask's rectangle as left, top, right, bottom in the normalized device
+// coordinates vertices are stored in
+fn mask_rect(mask: &RasterMask, window_size: &WindowSize) -> [f32; 4] {
+    let corner = |x: i32, y: i32| {
+        let ndc = point_to_ndc(
+            Point {
+                x: x as f32,
+                y: y as f32,
+            },
+            window_size,
+        );
+        [ndc.x, ndc.y]
+    };
+    let [left, top] = corner(mask.origin[0], mask.origin[1]);
+    let [right, bottom] = corner(
+        mask.origin[0] + mask.width as i32,
+        mask.origin[1] + mask.height as i32,
+    );
+
+    [left, top, right, bottom]
+}
+
+// what a layer was last bound with, so only what changed is uploaded again
+struct LayerTextures {
+    image: Option<(Arc<Vec<u8>>, wgpu::TextureView)>,
+    mask: Option<(Arc<Vec<u8>>, [f32; 4], wgpu::TextureView)>,
+    bind_group: wgpu::BindGroup,
+}
+
+/// A texture for each photo in the scene and for each painted mask, uploaded again only
+/// when the pixels or levels are replaced
+pub struct ImageTextures {
+    layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    blank_image: wgpu::TextureView,
+    blank_mask: wgpu::TextureView,
+    blank: wgpu::BindGroup,
+    // by layer
+    layers: HashMap<Uuid, LayerTextures>,
+}
+
+impl ImageTextures {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let layout = create_image_bind_group_layout(device);
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Image Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let blank_image = create_texture(
+            device,
+            queue,
+            wgpu::TextureFormat::Rgba8UnormSrgb,
+            &[255; 4],
+            1,
+            1,
+        );
+        let blank_mask = create_texture(device, queue, wgpu::TextureFormat::R8Unorm, &[255], 1, 1);
+        let blank = create_bind_group(device, &layout, &sampler, &blank_image, &blank_mask, None);
+
+        Self {
+            layout,
+            sampler,
+            blank_image,
+            blank_mask,
+            blank,
+            layers: HashMap::new(),
+        }
+    }
+
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        images: &ImageLayers,
+        masks: &LayerMasks,
+        window_size: &WindowSize,
+    ) {
+        let photo = |layer_id: Uuid| {
+            images
+                .get(layer_id)
+                .filter(|layer| !layer.pixels.is_empty())
+        };
+
+        self.layers
+            .retain(|id, _| photo(*id).is_some() || masks.painted(*id).is_some());
+
+        let mut layer_ids: Vec<Uuid> = images
+            .iter()
+            .map(|layer| layer.id)
+            .chain(masks.iter().map(|(layer_id, _)| layer_id))
+            .collect();
+        layer_ids.sort();
+        layer_ids.dedup();
+
+        for layer_id in layer_ids {
+            let photo = photo(layer_id);
+            let painted = masks.painted(layer_id);
+            if photo.is_none() && painted.is_none() {
+                continue;
+            }
+
+            let unchanged = self.layers.get(&layer_id).is_some_and(|textures| {
+                let image_matches = match (&textures.image, photo) {
+                    (Some((pixels, _)), Some(layer)) => Arc::ptr_eq(pixels, &layer.pixels),
+                    (None, None) => true,
+                    _ => false,
+                };
+                let mask_matches = match (&textures.mask, painted) {
+                    (Some((levels, rect, _)), Some(painted)) => {
+                        Arc::ptr_eq(levels, &painted.levels)
+                            && *rect == mask_rect(painted, window_size)
+                    }
+                    (None, None) => true,
+                    _ => false,
+                };
+
+                image_matches && mask_matches
+            });
+            if unchanged {
+                continue;
+            }
+
+            let previous = self.layers.remove(&layer_id);
+            let (previous_image, previous_mask) = match previous {
+                Some(textures) => (textures.image, textures.mask),
+                None => (None, None),
+            };
+
+            let image = photo.map(|layer| match previous_image {
+                Some((pixels, view)) if Arc::ptr_eq(&pixels, &layer.pixels) => (pixels, view),
+                _ => (
+                    Arc::clone(&layer.pixels),
+                    create_texture(
+                        device,
+                        queue,
+                        // photos are stored encoded, sampling decodes them like the canvas expects
+                        wgpu::TextureFormat::Rgba8UnormSrgb,
+                        &layer.pixels,
+                        layer.width,
+                        layer.height,
+                    ),
+                ),
+            });
+            let mask = painted.map(|painted| {
+                let rect = mask_rect(painted, window_size);
+                match previous_mask {
+                    Some((levels, _, view)) if Arc::ptr_eq(&levels, &painted.levels) => {
+                        (levels, rect, view)
+                    }
+                    _ => (
+                        Arc::clone(&painted.levels),
+                        rect,
+                        create_texture(
+                            device,
+                            queue,
+                            wgpu::TextureFormat::R8Unorm,
+                            &painted.levels,
+                            painted.width,
+                            painted.height,
+                        ),
+                    ),
+                }
+            });
+
+            let bind_group = create_bind_group(
+                device,
+                &self.layout,
+                &self.sampler,
+                image.as_ref().map_or(&self.blank_image, |(_, view)| view),
+                mask.as_ref().map_or(&self.blank_mask, |(_, _, view)| view),
+                mask.as_ref().map(|(_, rect, _)| *rect),
+            );
+
+            self.layers.insert(
+                layer_id,
+                LayerTextures {
+                    image,
+                    mask,
+                    bind_group,
+                },
+            );
+        }
+    }
+
+    /// The layer, if it has textures of its own. Layers without any share the blank ones.
+    pub fn key(&self, layer_id: Option<Uuid>) -> Option<Uuid> {
+        layer_id.filter(|id| self.layers.contains_key(id))
+    }
+
+    /// The layer's textures, or the blank ones if it has none
+    pub fn bind_group(&self, layer_id: Option<Uuid>) -> &wgpu::BindGroup {
+        layer_id
+            .and_then(|id| self.layers.get(&id))
+            .map(|textures| &textures.bind_group)
+            .unwrap_or(&self.blank)
+    }
+}
+
+fn create_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    format: wgpu::TextureFormat,
+    pixels: &[u8],
+    width: u32,
+    height: u32,
+) -> wgpu::TextureView {
+    let size = wgpu::Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+    };
+
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Image Texture"),
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+
+    let bytes_per_pixel = format.block_copy_size(None).unwrap_or(4);
+    queue.write_texture(
+        wgpu::ImageCopyTexture {
+            texture: &texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
+        },
+        pixels,
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(width * bytes_per_pixel),
+            rows_per_image: Some(height),
+        },
+        size,
+    );
+
+    texture.create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+// None for the mask rectangle turns the mask off
+fn create_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    sampler: &wgpu::Sampler,
+    image: &wgpu::TextureView,
+    mask: &wgpu::TextureView,
+    mask_rect: Option<[f32; 4]>,
+) -> wgpu::BindGroup {
+    let [left, top, right, bottom] = mask_rect.unwrap_or_default();
+    let enabled = if mask_rect.is_some() { 1.0 } else { 0.0 };
+    let uniform: [f32; 8] = [left, top, right, bottom, enabled, 0.0, 0.0, 0.0];
+
+    let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Mask Uniform Buffer"),
+        contents: bytemuck::cast_slice(&uniform),
+        usage: wgpu::BufferUsages::UNIFORM,
+    });
+
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Image Bind Group"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(image),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(mask),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: buffer.as_entire_binding(),
+            },
+        ],
+    })
+}
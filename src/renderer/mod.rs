@@ -0,0 +1,6 @@
+pub mod batch;
+pub mod canvas;
+pub mod compositor;
+pub mod images;
+pub mod overlay;
+pub mod readback;
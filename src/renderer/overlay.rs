@@ -0,0 +1,271 @@
+use common_vector::basic::{rgb_to_wgpu, Point, WindowSize};
+use common_vector::editor::Editor;
+use common_vector::guideline::point_to_ndc;
+use common_vector::vertex::Vertex;
+
+// in screen pixels, converted to ndc against the current window size and divided by the
+// camera's zoom, which the shader applies to the overlay like everything else
+const DOT_RADIUS: f32 = 5.0;
+const GUIDE_LINE_THICKNESS: f32 = 1.0;
+const ARTBOARD_BORDER_THICKNESS: f32 = 1.0;
+
+// room for the top left and hover dots, an artboard's four sides and a handful of guide lines
+const INITIAL_QUADS: u64 = 16;
+
+const VERTICES_PER_QUAD: u64 = 4;
+const INDICES_PER_QUAD: u64 = 6;
+
+/// Everything the overlay draws, compared each frame to decide whether to rewrite the buffers
+#[derive(Clone, PartialEq)]
+struct OverlayState {
+    window_size: (u32, u32),
+    zoom: f32,
+    top_left: (f32, f32),
+    hover_point: Option<(f32, f32)>,
+    guide_lines: Vec<((f32, f32), (f32, f32))>,
+    crop_guides: Vec<([f32; 2], [f32; 2])>,
+    artboard_bounds: Vec<([f32; 2], [f32; 2])>,
+}
+
+impl OverlayState {
+    fn from_editor(
+        editor: &Editor,
+        crop_guides: Vec<([f32; 2], [f32; 2])>,
+        artboard_bounds: Vec<([f32; 2], [f32; 2])>,
+        window_size: &WindowSize,
+    ) -> Self {
+        Self {
+            window_size: (window_size.width, window_size.height),
+            zoom: editor.camera.map_or(1.0, |camera| camera.zoom),
+            top_left: (editor.last_top_left.x, editor.last_top_left.y),
+            hover_point: editor
+                .hover_point
+                .as_ref()
+                .map(|edge_point| (edge_point.point.x, edge_point.point.y)),
+            guide_lines: editor
+                .guide_lines
+                .iter()
+                .map(|line| ((line.start.x, line.start.y), (line.end.x, line.end.y)))
+                .collect(),
+            crop_guides,
+            artboard_bounds,
+        }
+    }
+}
+
+/// Owns the vertex and index buffers for dots, guide lines, the crop guides and the
+/// artboard borders, reused across frames
+pub struct OverlayRenderer {
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    quad_capacity: u64,
+    index_count: u32,
+    state: Option<OverlayState>,
+}
+
+impl OverlayRenderer {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let (vertex_buffer, index_buffer) = create_buffers(device, INITIAL_QUADS);
+
+        Self {
+            vertex_buffer,
+            index_buffer,
+            quad_capacity: INITIAL_QUADS,
+            index_count: 0,
+            state: None,
+        }
+    }
+
+    /// Rewrites the buffers only when the overlay has changed since the last frame
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        editor: &Editor,
+        crop_guides: Vec<([f32; 2], [f32; 2])>,
+        artboard_bounds: Vec<([f32; 2], [f32; 2])>,
+        window_size: &WindowSize,
+    ) {
+        let state = OverlayState::from_editor(editor, crop_guides, artboard_bounds, window_size);
+
+        if self.state.as_ref() == Some(&state) {
+            return;
+        }
+
+        let color = rgb_to_wgpu(47, 131, 222, 1.0);
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+
+        for ([left, top], [right, bottom]) in &state.artboard_bounds {
+            let corners = [
+                (*left, *top),
+                (*right, *top),
+                (*right, *bottom),
+                (*left, *bottom),
+            ];
+
+            for side in 0..corners.len() {
+                push_line(
+                    &mut vertices,
+                    &mut indices,
+                    corners[side],
+                    corners[(side + 1) % corners.len()],
+                    ARTBOARD_BORDER_THICKNESS / state.zoom,
+                    rgb_to_wgpu(150, 150, 150, 1.0),
+                    window_size,
+                );
+            }
+        }
+
+        let mut dots = vec![state.top_left];
+        dots.extend(state.hover_point);
+
+        for (x, y) in dots {
+            let center = point_to_ndc(Point { x, y }, window_size);
+            // ndc spans 2.0 across the window
+            let half_width = 2.0 * DOT_RADIUS / state.zoom / window_size.width as f32;
+            let half_height = 2.0 * DOT_RADIUS / state.zoom / window_size.height as f32;
+
+            push_quad(
+                &mut vertices,
+                &mut indices,
+                [
+                    [center.x - half_width, center.y - half_height],
+                    [center.x + half_width, center.y - half_height],
+                    [center.x + half_width, center.y + half_height],
+                    [center.x - half_width, center.y + half_height],
+                ],
+                color,
+            );
+        }
+
+        for (start, end) in &state.guide_lines {
+            push_line(
+                &mut vertices,
+                &mut indices,
+                *start,
+                *end,
+                GUIDE_LINE_THICKNESS / state.zoom,
+                color,
+                window_size,
+            );
+        }
+
+        for ([start_x, start_y], [end_x, end_y]) in &state.crop_guides {
+            push_line(
+                &mut vertices,
+                &mut indices,
+                (*start_x, *start_y),
+                (*end_x, *end_y),
+                GUIDE_LINE_THICKNESS / state.zoom,
+                rgb_to_wgpu(255, 255, 255, 1.0),
+                window_size,
+            );
+        }
+
+        let quad_count = vertices.len() as u64 / VERTICES_PER_QUAD;
+
+        if quad_count > self.quad_capacity {
+            self.quad_capacity = quad_count.next_power_of_two();
+
+            let (vertex_buffer, index_buffer) = create_buffers(device, self.quad_capacity);
+            self.vertex_buffer = vertex_buffer;
+            self.index_buffer = index_buffer;
+        }
+
+        queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+        queue.write_buffer(&self.index_buffer, 0, bytemuck::cast_slice(&indices));
+
+        self.index_count = indices.len() as u32;
+        self.state = Some(state);
+    }
+
+    pub fn draw(&self, render_pass: &mut wgpu::RenderPass) {
+        if self.index_count == 0 {
+            return;
+        }
+
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        render_pass.draw_indexed(0..self.index_count, 0, 0..1);
+    }
+}
+
+fn create_buffers(device: &wgpu::Device, quad_capacity: u64) -> (wgpu::Buffer, wgpu::Buffer) {
+    let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Overlay Vertex Buffer"),
+        size: quad_capacity * VERTICES_PER_QUAD * std::mem::size_of::<Vertex>() as u64,
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    let index_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Overlay Index Buffer"),
+        size: quad_capacity * INDICES_PER_QUAD * std::mem::size_of::<u32>() as u64,
+        usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    (vertex_buffer, index_buffer)
+}
+
+fn push_line(
+    vertices: &mut Vec<Vertex>,
+    indices: &mut Vec<u32>,
+    (start_x, start_y): (f32, f32),
+    (end_x, end_y): (f32, f32),
+    thickness: f32,
+    color: [f32; 4],
+    window_size: &WindowSize,
+) {
+    let start = point_to_ndc(
+        Point {
+            x: start_x,
+            y: start_y,
+        },
+        window_size,
+    );
+    let end = point_to_ndc(Point { x: end_x, y: end_y }, window_size);
+
+    let (dx, dy) = (end.x - start.x, end.y - start.y);
+    let length = (dx * dx + dy * dy).sqrt();
+
+    if length == 0.0 {
+        return;
+    }
+
+    // perpendicular offset, scaled separately per axis since ndc isn't square
+    let offset_x = -dy / length * thickness / window_size.width as f32;
+    let offset_y = dx / length * thickness / window_size.height as f32;
+
+    push_quad(
+        vertices,
+        indices,
+        [
+            [start.x - offset_x, start.y - offset_y],
+            [end.x - offset_x, end.y - offset_y],
+            [end.x + offset_x, end.y + offset_y],
+            [start.x + offset_x, start.y + offset_y],
+        ],
+        color,
+    );
+}
+
+fn push_quad(
+    vertices: &mut Vec<Vertex>,
+    indices: &mut Vec<u32>,
+    corners: [[f32; 2]; 4],
+    color: [f32; 4],
+) {
+    let base = vertices.len() as u32;
+
+    for [x, y] in corners {
+        vertices.push(Vertex {
+            position: [x, y, 0.0],
+            tex_coords: [0.0, 0.0],
+            color,
+        });
+    }
+
+    indices.extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+}
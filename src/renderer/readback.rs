@@ -0,0 +1,304 @@
+use std::sync::{Arc, Mutex};
+
+use common_vector::basic::WindowSize;
+
+use crate::helpers::locking::LockRecover;
+
+use super::batch::SceneBatch;
+use super::canvas::create_scene_pipeline;
+use super::images::ImageTextures;
+
+// must match the swapchain
+const CANVAS_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Bgra8UnormSrgb;
+const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth24Plus;
+// the scopes only count levels, so a scene drawn again for them can be smaller
+const SCENE_DOWNSCALE: u32 = 2;
+
+type MapResult = Arc<Mutex<Option<Result<(), wgpu::BufferAsyncError>>>>;
+
+enum ReadbackState {
+    Idle,
+    // encoded in a frame that hasn't been submitted yet, so it's mapped in the next one
+    Copied,
+    Mapping(MapResult),
+}
+
+struct ReadbackBuffer {
+    size: (u32, u32),
+    // rows are padded out to what copies have to be aligned to
+    padded_row: u32,
+    buffer: wgpu::Buffer,
+}
+
+struct SceneTarget {
+    size: (u32, u32),
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    depth_view: wgpu::TextureView,
+}
+
+/// Copies what the canvas shows back from the GPU for the scopes. A copy takes a couple of
+/// frames to come back and only one is in flight at a time, so while the canvas keeps
+/// changing it's read at most every few frames.
+pub struct CanvasReadback {
+    pipeline: wgpu::RenderPipeline,
+    // for frames that weren't composited, which are drawn straight into the window
+    target: Option<SceneTarget>,
+    buffer: Option<ReadbackBuffer>,
+    state: ReadbackState,
+    // the invalidator's generation when the last copy was made
+    copied: Option<u64>,
+}
+
+impl CanvasReadback {
+    pub fn new(device: &wgpu::Device, camera_bind_group_layout: &wgpu::BindGroupLayout) -> Self {
+        Self {
+            pipeline: create_scene_pipeline(device, camera_bind_group_layout, 1),
+            target: None,
+            buffer: None,
+            state: ReadbackState::Idle,
+            copied: None,
+        }
+    }
+
+    /// A copy is waiting to come back, which needs frames of its own
+    pub fn in_flight(&self) -> bool {
+        !matches!(self.state, ReadbackState::Idle)
+    }
+
+    /// Whether the canvas has changed since the last copy, and there's none in flight
+    pub fn wants_copy(&self, generation: u64) -> bool {
+        !self.in_flight() && self.copied != Some(generation)
+    }
+
+    /// Moves the copy in flight along, once a frame. Returns the canvas as RGBA rows with
+    /// its width and height once it has come back.
+    pub fn poll(&mut self, device: &wgpu::Device) -> Option<(Vec<u8>, u32, u32)> {
+        let buffer = self.buffer.as_ref()?;
+
+        match &self.state {
+            ReadbackState::Idle => None,
+            ReadbackState::Copied => {
+                let result: MapResult = Arc::new(Mutex::new(None));
+                buffer.buffer.slice(..).map_async(wgpu::MapMode::Read, {
+                    let result = Arc::clone(&result);
+                    move |mapped| *result.lock_or_recover() = Some(mapped)
+                });
+                self.state = ReadbackState::Mapping(result);
+                None
+            }
+            ReadbackState::Mapping(result) => {
+                device.poll(wgpu::Maintain::Poll);
+
+                let mapped = result.lock_or_recover().take()?;
+                self.state = ReadbackState::Idle;
+                // a lost copy is made again the next time the canvas changes
+                if mapped.is_err() {
+                    return None;
+                }
+
+                let (width, height) = buffer.size;
+                let pixels = {
+                    let padded = buffer.buffer.slice(..).get_mapped_range();
+                    bgra_rows_to_rgba(&padded, width, height, buffer.padded_row)
+                };
+                buffer.buffer.unmap();
+
+                Some((pixels, width, height))
+            }
+        }
+    }
+
+    /// Copies a finished canvas, e.g. the compositor's
+    pub fn copy_texture(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+        generation: u64,
+    ) {
+        let size = (texture.width(), texture.height());
+        let buffer = self.prepare_buffer(device, size);
+
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer.buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(buffer.padded_row),
+                    rows_per_image: Some(size.1),
+                },
+            },
+            wgpu::Extent3d {
+                width: size.0,
+                height: size.1,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        self.state = ReadbackState::Copied;
+        self.copied = Some(generation);
+    }
+
+    /// Draws the scene again offscreen and copies that, for frames that weren't composited
+    pub fn copy_scene(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        camera_bind_group: &wgpu::BindGroup,
+        batch: &SceneBatch,
+        textures: &ImageTextures,
+        window_size: &WindowSize,
+        background: wgpu::Color,
+        generation: u64,
+    ) {
+        self.prepare_target(device, window_size);
+        // taken out so the pass and the copy can borrow the rest
+        let target = self.target.take().expect("Couldn't get readback target");
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Readback Scene Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &target.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(background),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &target.depth_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Discard,
+                    }),
+                    stencil_ops: None,
+                }),
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+
+            render_pass.set_pipeline(&self.pipeline);
+            render_pass.set_bind_group(0, camera_bind_group, &[]);
+            batch.draw(&mut render_pass, textures);
+        }
+
+        self.copy_texture(device, encoder, &target.texture, generation);
+        self.target = Some(target);
+    }
+
+    fn prepare_target(&mut self, device: &wgpu::Device, window_size: &WindowSize) {
+        let size = (
+            (window_size.width / SCENE_DOWNSCALE).max(1),
+            (window_size.height / SCENE_DOWNSCALE).max(1),
+        );
+
+        if self.target.as_ref().map(|target| target.size) != Some(size) {
+            let create = |label, format, usage| {
+                device.create_texture(&wgpu::TextureDescriptor {
+                    label: Some(label),
+                    size: wgpu::Extent3d {
+                        width: size.0,
+                        height: size.1,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format,
+                    usage,
+                    view_formats: &[],
+                })
+            };
+
+            let texture = create(
+                "Readback Scene",
+                CANVAS_FORMAT,
+                wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            );
+            let depth_view = create(
+                "Readback Scene Depth",
+                DEPTH_FORMAT,
+                wgpu::TextureUsages::RENDER_ATTACHMENT,
+            )
+            .create_view(&wgpu::TextureViewDescriptor::default());
+
+            self.target = Some(SceneTarget {
+                size,
+                view: texture.create_view(&wgpu::TextureViewDescriptor::default()),
+                texture,
+                depth_view,
+            });
+        }
+    }
+
+    fn prepare_buffer(&mut self, device: &wgpu::Device, size: (u32, u32)) -> &ReadbackBuffer {
+        if self.buffer.as_ref().map(|buffer| buffer.size) != Some(size) {
+            let padded_row = padded_row(size.0);
+
+            self.buffer = Some(ReadbackBuffer {
+                size,
+                padded_row,
+                buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Readback Buffer"),
+                    size: padded_row as u64 * size.1 as u64,
+                    usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                    mapped_at_creation: false,
+                }),
+            });
+        }
+
+        self.buffer.as_ref().expect("Couldn't get readback buffer")
+    }
+}
+
+fn padded_row(width: u32) -> u32 {
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    (width * 4).div_ceil(align) * align
+}
+
+// drops the padding off each row and puts the channels in the order the scopes read
+fn bgra_rows_to_rgba(padded: &[u8], width: u32, height: u32, padded_row: u32) -> Vec<u8> {
+    let row = width as usize * 4;
+    let mut pixels = Vec::with_capacity(row * height as usize);
+
+    for y in 0..height as usize {
+        let start = y * padded_row as usize;
+        for pixel in padded[start..start + row].chunks_exact(4) {
+            pixels.extend_from_slice(&[pixel[2], pixel[1], pixel[0], pixel[3]]);
+        }
+    }
+
+    pixels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rows_are_padded_to_the_copy_alignment() {
+        assert_eq!(padded_row(1), 256);
+        assert_eq!(padded_row(64), 256);
+        assert_eq!(padded_row(65), 512);
+    }
+
+    #[test]
+    fn padding_is_dropped_and_channels_swapped() {
+        let mut padded = vec![0; 512];
+        padded[..8].copy_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]);
+        padded[256..264].copy_from_slice(&[9, 10, 11, 12, 13, 14, 15, 16]);
+
+        assert_eq!(
+            bgra_rows_to_rgba(&padded, 2, 2, 256),
+            vec![3, 2, 1, 4, 7, 6, 5, 8, 11, 10, 9, 12, 15, 14, 13, 16]
+        );
+    }
+}
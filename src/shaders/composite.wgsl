@@ -0,0 +1,129 @@
+// Composites one offscreen layer onto the backdrop with the layer's blend mode and opacity.
+// The math follows LayerBlend::composite in helpers/blending.rs, keep the two in step.
+// Adjustments come baked into a table by AdjustmentStack::lut in photo/adjustments.rs.
+
+struct CompositeUniform {
+    mode: u32,
+    opacity: f32,
+    // whether the layer has adjustments to look up
+    adjusted: u32,
+};
+
+@group(0) @binding(0)
+var backdrop: texture_2d<f32>;
+@group(0) @binding(1)
+var layer: texture_2d<f32>;
+@group(0) @binding(2)
+var<uniform> params: CompositeUniform;
+
+@group(1) @binding(0)
+var lut: texture_3d<f32>;
+@group(1) @binding(1)
+var lut_sampler: sampler;
+
+const LUM: vec3<f32> = vec3<f32>(0.3, 0.59, 0.11);
+
+// one triangle that covers the whole target
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+fn screen(b: vec3<f32>, s: vec3<f32>) -> vec3<f32> {
+    return b + s - b * s;
+}
+
+fn lum(c: vec3<f32>) -> f32 {
+    return dot(c, LUM);
+}
+
+fn set_lum(color: vec3<f32>, l: f32) -> vec3<f32> {
+    let c = color + (l - lum(color));
+    let new_l = lum(c);
+    let low = min(min(c.r, c.g), c.b);
+    let high = max(max(c.r, c.g), c.b);
+
+    if low < 0.0 {
+        return new_l + (c - new_l) * new_l / max(new_l - low, 1e-7);
+    }
+    if high > 1.0 {
+        return new_l + (c - new_l) * (1.0 - new_l) / max(high - new_l, 1e-7);
+    }
+    return c;
+}
+
+fn to_srgb(color: vec3<f32>) -> vec3<f32> {
+    let c = max(color, vec3<f32>(0.0));
+    let curve = 1.055 * pow(c, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(curve, c * 12.92, c <= vec3<f32>(0.0031308));
+}
+
+fn to_linear(c: vec3<f32>) -> vec3<f32> {
+    let curve = pow((c + 0.055) / 1.055, vec3<f32>(2.4));
+    return select(curve, c / 12.92, c <= vec3<f32>(0.04045));
+}
+
+// The table is indexed by encoded colour, which the layer texture decodes on load
+fn adjust(c: vec3<f32>) -> vec3<f32> {
+    let size = f32(textureDimensions(lut).x);
+    // points sit at texel centres
+    let encoded = to_srgb(clamp(c, vec3<f32>(0.0), vec3<f32>(1.0)));
+    let coords = encoded * (size - 1.0) / size + 0.5 / size;
+    return to_linear(textureSampleLevel(lut, lut_sampler, coords, 0.0).rgb);
+}
+
+fn blend(b: vec3<f32>, s: vec3<f32>, mode: u32) -> vec3<f32> {
+    switch mode {
+        // Multiply
+        case 1u: { return b * s; }
+        // Screen
+        case 2u: { return screen(b, s); }
+        // Overlay
+        case 3u: {
+            return select(screen(s, 2.0 * b - 1.0), 2.0 * b * s, b <= vec3<f32>(0.5));
+        }
+        // Darken
+        case 4u: { return min(b, s); }
+        // Lighten
+        case 5u: { return max(b, s); }
+        // Difference
+        case 6u: { return abs(b - s); }
+        // Color
+        case 7u: { return set_lum(s, lum(b)); }
+        // Luminosity
+        case 8u: { return set_lum(b, lum(s)); }
+        // Normal
+        default: { return s; }
+    }
+}
+
+@fragment
+fn fs_composite(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let texel = vec2<i32>(position.xy);
+    let b = textureLoad(backdrop, texel, 0);
+    let s = textureLoad(layer, texel, 0);
+
+    let source_alpha = s.a * clamp(params.opacity, 0.0, 1.0);
+    if source_alpha <= 0.0 {
+        return b;
+    }
+
+    // both are premultiplied
+    let cb = b.rgb / max(b.a, 1e-7);
+    var cs = s.rgb / max(s.a, 1e-7);
+    if params.adjusted != 0u {
+        cs = adjust(cs);
+    }
+
+    let mixed = (1.0 - b.a) * cs + b.a * blend(cb, cs, params.mode);
+    let color = source_alpha * mixed + (1.0 - source_alpha) * b.rgb;
+
+    return vec4<f32>(color, source_alpha + b.a * (1.0 - source_alpha));
+}
+
+// copies the finished backdrop onto the canvas
+@fragment
+fn fs_present(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    return textureLoad(backdrop, vec2<i32>(position.xy), 0);
+}
@@ -1,9 +1,37 @@
 struct FragmentInput {
     @location(0) tex_coords: vec2<f32>,
     @location(1) color: vec4<f32>,  // Receive color from vertex shader
+    @location(2) scene_position: vec2<f32>,
 };
 
+// where the painted mask sits, as left, top, right, bottom, and whether there is one
+struct MaskArea {
+    rect: vec4<f32>,
+    enabled: vec4<f32>,
+};
+
+// A photo's pixels, or a single white texel for everything that isn't a photo
+@group(1) @binding(0)
+var image: texture_2d<f32>;
+@group(1) @binding(1)
+var image_sampler: sampler;
+// The layer's painted mask, one level per pixel. Outside it the layer shows.
+@group(1) @binding(2)
+var mask: texture_2d<f32>;
+@group(1) @binding(3)
+var<uniform> mask_area: MaskArea;
+
+fn mask_level(position: vec2<f32>) -> f32 {
+    let uv = (position - mask_area.rect.xy) / (mask_area.rect.zw - mask_area.rect.xy);
+    // sampled before the checks, which aren't the same across a triangle
+    let level = textureSampleLevel(mask, image_sampler, uv, 0.0).r;
+    let inside = all(uv >= vec2<f32>(0.0)) && all(uv <= vec2<f32>(1.0));
+
+    return select(1.0, level, inside && mask_area.enabled.x > 0.5);
+}
+
 @fragment
 fn fs_main(in: FragmentInput) -> @location(0) vec4<f32> {
-    return in.color;
-}
\ No newline at end of file
+    let color = in.color * textureSample(image, image_sampler, in.tex_coords);
+    return vec4<f32>(color.rgb, color.a * mask_level(in.scene_position));
+}
@@ -37,6 +37,8 @@ struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) tex_coords: vec2<f32>,
     @location(1) color: vec4<f32>,  // Pass color to the fragment shader
+    // before the camera, where painted masks are placed
+    @location(2) scene_position: vec2<f32>,
 };
 
 @vertex
@@ -48,5 +50,6 @@ fn vs_main(
     out.clip_position = camera.view_proj * pos;
     out.tex_coords = vertex.tex_coords;
     out.color = vertex.color;  // Pass color from input to output
+    out.scene_position = vertex.position.xy;
     return out;
 }
\ No newline at end of file
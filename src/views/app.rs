@@ -10,7 +10,7 @@ use common_vector::basic::{
     color_to_wgpu, rgb_to_wgpu, string_to_f32, wgpu_to_human, Point, WindowSize,
 };
 use common_vector::dot::draw_dot;
-use common_vector::editor::{self, Editor, PolygonClickHandler, Viewport};
+use common_vector::editor::{self, Editor, Viewport};
 use common_vector::guideline::create_guide_line_buffers;
 use common_vector::polygon::{Polygon, PolygonConfig, Stroke};
 use common_vector::vertex::Vertex;
@@ -49,16 +49,26 @@ use floem::{
 use floem::{Application, CustomRenderCallback};
 use floem::{GpuHelper, View, WindowHandle};
 
+use crate::document::recovery::{previous_sessions, start_autosave, AutosaveSession};
 use crate::editor_state::EditorState;
+use crate::helpers::events::{create_event_signal, subscribe, EditorEvent, EditorEventReceiver};
+use crate::helpers::layers::LayerChange;
+use crate::helpers::locking::LockRecover;
 
 use super::aside::tab_interface;
-use super::properties_panel::properties_view;
+use super::navigator_panel::navigator_view;
+use super::properties_panel::{image_properties_view, properties_view, stroke_properties_view};
+use super::recovery_panel::recovery_view;
+use super::scopes_panel::scopes_view;
+use super::zoom_panel::{with_queue, zoom_view};
 
 pub fn app_view(
     editor_state: Arc<Mutex<EditorState>>,
     editor: std::sync::Arc<Mutex<common_vector::editor::Editor>>,
     gpu_helper: Arc<Mutex<GpuHelper>>,
     viewport: std::sync::Arc<Mutex<Viewport>>,
+    events: EditorEventReceiver,
+    session: Option<AutosaveSession>,
     // editor_cloned: std::sync::Arc<Mutex<common_vector::editor::Editor>>,
     // editor_cloned2: std::sync::Arc<Mutex<common_vector::editor::Editor>>,
     // editor_cloned3: std::sync::Arc<Mutex<common_vector::editor::Editor>>,
@@ -70,6 +80,13 @@ pub fn app_view(
     let editor_cloned2 = Arc::clone(&editor);
     let editor_cloned3 = Arc::clone(&editor);
     let editor_cloned4 = Arc::clone(&editor);
+    let editor_state2 = Arc::clone(&editor_state);
+    let editor_state3 = Arc::clone(&editor_state);
+    let editor_state4 = Arc::clone(&editor_state);
+    let editor_state5 = Arc::clone(&editor_state);
+    let gpu_helper3 = Arc::clone(&gpu_helper);
+    let gpu_helper4 = Arc::clone(&gpu_helper);
+    let viewport3 = Arc::clone(&viewport);
 
     // // let (counter, mut set_counter) = create_signal(0);
     // let (polygon_selected, mut set_polygon_selected) = create_signal(false);
@@ -90,75 +107,137 @@ pub fn app_view(
         },
     });
 
-    // Create a RefCell to hold the set_counter function
-    // let set_counter_ref = Arc::new(Mutex::new(set_counter));
-    let polygon_selected_ref = Arc::new(Mutex::new(polygon_selected));
-    let selected_polygon_id_ref = Arc::new(Mutex::new(selected_polygon_id));
-    let selected_polygon_data_ref = Arc::new(Mutex::new(selected_polygon_data));
+    let selected_stroke_id: RwSignal<Option<Uuid>> = create_rw_signal(None);
+    let selected_image_id: RwSignal<Option<Uuid>> = create_rw_signal(None);
 
-    let editor_cloned2 = editor_cloned2.clone();
+    let events = create_event_signal(events);
 
-    // Create the handle_polygon_click function
-    let handle_polygon_click: Arc<PolygonClickHandler> = Arc::new({
+    // earlier sessions are listed before this one starts autosaving
+    let recoveries = create_rw_signal(previous_sessions(
+        session.as_ref().map(|session| session.path()),
+    ));
+    start_autosave(Arc::clone(&editor_state), session);
+
+    // only one of a polygon, a stroke or a photo is selected at a time
+    subscribe(events, {
         let editor_state = editor_state.clone();
-        // let set_counter_ref = Arc::clone(&set_counter_ref);
-        let polygon_selected_ref = Arc::clone(&polygon_selected_ref);
-        let selected_polygon_id_ref = Arc::clone(&selected_polygon_id_ref);
-        let selected_polygon_data_ref = Arc::clone(&selected_polygon_data_ref);
-        move || {
-            let editor_state = editor_state.clone();
-            // let set_counter_ref = set_counter_ref.clone();
-            let polygon_selected_ref = polygon_selected_ref.clone();
-            let selected_polygon_id_ref = selected_polygon_id_ref.clone();
-            let selected_polygon_data_ref = selected_polygon_data_ref.clone();
-            Some(
-                Box::new(move |polygon_id: Uuid, polygon_data: PolygonConfig| {
-                    // cannot lock editor here!
-                    // {
-                    //     let mut editor = new_editor.lock().unwrap();
-                    //     // Update editor as needed
-                    // }
-
-                    if let Ok(mut polygon_selected) = polygon_selected_ref.lock() {
-                        polygon_selected.update(|c| {
-                            *c = true;
-                        });
+        move |event| match event {
+            EditorEvent::SelectionChanged(selection) => {
+                let polygon_id = match selection {
+                    Some((polygon_id, polygon_data)) => {
+                        selected_polygon_data.set(polygon_data.clone());
+                        *polygon_id
                     }
-                    if let Ok(mut selected_polygon_id) = selected_polygon_id_ref.lock() {
-                        selected_polygon_id.update(|c| {
-                            *c = polygon_id;
-                        });
-                        let mut editor_state = editor_state.lock().unwrap();
-                        editor_state.selected_polygon_id = polygon_id;
-                        editor_state.polygon_selected = true;
+                    None => Uuid::nil(),
+                };
+
+                {
+                    let mut editor_state = editor_state.lock_or_recover();
+                    editor_state.selected_polygon_id = polygon_id;
+                    editor_state.polygon_selected = selection.is_some();
+                    if selection.is_some() {
+                        editor_state.selected_stroke_id = None;
+                        editor_state.selected_image_id = None;
                     }
-                    if let Ok(mut selected_polygon_data) = selected_polygon_data_ref.lock() {
-                        selected_polygon_data.update(|c| {
-                            *c = polygon_data;
-                        });
+                    editor_state.stop_cropping_unselected();
+                    editor_state.stop_filter_preview_unselected();
+                }
+
+                // the properties panel locks editor_state as it mounts
+                if selection.is_some() {
+                    selected_stroke_id.set(None);
+                    selected_image_id.set(None);
+                }
+                selected_polygon_id.set(polygon_id);
+                polygon_selected.set(selection.is_some());
+            }
+            EditorEvent::StrokeSelectionChanged(selection) => {
+                {
+                    let mut editor_state = editor_state.lock_or_recover();
+                    editor_state.selected_stroke_id = *selection;
+                    if selection.is_some() {
+                        editor_state.selected_polygon_id = Uuid::nil();
+                        editor_state.polygon_selected = false;
+                        editor_state.selected_image_id = None;
                     }
-                }) as Box<dyn FnMut(Uuid, PolygonConfig) + Send>,
-            )
+                    editor_state.stop_cropping_unselected();
+                    editor_state.stop_filter_preview_unselected();
+                }
+
+                if selection.is_some() {
+                    polygon_selected.set(false);
+                    selected_image_id.set(None);
+                }
+                selected_stroke_id.set(*selection);
+            }
+            EditorEvent::ImageSelectionChanged(selection) => {
+                {
+                    let mut editor_state = editor_state.lock_or_recover();
+                    editor_state.selected_image_id = *selection;
+                    if selection.is_some() {
+                        editor_state.selected_polygon_id = Uuid::nil();
+                        editor_state.polygon_selected = false;
+                        editor_state.selected_stroke_id = None;
+                    }
+                    editor_state.stop_cropping_unselected();
+                    editor_state.stop_filter_preview_unselected();
+                }
+
+                if selection.is_some() {
+                    polygon_selected.set(false);
+                    selected_stroke_id.set(None);
+                }
+                selected_image_id.set(*selection);
+            }
+            _ => {}
         }
     });
 
-    // create_effect(move |_| {
-    //     editor_cloned3.lock().unwrap().handle_polygon_click = Some(handle_polygon_click);
-    // });
-
-    // Use create_effect to set the handler only once
-    create_effect({
-        let handle_polygon_click = Arc::clone(&handle_polygon_click);
-        let editor_cloned3 = Arc::clone(&editor_cloned3);
-        move |_| {
-            let mut editor = editor_cloned3.lock().unwrap();
-            editor.handle_polygon_click = Some(Arc::clone(&handle_polygon_click));
+    // the editor and the history report layers coming and going, whatever was
+    // selected or worked on in a removed one is let go of here
+    subscribe(events, {
+        let editor_state = editor_state.clone();
+        move |event| match event {
+            EditorEvent::PolygonAdded(config) => {
+                editor_state.lock_or_recover().polygon_added(config.id);
+            }
+            EditorEvent::LayersChanged(changes) => {
+                let mut editor_state = editor_state.lock_or_recover();
+                for change in changes {
+                    if let LayerChange::Removed { id, kind } = change {
+                        editor_state.layer_removed(*id, *kind);
+                    }
+                }
+            }
+            _ => {}
+        }
+    });
+
+    // filters are baked off the UI thread, the result goes through the history here
+    subscribe(events, {
+        let editor_state = editor_state.clone();
+        move |event| {
+            if let EditorEvent::FilterBaked {
+                image_id,
+                filter,
+                source,
+                baked,
+            } = event
+            {
+                editor_state.lock_or_recover().commit_baked_filter(
+                    *image_id,
+                    *filter,
+                    Arc::clone(source),
+                    Arc::clone(baked),
+                );
+            }
         }
     });
 
     container((
         // label(move || format!("Value: {counter}")).style(|s| s.margin_bottom(10)),
         tab_interface(
+            editor_state.clone(),
             gpu_helper.clone(),
             editor,
             // editor_cloned,
@@ -166,6 +245,7 @@ pub fn app_view(
             // handler,
             // square_handler,
             polygon_selected,
+            events,
         ),
         dyn_container(
             move || polygon_selected.get(),
@@ -179,6 +259,7 @@ pub fn app_view(
                         polygon_selected,
                         selected_polygon_id,
                         selected_polygon_data,
+                        events,
                     )
                     .into_any()
                 } else {
@@ -186,6 +267,47 @@ pub fn app_view(
                 }
             },
         ),
+        dyn_container(
+            move || selected_stroke_id.get(),
+            move |stroke_id| match stroke_id {
+                Some(stroke_id) => {
+                    stroke_properties_view(editor_state2.clone(), stroke_id, events).into_any()
+                }
+                None => empty().into_any(),
+            },
+        ),
+        dyn_container(
+            move || selected_image_id.get(),
+            move |image_id| match image_id {
+                Some(image_id) => {
+                    image_properties_view(editor_state4.clone(), image_id, events).into_any()
+                }
+                None => empty().into_any(),
+            },
+        ),
+        // the canvas controls are stacked in one column
+        v_stack((
+            zoom_view(Arc::clone(&editor_state3), Arc::clone(&gpu_helper3), events),
+            navigator_view(Arc::clone(&editor_state3), Arc::clone(&gpu_helper3), events),
+            scopes_view(Arc::clone(&editor_state3), events),
+        )),
+        recovery_view(
+            Arc::clone(&editor_state3),
+            Arc::clone(&gpu_helper3),
+            Arc::clone(&viewport3),
+            recoveries,
+            events,
+        ),
     ))
+    // trackpad pinches zoom around the cursor, Ctrl+wheel covers platforms without them
+    .on_event(EventListener::PinchGesture, move |event| {
+        if let Event::PinchGesture(pinch) = event {
+            with_queue(&editor_state5, &gpu_helper4, |editor_state, queue| {
+                let cursor = [editor_state.last_cursor.x, editor_state.last_cursor.y];
+                editor_state.update_view(queue, |view| view.magnify(pinch.delta as f32, cursor));
+            });
+        }
+        EventPropagation::Continue
+    })
     // .style(|s| s.flex_col().items_center())
 }
@@ -37,11 +37,16 @@ use floem::{
 use floem::{Application, CustomRenderCallback};
 use floem::{GpuHelper, View, WindowHandle};
 
+use crate::editor_state::EditorState;
+use crate::helpers::events::EditorEvent;
+
 use super::assets_panel::assets_view;
+use super::document_panel::document_view;
 use super::settings_panel::settings_view;
 use super::tools_panel::tools_view;
 
 pub fn tab_interface(
+    editor_state: Arc<Mutex<EditorState>>,
     gpu_helper: Arc<Mutex<GpuHelper>>,
     editor: std::sync::Arc<Mutex<common_vector::editor::Editor>>,
     // editor_cloned: std::sync::Arc<Mutex<common_vector::editor::Editor>>,
@@ -49,10 +54,13 @@ pub fn tab_interface(
     // mut handler: std::sync::Arc<Mutex<Handler>>,
     // mut square_handler: std::sync::Arc<Mutex<Handler>>,
     polygon_selected: RwSignal<bool>,
+    events: RwSignal<Vec<EditorEvent>>,
 ) -> impl View {
     // let editor_cloned = Arc::clone(&editor);
 
-    let tabs: im::Vector<&str> = vec!["Tools", "Assets", "Settings"].into_iter().collect();
+    let tabs: im::Vector<&str> = vec!["Tools", "Assets", "Document", "Settings"]
+        .into_iter()
+        .collect();
     let (tabs, _set_tabs) = create_signal(tabs);
     let (active_tab, set_active_tab) = create_signal(0);
 
@@ -72,6 +80,7 @@ pub fn tab_interface(
                 let icon_name = match item {
                     "Tools" => "brush",
                     "Assets" => "shapes",
+                    "Document" => "square",
                     "Settings" => "gear",
                     _ => "plus",
                 };
@@ -146,6 +155,7 @@ pub fn tab_interface(
             dyn_container(
                 move || !polygon_selected.get(),
                 move |show_content| {
+                    let editor_state = editor_state.clone();
                     let editor = editor.clone();
                     // let editor_cloned = editor_cloned.clone();
                     let viewport = viewport.clone();
@@ -159,16 +169,27 @@ pub fn tab_interface(
                             |it| *it,
                             move |it| match it {
                                 "Tools" => tools_view(
+                                    editor_state.clone(),
                                     gpu_helper.clone(),
                                     editor.clone(),
                                     // editor_cloned.clone(),
                                     viewport.clone(),
+                                    events,
                                     // handler.clone(),
                                     // square_handler.clone(),
                                 )
                                 .into_any(),
-                                "Assets" => assets_view().into_any(),
-                                "Settings" => settings_view().into_any(),
+                                "Assets" => assets_view(
+                                    editor_state.clone(),
+                                    gpu_helper.clone(),
+                                    viewport.clone(),
+                                    events,
+                                )
+                                .into_any(),
+                                "Document" => {
+                                    document_view(editor_state.clone(), events).into_any()
+                                }
+                                "Settings" => settings_view(editor_state.clone()).into_any(),
                                 _ => label(|| "Not implemented".to_owned()).into_any(),
                             },
                         )
@@ -1,6 +1,262 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use common_vector::basic::WindowSize;
+use common_vector::editor::Viewport;
+use floem::common::{create_icon, small_button};
+use floem::event::{EventListener, EventPropagation};
+use floem::peniko::Color;
+use floem::reactive::{create_rw_signal, RwSignal, SignalGet, SignalUpdate};
+use floem::style::CursorStyle;
+use floem::taffy::{AlignItems, FlexWrap};
 use floem::views::Decorators;
-use floem::{views::label, IntoView};
+use floem::views::{
+    dyn_container, empty, h_stack, h_stack_from_iter, img, label, svg, v_stack, v_stack_from_iter,
+};
+use floem::{GpuHelper, IntoView};
+
+use crate::assets::library::{Asset, AssetKind};
+use crate::editor_state::EditorState;
+use crate::helpers::events::{subscribe, EditorEvent};
+use crate::helpers::locking::LockRecover;
+
+use super::inputs::styled_input;
+
+// larger files get an icon instead of being read in for a thumbnail
+const MAX_THUMBNAIL_BYTES: u64 = 2 * 1024 * 1024;
+
+fn thumbnail(asset: &Asset) -> impl IntoView {
+    let size = fs::metadata(&asset.path).map_or(u64::MAX, |metadata| metadata.len());
+    let contents = (size <= MAX_THUMBNAIL_BYTES)
+        .then(|| fs::read(&asset.path).ok())
+        .flatten();
+
+    match (asset.kind, contents) {
+        (AssetKind::Image, Some(bytes)) => img(move || bytes.clone())
+            .style(|s| s.width(64).height(64))
+            .into_any(),
+        (AssetKind::Svg | AssetKind::Shape, Some(bytes)) => {
+            svg(String::from_utf8_lossy(&bytes).into_owned())
+                .style(|s| s.width(64).height(64))
+                .into_any()
+        }
+        _ => svg(create_icon("shapes"))
+            .style(|s| s.width(32).height(32).margin(16).color(Color::GRAY))
+            .into_any(),
+    }
+}
+
+fn asset_tile(
+    editor_state: Arc<Mutex<EditorState>>,
+    gpu_helper: Arc<Mutex<GpuHelper>>,
+    viewport: Arc<Mutex<Viewport>>,
+    asset: Asset,
+) -> impl IntoView {
+    let name = asset.name.clone();
+    let kind = asset.kind;
+    let editor_state_end = Arc::clone(&editor_state);
+
+    v_stack((
+        thumbnail(&asset),
+        label(move || name.clone()).style(|s| s.font_size(11.0).max_width(72.0).selectable(false)),
+        label(move || kind.label()).style(|s| s.font_size(9.0).color(Color::GRAY)),
+    ))
+    .draggable()
+    .on_event(EventListener::DragStart, move |_| {
+        editor_state.lock_or_recover().asset_drag = Some(asset.clone());
+        EventPropagation::Continue
+    })
+    // floem sends Drop to whatever is under the pointer before the tile gets DragEnd,
+    // so a drag that was let go over the panel has been cancelled by now
+    .on_event(EventListener::DragEnd, move |_| {
+        let mut editor_state = editor_state_end.lock_or_recover();
+        let Some(asset) = editor_state.asset_drag.take() else {
+            return EventPropagation::Continue;
+        };
+
+        let gpu_helper = gpu_helper.lock_or_recover();
+        let device = &gpu_helper
+            .gpu_resources
+            .as_ref()
+            .expect("Couldn't get gpu resources")
+            .device;
+        let viewport = viewport.lock_or_recover();
+        let window_size = WindowSize {
+            width: viewport.width as u32,
+            height: viewport.height as u32,
+        };
+
+        if let Err(error) = editor_state.drop_asset(&asset, device, &window_size) {
+            println!("{}", error);
+        }
+
+        EventPropagation::Continue
+    })
+    .dragging_style(|s| {
+        s.box_shadow_blur(3)
+            .box_shadow_color(Color::rgba(100.0, 100.0, 100.0, 0.5))
+            .box_shadow_spread(2)
+    })
+    .style(|s| {
+        s.width(76.0)
+            .align_items(AlignItems::Center)
+            .padding(4.0)
+            .margin(2.0)
+            .border_radius(8.0)
+            .cursor(CursorStyle::Pointer)
+            .hover(|s| s.background(Color::rgb(235.0, 235.0, 235.0)))
+    })
+}
+
+fn folder_row(path: PathBuf, folder: RwSignal<PathBuf>) -> impl IntoView {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    h_stack((
+        svg(create_icon("shapes")).style(|s| s.width(16).height(16).margin_right(7.0)),
+        label(move || name.clone()).style(|s| s.selectable(false)),
+    ))
+    .on_click_stop(move |_| folder.set(path.clone()))
+    .style(|s| {
+        s.width_full()
+            .align_items(AlignItems::Center)
+            .padding_vert(5.0)
+            .cursor(CursorStyle::Pointer)
+            .hover(|s| s.background(Color::rgb(235.0, 235.0, 235.0)))
+    })
+}
+
+pub fn assets_view(
+    editor_state: Arc<Mutex<EditorState>>,
+    gpu_helper: Arc<Mutex<GpuHelper>>,
+    viewport: Arc<Mutex<Viewport>>,
+    events: RwSignal<Vec<EditorEvent>>,
+) -> impl IntoView {
+    let root = editor_state.lock_or_recover().asset_library.root.clone();
+    let folder = create_rw_signal(root.clone());
+    let query = create_rw_signal(String::new());
+    // bumped when the library's files change under the same folder
+    let revision = create_rw_signal(0);
+    let up_active = create_rw_signal(false);
+
+    subscribe(events, {
+        let editor_state = editor_state.clone();
+        move |event| {
+            if let EditorEvent::AssetsChanged = event {
+                let root = editor_state.lock_or_recover().asset_library.root.clone();
+                if !folder.get_untracked().starts_with(&root) {
+                    folder.set(root);
+                }
+                revision.update(|revision| *revision += 1);
+            }
+        }
+    });
+
+    let aside_width = 260.0;
+    let editor_state_drop = Arc::clone(&editor_state);
+    let editor_state_listing = Arc::clone(&editor_state);
+
+    v_stack((
+        label(|| "Assets").style(|s| s.margin_bottom(10)),
+        styled_input(
+            "Library Folder:".to_string(),
+            &root.to_string_lossy(),
+            "Enter folder",
+            Box::new(move |mut editor_state, value| {
+                // most of what's typed on the way to a folder isn't one yet
+                let _ = editor_state.set_asset_directory(&value);
+            }),
+            editor_state.clone(),
+            "asset_directory".to_string(),
+        )
+        .style(move |s| s.width(aside_width).margin_bottom(5.0)),
+        styled_input(
+            "Search:".to_string(),
+            "",
+            "Search assets",
+            Box::new(move |editor_state, value| {
+                // the listing below reads the library, so let go of the state first
+                drop(editor_state);
+                query.set(value);
+            }),
+            editor_state.clone(),
+            "asset_search".to_string(),
+        )
+        .style(move |s| s.width(aside_width).margin_bottom(10.0)),
+        dyn_container(
+            move || (folder.get(), query.get(), revision.get()),
+            move |(current, query, _)| {
+                let library = editor_state_listing.lock_or_recover().asset_library.clone();
+
+                // searching looks through every folder at once
+                let (folders, assets) = if query.trim().is_empty() {
+                    match library.read_folder(&current) {
+                        Ok(listing) => (listing.folders, listing.assets),
+                        Err(_) => {
+                            return label(move || {
+                                format!("No asset folder at {}", library.root.display())
+                            })
+                            .style(move |s| s.width(aside_width).color(Color::GRAY))
+                            .into_any();
+                        }
+                    }
+                } else {
+                    (Vec::new(), library.search(&query))
+                };
+
+                let at_root = current == library.root || !query.trim().is_empty();
+                let empty_folder = folders.is_empty() && assets.is_empty();
+
+                let editor_state = editor_state_listing.clone();
+                let gpu_helper = gpu_helper.clone();
+                let viewport = viewport.clone();
 
-pub fn assets_view() -> impl IntoView {
-    (label(move || format!("Assets")).style(|s| s.margin_bottom(10)),)
+                v_stack((
+                    if at_root {
+                        empty().into_any()
+                    } else {
+                        small_button(
+                            "Up",
+                            "arrow-left",
+                            move |_| {
+                                if let Some(parent) = current.parent() {
+                                    folder.set(parent.to_path_buf());
+                                }
+                            },
+                            up_active,
+                        )
+                        .style(|s| s.margin_bottom(5.0))
+                        .into_any()
+                    },
+                    v_stack_from_iter(
+                        folders
+                            .into_iter()
+                            .map(move |path| folder_row(path, folder)),
+                    )
+                    .style(|s| s.width_full()),
+                    h_stack_from_iter(assets.into_iter().map(move |asset| {
+                        asset_tile(
+                            editor_state.clone(),
+                            gpu_helper.clone(),
+                            viewport.clone(),
+                            asset,
+                        )
+                    }))
+                    .style(|s| s.width_full().flex_wrap(FlexWrap::Wrap)),
+                    label(|| "Nothing here yet")
+                        .style(move |s| s.color(Color::GRAY).apply_if(!empty_folder, |s| s.hide())),
+                ))
+                .style(move |s| s.width(aside_width))
+                .into_any()
+            },
+        ),
+    ))
+    // let go of a tile over the panel and nothing is placed
+    .on_event(EventListener::Drop, move |_| {
+        editor_state_drop.lock_or_recover().asset_drag = None;
+        EventPropagation::Continue
+    })
 }
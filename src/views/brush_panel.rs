@@ -0,0 +1,323 @@
+use std::sync::{Arc, Mutex};
+
+use floem::common::{option_button, small_button};
+use floem::reactive::{create_effect, RwSignal, SignalGet, SignalUpdate};
+use floem::taffy::FlexWrap;
+use floem::views::Decorators;
+use floem::views::{container, h_stack, label, v_stack};
+use floem::IntoView;
+
+use crate::brush::eraser::EraserMode;
+use crate::brush::smoothing::StabilizerKind;
+use crate::brush::{BrushKind, BrushTool};
+use crate::editor_state::EditorState;
+use crate::helpers::locking::LockRecover;
+
+use super::inputs::styled_input;
+
+fn brush_button(
+    editor_state: Arc<Mutex<EditorState>>,
+    brush_kind: RwSignal<BrushKind>,
+    brush_tool: RwSignal<BrushTool>,
+    kind: BrushKind,
+    active: bool,
+) -> impl IntoView {
+    option_button(
+        match kind {
+            BrushKind::Solid => "Use Solid",
+            BrushKind::Calligraphy => "Use Calligraphy",
+            BrushKind::Airbrush => "Use Airbrush",
+        },
+        "brush",
+        Some(move || {
+            editor_state.lock_or_recover().set_brush_kind(kind);
+            brush_kind.set(kind);
+            brush_tool.set(BrushTool::Paint);
+        }),
+        active,
+    )
+}
+
+fn eraser_button(
+    editor_state: Arc<Mutex<EditorState>>,
+    brush_tool: RwSignal<BrushTool>,
+    active: bool,
+) -> impl IntoView {
+    option_button(
+        "Use Eraser",
+        "brush",
+        Some(move || {
+            editor_state
+                .lock_or_recover()
+                .set_brush_tool(BrushTool::Erase);
+            brush_tool.set(BrushTool::Erase);
+        }),
+        active,
+    )
+}
+
+fn eraser_mode_button(
+    editor_state: Arc<Mutex<EditorState>>,
+    eraser_mode: RwSignal<EraserMode>,
+    mode: EraserMode,
+) -> impl IntoView {
+    let active = RwSignal::new(eraser_mode.get_untracked() == mode);
+
+    create_effect(move |_| {
+        active.set(eraser_mode.get() == mode);
+    });
+
+    small_button(
+        mode.label(),
+        "brush",
+        move |_| {
+            editor_state.lock_or_recover().set_eraser_mode(mode);
+            eraser_mode.set(mode);
+        },
+        active,
+    )
+}
+
+fn eraser_settings_view(editor_state: Arc<Mutex<EditorState>>) -> impl IntoView {
+    let settings = editor_state.lock_or_recover().eraser_settings;
+    let eraser_mode = RwSignal::new(settings.mode);
+
+    let aside_width = 260.0;
+
+    v_stack((
+        label(|| "Eraser").style(|s| s.margin_top(10.0).margin_bottom(5.0)),
+        h_stack((
+            eraser_mode_button(editor_state.clone(), eraser_mode, EraserMode::Vector),
+            eraser_mode_button(editor_state.clone(), eraser_mode, EraserMode::Shape),
+        ))
+        .style(|s| s.margin_bottom(7.0)),
+        label(move || match eraser_mode.get() {
+            EraserMode::Vector => "Cuts brush strokes",
+            EraserMode::Shape => "Cuts polygons",
+        })
+        .style(|s| s.margin_bottom(7.0)),
+        styled_input(
+            "Size:".to_string(),
+            &settings.size.to_string(),
+            "Enter size",
+            Box::new({
+                move |mut editor_state, value| {
+                    editor_state.update_eraser_size(&value);
+                }
+            }),
+            editor_state,
+            "eraser_size".to_string(),
+        )
+        .style(move |s| s.width(aside_width)),
+    ))
+}
+
+fn stabilizer_button(
+    editor_state: Arc<Mutex<EditorState>>,
+    stabilizer: RwSignal<StabilizerKind>,
+    kind: StabilizerKind,
+) -> impl IntoView {
+    let active = RwSignal::new(stabilizer.get_untracked() == kind);
+
+    create_effect(move |_| {
+        active.set(stabilizer.get() == kind);
+    });
+
+    small_button(
+        kind.label(),
+        "brush",
+        move |_| {
+            editor_state.lock_or_recover().set_stabilizer(kind);
+            stabilizer.set(kind);
+        },
+        active,
+    )
+}
+
+pub fn brushes_view(
+    editor_state: Arc<Mutex<EditorState>>,
+    brush_kind: RwSignal<BrushKind>,
+    brush_tool: RwSignal<BrushTool>,
+) -> impl IntoView {
+    let (settings, tool) = {
+        let editor_state = editor_state.lock_or_recover();
+        (editor_state.brush_settings, editor_state.brush_tool)
+    };
+    let painting = tool == BrushTool::Paint;
+
+    v_stack((
+        container((
+            brush_button(
+                editor_state.clone(),
+                brush_kind,
+                brush_tool,
+                BrushKind::Solid,
+                painting && settings.kind == BrushKind::Solid,
+            )
+            .style(|s| s.margin_right(5.0)),
+            brush_button(
+                editor_state.clone(),
+                brush_kind,
+                brush_tool,
+                BrushKind::Calligraphy,
+                painting && settings.kind == BrushKind::Calligraphy,
+            )
+            .style(|s| s.margin_right(5.0)),
+            brush_button(
+                editor_state.clone(),
+                brush_kind,
+                brush_tool,
+                BrushKind::Airbrush,
+                painting && settings.kind == BrushKind::Airbrush,
+            )
+            .style(|s| s.margin_right(5.0)),
+            eraser_button(editor_state.clone(), brush_tool, !painting),
+        ))
+        .style(|s| s.flex_wrap(FlexWrap::Wrap).margin_top(5.0)),
+        match tool {
+            BrushTool::Paint => brush_settings_view(editor_state).into_any(),
+            BrushTool::Erase => eraser_settings_view(editor_state).into_any(),
+        },
+    ))
+}
+
+fn brush_settings_view(editor_state: Arc<Mutex<EditorState>>) -> impl IntoView {
+    let settings = editor_state.lock_or_recover().brush_settings;
+    let stabilizer = RwSignal::new(settings.smoothing.stabilizer);
+
+    let aside_width = 260.0;
+    let halfs = (aside_width / 2.0) + (5.0 * 2.0);
+
+    v_stack((
+        label(|| "Settings").style(|s| s.margin_top(10.0).margin_bottom(5.0)),
+        h_stack((
+            styled_input(
+                "Size:".to_string(),
+                &settings.size.to_string(),
+                "Enter size",
+                Box::new({
+                    move |mut editor_state, value| {
+                        editor_state.update_brush_size(&value);
+                    }
+                }),
+                editor_state.clone(),
+                "brush_size".to_string(),
+            )
+            .style(move |s| s.width(halfs).margin_right(5.0)),
+            styled_input(
+                "Opacity (%):".to_string(),
+                &(settings.opacity * 100.0).round().to_string(),
+                "0-100",
+                Box::new({
+                    move |mut editor_state, value| {
+                        editor_state.update_brush_opacity(&value);
+                    }
+                }),
+                editor_state.clone(),
+                "brush_opacity".to_string(),
+            )
+            .style(move |s| s.width(halfs)),
+        ))
+        .style(move |s| s.width(aside_width)),
+        h_stack((
+            styled_input(
+                "Spacing (%):".to_string(),
+                &(settings.spacing * 100.0).round().to_string(),
+                "% of size",
+                Box::new({
+                    move |mut editor_state, value| {
+                        editor_state.update_brush_spacing(&value);
+                    }
+                }),
+                editor_state.clone(),
+                "brush_spacing".to_string(),
+            )
+            .style(move |s| s.width(halfs).margin_right(5.0)),
+            styled_input(
+                "Hardness (%):".to_string(),
+                &(settings.hardness * 100.0).round().to_string(),
+                "0-100",
+                Box::new({
+                    move |mut editor_state, value| {
+                        editor_state.update_brush_hardness(&value);
+                    }
+                }),
+                editor_state.clone(),
+                "brush_hardness".to_string(),
+            )
+            .style(move |s| s.width(halfs)),
+        ))
+        .style(move |s| s.width(aside_width)),
+        label(|| "Smoothing").style(|s| s.margin_top(10.0).margin_bottom(5.0)),
+        h_stack((
+            stabilizer_button(editor_state.clone(), stabilizer, StabilizerKind::Off),
+            stabilizer_button(editor_state.clone(), stabilizer, StabilizerKind::LazyMouse),
+            stabilizer_button(
+                editor_state.clone(),
+                stabilizer,
+                StabilizerKind::MovingAverage,
+            ),
+        ))
+        .style(|s| s.margin_bottom(7.0)),
+        styled_input(
+            "Strength (%):".to_string(),
+            &(settings.smoothing.strength * 100.0).round().to_string(),
+            "0-100",
+            Box::new({
+                move |mut editor_state, value| {
+                    editor_state.update_smoothing_strength(&value);
+                }
+            }),
+            editor_state.clone(),
+            "brush_smoothing_strength".to_string(),
+        )
+        .style(move |s| s.width(aside_width)),
+        label(|| "Pressure").style(|s| s.margin_top(10.0).margin_bottom(5.0)),
+        styled_input(
+            "Curve:".to_string(),
+            &settings.pressure_curve.gamma.to_string(),
+            "Below 1 is softer",
+            Box::new({
+                move |mut editor_state, value| {
+                    editor_state.update_pressure_gamma(&value);
+                }
+            }),
+            editor_state.clone(),
+            "brush_pressure_curve".to_string(),
+        )
+        .style(move |s| s.width(aside_width)),
+        h_stack((
+            styled_input(
+                "Min Size (%):".to_string(),
+                &(settings.pressure_curve.min_size * 100.0)
+                    .round()
+                    .to_string(),
+                "0-100",
+                Box::new({
+                    move |mut editor_state, value| {
+                        editor_state.update_pressure_min_size(&value);
+                    }
+                }),
+                editor_state.clone(),
+                "brush_pressure_min_size".to_string(),
+            )
+            .style(move |s| s.width(halfs).margin_right(5.0)),
+            styled_input(
+                "Min Opacity (%):".to_string(),
+                &(settings.pressure_curve.min_opacity * 100.0)
+                    .round()
+                    .to_string(),
+                "0-100",
+                Box::new({
+                    move |mut editor_state, value| {
+                        editor_state.update_pressure_min_opacity(&value);
+                    }
+                }),
+                editor_state,
+                "brush_pressure_min_opacity".to_string(),
+            )
+            .style(move |s| s.width(halfs)),
+        ))
+        .style(move |s| s.width(aside_width)),
+    ))
+}
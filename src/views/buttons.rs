@@ -28,17 +28,23 @@ use uuid::Uuid;
 use floem::reactive::SignalGet;
 use floem::reactive::SignalUpdate;
 
-use super::tools_panel::Layer;
+use crate::editor_state::EditorState;
+use crate::helpers::locking::LockRecover;
+use crate::helpers::masks::LayerLinks;
 
 pub fn sortable_item(
-    editor: std::sync::Arc<Mutex<common_vector::editor::Editor>>,
-    sortable_items: RwSignal<Vec<Layer>>,
+    editor_state: Arc<Mutex<EditorState>>,
     dragger_id: RwSignal<Uuid>,
     item_id: Uuid,
     layer_name: String,
     icon_name: &'static str,
+    links: LayerLinks,
 ) -> impl IntoView {
+    let editor_state_click = Arc::clone(&editor_state);
+
     h_stack((
+        // clipped layers point down at the layer they're clipped to
+        label(|| "↓").style(move |s| s.margin_right(5.0).apply_if(!links.clipped, |s| s.hide())),
         svg(create_icon(icon_name))
             .style(|s| s.width(24).height(24).color(Color::BLACK))
             .style(|s| s.margin_right(7.0))
@@ -46,6 +52,18 @@ pub fn sortable_item(
                 floem::event::EventListener::PointerDown,
                 |_| { /* Disable dragging for this view */ },
             ),
+        // the mask's thumbnail, linked to the layer's icon
+        h_stack((
+            label(|| "-").style(|s| s.color(Color::GRAY)),
+            svg(create_icon("square"))
+                .style(|s| s.width(18).height(18).color(Color::BLACK))
+                .style(|s| s.margin_horiz(4.0)),
+        ))
+        .style(move |s| {
+            s.align_items(AlignItems::Center)
+                .margin_right(7.0)
+                .apply_if(!links.masked, |s| s.hide())
+        }),
         label(move || layer_name.to_string())
             .style(|s| s.selectable(false).cursor(CursorStyle::RowResize)),
     ))
@@ -56,36 +74,12 @@ pub fn sortable_item(
         floem::event::EventPropagation::Continue
     })
     .on_event(floem::event::EventListener::DragOver, move |_| {
-        let mut editor = editor.lock().unwrap();
         let dragger_id = dragger_id.get_untracked();
         if dragger_id != item_id {
-            let dragger_pos = sortable_items
-                .get()
-                .iter()
-                .position(|layer| layer.instance_id == dragger_id)
-                .or_else(|| Some(usize::MAX))
-                .expect("Couldn't get dragger_pos");
-            let hover_pos = sortable_items
-                .get()
-                .iter()
-                .position(|layer| layer.instance_id == item_id)
-                .or_else(|| Some(usize::MAX))
-                .expect("Couldn't get hover_pos");
-
-            sortable_items.update(|items| {
-                if (dragger_pos <= items.len() && hover_pos <= items.len()) {
-                    let item = items.get(dragger_pos).cloned();
-                    items.remove(dragger_pos);
-                    editor.layer_list.remove(dragger_pos);
-
-                    if let Some(selected_item) = item {
-                        items.insert(hover_pos, selected_item.clone());
-                        editor
-                            .layer_list
-                            .insert(hover_pos, selected_item.instance_id);
-                    }
-                }
-            });
+            // the editor owns the order, the list just follows it
+            editor_state
+                .lock_or_recover()
+                .reorder_layer(dragger_id, item_id);
         }
         floem::event::EventPropagation::Continue
     })
@@ -94,8 +88,9 @@ pub fn sortable_item(
             .box_shadow_color(Color::rgba(100.0, 100.0, 100.0, 0.5))
             .box_shadow_spread(2)
     })
-    .style(|s| {
+    .style(move |s| {
         s.width(220.0)
+            .apply_if(links.clipped, |s| s.margin_left(20.0).width(200.0))
             .border_radius(15.0)
             .align_items(AlignItems::Center)
             .padding_vert(8)
@@ -105,8 +100,7 @@ pub fn sortable_item(
             .hover(|s| s.background(Color::rgb(222.0, 206.0, 160.0)))
             .active(|s| s.background(Color::rgb(237.0, 218.0, 164.0)))
     })
-    // .on_click(|_| {
-    //     println!("Layer selected");
-    //     EventPropagation::Stop
-    // })
+    .on_click_stop(move |_| {
+        editor_state_click.lock_or_recover().select_layer(item_id);
+    })
 }
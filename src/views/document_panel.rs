@@ -0,0 +1,222 @@
+use std::sync::{Arc, Mutex};
+
+use floem::common::{option_button, small_button};
+use floem::reactive::{create_effect, RwSignal, SignalGet, SignalUpdate};
+use floem::taffy::FlexWrap;
+use floem::views::Decorators;
+use floem::views::{dyn_container, h_stack, h_stack_from_iter, label, v_stack, v_stack_from_iter};
+use floem::IntoView;
+use strum::IntoEnumIterator;
+use uuid::Uuid;
+
+use crate::document::page::{Artboard, ArtboardField, PageSetup};
+use crate::editor_state::EditorState;
+use crate::helpers::events::{subscribe, EditorEvent};
+use crate::helpers::locking::LockRecover;
+use crate::helpers::preferences::Unit;
+
+use super::inputs::styled_input;
+
+fn artboard_input(
+    editor_state: Arc<Mutex<EditorState>>,
+    page: &PageSetup,
+    artboard: &Artboard,
+    field: ArtboardField,
+    width: f32,
+) -> impl IntoView {
+    let artboard_id = artboard.id;
+
+    styled_input(
+        field.label().to_string(),
+        &field.read(page, artboard),
+        "Enter value",
+        Box::new(move |mut editor_state, value| {
+            // half typed lengths are left alone until they parse
+            let _ = editor_state.update_artboard_field(artboard_id, field, &value);
+        }),
+        editor_state,
+        field.signal_name(artboard_id),
+    )
+    .style(move |s| s.width(width))
+}
+
+fn unit_button(
+    editor_state: Arc<Mutex<EditorState>>,
+    page: RwSignal<PageSetup>,
+    unit: Unit,
+) -> impl IntoView {
+    let active = RwSignal::new(page.get_untracked().unit == unit);
+
+    create_effect(move |_| {
+        active.set(page.get().unit == unit);
+    });
+
+    small_button(
+        unit.label(),
+        "square",
+        move |_| {
+            editor_state
+                .lock_or_recover()
+                .update_page(|page| page.set_unit(unit));
+        },
+        active,
+    )
+    .style(|s| s.margin_right(5.0))
+}
+
+fn export(editor_state: &Arc<Mutex<EditorState>>, artboard_id: Option<Uuid>) {
+    match editor_state.lock_or_recover().export_artboards(artboard_id) {
+        Ok(paths) => {
+            for path in paths {
+                println!("Exported {}", path.display());
+            }
+        }
+        Err(error) => println!("{}", error),
+    }
+}
+
+fn artboard_card(
+    editor_state: Arc<Mutex<EditorState>>,
+    page: &PageSetup,
+    artboard: &Artboard,
+    halves: f32,
+) -> impl IntoView {
+    let artboard_id = artboard.id;
+    let editor_state2 = editor_state.clone();
+    let editor_state3 = editor_state.clone();
+
+    v_stack((
+        artboard_input(
+            editor_state.clone(),
+            page,
+            artboard,
+            ArtboardField::Name,
+            halves * 2.0,
+        ),
+        h_stack((
+            artboard_input(
+                editor_state.clone(),
+                page,
+                artboard,
+                ArtboardField::Width,
+                halves,
+            )
+            .style(|s| s.margin_right(5.0)),
+            artboard_input(editor_state, page, artboard, ArtboardField::Height, halves),
+        )),
+        h_stack((
+            small_button(
+                "Export",
+                "shapes",
+                move |_| export(&editor_state2, Some(artboard_id)),
+                RwSignal::new(false),
+            )
+            .style(|s| s.margin_right(5.0)),
+            small_button(
+                "Remove",
+                "square",
+                move |_| {
+                    editor_state3
+                        .lock_or_recover()
+                        .update_page(|page| page.remove_artboard(artboard_id));
+                },
+                RwSignal::new(false),
+            )
+            // the document always keeps one artboard
+            .style({
+                let only = page.artboards.len() <= 1;
+                move |s| s.apply_if(only, |s| s.hide())
+            }),
+        ))
+        .style(|s| s.margin_top(5.0)),
+    ))
+    .style(|s| s.margin_bottom(15.0))
+}
+
+pub fn document_view(
+    editor_state: Arc<Mutex<EditorState>>,
+    events: RwSignal<Vec<EditorEvent>>,
+) -> impl IntoView {
+    let page = RwSignal::new(editor_state.lock_or_recover().page_setup());
+
+    subscribe(events, {
+        let editor_state = editor_state.clone();
+        move |event| {
+            if let EditorEvent::PageChanged = event {
+                page.set(editor_state.lock_or_recover().page_setup());
+            }
+        }
+    });
+
+    let aside_width = 260.0;
+    let halves = (aside_width / 2.0) + (5.0 * 2.0);
+    let editor_state2 = editor_state.clone();
+    let editor_state3 = editor_state.clone();
+    let editor_state4 = editor_state.clone();
+
+    v_stack((
+        label(|| "Document").style(|s| s.margin_bottom(10)),
+        styled_input(
+            "DPI:".to_string(),
+            &page.get_untracked().dpi.to_string(),
+            "Enter value",
+            Box::new(|mut editor_state, value| {
+                let _ = editor_state.update_dpi(&value);
+            }),
+            editor_state.clone(),
+            "page_dpi".to_string(),
+        )
+        .style(move |s| s.width(halves)),
+        label(|| "Units").style(|s| s.margin_top(10.0).margin_bottom(5.0)),
+        h_stack_from_iter(Unit::iter().map(|unit| unit_button(editor_state2.clone(), page, unit)))
+            .style(|s| s.flex_wrap(FlexWrap::Wrap)),
+        label(|| "Artboards").style(|s| s.margin_top(10.0).margin_bottom(5.0)),
+        // sizes are shown in the page's unit, so the cards are laid out again when it
+        // changes. Typing a name or size leaves them be.
+        dyn_container(
+            move || {
+                let page = page.get();
+                let ids = page
+                    .artboards
+                    .iter()
+                    .map(|artboard| artboard.id)
+                    .collect::<Vec<_>>();
+                (page.unit, ids)
+            },
+            move |_| {
+                let current = page.get_untracked();
+
+                v_stack_from_iter(
+                    current
+                        .artboards
+                        .iter()
+                        .map(|artboard| {
+                            artboard_card(editor_state3.clone(), &current, artboard, halves)
+                        })
+                        .collect::<Vec<_>>(),
+                )
+                .into_any()
+            },
+        ),
+        h_stack((
+            option_button(
+                "Add Artboard",
+                "plus",
+                Some(move || {
+                    editor_state4.lock_or_recover().update_page(|page| {
+                        page.add_artboard();
+                    });
+                }),
+                false,
+            )
+            .style(|s| s.margin_right(5.0)),
+            option_button(
+                "Export All",
+                "shapes",
+                Some(move || export(&editor_state, None)),
+                false,
+            ),
+        )),
+    ))
+    .style(move |s| s.width(aside_width).padding_bottom(20.0))
+}
@@ -55,6 +55,7 @@ use floem::unit::{Auto, DurationUnitExt, Pct, UnitExt};
 use std::time::Duration;
 
 use crate::editor_state::EditorState;
+use crate::helpers::locking::LockRecover;
 
 pub fn styled_input(
     label_text: String,
@@ -68,12 +69,13 @@ pub fn styled_input(
     let value = create_rw_signal(initial_value.to_string());
 
     let state_2 = Arc::clone(&editor_state);
+    let state_3 = Arc::clone(&editor_state);
 
     create_effect({
         let name = name.clone();
         move |_| {
             // need to value.set in undos defined in properties_panel
-            let mut editor_state = editor_state.lock().unwrap();
+            let mut editor_state = editor_state.lock_or_recover();
             editor_state.register_signal(name.to_string(), value);
         }
     });
@@ -83,7 +85,7 @@ pub fn styled_input(
         text_input(value)
             .on_event_stop(EventListener::KeyUp, move |event: &Event| {
                 if let Event::KeyUp(key_event) = event {
-                    let editor_state = state_2.lock().unwrap();
+                    let editor_state = state_2.lock_or_recover();
 
                     // Handle keyboard shortcuts first
                     if editor_state.current_modifiers.control_key() {
@@ -131,7 +133,26 @@ pub fn styled_input(
                 }
             })
             .placeholder(placeholder)
-            .style(|s| input_styles(s)),
+            .style(|s| input_styles(s))
+            .tracks_focus(state_3),
     ))
     .style(|s| s.margin_bottom(10))
 }
+
+pub trait TracksFocus: Decorators {
+    /// Tells the canvas while the input is focused, so keys typed into it aren't shortcuts
+    fn tracks_focus(self, editor_state: Arc<Mutex<EditorState>>) -> Self {
+        let state_2 = Arc::clone(&editor_state);
+
+        self.on_event(EventListener::FocusGained, move |_| {
+            editor_state.lock_or_recover().text_input_focused = true;
+            EventPropagation::Continue
+        })
+        .on_event(EventListener::FocusLost, move |_| {
+            state_2.lock_or_recover().text_input_focused = false;
+            EventPropagation::Continue
+        })
+    }
+}
+
+impl<V: Decorators> TracksFocus for V {}
@@ -1,8 +1,15 @@
 pub mod app;
 pub mod aside;
 pub mod assets_panel;
+pub mod brush_panel;
 pub mod buttons;
+pub mod document_panel;
 pub mod inputs;
+pub mod navigator_panel;
 pub mod properties_panel;
+pub mod recovery_panel;
+pub mod scopes_panel;
 pub mod settings_panel;
+pub mod theme;
 pub mod tools_panel;
+pub mod zoom_panel;
@@ -0,0 +1,214 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use floem::common::card_styles;
+use floem::event::{Event, EventListener};
+use floem::peniko::Color;
+use floem::reactive::{RwSignal, SignalGet, SignalUpdate};
+use floem::views::Decorators;
+use floem::views::{dyn_container, empty, label, stack, svg, v_stack};
+use floem::{GpuHelper, IntoView};
+
+use crate::editor_state::EditorState;
+use crate::helpers::events::{subscribe, EditorEvent};
+use crate::helpers::locking::LockRecover;
+use crate::helpers::navigation::{Overview, WHEEL_ZOOM_STEP};
+
+use super::theme::{theme_signal, theme_styles};
+use super::zoom_panel::with_queue;
+
+const NAVIGATOR_SIZE: [f32; 2] = [240.0, 160.0];
+// shapes moving under a drag redraw the overview at most this often, the drag's
+// history entry draws where it ended
+const DRAG_REBUILD_INTERVAL: Duration = Duration::from_millis(250);
+
+fn current_overview(editor_state: &EditorState) -> Overview {
+    Overview::new(
+        editor_state.content_bounds(),
+        editor_state.view().visible(),
+        NAVIGATOR_SIZE,
+    )
+}
+
+fn overview_svg(body: &str, overview: Overview) -> String {
+    let ([left, top], [right, bottom]) = overview.extent;
+
+    format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"{} {} {} {}\">\n<g stroke-width=\"{}\">\n{}</g>\n</svg>\n",
+        NAVIGATOR_SIZE[0],
+        NAVIGATOR_SIZE[1],
+        left,
+        top,
+        right - left,
+        bottom - top,
+        // a pixel wide in the panel, however far it's zoomed out
+        overview.scene_distance(1.0),
+        body,
+    )
+}
+
+/// A small wireframe of the whole scene with the part the canvas shows outlined.
+/// Dragging the outline pans the canvas and scrolling over it zooms.
+pub fn navigator_view(
+    editor_state: Arc<Mutex<EditorState>>,
+    gpu_helper: Arc<Mutex<GpuHelper>>,
+    events: RwSignal<Vec<EditorEvent>>,
+) -> impl IntoView {
+    let theme = theme_signal(&editor_state, events);
+    let (body, overview, visible) = {
+        let editor_state = editor_state.lock_or_recover();
+        (
+            RwSignal::new(editor_state.document_snapshot().overview_svg_body()),
+            RwSignal::new(current_overview(&editor_state)),
+            RwSignal::new(editor_state.view().visible()),
+        )
+    };
+    // where the pointer was last, while the outline is dragged
+    let drag_from: RwSignal<Option<[f32; 2]>> = RwSignal::new(None);
+    // when the overview was last drawn again
+    let rebuilt = Mutex::new(Instant::now());
+
+    subscribe(events, {
+        let editor_state = editor_state.clone();
+        move |event| match event {
+            EditorEvent::CameraChanged => {
+                let editor_state = editor_state.lock_or_recover();
+                visible.set(editor_state.view().visible());
+
+                // the overview holds still under a drag, or the outline would slip away
+                if drag_from.get_untracked().is_none() {
+                    overview.set(current_overview(&editor_state));
+                }
+            }
+            EditorEvent::LayersChanged(_)
+            | EditorEvent::PolygonUpdated { .. }
+            | EditorEvent::StrokeUpdated { .. }
+            | EditorEvent::PageChanged
+            | EditorEvent::HistoryChanged { .. } => {
+                let dragged = matches!(
+                    event,
+                    EditorEvent::PolygonUpdated { .. } | EditorEvent::StrokeUpdated { .. }
+                );
+                let mut rebuilt = rebuilt.lock_or_recover();
+                if dragged && rebuilt.elapsed() < DRAG_REBUILD_INTERVAL {
+                    return;
+                }
+                *rebuilt = Instant::now();
+
+                let editor_state = editor_state.lock_or_recover();
+                body.set(editor_state.document_snapshot().overview_svg_body());
+                overview.set(current_overview(&editor_state));
+            }
+            _ => {}
+        }
+    });
+
+    let editor_state2 = editor_state.clone();
+    let editor_state3 = editor_state.clone();
+    let editor_state4 = editor_state.clone();
+    let editor_state5 = editor_state.clone();
+    let gpu_helper2 = gpu_helper.clone();
+    let gpu_helper3 = gpu_helper.clone();
+
+    let end_drag = move |editor_state: &Arc<Mutex<EditorState>>| {
+        if drag_from.get_untracked().is_some() {
+            drag_from.set(None);
+            overview.set(current_overview(&editor_state.lock_or_recover()));
+        }
+    };
+
+    v_stack((
+        label(|| "Navigator").style(|s| s.font_size(14.0).margin_bottom(5.0)),
+        stack((
+            dyn_container(
+                move || overview_svg(&body.get(), overview.get()),
+                |overview_svg| {
+                    svg(overview_svg)
+                        .style(|s| s.width(NAVIGATOR_SIZE[0]).height(NAVIGATOR_SIZE[1]))
+                        .into_any()
+                },
+            ),
+            empty().style(move |s| {
+                let overview = overview.get();
+                let (min, max) = visible.get();
+                let [left, top] = overview.to_panel(min);
+                let [right, bottom] = overview.to_panel(max);
+
+                s.absolute()
+                    .inset_left(left)
+                    .inset_top(top)
+                    .width(right - left)
+                    .height(bottom - top)
+                    .border(1.5)
+                    .border_color(Color::rgb8(47, 131, 222))
+            }),
+        ))
+        .on_event_stop(EventListener::PointerDown, move |event: &Event| {
+            let Event::PointerDown(pointer) = event else {
+                return;
+            };
+            let point = [pointer.pos.x as f32, pointer.pos.y as f32];
+            let overview = overview.get_untracked();
+            let (min, max) = visible.get_untracked();
+            let ([left, top], [right, bottom]) = (overview.to_panel(min), overview.to_panel(max));
+
+            // grabbing outside the outline brings it there first
+            let inside =
+                point[0] >= left && point[0] <= right && point[1] >= top && point[1] <= bottom;
+            if !inside {
+                with_queue(&editor_state, &gpu_helper, |editor_state, queue| {
+                    editor_state.update_view(queue, |view| view.center_on(overview.to_scene(point)))
+                });
+            }
+
+            drag_from.set(Some(point));
+        })
+        .on_event_stop(EventListener::PointerMove, move |event: &Event| {
+            let Event::PointerMove(pointer) = event else {
+                return;
+            };
+            let Some(from) = drag_from.get_untracked() else {
+                return;
+            };
+            let point = [pointer.pos.x as f32, pointer.pos.y as f32];
+            let overview = overview.get_untracked();
+
+            with_queue(&editor_state2, &gpu_helper2, |editor_state, queue| {
+                editor_state.update_view(queue, |view| {
+                    view.shift(
+                        overview.scene_distance(point[0] - from[0]),
+                        overview.scene_distance(point[1] - from[1]),
+                    )
+                })
+            });
+            drag_from.set(Some(point));
+        })
+        .on_event_stop(EventListener::PointerUp, move |_| end_drag(&editor_state3))
+        .on_event_stop(EventListener::PointerLeave, move |_| {
+            end_drag(&editor_state4)
+        })
+        .on_event_stop(EventListener::PointerWheel, move |event: &Event| {
+            let Event::PointerWheel(wheel) = event else {
+                return;
+            };
+            // scrolling up zooms in, as it does over the canvas
+            let step = if wheel.delta.y < 0.0 {
+                WHEEL_ZOOM_STEP
+            } else {
+                1.0 / WHEEL_ZOOM_STEP
+            };
+
+            with_queue(&editor_state5, &gpu_helper3, |editor_state, queue| {
+                editor_state.update_view(queue, |view| view.zoom_centered(view.zoom * step))
+            });
+        })
+        .style(|s| {
+            s.width(NAVIGATOR_SIZE[0])
+                .height(NAVIGATOR_SIZE[1])
+                .background(Color::rgb8(200, 200, 200))
+        }),
+    ))
+    .style(|s| card_styles(s))
+    .style(move |s| theme_styles(s, theme.get()))
+    .style(|s| s.margin_left(20.0).margin_top(20).z_index(10))
+}
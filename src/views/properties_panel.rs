@@ -1,7 +1,9 @@
 use floem::common::card_styles;
+use floem::common::option_button;
 use floem::common::small_button;
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex, MutexGuard};
+use strum::IntoEnumIterator;
 use uuid::Uuid;
 use wgpu::util::DeviceExt;
 
@@ -13,16 +15,26 @@ use common_vector::polygon::{Polygon, PolygonConfig};
 use floem::peniko::{Brush, Color};
 use floem::reactive::{create_effect, create_rw_signal, create_signal, RwSignal, SignalRead};
 use floem::reactive::{SignalGet, SignalUpdate};
+use floem::taffy::FlexWrap;
 use floem::text::Weight;
 use floem::views::Decorators;
-use floem::views::{container, dyn_container, empty, label};
-use floem::views::{h_stack, v_stack};
+use floem::views::{container, dyn_container, dyn_stack, empty, label};
+use floem::views::{h_stack, h_stack_from_iter, v_stack};
 use floem::GpuHelper;
 use floem::IntoView;
 
 use crate::editor_state::{self, EditorState};
+use crate::helpers::blending::BlendMode;
+use crate::helpers::effects::{EffectKind, ShapeEffect};
+use crate::helpers::events::{subscribe, EditorEvent};
+use crate::helpers::locking::LockRecover;
+use crate::photo::adjustments::Adjustment;
+use crate::photo::crop::AspectPreset;
+use crate::photo::filters::{FilterField, FilterKind, FilterMode};
+use crate::photo::metadata::PhotoMetadata;
 
 use super::inputs::styled_input;
+use super::theme::{theme_signal, theme_styles};
 
 pub fn properties_view(
     editor_state: Arc<Mutex<EditorState>>,
@@ -32,8 +44,13 @@ pub fn properties_view(
     polygon_selected: RwSignal<bool>,
     selected_polygon_id: RwSignal<Uuid>,
     selected_polygon_data: RwSignal<PolygonConfig>,
+    events: RwSignal<Vec<EditorEvent>>,
 ) -> impl IntoView {
     // let polygon_data = selected_polygon_data.read();
+    let theme = theme_signal(&editor_state, events);
+    // lengths are shown and typed in the page's unit, "10mm" and the like also work
+    let page = editor_state.lock_or_recover().page_setup();
+    let length_label = move |text: &str| format!("{} ({}):", text, page.unit.label());
 
     let editor_cloned = Arc::clone(&editor);
     let editor_cloned2 = Arc::clone(&editor);
@@ -60,6 +77,10 @@ pub fn properties_view(
     let editor_state12 = Arc::clone(&editor_state);
     let editor_state13 = Arc::clone(&editor_state);
     let editor_state14 = Arc::clone(&editor_state);
+    let editor_state15 = Arc::clone(&editor_state);
+    let editor_state16 = Arc::clone(&editor_state);
+    let editor_state17 = Arc::clone(&editor_state);
+    let editor_state18 = Arc::clone(&editor_state);
 
     let aside_width = 260.0;
     let quarters = (aside_width / 4.0) + (5.0 * 4.0);
@@ -77,15 +98,10 @@ pub fn properties_view(
                     move |_| {
                         println!("Click back!");
                         // this action runs on_click_stop so should stop propagation
-                        polygon_selected.update(|v| {
-                            *v = false;
-                        });
-                        selected_polygon_id.update(|v| {
-                            *v = Uuid::nil();
-                        });
-                        let mut editor_state = editor_state2.lock().unwrap();
-                        editor_state.selected_polygon_id = Uuid::nil();
-                        editor_state.polygon_selected = false;
+                        let editor_state = editor_state2.lock_or_recover();
+                        let _ = editor_state
+                            .events
+                            .send(EditorEvent::SelectionChanged(None));
                     }
                 },
                 back_active,
@@ -96,13 +112,8 @@ pub fn properties_view(
         .style(|s| s.margin_bottom(12.0)),
         h_stack((
             styled_input(
-                "Width:".to_string(),
-                &selected_polygon_data
-                    .read()
-                    .borrow()
-                    .dimensions
-                    .0
-                    .to_string(),
+                length_label("Width"),
+                &page.format_length(selected_polygon_data.read().borrow().dimensions.0),
                 "Enter width",
                 Box::new({
                     move |mut editor_state, value| {
@@ -114,13 +125,8 @@ pub fn properties_view(
             )
             .style(move |s| s.width(halfs).margin_right(5.0)),
             styled_input(
-                "Height:".to_string(),
-                &selected_polygon_data
-                    .read()
-                    .borrow()
-                    .dimensions
-                    .1
-                    .to_string(),
+                length_label("Height"),
+                &page.format_length(selected_polygon_data.read().borrow().dimensions.1),
                 "Enter height",
                 Box::new({
                     move |mut editor_state, value| {
@@ -197,12 +203,8 @@ pub fn properties_view(
             // )])
         }),
         styled_input(
-            "Border Radius:".to_string(),
-            &selected_polygon_data
-                .read()
-                .borrow()
-                .border_radius
-                .to_string(),
+            length_label("Border Radius"),
+            &page.format_length(selected_polygon_data.read().borrow().border_radius),
             "Enter radius",
             Box::new({
                 move |mut editor_state, value| {
@@ -215,13 +217,8 @@ pub fn properties_view(
         label(|| "Stroke").style(|s| s.margin_bottom(5.0)),
         h_stack((
             styled_input(
-                "Thickness:".to_string(),
-                &selected_polygon_data
-                    .read()
-                    .borrow()
-                    .stroke
-                    .thickness
-                    .to_string(),
+                length_label("Thickness"),
+                &page.format_length(selected_polygon_data.read().borrow().stroke.thickness),
                 "Enter thickness",
                 Box::new({
                     move |mut editor_state, value| {
@@ -273,8 +270,27 @@ pub fn properties_view(
             .style(move |s| s.width(quarters)),
         ))
         .style(move |s| s.width(aside_width)),
+        option_button(
+            "Save To Assets",
+            "shapes",
+            Some(move || {
+                let editor_state = editor_state18.lock_or_recover();
+                if let Err(error) =
+                    editor_state.save_shape_asset(selected_polygon_id.get_untracked())
+                {
+                    println!("{}", error);
+                }
+            }),
+            false,
+        )
+        .style(|s| s.margin_top(7.0)),
+        blend_view(editor_state16, selected_polygon_id.get_untracked(), events),
+        effects_view(editor_state17, selected_polygon_id.get_untracked(), events),
+        adjustments_view(editor_state18, selected_polygon_id.get_untracked(), events),
+        mask_view(editor_state15, selected_polygon_id.get_untracked(), events),
     ))
     .style(|s| card_styles(s))
+    .style(move |s| theme_styles(s, theme.get()))
     .style(|s| {
         s.width(300)
             // .absolute()
@@ -284,3 +300,1100 @@ pub fn properties_view(
             .z_index(10)
     })
 }
+
+pub fn stroke_properties_view(
+    editor_state: Arc<Mutex<EditorState>>,
+    selected_stroke_id: Uuid,
+    events: RwSignal<Vec<EditorEvent>>,
+) -> impl IntoView {
+    let theme = theme_signal(&editor_state, events);
+    let settings = editor_state
+        .lock_or_recover()
+        .strokes
+        .lock_or_recover()
+        .get(selected_stroke_id)
+        .map(|layer| layer.settings)
+        .unwrap_or_default();
+
+    let aside_width = 260.0;
+    let thirds = (aside_width / 3.0) + (5.0 * 3.0);
+    let halfs = (aside_width / 2.0) + (5.0 * 2.0);
+
+    let back_active = RwSignal::new(false);
+
+    v_stack((
+        h_stack((
+            small_button(
+                "",
+                "arrow-left",
+                {
+                    let editor_state = editor_state.clone();
+                    move |_| {
+                        let editor_state = editor_state.lock_or_recover();
+                        let _ = editor_state
+                            .events
+                            .send(EditorEvent::StrokeSelectionChanged(None));
+                    }
+                },
+                back_active,
+            )
+            .style(|s| s.margin_right(7.0)),
+            label(|| "Stroke").style(|s| s.font_size(24.0).font_weight(Weight::THIN)),
+        ))
+        .style(|s| s.margin_bottom(12.0)),
+        h_stack((
+            styled_input(
+                "Width:".to_string(),
+                &settings.size.to_string(),
+                "Enter width",
+                Box::new({
+                    move |mut editor_state, value| {
+                        editor_state.update_stroke_width(&value);
+                    }
+                }),
+                editor_state.clone(),
+                "stroke_layer_width".to_string(),
+            )
+            .style(move |s| s.width(halfs).margin_right(5.0)),
+            styled_input(
+                "Opacity (%):".to_string(),
+                &(settings.opacity * 100.0).round().to_string(),
+                "0-100",
+                Box::new({
+                    move |mut editor_state, value| {
+                        editor_state.update_stroke_opacity(&value);
+                    }
+                }),
+                editor_state.clone(),
+                "stroke_layer_opacity".to_string(),
+            )
+            .style(move |s| s.width(halfs)),
+        ))
+        .style(move |s| s.width(aside_width)),
+        h_stack((
+            styled_input(
+                "Red:".to_string(),
+                &wgpu_to_human(settings.color[0]).to_string(),
+                "0-255",
+                Box::new({
+                    move |mut editor_state, value| {
+                        editor_state.update_stroke_layer_red(&value);
+                    }
+                }),
+                editor_state.clone(),
+                "stroke_layer_red".to_string(),
+            )
+            .style(move |s| s.width(thirds).margin_right(5.0)),
+            styled_input(
+                "Green:".to_string(),
+                &wgpu_to_human(settings.color[1]).to_string(),
+                "0-255",
+                Box::new({
+                    move |mut editor_state, value| {
+                        editor_state.update_stroke_layer_green(&value);
+                    }
+                }),
+                editor_state.clone(),
+                "stroke_layer_green".to_string(),
+            )
+            .style(move |s| s.width(thirds).margin_right(5.0)),
+            styled_input(
+                "Blue:".to_string(),
+                &wgpu_to_human(settings.color[2]).to_string(),
+                "0-255",
+                Box::new({
+                    move |mut editor_state, value| {
+                        editor_state.update_stroke_layer_blue(&value);
+                    }
+                }),
+                editor_state.clone(),
+                "stroke_layer_blue".to_string(),
+            )
+            .style(move |s| s.width(thirds)),
+        ))
+        .style(move |s| s.width(aside_width)),
+        option_button(
+            "Delete Stroke",
+            "brush",
+            Some({
+                let editor_state = editor_state.clone();
+                move || {
+                    editor_state.lock_or_recover().delete_selected_stroke();
+                }
+            }),
+            false,
+        ),
+        blend_view(editor_state.clone(), selected_stroke_id, events),
+        adjustments_view(editor_state.clone(), selected_stroke_id, events),
+        mask_view(editor_state, selected_stroke_id, events),
+    ))
+    .style(|s| card_styles(s))
+    .style(move |s| theme_styles(s, theme.get()))
+    .style(|s| {
+        s.width(300)
+            .height(800.0)
+            .margin_left(0.0)
+            .margin_top(20)
+            .z_index(10)
+    })
+}
+
+pub fn image_properties_view(
+    editor_state: Arc<Mutex<EditorState>>,
+    selected_image_id: Uuid,
+    events: RwSignal<Vec<EditorEvent>>,
+) -> impl IntoView {
+    let theme = theme_signal(&editor_state, events);
+    let metadata = editor_state
+        .lock_or_recover()
+        .image_metadata(selected_image_id);
+
+    let back_active = RwSignal::new(false);
+
+    v_stack((
+        h_stack((
+            small_button(
+                "",
+                "arrow-left",
+                {
+                    let editor_state = editor_state.clone();
+                    move |_| {
+                        let editor_state = editor_state.lock_or_recover();
+                        let _ = editor_state
+                            .events
+                            .send(EditorEvent::ImageSelectionChanged(None));
+                    }
+                },
+                back_active,
+            )
+            .style(|s| s.margin_right(7.0)),
+            label(|| "Image").style(|s| s.font_size(24.0).font_weight(Weight::THIN)),
+        ))
+        .style(|s| s.margin_bottom(12.0)),
+        option_button(
+            "Delete Image",
+            "square",
+            Some({
+                let editor_state = editor_state.clone();
+                move || {
+                    editor_state.lock_or_recover().delete_selected_image();
+                }
+            }),
+            false,
+        ),
+        crop_view(editor_state.clone(), selected_image_id, events),
+        blend_view(editor_state.clone(), selected_image_id, events),
+        adjustments_view(editor_state.clone(), selected_image_id, events),
+        filters_view(editor_state.clone(), selected_image_id, events),
+        mask_view(editor_state, selected_image_id, events),
+        metadata_view(metadata),
+    ))
+    .style(|s| card_styles(s))
+    .style(move |s| theme_styles(s, theme.get()))
+    .style(|s| {
+        s.width(300)
+            .height(800.0)
+            .margin_left(0.0)
+            .margin_top(20)
+            .z_index(10)
+    })
+}
+
+/// Crop, turn and flip for a photo. The crop is kept as parameters on the layer, so any
+/// of it can be changed or reset later.
+pub fn crop_view(
+    editor_state: Arc<Mutex<EditorState>>,
+    image_id: Uuid,
+    events: RwSignal<Vec<EditorEvent>>,
+) -> impl IntoView {
+    let crop = editor_state.lock_or_recover().image_crop(image_id);
+    let cropping = create_rw_signal(editor_state.lock_or_recover().cropping() == Some(image_id));
+
+    subscribe(events, {
+        let editor_state = editor_state.clone();
+        move |event| {
+            if let EditorEvent::CropChanged(id) = event {
+                if *id == image_id {
+                    cropping.set(editor_state.lock_or_recover().cropping() == Some(image_id));
+                }
+            }
+        }
+    });
+
+    let aside_width = 260.0;
+    let halfs = (aside_width / 2.0) + (5.0 * 2.0);
+
+    v_stack((
+        label(|| "Crop").style(|s| s.margin_top(10.0).margin_bottom(5.0)),
+        small_button(
+            "Crop on Canvas",
+            "square",
+            {
+                let editor_state = editor_state.clone();
+                move |_| {
+                    let editor_state = editor_state.lock_or_recover();
+                    let cropping = editor_state.cropping() == Some(image_id);
+                    editor_state.set_cropping((!cropping).then_some(image_id));
+                }
+            },
+            cropping,
+        ),
+        label(|| "Drag along the horizon to straighten").style(move |s| {
+            s.color(Color::GRAY)
+                .margin_vert(5.0)
+                .apply_if(!cropping.get(), |s| s.hide())
+        }),
+        h_stack_from_iter(
+            AspectPreset::iter()
+                // free leaves the crop as it is, reset goes back to the whole photo
+                .filter(|preset| *preset != AspectPreset::Free)
+                .map(|preset| {
+                    let editor_state = editor_state.clone();
+                    small_button(
+                        preset.label(),
+                        "square",
+                        move |_| {
+                            editor_state
+                                .lock_or_recover()
+                                .apply_crop_preset(image_id, preset);
+                        },
+                        RwSignal::new(false),
+                    )
+                }),
+        )
+        .style(move |s| {
+            s.flex_wrap(FlexWrap::Wrap)
+                .width(aside_width)
+                .margin_vert(7.0)
+        }),
+        h_stack((
+            small_button(
+                "Left",
+                "arrow-left",
+                {
+                    let editor_state = editor_state.clone();
+                    move |_| {
+                        editor_state.lock_or_recover().rotate_crop(image_id, -90.0);
+                    }
+                },
+                RwSignal::new(false),
+            ),
+            small_button(
+                "Right",
+                "square",
+                {
+                    let editor_state = editor_state.clone();
+                    move |_| {
+                        editor_state.lock_or_recover().rotate_crop(image_id, 90.0);
+                    }
+                },
+                RwSignal::new(false),
+            ),
+            small_button(
+                "Flip H",
+                "square",
+                {
+                    let editor_state = editor_state.clone();
+                    move |_| {
+                        editor_state.lock_or_recover().flip_crop(image_id, true);
+                    }
+                },
+                RwSignal::new(false),
+            ),
+            small_button(
+                "Flip V",
+                "square",
+                {
+                    let editor_state = editor_state.clone();
+                    move |_| {
+                        editor_state.lock_or_recover().flip_crop(image_id, false);
+                    }
+                },
+                RwSignal::new(false),
+            ),
+        ))
+        .style(move |s| s.flex_wrap(FlexWrap::Wrap).width(aside_width)),
+        h_stack((
+            styled_input(
+                "Rotation (°):".to_string(),
+                &crop.rotation.to_string(),
+                "Enter degrees",
+                Box::new({
+                    move |mut editor_state, value| {
+                        let _ = editor_state.update_crop_rotation(&value);
+                    }
+                }),
+                editor_state.clone(),
+                "crop_rotation".to_string(),
+            )
+            .style(move |s| s.width(halfs).margin_right(5.0)),
+            small_button(
+                "Reset",
+                "square",
+                {
+                    let editor_state = editor_state.clone();
+                    move |_| {
+                        editor_state.lock_or_recover().reset_crop(image_id);
+                    }
+                },
+                RwSignal::new(false),
+            ),
+        ))
+        .style(move |s| s.width(aside_width).items_end().margin_top(7.0)),
+    ))
+}
+
+/// The filters on a photo. A filter is previewed on the canvas while its settings are
+/// changed, then applied to the pixels or added as an effect that can be removed later.
+pub fn filters_view(
+    editor_state: Arc<Mutex<EditorState>>,
+    image_id: Uuid,
+    events: RwSignal<Vec<EditorEvent>>,
+) -> impl IntoView {
+    let rows = {
+        let editor_state = editor_state.clone();
+        move || -> Vec<(usize, &'static str, bool)> {
+            let filters = editor_state.lock_or_recover().image_filters(image_id);
+            let baked = filters
+                .baked
+                .iter()
+                .enumerate()
+                .map(|(index, filter)| (index, filter.kind().label(), true));
+            let effects = filters
+                .effects
+                .iter()
+                .enumerate()
+                .map(|(index, filter)| (index, filter.kind().label(), false));
+
+            baked.chain(effects).collect()
+        }
+    };
+    let preview_kind = {
+        let editor_state = editor_state.clone();
+        move || {
+            editor_state
+                .lock_or_recover()
+                .filter_preview(image_id)
+                .map(|filter| filter.kind())
+        }
+    };
+    let layout = create_rw_signal(rows());
+    let previewing = create_rw_signal(preview_kind());
+
+    // the preview inputs are only rebuilt when another filter is tried, so they keep
+    // their focus while the settings change
+    subscribe(events, move |event| {
+        if let EditorEvent::FiltersChanged(id) = event {
+            if *id != image_id {
+                return;
+            }
+
+            let next = rows();
+            if next != layout.get_untracked() {
+                layout.set(next);
+            }
+            let next = preview_kind();
+            if next != previewing.get_untracked() {
+                previewing.set(next);
+            }
+        }
+    });
+
+    let aside_width = 260.0;
+    let quarters = (aside_width / 4.0) + (5.0 * 4.0);
+
+    v_stack((
+        label(|| "Filters").style(|s| s.margin_top(10.0).margin_bottom(5.0)),
+        h_stack_from_iter(FilterKind::iter().map(|kind| {
+            let editor_state = editor_state.clone();
+            small_button(
+                kind.label(),
+                "plus",
+                move |_| {
+                    editor_state
+                        .lock_or_recover()
+                        .start_filter_preview(image_id, kind);
+                },
+                RwSignal::new(false),
+            )
+        }))
+        .style(move |s| {
+            s.flex_wrap(FlexWrap::Wrap)
+                .width(aside_width)
+                .margin_bottom(7.0)
+        }),
+        dyn_stack(move || layout.get(), |row| *row, {
+            let editor_state = editor_state.clone();
+            move |(index, name, baked)| {
+                // baked filters are part of the pixels now, only effects come off
+                let remove = if baked {
+                    label(|| "(applied)")
+                        .style(|s| s.color(Color::GRAY))
+                        .into_any()
+                } else {
+                    let editor_state = editor_state.clone();
+                    small_button(
+                        "Remove",
+                        "square",
+                        move |_| {
+                            editor_state
+                                .lock_or_recover()
+                                .remove_filter_effect(image_id, index);
+                        },
+                        RwSignal::new(false),
+                    )
+                    .into_any()
+                };
+
+                h_stack((label(move || name).style(|s| s.width(110.0)), remove))
+                    .style(|s| s.items_center().margin_bottom(5.0))
+            }
+        })
+        .style(|s| s.flex_col()),
+        dyn_container(
+            move || previewing.get(),
+            move |kind| {
+                let Some(kind) = kind else {
+                    return empty().into_any();
+                };
+                let filter = editor_state
+                    .lock_or_recover()
+                    .filter_preview(image_id)
+                    .unwrap_or_else(|| kind.default_filter());
+
+                v_stack((
+                    label(move || format!("Previewing {}", kind.label()))
+                        .style(|s| s.color(Color::GRAY).margin_vert(5.0)),
+                    h_stack_from_iter(filter.fields().into_iter().map(|field: FilterField| {
+                        let value = field.read(&filter).unwrap_or_default();
+
+                        styled_input(
+                            field.label().to_string(),
+                            &value.to_string(),
+                            "0",
+                            Box::new(move |mut editor_state, value| {
+                                let _ = editor_state.update_filter_field(image_id, field, &value);
+                            }),
+                            editor_state.clone(),
+                            format!("filter_{:?}", field),
+                        )
+                        .style(move |s| s.width(quarters).margin_right(5.0))
+                    }))
+                    .style(move |s| s.flex_wrap(FlexWrap::Wrap).width(aside_width)),
+                    h_stack((
+                        small_button(
+                            "Apply",
+                            "square",
+                            {
+                                let editor_state = editor_state.clone();
+                                move |_| {
+                                    editor_state
+                                        .lock_or_recover()
+                                        .apply_filter_preview(image_id, FilterMode::Destructive);
+                                }
+                            },
+                            RwSignal::new(false),
+                        )
+                        .style(|s| s.margin_right(5.0)),
+                        small_button(
+                            "Add as Effect",
+                            "square",
+                            {
+                                let editor_state = editor_state.clone();
+                                move |_| {
+                                    editor_state
+                                        .lock_or_recover()
+                                        .apply_filter_preview(image_id, FilterMode::Effect);
+                                }
+                            },
+                            RwSignal::new(false),
+                        )
+                        .style(|s| s.margin_right(5.0)),
+                        small_button(
+                            "Cancel",
+                            "square",
+                            {
+                                let editor_state = editor_state.clone();
+                                move |_| {
+                                    editor_state.lock_or_recover().stop_filter_preview();
+                                }
+                            },
+                            RwSignal::new(false),
+                        ),
+                    ))
+                    .style(move |s| {
+                        s.flex_wrap(FlexWrap::Wrap)
+                            .width(aside_width)
+                            .margin_top(7.0)
+                    }),
+                ))
+                .into_any()
+            },
+        ),
+    ))
+}
+
+fn blend_mode_button(
+    editor_state: Arc<Mutex<EditorState>>,
+    layer_id: Uuid,
+    blend_mode: RwSignal<BlendMode>,
+    mode: BlendMode,
+) -> impl IntoView {
+    let active = RwSignal::new(blend_mode.get_untracked() == mode);
+
+    create_effect(move |_| {
+        active.set(blend_mode.get() == mode);
+    });
+
+    small_button(
+        mode.label(),
+        "square",
+        move |_| {
+            editor_state
+                .lock_or_recover()
+                .set_blend_mode(layer_id, mode);
+        },
+        active,
+    )
+}
+
+/// Blend mode and opacity for a layer, shared by the polygon and stroke panels
+pub fn blend_view(
+    editor_state: Arc<Mutex<EditorState>>,
+    layer_id: Uuid,
+    events: RwSignal<Vec<EditorEvent>>,
+) -> impl IntoView {
+    let blend = editor_state.lock_or_recover().layer_blend(layer_id);
+    let blend_mode = create_rw_signal(blend.mode);
+
+    // follows undo as well as the buttons
+    subscribe(events, {
+        let editor_state = editor_state.clone();
+        move |event| {
+            if let EditorEvent::BlendChanged(id) = event {
+                if *id == layer_id {
+                    blend_mode.set(editor_state.lock_or_recover().layer_blend(layer_id).mode);
+                }
+            }
+        }
+    });
+
+    let aside_width = 260.0;
+
+    v_stack((
+        label(|| "Blending").style(|s| s.margin_top(10.0).margin_bottom(5.0)),
+        h_stack((
+            blend_mode_button(
+                editor_state.clone(),
+                layer_id,
+                blend_mode,
+                BlendMode::Normal,
+            ),
+            blend_mode_button(
+                editor_state.clone(),
+                layer_id,
+                blend_mode,
+                BlendMode::Multiply,
+            ),
+            blend_mode_button(
+                editor_state.clone(),
+                layer_id,
+                blend_mode,
+                BlendMode::Screen,
+            ),
+            blend_mode_button(
+                editor_state.clone(),
+                layer_id,
+                blend_mode,
+                BlendMode::Overlay,
+            ),
+            blend_mode_button(
+                editor_state.clone(),
+                layer_id,
+                blend_mode,
+                BlendMode::Darken,
+            ),
+            blend_mode_button(
+                editor_state.clone(),
+                layer_id,
+                blend_mode,
+                BlendMode::Lighten,
+            ),
+            blend_mode_button(
+                editor_state.clone(),
+                layer_id,
+                blend_mode,
+                BlendMode::Difference,
+            ),
+            blend_mode_button(editor_state.clone(), layer_id, blend_mode, BlendMode::Color),
+            blend_mode_button(
+                editor_state.clone(),
+                layer_id,
+                blend_mode,
+                BlendMode::Luminosity,
+            ),
+        ))
+        .style(move |s| {
+            s.flex_wrap(FlexWrap::Wrap)
+                .width(aside_width)
+                .margin_bottom(7.0)
+        }),
+        styled_input(
+            "Layer Opacity (%):".to_string(),
+            &(blend.opacity * 100.0).round().to_string(),
+            "0-100",
+            Box::new({
+                move |mut editor_state, value| {
+                    editor_state.update_layer_opacity(&value);
+                }
+            }),
+            editor_state,
+            "layer_opacity".to_string(),
+        )
+        .style(move |s| s.width(aside_width)),
+    ))
+}
+
+fn add_effect_button(
+    editor_state: Arc<Mutex<EditorState>>,
+    layer_id: Uuid,
+    kind: EffectKind,
+) -> impl IntoView {
+    small_button(
+        kind.label(),
+        "plus",
+        move |_| {
+            editor_state.lock_or_recover().add_effect(layer_id, kind);
+        },
+        RwSignal::new(false),
+    )
+}
+
+fn effect_view(
+    editor_state: Arc<Mutex<EditorState>>,
+    layer_id: Uuid,
+    index: usize,
+    kind: EffectKind,
+    enabled: bool,
+) -> impl IntoView {
+    let effect = editor_state
+        .lock_or_recover()
+        .shape_effects(layer_id)
+        .get(index)
+        .copied()
+        .unwrap_or(ShapeEffect::new(kind));
+
+    let aside_width = 260.0;
+    let quarters = (aside_width / 4.0) + (5.0 * 4.0);
+
+    v_stack((
+        h_stack((
+            label(move || kind.label()).style(|s| s.width(110.0)),
+            small_button(
+                if enabled { "Hide" } else { "Show" },
+                "square",
+                {
+                    let editor_state = editor_state.clone();
+                    move |_| {
+                        editor_state
+                            .lock_or_recover()
+                            .toggle_effect(layer_id, index);
+                    }
+                },
+                RwSignal::new(!enabled),
+            )
+            .style(|s| s.margin_right(5.0)),
+            small_button(
+                "Remove",
+                "square",
+                {
+                    let editor_state = editor_state.clone();
+                    move |_| {
+                        editor_state
+                            .lock_or_recover()
+                            .remove_effect(layer_id, index);
+                    }
+                },
+                RwSignal::new(false),
+            ),
+        ))
+        .style(|s| s.margin_bottom(5.0)),
+        dyn_stack(
+            move || kind.fields(),
+            |field| *field,
+            move |field| {
+                styled_input(
+                    field.label().to_string(),
+                    &field.read(&effect).to_string(),
+                    "0",
+                    Box::new(move |mut editor_state, value| {
+                        editor_state.update_effect_field(layer_id, index, field, &value);
+                    }),
+                    editor_state.clone(),
+                    field.signal_name(index),
+                )
+                .style(move |s| s.width(quarters).margin_right(5.0))
+            },
+        )
+        .style(move |s| s.flex_row().flex_wrap(FlexWrap::Wrap).width(aside_width)),
+    ))
+    .style(|s| s.margin_bottom(5.0))
+}
+
+/// The effects stack for a polygon, drawn top to bottom in stack order
+pub fn effects_view(
+    editor_state: Arc<Mutex<EditorState>>,
+    layer_id: Uuid,
+    events: RwSignal<Vec<EditorEvent>>,
+) -> impl IntoView {
+    let entries = {
+        let editor_state = editor_state.clone();
+        move || -> Vec<(EffectKind, bool)> {
+            editor_state
+                .lock_or_recover()
+                .shape_effects(layer_id)
+                .iter()
+                .map(|effect| (effect.kind, effect.enabled))
+                .collect()
+        }
+    };
+    let layout = create_rw_signal(entries());
+
+    // rows are only rebuilt when effects come, go or are hidden, so inputs keep their focus
+    subscribe(events, move |event| {
+        if let EditorEvent::EffectsChanged(id) = event {
+            if *id != layer_id {
+                return;
+            }
+
+            let next = entries();
+            if next != layout.get_untracked() {
+                layout.set(next);
+            }
+        }
+    });
+
+    let aside_width = 260.0;
+
+    v_stack((
+        label(|| "Effects").style(|s| s.margin_top(10.0).margin_bottom(5.0)),
+        h_stack((
+            add_effect_button(editor_state.clone(), layer_id, EffectKind::DropShadow),
+            add_effect_button(editor_state.clone(), layer_id, EffectKind::InnerShadow),
+            add_effect_button(editor_state.clone(), layer_id, EffectKind::LayerBlur),
+            add_effect_button(editor_state.clone(), layer_id, EffectKind::OuterGlow),
+        ))
+        .style(move |s| {
+            s.flex_wrap(FlexWrap::Wrap)
+                .width(aside_width)
+                .margin_bottom(7.0)
+        }),
+        dyn_stack(
+            move || layout.get().into_iter().enumerate().collect::<Vec<_>>(),
+            |entry| *entry,
+            move |(index, (kind, enabled))| {
+                effect_view(editor_state.clone(), layer_id, index, kind, enabled)
+            },
+        )
+        .style(|s| s.flex_col()),
+    ))
+}
+
+/// Mask and clipping controls for a layer, shared by the polygon and stroke panels
+pub fn mask_view(
+    editor_state: Arc<Mutex<EditorState>>,
+    layer_id: Uuid,
+    events: RwSignal<Vec<EditorEvent>>,
+) -> impl IntoView {
+    let status = create_rw_signal(editor_state.lock_or_recover().mask_status(layer_id));
+
+    subscribe(events, {
+        let editor_state = editor_state.clone();
+        move |event| {
+            if let EditorEvent::MaskChanged(id) = event {
+                if *id == layer_id {
+                    status.set(editor_state.lock_or_recover().mask_status(layer_id));
+                }
+            }
+        }
+    });
+
+    // each button needs its own handle on the editor state
+    let action = move |f: fn(&mut EditorState, Uuid)| {
+        let editor_state = editor_state.clone();
+        Some(move || f(&mut editor_state.lock_or_recover(), layer_id))
+    };
+
+    v_stack((
+        label(|| "Mask").style(|s| s.margin_top(10.0).margin_bottom(5.0)),
+        dyn_container(
+            move || status.get(),
+            move |(links, enabled, editing)| {
+                let mask_buttons = if links.masked {
+                    v_stack((
+                        h_stack((
+                            option_button(
+                                if editing { "Stop Editing" } else { "Edit Mask" },
+                                "brush",
+                                action(|editor_state, layer_id| {
+                                    let editing = editor_state.mask_editing == Some(layer_id);
+                                    editor_state.set_mask_editing((!editing).then_some(layer_id));
+                                }),
+                                editing,
+                            )
+                            .style(|s| s.margin_right(5.0)),
+                            option_button(
+                                if enabled {
+                                    "Disable Mask"
+                                } else {
+                                    "Enable Mask"
+                                },
+                                "square",
+                                action(|editor_state, layer_id| {
+                                    editor_state.toggle_mask_enabled(layer_id)
+                                }),
+                                false,
+                            ),
+                        )),
+                        label(|| "Paint with a brush to show, erase to hide").style(move |s| {
+                            s.color(Color::GRAY)
+                                .margin_vert(5.0)
+                                .apply_if(!editing, |s| s.hide())
+                        }),
+                        option_button(
+                            "Delete Mask",
+                            "square",
+                            action(|editor_state, layer_id| editor_state.delete_mask(layer_id)),
+                            false,
+                        ),
+                    ))
+                    .into_any()
+                } else {
+                    option_button(
+                        "Add Mask",
+                        "square",
+                        action(|editor_state, layer_id| editor_state.add_mask(layer_id)),
+                        false,
+                    )
+                    .into_any()
+                };
+
+                v_stack((
+                    mask_buttons,
+                    h_stack((
+                        option_button(
+                            "Mask With Layer Above",
+                            "square",
+                            action(|editor_state, layer_id| {
+                                editor_state.mask_with_layer_above(layer_id)
+                            }),
+                            false,
+                        )
+                        .style(|s| s.margin_right(5.0)),
+                        option_button(
+                            if links.clipped {
+                                "Release Clipping"
+                            } else {
+                                "Clip To Layer Below"
+                            },
+                            "arrow-left",
+                            action(|editor_state, layer_id| editor_state.toggle_clipping(layer_id)),
+                            links.clipped,
+                        ),
+                    ))
+                    .style(|s| s.margin_top(5.0)),
+                ))
+            },
+        ),
+    ))
+}
+
+/// The metadata section for an imported photo, read only
+pub fn metadata_view(metadata: PhotoMetadata) -> impl IntoView {
+    let fields = metadata.fields();
+    let is_empty = fields.is_empty();
+
+    let aside_width = 260.0;
+    let halfs = (aside_width / 2.0) + (5.0 * 2.0);
+
+    v_stack((
+        label(|| "Metadata").style(|s| s.font_size(14.0).margin_bottom(10)),
+        label(|| "No metadata").style(move |s| s.apply_if(!is_empty, |s| s.hide())),
+        dyn_stack(
+            move || fields.clone(),
+            |(name, value)| format!("{}{}", name, value),
+            move |(name, value)| {
+                h_stack((
+                    label(move || name.clone()).style(move |s| s.width(halfs).color(Color::GRAY)),
+                    label(move || value.clone()).style(move |s| s.width(halfs)),
+                ))
+                .style(|s| s.margin_bottom(5.0))
+            },
+        )
+        .style(|s| s.flex_col()),
+    ))
+    .style(move |s| s.width(aside_width).margin_top(15.0))
+}
+
+fn add_adjustment_button(
+    editor_state: Arc<Mutex<EditorState>>,
+    layer_id: Uuid,
+    adjustment: Adjustment,
+) -> impl IntoView {
+    small_button(
+        adjustment.label(),
+        "plus",
+        move |_| {
+            editor_state
+                .lock_or_recover()
+                .add_adjustment(layer_id, adjustment.clone());
+        },
+        RwSignal::new(false),
+    )
+}
+
+fn adjustment_view(
+    editor_state: Arc<Mutex<EditorState>>,
+    layer_id: Uuid,
+    entry_id: Uuid,
+    enabled: bool,
+) -> impl IntoView {
+    let Some(entry) = editor_state
+        .lock_or_recover()
+        .layer_adjustments(layer_id)
+        .get(entry_id)
+        .cloned()
+    else {
+        return empty().into_any();
+    };
+    let adjustment = entry.adjustment;
+    let name = adjustment.label();
+
+    let aside_width = 260.0;
+    let quarters = (aside_width / 4.0) + (5.0 * 4.0);
+
+    let button = |text: &'static str, active: bool, f: fn(&mut EditorState, Uuid, Uuid)| {
+        let editor_state = editor_state.clone();
+        small_button(
+            text,
+            "square",
+            move |_| f(&mut editor_state.lock_or_recover(), layer_id, entry_id),
+            RwSignal::new(active),
+        )
+    };
+
+    v_stack((
+        h_stack((
+            label(move || name).style(|s| s.width(110.0)),
+            button(
+                if enabled { "Hide" } else { "Show" },
+                !enabled,
+                |editor_state, layer_id, entry_id| {
+                    editor_state.toggle_adjustment(layer_id, entry_id)
+                },
+            )
+            .style(|s| s.margin_right(5.0)),
+            button("Remove", false, |editor_state, layer_id, entry_id| {
+                editor_state.remove_adjustment(layer_id, entry_id)
+            }),
+        ))
+        .style(|s| s.margin_bottom(5.0)),
+        // earlier in the stack is applied first
+        h_stack((
+            button("Up", false, |editor_state, layer_id, entry_id| {
+                editor_state.move_adjustment(layer_id, entry_id, -1)
+            })
+            .style(|s| s.margin_right(5.0)),
+            button("Down", false, |editor_state, layer_id, entry_id| {
+                editor_state.move_adjustment(layer_id, entry_id, 1)
+            }),
+        ))
+        .style(|s| s.margin_bottom(5.0)),
+        dyn_stack(
+            {
+                let adjustment = adjustment.clone();
+                move || adjustment.fields()
+            },
+            |field| *field,
+            move |field| {
+                let value = field.read(&adjustment).unwrap_or_default();
+
+                styled_input(
+                    field.label().to_string(),
+                    &value.to_string(),
+                    "0",
+                    Box::new(move |mut editor_state, value| {
+                        editor_state.update_adjustment_field(layer_id, entry_id, field, &value);
+                    }),
+                    editor_state.clone(),
+                    field.signal_name(entry_id),
+                )
+                .style(move |s| s.width(quarters).margin_right(5.0))
+            },
+        )
+        .style(move |s| s.flex_row().flex_wrap(FlexWrap::Wrap).width(aside_width)),
+    ))
+    .style(|s| s.margin_bottom(5.0))
+    .into_any()
+}
+
+/// The adjustment stack for a layer, applied top to bottom. Shared by the polygon,
+/// stroke and image panels.
+pub fn adjustments_view(
+    editor_state: Arc<Mutex<EditorState>>,
+    layer_id: Uuid,
+    events: RwSignal<Vec<EditorEvent>>,
+) -> impl IntoView {
+    let entries = {
+        let editor_state = editor_state.clone();
+        move || -> Vec<(Uuid, bool)> {
+            editor_state
+                .lock_or_recover()
+                .layer_adjustments(layer_id)
+                .entries()
+                .iter()
+                .map(|entry| (entry.id, entry.enabled))
+                .collect()
+        }
+    };
+    let layout = create_rw_signal(entries());
+
+    // rows are only rebuilt when adjustments come, go, move or are hidden, so inputs
+    // keep their focus
+    subscribe(events, move |event| {
+        if let EditorEvent::AdjustmentsChanged(id) = event {
+            if *id != layer_id {
+                return;
+            }
+
+            let next = entries();
+            if next != layout.get_untracked() {
+                layout.set(next);
+            }
+        }
+    });
+
+    let aside_width = 260.0;
+
+    v_stack((
+        label(|| "Adjustments").style(|s| s.margin_top(10.0).margin_bottom(5.0)),
+        h_stack_from_iter(
+            Adjustment::all().into_iter().map(|adjustment| {
+                add_adjustment_button(editor_state.clone(), layer_id, adjustment)
+            }),
+        )
+        .style(move |s| {
+            s.flex_wrap(FlexWrap::Wrap)
+                .width(aside_width)
+                .margin_bottom(7.0)
+        }),
+        dyn_stack(
+            move || layout.get(),
+            |entry| *entry,
+            move |(entry_id, enabled)| {
+                adjustment_view(editor_state.clone(), layer_id, entry_id, enabled)
+            },
+        )
+        .style(|s| s.flex_col()),
+    ))
+}
@@ -0,0 +1,138 @@
+use std::sync::{Arc, Mutex};
+
+use common_vector::basic::WindowSize;
+use common_vector::editor::Viewport;
+use floem::common::{card_styles, small_button};
+use floem::peniko::Color;
+use floem::reactive::{RwSignal, SignalGet, SignalUpdate};
+use floem::taffy::AlignItems;
+use floem::views::Decorators;
+use floem::views::{dyn_container, empty, h_stack, label, svg, v_stack, v_stack_from_iter};
+use floem::{GpuHelper, IntoView};
+
+use crate::document::recovery::Recovery;
+use crate::editor_state::EditorState;
+use crate::helpers::events::EditorEvent;
+use crate::helpers::locking::LockRecover;
+
+use super::theme::{theme_signal, theme_styles};
+
+fn recovery_row(
+    editor_state: Arc<Mutex<EditorState>>,
+    gpu_helper: Arc<Mutex<GpuHelper>>,
+    viewport: Arc<Mutex<Viewport>>,
+    recoveries: RwSignal<Vec<Recovery>>,
+    recovery: Recovery,
+) -> impl IntoView {
+    let preview = recovery.file.document.preview_svg();
+    let saved_label = recovery.saved_label();
+    let layer_count = recovery.file.document.layer_list.len();
+    let restore_active = RwSignal::new(false);
+    let discard_active = RwSignal::new(false);
+    let discarded = recovery.clone();
+
+    v_stack((
+        if preview.is_empty() {
+            empty().into_any()
+        } else {
+            svg(preview)
+                .style(|s| s.width(240).height(135).margin_bottom(5.0))
+                .into_any()
+        },
+        label(move || saved_label.clone()),
+        label(move || format!("{} layers", layer_count))
+            .style(|s| s.font_size(10.0).color(Color::GRAY).margin_bottom(5.0)),
+        h_stack((
+            small_button(
+                "Restore",
+                "arrow-left",
+                move |_| {
+                    let mut editor_state = editor_state.lock_or_recover();
+                    let gpu_helper = gpu_helper.lock_or_recover();
+                    let device = &gpu_helper
+                        .gpu_resources
+                        .as_ref()
+                        .expect("Couldn't get gpu resources")
+                        .device;
+                    let viewport = viewport.lock_or_recover();
+                    let window_size = WindowSize {
+                        width: viewport.width as u32,
+                        height: viewport.height as u32,
+                    };
+
+                    match editor_state.restore_recovery(recovery.file.clone(), device, &window_size)
+                    {
+                        Ok(()) => {
+                            // this session's autosave carries it on from here
+                            if let Err(error) = recovery.discard() {
+                                println!("{}", error);
+                            }
+                            // only one document is open, the others wait for the next launch
+                            recoveries.set(Vec::new());
+                        }
+                        Err(error) => println!("{}", error),
+                    }
+                },
+                restore_active,
+            )
+            .style(|s| s.margin_right(5.0)),
+            small_button(
+                "Discard",
+                "square",
+                move |_| {
+                    if let Err(error) = discarded.discard() {
+                        println!("{}", error);
+                    }
+                    let path = discarded.path.clone();
+                    recoveries
+                        .update(|recoveries| recoveries.retain(|recovery| recovery.path != path));
+                },
+                discard_active,
+            ),
+        )),
+    ))
+    .style(|s| s.margin_bottom(15.0).align_items(AlignItems::Start))
+}
+
+/// Offers to bring back what earlier sessions autosaved, until it's restored or discarded
+pub fn recovery_view(
+    editor_state: Arc<Mutex<EditorState>>,
+    gpu_helper: Arc<Mutex<GpuHelper>>,
+    viewport: Arc<Mutex<Viewport>>,
+    recoveries: RwSignal<Vec<Recovery>>,
+    events: RwSignal<Vec<EditorEvent>>,
+) -> impl IntoView {
+    let theme = theme_signal(&editor_state, events);
+
+    dyn_container(
+        move || recoveries.get().len(),
+        move |count| {
+            if count == 0 {
+                return empty().into_any();
+            }
+
+            let editor_state = editor_state.clone();
+            let gpu_helper = gpu_helper.clone();
+            let viewport = viewport.clone();
+
+            v_stack((
+                label(|| "Recover Unsaved Work").style(|s| s.font_size(14.0).margin_bottom(5.0)),
+                label(|| "These were autosaved before the editor last closed")
+                    .style(|s| s.font_size(10.0).color(Color::GRAY).margin_bottom(15.0)),
+                v_stack_from_iter(recoveries.get_untracked().into_iter().map(move |recovery| {
+                    recovery_row(
+                        editor_state.clone(),
+                        gpu_helper.clone(),
+                        viewport.clone(),
+                        recoveries,
+                        recovery,
+                    )
+                })),
+            ))
+            .style(|s| card_styles(s))
+            .style(move |s| theme_styles(s, theme.get()))
+            .style(|s| s.width(300).margin_left(20.0).margin_top(20).z_index(20))
+            .into_any()
+        },
+    )
+}
@@ -0,0 +1,100 @@
+use std::sync::{Arc, Mutex};
+
+use floem::common::card_styles;
+use floem::peniko::Color;
+use floem::reactive::{RwSignal, SignalGet, SignalUpdate};
+use floem::taffy::AlignItems;
+use floem::views::Decorators;
+use floem::views::{dyn_stack, empty, h_stack, label, v_stack};
+use floem::IntoView;
+
+use crate::editor_state::EditorState;
+use crate::helpers::events::{subscribe, EditorEvent};
+use crate::photo::scopes::Histogram;
+
+use super::theme::{theme_signal, theme_styles};
+
+// bars across the panel, each a group of four levels
+const HISTOGRAM_BINS: usize = 64;
+const HISTOGRAM_HEIGHT: f64 = 80.0;
+const BAR_WIDTH: f64 = 4.0;
+
+/// A luminance histogram with clipping readouts. The signal is filled in by a ScopeWorker,
+/// so the panel only redraws once a pass has finished.
+pub fn histogram_view(histogram: RwSignal<Option<Histogram>>) -> impl IntoView {
+    let bars = move || {
+        histogram
+            .get()
+            .map(|histogram| histogram.bins(&histogram.luminance, HISTOGRAM_BINS))
+            .unwrap_or_else(|| vec![0.0; HISTOGRAM_BINS])
+            .into_iter()
+            .enumerate()
+            .collect::<Vec<_>>()
+    };
+
+    let clipping = move |shadows: bool| {
+        histogram
+            .get()
+            .map(|histogram| {
+                let clipping = histogram.clipping();
+                let fraction = if shadows {
+                    clipping.shadows
+                } else {
+                    clipping.highlights
+                };
+                format!("{:.1}%", fraction * 100.0)
+            })
+            .unwrap_or_else(|| "-".to_string())
+    };
+
+    v_stack((
+        label(|| "Histogram").style(|s| s.font_size(14.0).margin_bottom(10)),
+        dyn_stack(
+            bars,
+            // bars are rebuilt when their height changes
+            |(index, height)| (*index, (height * HISTOGRAM_HEIGHT as f32) as u32),
+            |(_, height)| {
+                empty().style(move |s| {
+                    s.width(BAR_WIDTH)
+                        .height(height as f64 * HISTOGRAM_HEIGHT)
+                        .background(Color::DIM_GRAY)
+                })
+            },
+        )
+        .style(|s| {
+            s.flex_row()
+                .align_items(AlignItems::FlexEnd)
+                .height(HISTOGRAM_HEIGHT)
+                .background(Color::rgb8(240, 240, 240))
+        }),
+        h_stack((
+            label(move || format!("Shadows clipped: {}", clipping(true)))
+                .style(|s| s.font_size(10.0).margin_right(10.0)),
+            label(move || format!("Highlights clipped: {}", clipping(false)))
+                .style(|s| s.font_size(10.0)),
+        ))
+        .style(|s| s.margin_top(5.0)),
+    ))
+    .style(|s| s.margin_top(15.0))
+}
+
+/// The histogram of the canvas as it's shown. The render callback reads the canvas back
+/// whenever it changes, adjustments and blending included, and counts it off the UI thread.
+pub fn scopes_view(
+    editor_state: Arc<Mutex<EditorState>>,
+    events: RwSignal<Vec<EditorEvent>>,
+) -> impl IntoView {
+    let theme = theme_signal(&editor_state, events);
+    let histogram = RwSignal::new(None);
+
+    subscribe(events, move |event| {
+        if let EditorEvent::HistogramUpdated(updated) = event {
+            histogram.set(Some(updated.clone()));
+        }
+    });
+
+    histogram_view(histogram)
+        .style(|s| card_styles(s))
+        .style(move |s| theme_styles(s, theme.get()))
+        .style(|s| s.margin_left(20.0).margin_top(20).z_index(10))
+}
@@ -1,6 +1,204 @@
+use std::sync::{Arc, Mutex};
+
+use floem::common::small_button;
+use floem::reactive::{create_effect, RwSignal, SignalGet, SignalUpdate};
+use floem::taffy::FlexWrap;
 use floem::views::Decorators;
-use floem::{views::label, IntoView};
+use floem::views::{h_stack, h_stack_from_iter, label, scroll, v_stack};
+use floem::IntoView;
+use strum::IntoEnumIterator;
+
+use crate::editor_state::EditorState;
+use crate::helpers::locking::LockRecover;
+use crate::helpers::preferences::{PreferenceField, Preferences, SampleCount, Theme, Unit};
+use crate::photo::metadata::ExportMetadata;
+
+use super::inputs::styled_input;
+
+fn section_label(text: &'static str) -> impl IntoView {
+    label(move || text).style(|s| s.margin_top(10.0).margin_bottom(5.0))
+}
+
+fn preference_input(
+    editor_state: Arc<Mutex<EditorState>>,
+    preferences: &Preferences,
+    field: PreferenceField,
+    width: f32,
+) -> impl IntoView {
+    styled_input(
+        field.label().to_string(),
+        &field.read(preferences).to_string(),
+        "Enter value",
+        Box::new(move |mut editor_state, value| {
+            // half typed numbers are left alone until they parse
+            let _ = editor_state.update_preference_field(field, &value);
+        }),
+        editor_state,
+        field.signal_name(),
+    )
+    .style(move |s| s.width(width))
+}
+
+// the three channels of a colour side by side
+fn color_inputs(
+    editor_state: Arc<Mutex<EditorState>>,
+    preferences: &Preferences,
+    fields: [PreferenceField; 3],
+) -> impl IntoView {
+    h_stack_from_iter(fields.map(|field| {
+        preference_input(editor_state.clone(), preferences, field, 80.0)
+            .style(|s| s.margin_right(5.0))
+    }))
+}
+
+fn choice_button<T: Copy + PartialEq + 'static>(
+    editor_state: Arc<Mutex<EditorState>>,
+    current: RwSignal<T>,
+    choice: T,
+    text: &'static str,
+    apply: fn(&mut Preferences, T),
+) -> impl IntoView {
+    let active = RwSignal::new(current.get_untracked() == choice);
+
+    create_effect(move |_| {
+        active.set(current.get() == choice);
+    });
+
+    small_button(
+        text,
+        "gear",
+        move |_| {
+            editor_state
+                .lock_or_recover()
+                .update_preferences(|preferences| apply(preferences, choice));
+            current.set(choice);
+        },
+        active,
+    )
+    .style(|s| s.margin_right(5.0))
+}
+
+pub fn settings_view(editor_state: Arc<Mutex<EditorState>>) -> impl IntoView {
+    let preferences = editor_state
+        .lock_or_recover()
+        .preferences
+        .lock_or_recover()
+        .clone();
+
+    let units = RwSignal::new(preferences.units);
+    let sample_count = RwSignal::new(preferences.sample_count);
+    let theme = RwSignal::new(preferences.theme);
+    let photo_metadata = RwSignal::new(preferences.photo_metadata);
+
+    let aside_width = 260.0;
 
-pub fn settings_view() -> impl IntoView {
-    (label(move || format!("Settings")).style(|s| s.margin_bottom(10)),)
+    scroll(
+        v_stack((
+            label(|| "Settings").style(|s| s.margin_bottom(10)),
+            section_label("New Shapes"),
+            label(|| "Fill").style(|s| s.font_size(10.0).margin_bottom(3.0)),
+            color_inputs(
+                editor_state.clone(),
+                &preferences,
+                [
+                    PreferenceField::FillRed,
+                    PreferenceField::FillGreen,
+                    PreferenceField::FillBlue,
+                ],
+            ),
+            label(|| "Stroke").style(|s| s.font_size(10.0).margin_bottom(3.0)),
+            color_inputs(
+                editor_state.clone(),
+                &preferences,
+                [
+                    PreferenceField::StrokeRed,
+                    PreferenceField::StrokeGreen,
+                    PreferenceField::StrokeBlue,
+                ],
+            ),
+            preference_input(
+                editor_state.clone(),
+                &preferences,
+                PreferenceField::StrokeThickness,
+                aside_width,
+            ),
+            section_label("Units for New Documents"),
+            h_stack_from_iter(Unit::iter().map(|unit| {
+                choice_button(
+                    editor_state.clone(),
+                    units,
+                    unit,
+                    unit.label(),
+                    |preferences, unit| preferences.units = unit,
+                )
+            }))
+            .style(|s| s.flex_wrap(FlexWrap::Wrap)),
+            section_label("Canvas"),
+            label(|| "Background").style(|s| s.font_size(10.0).margin_bottom(3.0)),
+            color_inputs(
+                editor_state.clone(),
+                &preferences,
+                [
+                    PreferenceField::BackgroundRed,
+                    PreferenceField::BackgroundGreen,
+                    PreferenceField::BackgroundBlue,
+                ],
+            ),
+            label(|| "Antialiasing").style(|s| s.font_size(10.0).margin_bottom(3.0)),
+            h_stack_from_iter(SampleCount::iter().map(|count| {
+                choice_button(
+                    editor_state.clone(),
+                    sample_count,
+                    count,
+                    count.label(),
+                    |preferences, count| preferences.sample_count = count,
+                )
+            })),
+            section_label("Autosave"),
+            preference_input(
+                editor_state.clone(),
+                &preferences,
+                PreferenceField::AutosaveMinutes,
+                aside_width,
+            ),
+            section_label("Snapping"),
+            h_stack((
+                preference_input(
+                    editor_state.clone(),
+                    &preferences,
+                    PreferenceField::SnapDistance,
+                    125.0,
+                )
+                .style(|s| s.margin_right(10.0)),
+                preference_input(
+                    editor_state.clone(),
+                    &preferences,
+                    PreferenceField::PickTolerance,
+                    125.0,
+                ),
+            )),
+            section_label("Export"),
+            label(|| "Photo Metadata").style(|s| s.font_size(10.0).margin_bottom(3.0)),
+            h_stack_from_iter(ExportMetadata::iter().map(|choice| {
+                choice_button(
+                    editor_state.clone(),
+                    photo_metadata,
+                    choice,
+                    choice.label(),
+                    |preferences, metadata| preferences.photo_metadata = metadata,
+                )
+            })),
+            section_label("Theme"),
+            h_stack_from_iter(Theme::iter().map(|choice| {
+                choice_button(
+                    editor_state.clone(),
+                    theme,
+                    choice,
+                    choice.label(),
+                    |preferences, theme| preferences.theme = theme,
+                )
+            })),
+        ))
+        .style(move |s| s.width(aside_width).padding_bottom(20.0)),
+    )
 }
@@ -0,0 +1,38 @@
+use std::sync::{Arc, Mutex};
+
+use floem::peniko::Color;
+use floem::reactive::{RwSignal, SignalUpdate};
+use floem::style::Style;
+
+use crate::editor_state::EditorState;
+use crate::helpers::events::{subscribe, EditorEvent};
+use crate::helpers::locking::LockRecover;
+use crate::helpers::preferences::Theme;
+
+/// The chosen theme, kept up to date for as long as the calling view lives
+pub fn theme_signal(
+    editor_state: &Arc<Mutex<EditorState>>,
+    events: RwSignal<Vec<EditorEvent>>,
+) -> RwSignal<Theme> {
+    let preferences = Arc::clone(&editor_state.lock_or_recover().preferences);
+    let theme = RwSignal::new(preferences.lock_or_recover().theme);
+
+    subscribe(events, move |event| {
+        if let EditorEvent::PreferencesChanged = event {
+            theme.set(preferences.lock_or_recover().theme);
+        }
+    });
+
+    theme
+}
+
+// goes after card_styles, which is always light
+pub fn theme_styles(s: Style, theme: Theme) -> Style {
+    match theme {
+        Theme::Light => s,
+        Theme::Dark => s
+            .background(Color::rgb8(40, 40, 40))
+            .border_color(Color::rgb8(70, 70, 70))
+            .color(Color::rgb8(230, 230, 230)),
+    }
+}
@@ -2,7 +2,7 @@ use std::borrow::{Borrow, BorrowMut};
 use std::cell::RefCell;
 use std::fmt::Display;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::rc::{Rc, Weak};
 use std::sync::{Arc, Mutex, MutexGuard};
 
@@ -11,9 +11,7 @@ use common_vector::basic::{
     color_to_wgpu, rgb_to_wgpu, string_to_f32, wgpu_to_human, Point, WindowSize,
 };
 use common_vector::dot::draw_dot;
-use common_vector::editor::{
-    self, ControlMode, Editor, LayersUpdateHandler, ToolCategory, Viewport,
-};
+use common_vector::editor::{self, ControlMode, Editor, ToolCategory, Viewport};
 use common_vector::guideline::create_guide_line_buffers;
 use common_vector::polygon::{self, Polygon, PolygonConfig, Stroke};
 use floem::common::{card_styles, option_button, small_button};
@@ -23,7 +21,7 @@ use floem::style::{Background, CursorStyle, Transition};
 use floem::taffy::{AlignItems, Display as TaffyDisplay, FlexDirection, FlexWrap};
 use floem::views::{
     container, dyn_container, dyn_stack, empty, label, list, scroll, stack, tab, text_input,
-    virtual_stack, RadioButton, StackExt, VirtualDirection, VirtualItemSize,
+    v_stack_from_iter, virtual_stack, RadioButton, StackExt, VirtualDirection, VirtualItemSize,
 };
 
 use floem_renderer::gpu_resources;
@@ -48,53 +46,99 @@ use floem::{GpuHelper, View, WindowHandle};
 use strum::IntoEnumIterator;
 use strum_macros::EnumIter;
 
-use super::buttons::sortable_item;
+use crate::document::set_polygon_stroke;
+use crate::editor_state::EditorState;
+use crate::helpers::events::{subscribe, EditorEvent};
+use crate::helpers::layers::{apply_layer_change, Layer, LayerKind};
+use crate::helpers::locking::LockRecover;
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
-pub enum LayerKind {
-    Polygon,
-    // Path,
-    // Image,
-    // Text,
-    // Group,
+use super::brush_panel::brushes_view;
+use super::buttons::sortable_item;
+use super::inputs::styled_input;
+use super::theme::{theme_signal, theme_styles};
+
+fn artboard_names(editor_state: &EditorState) -> Vec<(Uuid, String)> {
+    editor_state
+        .page_setup()
+        .artboards
+        .into_iter()
+        .map(|artboard| (artboard.id, artboard.name))
+        .collect()
 }
 
-#[derive(Clone, PartialEq, Eq)]
-pub struct Layer {
-    pub instance_id: Uuid,
-    pub instance_name: String,
-    pub instance_kind: LayerKind,
-}
+/// The layers sitting on one artboard, or on none of them
+fn scene_section(
+    editor_state: Arc<Mutex<EditorState>>,
+    dragger_id: RwSignal<Uuid>,
+    layers: RwSignal<Vec<Layer>>,
+    artboard: Option<Uuid>,
+    name: String,
+) -> impl IntoView {
+    // shapes off every artboard are rare, so that section only shows when there are some
+    let hidden =
+        move || artboard.is_none() && !layers.get().iter().any(|layer| layer.artboard.is_none());
 
-impl Layer {
-    pub fn from_polygon_config(config: &PolygonConfig) -> Self {
-        Layer {
-            instance_id: config.id,
-            instance_name: config.name.clone(),
-            instance_kind: LayerKind::Polygon,
-        }
-    }
+    v_stack((
+        label(move || name.clone()).style(|s| s.font_size(12.0).margin_top(5.0).margin_left(10.0)),
+        dyn_stack(
+            move || {
+                layers
+                    .get()
+                    .into_iter()
+                    .filter(|layer| layer.artboard == artboard)
+                    .collect::<Vec<_>>()
+            },
+            // rows are rebuilt when their mask or clipping changes
+            |layer: &Layer| (layer.instance_id, layer.links),
+            move |layer| {
+                let editor_state = editor_state.clone();
+                let icon_name = match layer.instance_kind {
+                    LayerKind::Polygon => "triangle",
+                    LayerKind::Stroke => "brush",
+                    LayerKind::Image => "square",
+                    // LayerKind::Path =>
+                    //         // LayerKind::Imag(data) =>
+                    //         // LayerKind::Text =>
+                    //         // LayerKind::Group =>
+                };
+                sortable_item(
+                    editor_state,
+                    dragger_id,
+                    layer.instance_id,
+                    layer.instance_name.clone(),
+                    icon_name,
+                    layer.links,
+                )
+            },
+        )
+        .style(|s: floem::style::Style| s.flex_col().column_gap(5).padding(10)),
+    ))
+    .style(move |s| s.apply_if(hidden(), |s| s.hide()))
 }
 
 pub fn tools_view(
+    editor_state: Arc<Mutex<EditorState>>,
     gpu_helper: Arc<Mutex<GpuHelper>>,
     editor: std::sync::Arc<Mutex<common_vector::editor::Editor>>,
     viewport: Arc<Mutex<Viewport>>,
+    events: RwSignal<Vec<EditorEvent>>,
 ) -> impl IntoView {
     // let ui_update_trigger = create_rw_signal(0);
     let window_height = create_rw_signal(0.0);
     let layers: RwSignal<Vec<Layer>> = create_rw_signal(Vec::new());
 
-    let layers_ref = Arc::new(Mutex::new(layers));
-
     let editor_cloned = Arc::clone(&editor);
     let editor_cloned2 = Arc::clone(&editor);
     let editor_cloned3 = Arc::clone(&editor);
     let editor_cloned4 = Arc::clone(&editor);
-    let editor_cloned5 = Arc::clone(&editor);
-    let editor_cloned6 = Arc::clone(&editor);
+    let editor_state2 = Arc::clone(&editor_state);
+    let editor_state3 = Arc::clone(&editor_state);
+    let editor_state4 = Arc::clone(&editor_state);
     let gpu_cloned = Arc::clone(&gpu_helper);
     let viewport_cloned = Arc::clone(&viewport);
+    let viewport_cloned2 = Arc::clone(&viewport);
+    let preferences = Arc::clone(&editor_state.lock_or_recover().preferences);
+    let theme = theme_signal(&editor_state, events);
 
     let shape_tab_active = RwSignal::new(true);
     let brush_tab_active = RwSignal::new(false);
@@ -102,6 +146,9 @@ pub fn tools_view(
     let edge_mode_active = RwSignal::new(false);
     let tool_category = RwSignal::new(ToolCategory::Shape);
     let control_mode = RwSignal::new(ControlMode::Point);
+    let brush_kind = RwSignal::new(editor_state.lock_or_recover().brush_settings.kind);
+    let brush_tool = RwSignal::new(editor_state.lock_or_recover().brush_tool);
+    let photo_path = create_rw_signal(String::new());
 
     // let mode_picker = ControlMode::iter()
     //     .map(move |fm| RadioButton::new_labeled_rw(fm, control_mode, move || fm))
@@ -110,7 +157,7 @@ pub fn tools_view(
     create_effect({
         move |_| {
             let selected_mode = control_mode.get();
-            let mut editor = editor_cloned4.lock().unwrap();
+            let mut editor = editor_cloned4.lock_or_recover();
             println!("selected_mode {:?}", selected_mode);
             editor.control_mode = selected_mode;
         }
@@ -119,81 +166,48 @@ pub fn tools_view(
     create_effect({
         let editor_cloned3 = Arc::clone(&editor_cloned2);
         move |_| {
-            let mut editor = editor_cloned3.lock().unwrap();
-            let viewport = editor.viewport.lock().unwrap();
+            let mut editor = editor_cloned3.lock_or_recover();
+            let viewport = editor.viewport.lock_or_recover();
 
             window_height.set(viewport.height);
         }
     });
 
-    // Update ui list when layers list is updated
-    let handle_layers_update: Arc<LayersUpdateHandler> = Arc::new({
-        // let set_counter_ref = Arc::clone(&set_counter_ref);
-        let layers_ref = Arc::clone(&layers_ref);
-        move || {
-            let new_editor = editor_cloned2.clone();
-            // let set_counter_ref = set_counter_ref.clone();
-            let layers_ref = layers_ref.clone();
-            Some(Box::new(move |polygon_data: PolygonConfig| {
-                // cannot lock editor here!
-                // {
-                //     let mut editor = new_editor.lock().unwrap();
-                //     // Update editor as needed
-                // }
-
-                if let Ok(mut layers) = layers_ref.lock() {
-                    // layers.update(|c| {
-                    //     *c = true;
-                    // });
-                    let new_layer: Layer = Layer::from_polygon_config(&polygon_data);
-
-                    // if (new_layers.len() > 0) {
-                    // println!("Layers change {:?}", new_layers.len());
-                    // layers.set(new_layers);
-                    // layers.update(|c| c.push(new_layers[0].clone()));
-                    layers.update(|l| l.push(new_layer));
-                    // ui_update_trigger.update(|count| *count += 1);
-                    // }
-                }
-            }) as Box<dyn FnMut(PolygonConfig) + Send>)
-        }
-    });
-
-    // Use create_effect to set the handler only once
-    create_effect({
-        let handle_layers_update = Arc::clone(&handle_layers_update);
-        let editor_cloned3 = Arc::clone(&editor_cloned3);
-        move |_| {
-            let mut editor = editor_cloned3.lock().unwrap();
-            editor.handle_layers_update = Some(Arc::clone(&handle_layers_update));
-            // editor.run_layers_update(); // not good here
-        }
-    });
-
     // if polygons are already set, reset layer list upon remount
     create_effect({
-        let editor_cloned5 = Arc::clone(&editor_cloned5);
+        let editor_state = Arc::clone(&editor_state);
 
         move |_| {
-            let mut editor = editor_cloned5.lock().unwrap();
-
-            editor.layer_list.iter().for_each(|layer_id| {
-                let polygon = editor
-                    .polygons
-                    .iter()
-                    .find(|polygon| polygon.id == *layer_id)
-                    .expect("Couldn't find polygon for layer");
-
-                let polygon_config: PolygonConfig = polygon.to_config();
-
-                let new_layer: Layer = Layer::from_polygon_config(&polygon_config);
+            let current = editor_state.lock_or_recover().scene_layers();
+            layers.set(current);
+        }
+    });
 
-                layers.update(|l| l.push(new_layer));
+    // Update ui list when layers list is updated
+    subscribe(events, move |event| {
+        if let EditorEvent::LayersChanged(changes) = event {
+            layers.update(|l| {
+                for change in changes {
+                    apply_layer_change(l, change);
+                }
             });
         }
     });
 
     let dragger_id = create_rw_signal(Uuid::nil());
+    let artboards = create_rw_signal(artboard_names(&editor_state.lock_or_recover()));
+
+    subscribe(events, {
+        let editor_state = Arc::clone(&editor_state);
+        move |event| {
+            if let EditorEvent::PageChanged = event {
+                let current = artboard_names(&editor_state.lock_or_recover());
+                if current != artboards.get_untracked() {
+                    artboards.set(current);
+                }
+            }
+        }
+    });
 
     v_stack((
         // label(move || format!("Tools")).style(|s| s.margin_bottom(10)),
@@ -245,8 +259,13 @@ pub fn tools_view(
                         let viewport = viewport.clone();
 
                         let editor_cloned = editor_cloned.clone();
+                        let editor_state3 = editor_state3.clone();
+                        let editor_state4 = editor_state4.clone();
                         let gpu_cloned = gpu_cloned.clone();
                         let viewport_cloned = viewport_cloned.clone();
+                        let viewport_cloned2 = viewport_cloned2.clone();
+                        let preferences = preferences.clone();
+                        let preferences2 = preferences.clone();
 
                         if tool_category_real == ToolCategory::Shape {
                             v_stack((
@@ -284,8 +303,9 @@ pub fn tools_view(
                                         "Add Polygon",
                                         "triangle",
                                         Some(move || {
-                                            let mut editor = editor.lock().unwrap();
-                                            // let mut handler = handler.lock().unwrap();
+                                            let mut editor = editor.lock_or_recover();
+                                            let preferences = preferences.lock_or_recover().clone();
+                                            // let mut handler = handler.lock_or_recover();
                                             println!("Handle click...");
 
                                             // handler.handle_button_click(editor);
@@ -301,26 +321,26 @@ pub fn tools_view(
                                                 dimensions: (100.0, 100.0),
                                                 position: Point { x: 600.0, y: 100.0 },
                                                 border_radius: 5.0,
-                                                fill: [1.0, 1.0, 1.0, 1.0],
+                                                fill: preferences.fill,
                                                 stroke: Stroke {
-                                                    fill: [1.0, 1.0, 1.0, 1.0],
-                                                    thickness: 2.0,
+                                                    fill: preferences.stroke,
+                                                    thickness: preferences.stroke_thickness,
                                                 },
                                             };
-                                            let gpu_helper = gpu_helper.lock().unwrap();
+                                            let gpu_helper = gpu_helper.lock_or_recover();
                                             let device = &gpu_helper
                                                 .gpu_resources
                                                 .as_ref()
                                                 .expect("Couldn't get gpu resources")
                                                 .device;
-                                            let viewport = viewport.lock().unwrap();
+                                            let viewport = viewport.lock_or_recover();
                                             let window_size = WindowSize {
                                                 width: viewport.width as u32,
                                                 height: viewport.height as u32,
                                             };
                                             let camera =
                                                 editor.camera.expect("Couldn't get camera");
-                                            editor.add_polygon(Polygon::new(
+                                            let polygon = Polygon::new(
                                                 &window_size,
                                                 &device,
                                                 &camera,
@@ -330,7 +350,14 @@ pub fn tools_view(
                                                 polygon_config.border_radius,
                                                 polygon_config.fill,
                                                 "Polygon".to_string(),
-                                            ));
+                                            );
+                                            let polygon_id = polygon.id;
+                                            editor.add_polygon(polygon);
+                                            set_polygon_stroke(
+                                                &mut editor,
+                                                polygon_id,
+                                                &polygon_config.stroke,
+                                            );
                                         }),
                                         false,
                                     )
@@ -339,8 +366,10 @@ pub fn tools_view(
                                         "Add Square",
                                         "square",
                                         Some(move || {
-                                            let mut editor = editor_cloned.lock().unwrap();
-                                            // let mut square_handler = square_handler.lock().unwrap();
+                                            let mut editor = editor_cloned.lock_or_recover();
+                                            let preferences =
+                                                preferences2.lock_or_recover().clone();
+                                            // let mut square_handler = square_handler.lock_or_recover();
                                             println!("Handle square...");
 
                                             // square_handler.handle_button_click(editor_cloned);
@@ -357,26 +386,26 @@ pub fn tools_view(
                                                 dimensions: (100.0, 100.0),
                                                 position: Point { x: 600.0, y: 100.0 },
                                                 border_radius: 5.0,
-                                                fill: [1.0, 1.0, 1.0, 1.0],
+                                                fill: preferences.fill,
                                                 stroke: Stroke {
-                                                    fill: [1.0, 1.0, 1.0, 1.0],
-                                                    thickness: 2.0,
+                                                    fill: preferences.stroke,
+                                                    thickness: preferences.stroke_thickness,
                                                 },
                                             };
-                                            let gpu_helper = gpu_cloned.lock().unwrap();
+                                            let gpu_helper = gpu_cloned.lock_or_recover();
                                             let device = &gpu_helper
                                                 .gpu_resources
                                                 .as_ref()
                                                 .expect("Couldn't get gpu resources")
                                                 .device;
-                                            let viewport = viewport_cloned.lock().unwrap();
+                                            let viewport = viewport_cloned.lock_or_recover();
                                             let window_size = WindowSize {
                                                 width: viewport.width as u32,
                                                 height: viewport.height as u32,
                                             };
                                             let camera =
                                                 editor.camera.expect("Couldn't get camera");
-                                            editor.add_polygon(Polygon::new(
+                                            let polygon = Polygon::new(
                                                 &window_size,
                                                 &device,
                                                 &camera,
@@ -386,50 +415,69 @@ pub fn tools_view(
                                                 polygon_config.border_radius,
                                                 polygon_config.fill,
                                                 "Polygon".to_string(),
-                                            ));
+                                            );
+                                            let polygon_id = polygon.id;
+                                            editor.add_polygon(polygon);
+                                            set_polygon_stroke(
+                                                &mut editor,
+                                                polygon_id,
+                                                &polygon_config.stroke,
+                                            );
                                         }),
                                         false,
                                     ),
                                 ))
                                 .style(|s| s.flex_wrap(FlexWrap::Wrap).margin_top(5.0)),
+                                // photos come in as image layers, centred in the view
+                                v_stack((
+                                    styled_input(
+                                        "Photo:".to_string(),
+                                        &photo_path.get_untracked(),
+                                        "Path to a JPEG, PNG, GIF, BMP or WebP",
+                                        Box::new(move |_, value| photo_path.set(value)),
+                                        editor_state3.clone(),
+                                        "photo_path".to_string(),
+                                    )
+                                    .style(|s| s.margin_bottom(5.0)),
+                                    option_button(
+                                        "Import Photo",
+                                        "plus",
+                                        Some(move || {
+                                            let window_size = {
+                                                let viewport = viewport_cloned2.lock_or_recover();
+                                                WindowSize {
+                                                    width: viewport.width as u32,
+                                                    height: viewport.height as u32,
+                                                }
+                                            };
+                                            let center = [
+                                                window_size.width as f32 / 2.0,
+                                                window_size.height as f32 / 2.0,
+                                            ];
+                                            let path = PathBuf::from(photo_path.get().trim());
+
+                                            if let Err(error) = editor_state4
+                                                .lock_or_recover()
+                                                .import_image(path, center, &window_size)
+                                            {
+                                                println!("{}", error);
+                                            }
+                                        }),
+                                        false,
+                                    ),
+                                ))
+                                .style(|s| s.margin_top(7.0)),
                             ))
                             .into_any()
                         } else if tool_category_real == ToolCategory::Brush {
-                            container((
-                                option_button(
-                                    "Use Solid",
-                                    "brush",
-                                    Some(move || {
-                                        // let mut editor = editor.lock().unwrap();
-                                        // let mut handler = handler.lock().unwrap();
-                                        println!("Handle Solid...");
-                                    }),
-                                    false,
-                                )
-                                .style(|s| s.margin_right(5.0)),
-                                option_button(
-                                    "Use Calligraphy",
-                                    "brush",
-                                    Some(move || {
-                                        // let mut editor = editor.lock().unwrap();
-                                        // let mut handler = handler.lock().unwrap();
-                                        println!("Handle Calligraphy...");
-                                    }),
-                                    false,
-                                )
-                                .style(|s| s.margin_right(5.0)),
-                                option_button(
-                                    "Use Airbrush",
-                                    "brush",
-                                    Some(move || {
-                                        // let mut editor = editor_cloned.lock().unwrap();
-                                        // let mut square_handler = square_handler.lock().unwrap();
-                                        println!("Handle Airbrush...");
-                                    }),
-                                    false,
-                                ),
-                            ))
-                            .style(|s| s.flex_wrap(FlexWrap::Wrap).margin_top(5.0))
+                            let editor_state = editor_state3.clone();
+                            dyn_container(
+                                move || (brush_kind.get(), brush_tool.get()),
+                                move |_| {
+                                    brushes_view(editor_state.clone(), brush_kind, brush_tool)
+                                        .into_any()
+                                },
+                            )
                             .into_any()
                         } else {
                             empty().into_any()
@@ -439,6 +487,7 @@ pub fn tools_view(
             )),
         ))
         .style(|s| card_styles(s))
+        .style(move |s| theme_styles(s, theme.get()))
         .style(move |s| {
             s.width(300)
                 // .absolute()
@@ -468,34 +517,42 @@ pub fn tools_view(
                 //     s.display(TaffyDisplay::Flex)
                 //         .flex_direction(FlexDirection::Column)
                 // }),
-                dyn_stack(
-                    move || layers.get(),
-                    |layer: &Layer| layer.instance_id,
-                    move |layer| {
-                        let editor = editor_cloned6.clone();
-                        let icon_name = match layer.instance_kind {
-                            LayerKind::Polygon => "triangle",
-                            // LayerKind::Path =>
-                            //         // LayerKind::Imag(data) =>
-                            //         // LayerKind::Text =>
-                            //         // LayerKind::Group =>
-                        };
-                        sortable_item(
-                            editor,
-                            layers,
+                // one section per artboard, laid out again when they're added, removed or renamed
+                dyn_container(
+                    move || artboards.get(),
+                    move |artboards| {
+                        let outside = scene_section(
+                            editor_state2.clone(),
                             dragger_id,
-                            layer.instance_id,
-                            layer.instance_name.clone(),
-                            icon_name,
+                            layers,
+                            None,
+                            "Outside Artboards".to_string(),
+                        );
+
+                        v_stack_from_iter(
+                            artboards
+                                .into_iter()
+                                .map(|(artboard_id, name)| {
+                                    scene_section(
+                                        editor_state2.clone(),
+                                        dragger_id,
+                                        layers,
+                                        Some(artboard_id),
+                                        name,
+                                    )
+                                    .into_any()
+                                })
+                                .chain(std::iter::once(outside.into_any())),
                         )
+                        .into_any()
                     },
                 )
-                .style(|s: floem::style::Style| s.flex_col().column_gap(5).padding(10))
                 .into_view(),
             )
             .style(move |s| s.height(window_height.get() / 2.0 - 190.0)),
         ))
         .style(|s| card_styles(s))
+        .style(move |s| theme_styles(s, theme.get()))
         .style(move |s| {
             s.width(300)
                 // .absolute()
@@ -0,0 +1,130 @@
+use std::sync::{Arc, Mutex};
+
+use floem::common::{card_styles, input_styles, small_button};
+use floem::event::{Event, EventListener};
+use floem::keyboard::{Key, NamedKey};
+use floem::reactive::{RwSignal, SignalGet, SignalUpdate};
+use floem::views::Decorators;
+use floem::views::{h_stack, label, text_input, v_stack};
+use floem::{GpuHelper, IntoView};
+
+use crate::editor_state::EditorState;
+use crate::helpers::events::{subscribe, EditorEvent};
+use crate::helpers::locking::LockRecover;
+use crate::helpers::navigation::parse_zoom;
+
+use super::inputs::TracksFocus;
+use super::theme::{theme_signal, theme_styles};
+
+/// The view is moved on the gpu, so every command that moves it needs the queue
+pub fn with_queue(
+    editor_state: &Arc<Mutex<EditorState>>,
+    gpu_helper: &Arc<Mutex<GpuHelper>>,
+    command: impl FnOnce(&EditorState, &wgpu::Queue),
+) {
+    let editor_state = editor_state.lock_or_recover();
+    let gpu_helper = gpu_helper.lock_or_recover();
+    let queue = &gpu_helper
+        .gpu_resources
+        .as_ref()
+        .expect("Couldn't get gpu resources")
+        .queue;
+
+    command(&editor_state, queue);
+}
+
+fn zoom_button(
+    editor_state: Arc<Mutex<EditorState>>,
+    gpu_helper: Arc<Mutex<GpuHelper>>,
+    text: &'static str,
+    icon: &'static str,
+    command: impl Fn(&EditorState, &wgpu::Queue) + 'static,
+) -> impl IntoView {
+    small_button(
+        text,
+        icon,
+        move |_| with_queue(&editor_state, &gpu_helper, &command),
+        RwSignal::new(false),
+    )
+    .style(|s| s.margin_right(5.0))
+}
+
+/// The zoom level, which can be typed over, and the commands that frame the canvas
+pub fn zoom_view(
+    editor_state: Arc<Mutex<EditorState>>,
+    gpu_helper: Arc<Mutex<GpuHelper>>,
+    events: RwSignal<Vec<EditorEvent>>,
+) -> impl IntoView {
+    let theme = theme_signal(&editor_state, events);
+    let percent = RwSignal::new(editor_state.lock_or_recover().view().percent());
+
+    subscribe(events, {
+        let editor_state = editor_state.clone();
+        move |event| {
+            if let EditorEvent::CameraChanged = event {
+                percent.set(editor_state.lock_or_recover().view().percent());
+            }
+        }
+    });
+
+    let editor_state2 = editor_state.clone();
+    let gpu_helper2 = gpu_helper.clone();
+
+    v_stack((
+        label(|| "Zoom").style(|s| s.font_size(14.0).margin_bottom(5.0)),
+        // applied on enter, since a half typed zoom would jump the view around
+        text_input(percent)
+            .on_event_stop(EventListener::KeyUp, move |event: &Event| {
+                let Event::KeyUp(key_event) = event else {
+                    return;
+                };
+                if key_event.key.logical_key != Key::Named(NamedKey::Enter) {
+                    return;
+                }
+
+                match parse_zoom(&percent.get_untracked()) {
+                    Ok(zoom) => with_queue(&editor_state2, &gpu_helper2, |editor_state, queue| {
+                        editor_state.update_view(queue, |view| view.zoom_centered(zoom))
+                    }),
+                    Err(error) => {
+                        println!("{}", error);
+                        percent.set(editor_state2.lock_or_recover().view().percent());
+                    }
+                }
+            })
+            .style(|s| input_styles(s).width(100.0).margin_bottom(5.0))
+            .tracks_focus(editor_state.clone()),
+        h_stack((
+            zoom_button(
+                editor_state.clone(),
+                gpu_helper.clone(),
+                "Fit",
+                "square",
+                |editor_state, queue| editor_state.zoom_to_fit(queue),
+            ),
+            zoom_button(
+                editor_state.clone(),
+                gpu_helper.clone(),
+                "Selection",
+                "triangle",
+                |editor_state, queue| {
+                    if let Err(error) = editor_state.zoom_to_selection(queue) {
+                        println!("{}", error);
+                    }
+                },
+            ),
+            zoom_button(
+                editor_state,
+                gpu_helper,
+                "100%",
+                "plus",
+                |editor_state, queue| {
+                    editor_state.update_view(queue, |view| view.zoom_centered(1.0))
+                },
+            ),
+        )),
+    ))
+    .style(|s| card_styles(s))
+    .style(move |s| theme_styles(s, theme.get()))
+    .style(|s| s.margin_left(20.0).margin_top(20).z_index(10))
+}